@@ -0,0 +1,164 @@
+//! Writing checkpoints out as tar archives, and reading archives back in.
+//!
+//! Archives use the POSIX ustar layout, with GNU long-name records for
+//! paths that do not fit in the header, which every common `tar` reads.
+//! The reader also understands pax extended headers for long names.
+
+use anyhow::{bail, Context, Result};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tar::{Archive, Builder, EntryType, Header};
+
+use crate::objects::read_object_raw;
+use crate::tree::{read_tree, EntryKind, MODE_EXECUTABLE};
+
+/// Streams a tree into `out` as a tar archive of the files beneath it,
+/// stamped with `mtime` (seconds since the epoch).
+pub(crate) fn write_tar(
+    out: &mut dyn Write,
+    objects_path: &Path,
+    tree_hash: &str,
+    mtime: u64,
+) -> Result<()> {
+    let mut builder = Builder::new(out);
+    write_tar_tree(&mut builder, objects_path, tree_hash, "", mtime)?;
+    builder.finish()?;
+    Ok(())
+}
+
+fn write_tar_tree(
+    builder: &mut Builder<&mut dyn Write>,
+    objects_path: &Path,
+    tree_hash: &str,
+    prefix: &str,
+    mtime: u64,
+) -> Result<()> {
+    for entry in read_tree(objects_path, tree_hash)? {
+        let path = format!("{}{}", prefix, entry.name);
+        match entry.kind {
+            EntryKind::Tree => {
+                let dir = format!("{}/", path);
+                let mut header = new_header(EntryType::Directory, 0o755, mtime);
+                builder.append_data(&mut header, &dir, std::io::empty())?;
+                write_tar_tree(builder, objects_path, &entry.hash, &dir, mtime)?;
+            }
+            EntryKind::Blob => {
+                let content = read_object_raw(objects_path, &entry.hash)?;
+                let mode = if entry.mode == MODE_EXECUTABLE { 0o755 } else { 0o644 };
+                let mut header = new_header(EntryType::Regular, mode, mtime);
+                header.set_size(content.len() as u64);
+                builder.append_data(&mut header, &path, content.as_slice())?;
+            }
+            EntryKind::Link => {
+                let target = read_object_raw(objects_path, &entry.hash)?;
+                let mut header = new_header(EntryType::Symlink, 0o777, mtime);
+                builder.append_link(&mut header, &path, bytes_to_path(&target))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A ustar header owned by root, with nothing but its type, mode and time
+/// filled in.
+fn new_header(kind: EntryType, mode: u32, mtime: u64) -> Header {
+    let mut header = Header::new_ustar();
+    header.set_entry_type(kind);
+    header.set_mode(mode);
+    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_size(0);
+    header
+}
+
+/// A symlink target as stored in a blob, as a path.
+fn bytes_to_path(target: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(OsStr::from_bytes(target))
+    }
+    #[cfg(not(unix))]
+    PathBuf::from(String::from_utf8_lossy(target).into_owned())
+}
+
+/// What a tar member is, as far as a checkpoint cares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TarMemberKind {
+    File { executable: bool },
+    Directory,
+    Symlink { target: Vec<u8> },
+    /// A hard link to an earlier member, by path.
+    HardLink { target: String },
+}
+
+/// One member read from an archive. `path` is relative, `/`-separated, and
+/// has no trailing `/`.
+#[derive(Debug)]
+pub(crate) struct TarMember {
+    pub(crate) path: String,
+    pub(crate) kind: TarMemberKind,
+    pub(crate) content: Vec<u8>,
+}
+
+/// Reads every file, directory, and link from a tar archive, in archive
+/// order. Members larger than `max_size`, and paths that are absolute or
+/// climb out with `..`, are errors; device nodes and the like are skipped.
+pub(crate) fn read_tar(input: &mut dyn Read, max_size: u64) -> Result<Vec<TarMember>> {
+    let mut members = Vec::new();
+    let mut archive = Archive::new(input);
+    for entry in archive.entries().context("Failed to read tar archive")? {
+        let mut entry = entry.context("Not a tar archive, or it is corrupt")?;
+        let header = entry.header();
+        let kind = header.entry_type();
+        let size = header.size()?;
+        if size > max_size && matches!(kind, EntryType::Regular | EntryType::Continuous) {
+            bail!("Archive member too large: {} bytes (max {})", size, max_size);
+        }
+        let Some(path) = member_path(&entry.path_bytes())? else {
+            continue;
+        };
+        let link = entry.link_name_bytes().map(|link| link.into_owned()).unwrap_or_default();
+        let mode = header.mode()?;
+        let kind = match kind {
+            EntryType::Regular | EntryType::Continuous => TarMemberKind::File {
+                executable: mode & 0o111 != 0,
+            },
+            EntryType::Directory => TarMemberKind::Directory,
+            EntryType::Symlink => TarMemberKind::Symlink { target: link },
+            EntryType::Link => match member_path(&link)? {
+                Some(target) => TarMemberKind::HardLink { target },
+                None => continue,
+            },
+            _ => continue,
+        };
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).context("Failed to read tar archive")?;
+        members.push(TarMember { path, kind, content });
+    }
+    Ok(members)
+}
+
+/// Normalizes a member name to a relative `/`-separated path. Returns `None`
+/// for the archive root itself (`./`).
+fn member_path(name: &[u8]) -> Result<Option<String>> {
+    let name = std::str::from_utf8(name)
+        .map_err(|_| anyhow::anyhow!("Archive path is not valid UTF-8: {}", String::from_utf8_lossy(name)))?;
+    if name.starts_with('/') {
+        bail!("Refusing absolute path in archive: {}", name);
+    }
+    let mut parts = Vec::new();
+    for part in name.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => bail!("Refusing path that leaves the archive: {}", name),
+            part => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(parts.join("/")))
+}
@@ -0,0 +1,306 @@
+//! Safety snapshots of the working tree taken before destructive operations.
+//!
+//! A backup is a small manifest, `.gini/backups/backup_<timestamp>`, naming
+//! a tree in the object store, so files that did not change between backups
+//! (or since the last checkpoint) are stored once. Backups made by older
+//! versions are full copies of the working tree in a directory of that
+//! name; they are still listed and restored. `gini move-backup` can give a
+//! backup any other name, and sidecars beside it (`<name>.msg` and so on)
+//! hold what else is known about it.
+
+use anyhow::{bail, Result};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::ignore::IgnoreRules;
+use crate::objects::{is_valid_hash, read_object_raw};
+use crate::refs::get_head_commit;
+use crate::tree::{flatten_tree, restore_tree, TreeBuilder};
+use crate::util::{copy_directory_excluding, gini_dir, remove_all, replace_working_tree, write_file_atomic};
+use crate::DEFAULT_BACKUP_KEEP;
+
+/// Returns the backup manifests and old-style directories, newest first.
+pub(crate) fn list_backups(root_path: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let backup_dir = gini_dir(root_path).join("backups");
+    let mut backups = Vec::new();
+    if !backup_dir.exists() {
+        return Ok(backups);
+    }
+
+    for entry in fs::read_dir(&backup_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // Sidecars share the name but carry an extension.
+        if path.is_dir() || (path.is_file() && path.extension().is_none()) {
+            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+                backups.push((name.to_string(), path));
+            }
+        }
+    }
+
+    // Sort backups by timestamp (newest first)
+    backups.sort_by_cached_key(|(name, path)| Reverse((backup_time(name, path), name.clone())));
+    Ok(backups)
+}
+
+/// Parses the timestamp out of `backup_<timestamp>` or, for a second backup
+/// taken within the same second, `backup_<timestamp>_<n>`.
+pub(crate) fn backup_timestamp(name: &str) -> Option<u64> {
+    let rest = name.strip_prefix("backup_")?;
+    rest.split('_').next()?.parse().ok()
+}
+
+/// When a backup was taken: from its name, or for one renamed by
+/// `move-backup`, from its manifest's mtime, which renaming keeps and
+/// nothing else touches.
+pub(crate) fn backup_time(name: &str, path: &Path) -> Option<u64> {
+    backup_timestamp(name).or_else(|| {
+        let modified = fs::symlink_metadata(path).ok()?.modified().ok()?;
+        Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+    })
+}
+
+/// The sidecar file holding a backup's optional message.
+pub(crate) fn backup_message_path(backup_path: &Path) -> PathBuf {
+    backup_path.with_extension("msg")
+}
+
+pub(crate) fn read_backup_message(backup_path: &Path) -> Option<String> {
+    let message = fs::read_to_string(backup_message_path(backup_path)).ok()?;
+    Some(message.trim_end().to_string())
+}
+
+/// The sidecar file recording which commit HEAD pointed at when a backup was
+/// taken.
+pub(crate) fn backup_head_path(backup_path: &Path) -> PathBuf {
+    backup_path.with_extension("head")
+}
+
+pub(crate) fn read_backup_head(backup_path: &Path) -> Option<String> {
+    let head = fs::read_to_string(backup_head_path(backup_path)).ok()?;
+    Some(head.trim().to_string())
+}
+
+/// The sidecar file holding the label `gini backup --label` gave a backup.
+pub(crate) fn backup_label_path(backup_path: &Path) -> PathBuf {
+    backup_path.with_extension("label")
+}
+
+pub(crate) fn read_backup_label(backup_path: &Path) -> Option<String> {
+    let label = fs::read_to_string(backup_label_path(backup_path)).ok()?;
+    Some(label.trim_end().to_string())
+}
+
+/// Every sidecar a backup may have.
+fn backup_sidecars(backup_path: &Path) -> [PathBuf; 3] {
+    [backup_message_path(backup_path), backup_head_path(backup_path), backup_label_path(backup_path)]
+}
+
+/// The tree a backup manifest names, or `None` for an old-style backup
+/// directory.
+pub(crate) fn read_backup_tree(backup_path: &Path) -> Result<Option<String>> {
+    if backup_path.is_dir() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(backup_path)?;
+    match content.trim().strip_prefix("tree ") {
+        Some(hash) if is_valid_hash(hash) => Ok(Some(hash.to_string())),
+        _ => bail!("Invalid backup manifest: {}", backup_path.display()),
+    }
+}
+
+/// Lists every file in a backup with its size in bytes, sorted by path.
+/// Symlinks are listed rather than followed.
+pub(crate) fn backup_files(root_path: &Path, backup_path: &Path) -> Result<Vec<(String, u64)>> {
+    if let Some(tree_hash) = read_backup_tree(backup_path)? {
+        let objects_path = gini_dir(root_path).join("objects");
+        let mut files = Vec::new();
+        for (path, entry) in flatten_tree(&objects_path, &tree_hash)? {
+            if !entry.is_empty_dir() {
+                files.push((path, read_object_raw(&objects_path, &entry.hash)?.len() as u64));
+            }
+        }
+        return Ok(files);
+    }
+
+
+    fn walk(dir: &Path, prefix: &str, files: &mut Vec<(String, u64)>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &format!("{}/", path), files)?;
+            } else {
+                files.push((path, fs::symlink_metadata(entry.path())?.len()));
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    walk(backup_path, "", &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Deletes the backups beyond the newest `keep` (`backup.keep` when not
+/// given), and any older than `backup.maxAge`. Returns how many went.
+pub(crate) fn apply_backup_retention(root_path: &Path, keep: Option<usize>) -> Result<usize> {
+    let config = Config::load(root_path)?;
+    let keep = match keep {
+        Some(keep) => keep,
+        None => match config.get("backup.keep") {
+            Some(value) => value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid backup.keep value: {}", value))?,
+            None => DEFAULT_BACKUP_KEEP,
+        },
+    };
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let max_age = config.backup_max_age()?;
+    let too_old = |name: &str, path: &Path| {
+        max_age
+            .zip(backup_time(name, path))
+            .is_some_and(|(max_age, timestamp)| now.saturating_sub(timestamp) > max_age)
+    };
+
+    let backups = list_backups(root_path)?;
+    let mut removed = 0;
+    for (i, (name, path)) in backups.iter().enumerate() {
+        if i < keep && !too_old(name, path) {
+            continue;
+        }
+        remove_backup(name, path)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Deletes every backup taken before `before` (a Unix timestamp). Returns
+/// how many went.
+pub(crate) fn prune_backups_before(root_path: &Path, before: u64) -> Result<usize> {
+    let mut removed = 0;
+    for (name, path) in list_backups(root_path)? {
+        if backup_time(&name, &path).is_some_and(|timestamp| timestamp < before) {
+            remove_backup(&name, &path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Deletes every backup. Returns how many went.
+pub(crate) fn remove_all_backups(root_path: &Path) -> Result<usize> {
+    let backups = list_backups(root_path)?;
+    for (name, path) in &backups {
+        remove_backup(name, path)?;
+    }
+    Ok(backups.len())
+}
+
+/// Deletes every backup that is an empty directory: a legacy copy that
+/// lost its files, with nothing left to restore. Returns how many went.
+pub(crate) fn remove_empty_backups(root_path: &Path) -> Result<usize> {
+    let mut removed = 0;
+    for (name, path) in list_backups(root_path)? {
+        if path.is_dir() && fs::read_dir(&path)?.next().is_none() {
+            remove_backup(&name, &path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Deletes one backup along with its sidecars.
+fn remove_backup(name: &str, path: &Path) -> Result<()> {
+    remove_all(path).map_err(|e| anyhow::anyhow!("Failed to remove backup {}: {}", name, e))?;
+    for sidecar in backup_sidecars(path) {
+        if sidecar.exists() {
+            fs::remove_file(sidecar)?;
+        }
+    }
+    Ok(())
+}
+
+/// Renames a backup and its sidecars to `new_name`, which must be free and
+/// made of letters, digits, `-` and `_`. Returns the new path.
+pub(crate) fn move_backup(backup_path: &Path, new_name: &str) -> Result<PathBuf> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if new_name.is_empty() || new_name.starts_with('-') || !new_name.chars().all(valid) {
+        bail!("Invalid backup name: {} (use letters, digits, - and _)", new_name);
+    }
+    let new_path = backup_path.with_file_name(new_name);
+    if fs::symlink_metadata(&new_path).is_ok() {
+        bail!("A backup named {} already exists", new_name);
+    }
+    fs::rename(backup_path, &new_path)?;
+    for (sidecar, new_sidecar) in backup_sidecars(backup_path).into_iter().zip(backup_sidecars(&new_path)) {
+        if sidecar.exists() {
+            fs::rename(sidecar, new_sidecar)?;
+        }
+    }
+    Ok(new_path)
+}
+
+/// Sets or, given `None`, removes a backup's label.
+pub(crate) fn label_backup(backup_path: &Path, label: Option<&str>) -> Result<()> {
+    let path = backup_label_path(backup_path);
+    match label {
+        Some(label) => write_file_atomic(&path, format!("{}\n", label).as_bytes()),
+        None if path.exists() => Ok(fs::remove_file(path)?),
+        None => Ok(()),
+    }
+}
+
+/// Snapshots the working tree into the object store and names the tree in
+/// `.gini/backups/backup_<timestamp>`, with
+/// `message` in a `.msg` sidecar if given and the current HEAD commit in a
+/// `.head` sidecar, and applies the retention policy.
+/// Returns the new backup's path.
+pub(crate) fn create_backup(root_path: &Path, message: Option<&str>) -> Result<PathBuf> {
+    let backup_dir = gini_dir(root_path).join("backups");
+    fs::create_dir_all(&backup_dir)?;
+    
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut backup_path = backup_dir.join(format!("backup_{}", timestamp));
+    let mut n = 1;
+    while backup_path.exists() {
+        backup_path = backup_dir.join(format!("backup_{}_{}", timestamp, n));
+        n += 1;
+    }
+    
+    // A restore replaces everything but `.gini` and `.git`, so ignored and
+    // oversized files go in too.
+    let mut builder = TreeBuilder::new(root_path, true)?;
+    builder.ignore = IgnoreRules::parse(root_path, "/.git");
+    builder.max_file_size = u64::MAX;
+    // Files are kept byte for byte, whatever `core.autocrlf` says.
+    builder.autocrlf = false;
+    let tree_hash = builder.write_tree(root_path)?;
+    write_file_atomic(&backup_path, format!("tree {}\n", tree_hash).as_bytes())?;
+    if let Some(message) = message {
+        fs::write(backup_message_path(&backup_path), message)?;
+    }
+    if let Some(head) = get_head_commit(root_path)? {
+        fs::write(backup_head_path(&backup_path), head)?;
+    }
+
+    apply_backup_retention(root_path, None)?;
+    Ok(backup_path)
+}
+
+pub(crate) fn restore_from_backup(root_path: &Path, backup_path: &Path) -> Result<()> {
+    match read_backup_tree(backup_path)? {
+        Some(tree_hash) => {
+            let objects_path = gini_dir(root_path).join("objects");
+            replace_working_tree(root_path, |staging| restore_tree(staging, &objects_path, &tree_hash, false))
+        }
+        None => replace_working_tree(root_path, |staging| {
+            copy_directory_excluding(backup_path, staging, &[".gini"])
+        }),
+    }
+}
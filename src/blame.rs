@@ -0,0 +1,102 @@
+//! `gini blame`: which checkpoint last changed each line of a file.
+
+use anyhow::{bail, Result};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::commit::{parse_commit_details, parse_commit_tree};
+use crate::content::as_text;
+use crate::diff::{diff_lines, Edit};
+use crate::objects::{read_object, read_object_raw};
+use crate::tree::{find_tree_entry, EntryKind};
+
+/// A file's blob hash and text at one checkpoint, or `None` if the
+/// checkpoint has no such file or only a binary one.
+fn text_at(objects_path: &Path, commit: &str, path: &str) -> Result<Option<(String, String)>> {
+    let tree_hash = parse_commit_tree(&read_object(objects_path, commit)?)?;
+    let entry = match find_tree_entry(objects_path, &tree_hash, path)? {
+        Some(entry) if entry.kind == EntryKind::Blob => entry,
+        _ => return Ok(None),
+    };
+    let content = read_object_raw(objects_path, &entry.hash)?;
+    Ok(as_text(&content).map(|text| (entry.hash, text.to_string())))
+}
+
+/// The lines `wanted` (0-based) of `path` as of checkpoint `start`, each
+/// with the checkpoint that last changed it.
+///
+/// Walks back through the parents of `start`, diffing the file at each
+/// checkpoint against its parent and following every unclaimed line to its
+/// place in the older version. A line is claimed by the first checkpoint
+/// whose diff inserts it; whatever is left when the file disappears (or
+/// history ends) belongs to the checkpoint that added the file.
+pub(crate) fn blame_file(
+    objects_path: &Path,
+    start: &str,
+    path: &str,
+    wanted: Range<usize>,
+) -> Result<Vec<(String, String)>> {
+    let Some((mut hash, mut text)) = text_at(objects_path, start, path)? else {
+        let tree_hash = parse_commit_tree(&read_object(objects_path, start)?)?;
+        match find_tree_entry(objects_path, &tree_hash, path)? {
+            Some(_) => bail!("{} is not a text file in checkpoint {}", path, &start[..7]),
+            None => bail!("Path '{}' does not exist in checkpoint {}", path, &start[..7]),
+        }
+    };
+    let lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let wanted = wanted.start.min(lines.len())..wanted.end.min(lines.len());
+
+    let mut owners: Vec<Option<String>> = vec![None; wanted.len()];
+    // Where each wanted line sits in the version being examined, until claimed.
+    let mut positions: Vec<Option<usize>> = wanted.clone().map(Some).collect();
+    let mut commit = start.to_string();
+    while positions.iter().any(Option::is_some) {
+        let parent = parse_commit_details(&read_object(objects_path, &commit)?)?.parent().map(str::to_string);
+        let older = match &parent {
+            Some(parent) => text_at(objects_path, parent, path)?,
+            None => None,
+        };
+        let (Some(parent), Some((older_hash, older_text))) = (parent, older) else {
+            for (owner, position) in owners.iter_mut().zip(&mut positions) {
+                if position.take().is_some() {
+                    *owner = Some(commit.clone());
+                }
+            }
+            break;
+        };
+
+        if older_hash != hash {
+            let old: Vec<&str> = older_text.lines().collect();
+            let new: Vec<&str> = text.lines().collect();
+            let mut to_old = vec![None; new.len()];
+            let (mut i, mut j) = (0, 0);
+            for edit in diff_lines(&old, &new) {
+                match edit {
+                    Edit::Keep(_) => {
+                        to_old[j] = Some(i);
+                        i += 1;
+                        j += 1;
+                    }
+                    Edit::Delete(_) => i += 1,
+                    Edit::Insert(_) => j += 1,
+                }
+            }
+            for (owner, position) in owners.iter_mut().zip(&mut positions) {
+                if let Some(current) = *position {
+                    *position = to_old[current];
+                    if position.is_none() {
+                        *owner = Some(commit.clone());
+                    }
+                }
+            }
+        }
+        hash = older_hash;
+        text = older_text;
+        commit = parent;
+    }
+
+    Ok(wanted
+        .zip(owners)
+        .map(|(line, owner)| (owner.unwrap_or_default(), lines[line].clone()))
+        .collect())
+}
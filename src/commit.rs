@@ -0,0 +1,325 @@
+//! Parsing of commit and tag objects.
+
+use anyhow::{bail, Result};
+
+use crate::objects::is_valid_hash;
+
+pub(crate) fn parse_commit_tree(commit_content: &str) -> Result<String> {
+    let tree_line = commit_content
+        .lines()
+        .find(|line| line.starts_with("tree "))
+        .ok_or_else(|| anyhow::anyhow!("Could not find tree in commit object"))?;
+    
+    let parts: Vec<_> = tree_line.split_whitespace().collect();
+    if parts.len() != 2 {
+        bail!("Invalid tree line format: {}", tree_line);
+    }
+    
+    let hash = parts[1];
+    if !is_valid_hash(hash) {
+        bail!("Invalid tree hash in commit: {}", hash);
+    }
+    
+    Ok(hash.to_string())
+}
+
+/// Metadata extracted from a commit object.
+#[derive(Debug, Clone)]
+pub(crate) struct CommitDetails {
+    /// One `parent` line each, in order; a merge has more than one.
+    pub(crate) parents: Vec<String>,
+    /// Author identity, e.g. `Name <email>`.
+    pub(crate) author: String,
+    /// The `Name` part of `author`.
+    pub(crate) name: String,
+    /// The `email` part of `author`; empty when it records none.
+    pub(crate) email: String,
+    /// Seconds since the Unix epoch.
+    pub(crate) timestamp: i64,
+    /// The author's UTC offset in seconds, when the commit records a valid one.
+    pub(crate) offset: Option<i32>,
+    pub(crate) message: String,
+}
+
+impl CommitDetails {
+    /// The first parent: the checkpoint this one was made on top of.
+    pub(crate) fn parent(&self) -> Option<&str> {
+        self.parents.first().map(String::as_str)
+    }
+
+    pub(crate) fn is_merge(&self) -> bool {
+        self.parents.len() > 1
+    }
+}
+
+pub(crate) fn parse_commit_details(commit_content: &str) -> Result<CommitDetails> {
+    let mut parents = Vec::new();
+    let mut author = String::new();
+    let mut timestamp = 0;
+    let mut offset = None;
+    let mut message_lines = Vec::new();
+    let mut in_message = false;
+
+    for line in commit_content.lines() {
+        if in_message {
+            message_lines.push(line);
+            continue;
+        }
+        if line.starts_with("parent ") {
+            let parts: Vec<_> = line.split_whitespace().collect();
+            if parts.len() == 2 && is_valid_hash(parts[1]) {
+                parents.push(parts[1].to_string());
+            } else {
+                bail!("Invalid parent line: {}", line);
+            }
+        } else if let Some(rest) = line.strip_prefix("author ") {
+            // The author line ends with "<timestamp> <offset>".
+            let mut parts = rest.rsplitn(3, ' ');
+            let tz = parts.next();
+            let ts = parts.next().and_then(|t| t.parse::<i64>().ok());
+            match (ts, parts.next()) {
+                (Some(ts), Some(identity)) => {
+                    timestamp = ts;
+                    offset = tz.and_then(parse_offset);
+                    author = identity.to_string();
+                }
+                _ => author = rest.to_string(),
+            }
+        } else if line.is_empty() {
+            in_message = true;
+        }
+    }
+    let (name, email) = split_identity(&author);
+    Ok(CommitDetails {
+        parents,
+        name: name.to_string(),
+        email: email.to_string(),
+        author,
+        timestamp,
+        offset,
+        message: message_lines.join("\n"),
+    })
+}
+
+/// Splits `Name <email>` into its two parts. An identity without the
+/// angle brackets is all name.
+pub(crate) fn split_identity(identity: &str) -> (&str, &str) {
+    match identity.strip_suffix('>').and_then(|rest| rest.rsplit_once('<')) {
+        Some((name, email)) => (name.trim_end(), email),
+        None => (identity, ""),
+    }
+}
+
+/// Checks that a commit object has everything a checkpoint needs, which
+/// [`parse_commit_details`] is lenient about: a `tree` line first, valid
+/// `parent` lines, and an `author` line ending in a timestamp.
+pub(crate) fn validate_commit(commit_content: &str) -> Result<()> {
+    let Some(first) = commit_content.lines().next().filter(|line| line.starts_with("tree ")) else {
+        bail!("it doesn't start with a tree line");
+    };
+    parse_commit_tree(first)?;
+    let headers = commit_content.split("\n\n").next().unwrap_or("");
+    let mut authors = headers.lines().filter_map(|line| line.strip_prefix("author "));
+    let (Some(author), None) = (authors.next(), authors.next()) else {
+        bail!("it needs exactly one author line");
+    };
+    let mut parts = author.rsplitn(3, ' ');
+    let (_, timestamp, identity) = (parts.next(), parts.next(), parts.next());
+    if identity.is_none() || timestamp.and_then(|t| t.parse::<i64>().ok()).is_none() {
+        bail!("its author line has no timestamp: {}", author);
+    }
+    parse_commit_details(commit_content)?;
+    Ok(())
+}
+
+/// Formats a commit time in the author's recorded offset, falling back to
+/// the local timezone when the commit has none.
+pub(crate) fn format_timestamp(timestamp: i64, offset: Option<i32>) -> String {
+    format_timestamp_as(timestamp, offset, "%Y-%m-%d %H:%M:%S %z")
+}
+
+/// [`format_timestamp`] in ISO 8601, e.g. `2024-03-01T14:30:00+01:00`.
+pub(crate) fn iso_timestamp(timestamp: i64, offset: Option<i32>) -> String {
+    format_timestamp_as(timestamp, offset, "%Y-%m-%dT%H:%M:%S%:z")
+}
+
+fn format_timestamp_as(timestamp: i64, offset: Option<i32>, format: &str) -> String {
+    let Some(dt) = chrono::DateTime::from_timestamp(timestamp, 0) else {
+        return timestamp.to_string();
+    };
+    match offset.and_then(chrono::FixedOffset::east_opt) {
+        Some(tz) => dt.with_timezone(&tz).format(format).to_string(),
+        None => dt.with_timezone(&chrono::Local).format(format).to_string(),
+    }
+}
+
+/// Describes how long before `now` a timestamp was, e.g. `2 hours ago`.
+/// Anything older than a month is shown as a date.
+pub fn relative_time(timestamp: i64, now: i64) -> String {
+    let seconds = now - timestamp;
+    let plural = |n: i64, unit: &str| {
+        format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+    };
+    match seconds {
+        i64::MIN..=0 => "just now".to_string(),
+        1..=59 => plural(seconds, "second"),
+        60..=3599 => plural(seconds / 60, "minute"),
+        3600..=86_399 => plural(seconds / 3600, "hour"),
+        86_400..=604_799 => plural(seconds / 86_400, "day"),
+        604_800..=2_591_999 => plural(seconds / 604_800, "week"),
+        _ => match chrono::DateTime::from_timestamp(timestamp, 0) {
+            Some(date) => date.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string(),
+            None => timestamp.to_string(),
+        },
+    }
+}
+
+/// Expands a `log --format` template for one commit, given its already
+/// formatted date. `%%` is a literal `%`; any other placeholder not listed
+/// here is an error.
+pub(crate) fn format_commit(template: &str, hash: &str, details: &CommitDetails, date: &str) -> Result<String> {
+    let (name, email) = (details.name.as_str(), details.email.as_str());
+    let timestamp = details.timestamp.to_string();
+    let short_parents: Vec<&str> = details.parents.iter().map(|parent| &parent[..7]).collect();
+    let short_parents = short_parents.join(" ");
+    let placeholders = [
+        ("H", hash),
+        ("h", &hash[..7]),
+        ("P", &details.parents.join(" ")),
+        ("p", &short_parents),
+        ("an", name),
+        ("ae", email),
+        ("ad", date),
+        ("at", &timestamp),
+        ("s", details.message.lines().next().unwrap_or("")),
+        ("n", "\n"),
+        ("%", "%"),
+    ];
+
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(at) = rest.find('%') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+        let Some((placeholder, value)) = placeholders.iter().find(|(p, _)| rest.starts_with(p)) else {
+            match rest.chars().next() {
+                Some(c) => bail!("Unknown placeholder %{} in --format", c),
+                None => bail!("--format ends with a lone %"),
+            }
+        };
+        out.push_str(value);
+        rest = &rest[placeholder.len()..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Parses a `+HHMM`/`-HHMM` offset into seconds east of UTC.
+pub(crate) fn parse_offset(value: &str) -> Option<i32> {
+    let (sign, digits) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Formats seconds east of UTC as `+HHMM`/`-HHMM`.
+pub(crate) fn format_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+}
+
+/// An annotated tag object:
+/// `object <hash>\ntype commit\ntag <name>\ntagger <identity> <ts> <offset>\n\n<message>`.
+#[derive(Debug, Clone)]
+pub(crate) struct TagDetails {
+    pub(crate) object: String,
+    pub(crate) message: String,
+}
+
+/// Parses an annotated tag object, or returns `None` if `content` is not one.
+pub(crate) fn parse_tag(content: &str) -> Option<TagDetails> {
+    let object = content
+        .lines()
+        .next()?
+        .strip_prefix("object ")
+        .filter(|hash| is_valid_hash(hash))?;
+    let message = content
+        .split_once("\n\n")
+        .map(|(_, message)| message)
+        .unwrap_or("");
+    Some(TagDetails {
+        object: object.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// Whether `line` is a `Key: value` trailer: a key of letters, digits and
+/// `-`, then `: ` and a non-empty value.
+fn is_trailer(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(key, value)| {
+        !key.is_empty()
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !value.trim().is_empty()
+    })
+}
+
+/// A commit's content with its tree and parent swapped for `tree_hash` and
+/// `parent`; the author and message stay as they are.
+pub(crate) fn rewrite_commit(commit_content: &str, tree_hash: &str, parent: Option<&str>) -> String {
+    let (headers, message) = commit_content.split_once("\n\n").unwrap_or((commit_content, ""));
+    let mut out = format!("tree {}\n", tree_hash);
+    if let Some(parent) = parent {
+        out.push_str(&format!("parent {}\n", parent));
+    }
+    for line in headers.lines().filter(|line| !line.starts_with("tree ") && !line.starts_with("parent ")) {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push('\n');
+    out.push_str(message);
+    out
+}
+
+/// The subject a `fixup! <subject>` message points at, with any repeated
+/// `fixup! ` prefixes removed, or `None` if it isn't a fixup.
+pub(crate) fn fixup_target(subject: &str) -> Option<&str> {
+    let mut target = subject.strip_prefix("fixup! ")?;
+    while let Some(rest) = target.strip_prefix("fixup! ") {
+        target = rest;
+    }
+    Some(target)
+}
+
+/// Appends `trailers` to `message` as its last paragraph, or to the
+/// trailer paragraph it already ends with. Trailers the message already
+/// has are not repeated.
+pub(crate) fn append_trailers(message: &str, trailers: &[String]) -> Result<String> {
+    if let Some(bad) = trailers.iter().find(|trailer| !is_trailer(trailer) || trailer.contains('\n')) {
+        bail!("Invalid trailer: {} (expected \"Key: value\")", bad);
+    }
+    let body = message.trim_end();
+    let last_paragraph = body.rsplit("\n\n").next().unwrap_or("");
+    let has_trailers = body.contains("\n\n") && last_paragraph.lines().all(is_trailer);
+    let mut result = body.to_string();
+    let mut first = true;
+    for trailer in trailers {
+        if has_trailers && last_paragraph.lines().any(|line| line == trailer) {
+            continue;
+        }
+        result.push_str(if first && !has_trailers { "\n\n" } else { "\n" });
+        result.push_str(trailer);
+        first = false;
+    }
+    Ok(result)
+}
@@ -0,0 +1,328 @@
+//! Repository settings stored in `.gini/config`, and the user-wide ones
+//! in `~/.config/gini/config`.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::hash::HashAlgo;
+use crate::util::{gini_dir, write_file_atomic};
+use crate::{
+    CONFIG_FILE_NAME, DEFAULT_AUTO_GC_EVERY, DEFAULT_EXCLUDES, DEFAULT_PRUNE_EXPIRE, DEFAULT_REFLOG_EXPIRE, DEFAULT_WRITE_RETRIES,
+    MAX_FILE_SIZE,
+};
+
+/// Repository settings stored as `key = value` lines in `.gini/config`.
+#[derive(Debug, Default)]
+pub(crate) struct Config {
+    pub(crate) values: BTreeMap<String, String>,
+}
+
+impl Config {
+    pub(crate) fn load(root_path: &Path) -> Result<Self> {
+        let config_path = gini_dir(root_path).join(CONFIG_FILE_NAME);
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&config_path).context("Failed to read .gini/config")?;
+        Ok(Self::parse(&content))
+    }
+
+    /// The user-wide settings from [`global_config_path`], or none if there
+    /// is no such file.
+    pub(crate) fn load_global() -> Result<Self> {
+        let Some(config_path) = global_config_path().filter(|path| path.exists()) else {
+            return Ok(Self::default());
+        };
+        let content = fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        Ok(Self::parse(&content))
+    }
+
+    pub(crate) fn parse(content: &str) -> Self {
+        let mut values = BTreeMap::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                values.insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+        Config { values }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    pub(crate) fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        if key.is_empty() || key.contains(|c: char| c == '=' || c == '#' || c.is_whitespace()) {
+            bail!("Invalid config key: {}", key);
+        }
+        if value.contains(['\n', '\r']) {
+            bail!("Config values cannot span multiple lines");
+        }
+        self.values.insert(key.to_string(), value.trim().to_string());
+        Ok(())
+    }
+
+    /// The hash function objects are named with: `core.hashAlgo`, or SHA-1
+    /// when unset.
+    pub(crate) fn hash_algo(&self) -> Result<HashAlgo> {
+        match self.get("core.hashAlgo") {
+            Some(value) => HashAlgo::parse(value).ok_or_else(|| {
+                anyhow::anyhow!("Invalid core.hashAlgo value: {} (expected sha1 or sha256)", value)
+            }),
+            None => Ok(HashAlgo::Sha1),
+        }
+    }
+
+    /// The largest file a checkpoint may store: `core.maxFileSize`, or
+    /// [`MAX_FILE_SIZE`] when unset.
+    pub(crate) fn max_file_size(&self) -> Result<u64> {
+        match self.get("core.maxFileSize") {
+            Some(value) => parse_size(value).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid core.maxFileSize value: {} (expected a size like 500M)",
+                    value
+                )
+            }),
+            None => Ok(MAX_FILE_SIZE),
+        }
+    }
+
+    /// How old, in seconds, a backup may get before pruning deletes it:
+    /// `backup.maxAge`, or no limit when unset.
+    pub(crate) fn backup_max_age(&self) -> Result<Option<u64>> {
+        match self.get("backup.maxAge") {
+            Some(value) => parse_duration(value).map(Some).ok_or_else(|| {
+                anyhow::anyhow!("Invalid backup.maxAge value: {} (expected a duration like 30d)", value)
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether files over the size limit are left out of checkpoints rather
+    /// than stopping them: `core.excludeLarge`, off when unset.
+    pub(crate) fn exclude_large(&self) -> Result<bool> {
+        match self.get("core.excludeLarge") {
+            Some(value) => parse_bool(value).ok_or_else(|| {
+                anyhow::anyhow!("Invalid core.excludeLarge value: {} (expected true or false)", value)
+            }),
+            None => Ok(false),
+        }
+    }
+
+    /// The most files a checkpoint of the working tree may record:
+    /// `core.maxFiles`, or no limit when unset.
+    pub(crate) fn max_files(&self) -> Result<Option<usize>> {
+        match self.get("core.maxFiles") {
+            Some(value) => value.parse().map(Some).map_err(|_| {
+                anyhow::anyhow!("Invalid core.maxFiles value: {} (expected a number of files)", value)
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// The most bytes a checkpoint of the working tree may record, all files
+    /// together: `core.maxTreeSize`, or no limit when unset.
+    pub(crate) fn max_tree_size(&self) -> Result<Option<u64>> {
+        match self.get("core.maxTreeSize") {
+            Some(value) => parse_size(value).map(Some).ok_or_else(|| {
+                anyhow::anyhow!("Invalid core.maxTreeSize value: {} (expected a size like 2G)", value)
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// How long, in seconds, a reflog entry keeps its checkpoint safe from
+    /// `gc`: `gc.reflogExpire`, or [`DEFAULT_REFLOG_EXPIRE`] when unset.
+    pub(crate) fn reflog_expire(&self) -> Result<u64> {
+        match self.get("gc.reflogExpire") {
+            Some(value) => parse_duration(value).ok_or_else(|| {
+                anyhow::anyhow!("Invalid gc.reflogExpire value: {} (expected a duration like 90d)", value)
+            }),
+            None => Ok(DEFAULT_REFLOG_EXPIRE),
+        }
+    }
+
+    /// How long, in seconds, an unreachable loose object is spared by `gc`
+    /// after it was written, so a checkpoint still in progress doesn't lose
+    /// its objects: `gc.pruneExpire`, or [`DEFAULT_PRUNE_EXPIRE`] when unset.
+    pub(crate) fn prune_expire(&self) -> Result<u64> {
+        match self.get("gc.pruneExpire") {
+            Some(value) => parse_duration(value).ok_or_else(|| {
+                anyhow::anyhow!("Invalid gc.pruneExpire value: {} (expected a duration like 2w)", value)
+            }),
+            None => Ok(DEFAULT_PRUNE_EXPIRE),
+        }
+    }
+
+    /// Whether text files are stored with LF line endings and written out
+    /// with the platform's own: `core.autocrlf`, off when unset.
+    pub(crate) fn autocrlf(&self) -> Result<bool> {
+        match self.get("core.autocrlf") {
+            Some(value) => parse_bool(value).ok_or_else(|| {
+                anyhow::anyhow!("Invalid core.autocrlf value: {} (expected true or false)", value)
+            }),
+            None => Ok(false),
+        }
+    }
+
+    /// Whether a restore re-hashes the working tree afterwards to check it
+    /// matches the checkpoint: `restore.verify`, off when unset.
+    pub(crate) fn restore_verify(&self) -> Result<bool> {
+        match self.get("restore.verify") {
+            Some(value) => parse_bool(value).ok_or_else(|| {
+                anyhow::anyhow!("Invalid restore.verify value: {} (expected true or false)", value)
+            }),
+            None => Ok(false),
+        }
+    }
+
+    /// Whether restores and checkouts back up the working tree before
+    /// overwriting it: `core.backupOnRestore`, on when unset.
+    pub(crate) fn backup_on_restore(&self) -> Result<bool> {
+        match self.get("core.backupOnRestore") {
+            Some(value) => parse_bool(value).ok_or_else(|| {
+                anyhow::anyhow!("Invalid core.backupOnRestore value: {} (expected true or false)", value)
+            }),
+            None => Ok(true),
+        }
+    }
+
+    /// Whether every object read is re-hashed to catch corruption:
+    /// `core.verifyOnRead`, off when unset.
+    pub(crate) fn verify_on_read(&self) -> Result<bool> {
+        match self.get("core.verifyOnRead") {
+            Some(value) => parse_bool(value).ok_or_else(|| {
+                anyhow::anyhow!("Invalid core.verifyOnRead value: {} (expected true or false)", value)
+            }),
+            None => Ok(false),
+        }
+    }
+
+    /// How many times a failed object write is retried when the error looks
+    /// transient: `core.writeRetries`, or [`DEFAULT_WRITE_RETRIES`] when unset.
+    pub(crate) fn write_retries(&self) -> Result<u32> {
+        match self.get("core.writeRetries") {
+            Some(value) => value.parse().map_err(|_| {
+                anyhow::anyhow!("Invalid core.writeRetries value: {} (expected a whole number)", value)
+            }),
+            None => Ok(DEFAULT_WRITE_RETRIES),
+        }
+    }
+
+    /// Whether checkpoints now and then delete unreachable objects:
+    /// `maintenance.autoGc`, off when unset.
+    pub(crate) fn auto_gc(&self) -> Result<bool> {
+        match self.get("maintenance.autoGc") {
+            Some(value) => parse_bool(value).ok_or_else(|| {
+                anyhow::anyhow!("Invalid maintenance.autoGc value: {} (expected true or false)", value)
+            }),
+            None => Ok(false),
+        }
+    }
+
+    /// How many checkpoints pass between automatic collections:
+    /// `maintenance.autoGcEvery`, or [`DEFAULT_AUTO_GC_EVERY`] when unset.
+    pub(crate) fn auto_gc_every(&self) -> Result<u32> {
+        match self.get("maintenance.autoGcEvery") {
+            Some(value) => value.parse().ok().filter(|&every| every > 0).ok_or_else(|| {
+                anyhow::anyhow!("Invalid maintenance.autoGcEvery value: {} (expected a positive number)", value)
+            }),
+            None => Ok(DEFAULT_AUTO_GC_EVERY),
+        }
+    }
+
+    /// Whether `.gitignore` files are honored: `core.useGitignore`, or when
+    /// unset, whether the project is also a git repository.
+    pub(crate) fn use_gitignore(&self, root_path: &Path) -> Result<bool> {
+        match self.get("core.useGitignore") {
+            Some(value) => parse_bool(value).ok_or_else(|| {
+                anyhow::anyhow!("Invalid core.useGitignore value: {} (expected true or false)", value)
+            }),
+            None => Ok(root_path.join(".git").exists()),
+        }
+    }
+
+    /// The ignore patterns applied before `.giniignore`: the comma-separated
+    /// `core.exclude`, or [`DEFAULT_EXCLUDES`] when unset. An empty value
+    /// excludes nothing.
+    pub(crate) fn excludes(&self) -> Vec<String> {
+        match self.get("core.exclude") {
+            Some(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|pattern| !pattern.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => DEFAULT_EXCLUDES.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    pub(crate) fn save(&self, root_path: &Path) -> Result<()> {
+        let content: String = self
+            .values
+            .iter()
+            .map(|(key, value)| format!("{} = {}\n", key, value))
+            .collect();
+        let config_path = gini_dir(root_path).join(CONFIG_FILE_NAME);
+        write_file_atomic(&config_path, content.as_bytes()).context("Failed to write .gini/config")
+    }
+}
+
+/// Where the user-wide settings live: `$GINI_CONFIG_HOME/config`, else
+/// `~/.config/gini/config`. `None` when neither variable is set.
+pub(crate) fn global_config_path() -> Option<PathBuf> {
+    match std::env::var_os("GINI_CONFIG_HOME") {
+        Some(dir) => Some(PathBuf::from(dir).join("config")),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/gini/config")),
+    }
+}
+
+/// Parses a byte count with an optional `K`, `M` or `G` suffix (powers of
+/// 1024), e.g. `500M`.
+pub(crate) fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (digits, multiplier) = match value.char_indices().last()? {
+        (i, 'k' | 'K') => (&value[..i], 1 << 10),
+        (i, 'm' | 'M') => (&value[..i], 1 << 20),
+        (i, 'g' | 'G') => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Parses a duration in seconds with an optional `s`, `m`, `h`, `d` or `w`
+/// suffix, e.g. `30d`.
+pub fn parse_duration(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (digits, multiplier) = match value.char_indices().last()? {
+        (i, 's') => (&value[..i], 1),
+        (i, 'm') => (&value[..i], 60),
+        (i, 'h') => (&value[..i], 60 * 60),
+        (i, 'd') => (&value[..i], 24 * 60 * 60),
+        (i, 'w') => (&value[..i], 7 * 24 * 60 * 60),
+        _ => (value, 1),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Parses a boolean setting: `true`/`false`, `yes`/`no`, `on`/`off` or `1`/`0`.
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,61 @@
+//! Telling text from binary content, and naming common binary formats by
+//! their magic bytes so diffs and `cat-file` can describe a binary file
+//! rather than dump it.
+
+/// Leading bytes of well-known binary formats, checked in order.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (b"\xff\xd8\xff", "JPEG image"),
+    (b"GIF87a", "GIF image"),
+    (b"GIF89a", "GIF image"),
+    (b"II*\x00", "TIFF image"),
+    (b"MM\x00*", "TIFF image"),
+    (b"\x00\x00\x01\x00", "ICO image"),
+    (b"BM", "BMP image"),
+    (b"%PDF-", "PDF document"),
+    (b"PK\x03\x04", "ZIP archive"),
+    (b"\x1f\x8b", "gzip data"),
+    (b"7z\xbc\xaf\x27\x1c", "7-Zip archive"),
+    (b"\x7fELF", "ELF executable"),
+    (b"\x00asm", "WebAssembly module"),
+    (b"SQLite format 3\x00", "SQLite database"),
+    (b"OggS", "Ogg media"),
+    (b"ID3", "MP3 audio"),
+    (b"fLaC", "FLAC audio"),
+];
+
+/// Returns the content as text, or `None` if it looks binary: it holds a
+/// NUL byte or is not valid UTF-8.
+pub(crate) fn as_text(content: &[u8]) -> Option<&str> {
+    if content.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(content).ok()
+}
+
+/// The format `content` is in, going by its magic bytes, if it is one gini
+/// knows.
+pub(crate) fn sniff_type(content: &[u8]) -> Option<&'static str> {
+    if content.len() >= 12 && content.starts_with(b"RIFF") {
+        match &content[8..12] {
+            b"WEBP" => return Some("WebP image"),
+            b"WAVE" => return Some("WAV audio"),
+            b"AVI " => return Some("AVI video"),
+            _ => {}
+        }
+    }
+    SIGNATURES
+        .iter()
+        .find(|(magic, _)| content.starts_with(magic))
+        .map(|&(_, name)| name)
+}
+
+/// A short label for binary content, such as `PNG image, 1204 bytes`, or
+/// `None` if the content is text.
+pub fn describe_binary(content: &[u8]) -> Option<String> {
+    if as_text(content).is_some() {
+        return None;
+    }
+    let kind = sniff_type(content).unwrap_or("binary data");
+    Some(format!("{}, {} bytes", kind, content.len()))
+}
@@ -0,0 +1,670 @@
+//! Tree comparison, line-based unified diffs, and word diffs for prose.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::content::{as_text, describe_binary};
+use crate::objects::read_object_raw;
+use crate::tree::FileEntry;
+use crate::CheckProblem;
+
+/// Lines of context shown around each change in a unified diff.
+pub(crate) const DIFF_CONTEXT: usize = 3;
+
+/// Above this many comparison cells the LCS table is skipped and the
+/// changed region is reported as a full replacement.
+pub(crate) const MAX_DIFF_CELLS: usize = 16 * 1024 * 1024;
+
+/// How a file differs between two trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    /// Moved to a new path with identical content.
+    Renamed,
+}
+
+impl ChangeKind {
+    pub(crate) fn letter(self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Modified => 'M',
+            ChangeKind::Deleted => 'D',
+            ChangeKind::Renamed => 'R',
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct FileChange {
+    pub(crate) path: String,
+    /// For renames, the path the file moved from.
+    pub(crate) old_path: Option<String>,
+    pub(crate) kind: ChangeKind,
+    pub(crate) old: Option<FileEntry>,
+    pub(crate) new: Option<FileEntry>,
+}
+
+impl FileChange {
+    /// The path as shown to users, with a trailing `/` for directories.
+    /// Renames read `old -> new`.
+    pub(crate) fn display_path(&self) -> String {
+        let file = self.new.as_ref().or(self.old.as_ref());
+        let path = format!("{}{}", self.path, file.map(dir_suffix).unwrap_or(""));
+        match &self.old_path {
+            Some(old_path) => format!("{} -> {}", old_path, path),
+            None => path,
+        }
+    }
+}
+
+/// `/` for empty-directory entries, nothing for files.
+pub(crate) fn dir_suffix(file: &FileEntry) -> &'static str {
+    if file.is_empty_dir() {
+        "/"
+    } else {
+        ""
+    }
+}
+
+/// True if `files` holds anything beneath the directory `path`.
+fn has_children(files: &BTreeMap<String, FileEntry>, path: &str) -> bool {
+    let prefix = format!("{}/", path);
+    files
+        .range(prefix.clone()..)
+        .next()
+        .is_some_and(|(p, _)| p.starts_with(&prefix))
+}
+
+/// Compares two path -> file maps, yielding changes in path order. A file
+/// whose mode alone changed counts as modified. An empty directory that
+/// merely gained or lost its contents is not reported; its files are.
+pub(crate) fn diff_file_maps(
+    old: &BTreeMap<String, FileEntry>,
+    new: &BTreeMap<String, FileEntry>,
+) -> Vec<FileChange> {
+    let mut changes = Vec::new();
+    for (path, old_file) in old {
+        match new.get(path) {
+            Some(new_file) if new_file == old_file => {}
+            None if old_file.is_empty_dir() && has_children(new, path) => {}
+            Some(new_file) => changes.push(FileChange {
+                path: path.clone(),
+                old_path: None,
+                kind: ChangeKind::Modified,
+                old: Some(old_file.clone()),
+                new: Some(new_file.clone()),
+            }),
+            None => changes.push(FileChange {
+                path: path.clone(),
+                old_path: None,
+                kind: ChangeKind::Deleted,
+                old: Some(old_file.clone()),
+                new: None,
+            }),
+        }
+    }
+    for (path, new_file) in new {
+        if new_file.is_empty_dir() && has_children(old, path) {
+            continue;
+        }
+        if !old.contains_key(path) {
+            changes.push(FileChange {
+                path: path.clone(),
+                old_path: None,
+                kind: ChangeKind::Added,
+                old: None,
+                new: Some(new_file.clone()),
+            });
+        }
+    }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+/// The hash of an empty blob, under SHA-1 and SHA-256. Empty files all
+/// share it, so they are never paired up as renames.
+const EMPTY_BLOB_HASHES: [&str; 2] = [
+    "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+];
+
+/// Pairs each added file with a deleted file of identical content and mode,
+/// replacing the two with a single rename. Changes stay in (new) path order.
+pub(crate) fn detect_renames(changes: Vec<FileChange>) -> Vec<FileChange> {
+    let renamable = |file: &Option<FileEntry>| {
+        file.as_ref().is_some_and(|file| !file.is_empty_dir() && !EMPTY_BLOB_HASHES.contains(&file.hash.as_str()))
+    };
+    let mut deleted: Vec<FileChange> = Vec::new();
+    let mut others = Vec::new();
+    for change in changes {
+        if change.kind == ChangeKind::Deleted && renamable(&change.old) {
+            deleted.push(change);
+        } else {
+            others.push(change);
+        }
+    }
+
+    let mut result = Vec::new();
+    for change in others {
+        if change.kind != ChangeKind::Added || !renamable(&change.new) {
+            result.push(change);
+            continue;
+        }
+        let source = deleted.iter().position(|old| old.old == change.new);
+        match source {
+            Some(index) => {
+                let old = deleted.remove(index);
+                result.push(FileChange {
+                    path: change.path,
+                    old_path: Some(old.path),
+                    kind: ChangeKind::Renamed,
+                    old: old.old,
+                    new: change.new,
+                });
+            }
+            None => result.push(change),
+        }
+    }
+    result.extend(deleted);
+    result.sort_by(|a, b| a.path.cmp(&b.path));
+    result
+}
+
+/// One `<letter>\t<path>` line per change.
+pub(crate) fn render_name_status(changes: &[FileChange]) -> String {
+    changes
+        .iter()
+        .map(|change| format!("{}\t{}\n", change.kind.letter(), change.display_path()))
+        .collect()
+}
+
+/// A one-line summary such as `3 files changed: 2 added, 1 modified`.
+pub(crate) fn render_stat(changes: &[FileChange]) -> String {
+    let count = |kind: ChangeKind| changes.iter().filter(|change| change.kind == kind).count();
+    let parts: Vec<String> = [
+        (ChangeKind::Added, "added"),
+        (ChangeKind::Modified, "modified"),
+        (ChangeKind::Deleted, "deleted"),
+        (ChangeKind::Renamed, "renamed"),
+    ]
+    .into_iter()
+    .filter_map(|(kind, label)| match count(kind) {
+        0 => None,
+        n => Some(format!("{} {}", n, label)),
+    })
+    .collect();
+    let files = if changes.len() == 1 { "file" } else { "files" };
+    if parts.is_empty() {
+        format!("{} {} changed", changes.len(), files)
+    } else {
+        format!("{} {} changed: {}", changes.len(), files, parts.join(", "))
+    }
+}
+
+/// `+X bytes / -Y bytes`: the size of every file version `changes` brings
+/// in, and of every one it replaces or deletes. A file moved without
+/// changes counts for neither.
+pub(crate) fn render_byte_stat(objects_path: &Path, changes: &[FileChange]) -> Result<String> {
+    let size = |file: &Option<FileEntry>| -> Result<u64> {
+        match file {
+            Some(file) if !file.is_empty_dir() => Ok(read_object_raw(objects_path, &file.hash)?.len() as u64),
+            _ => Ok(0),
+        }
+    };
+    let (mut added, mut removed) = (0, 0);
+    for change in changes {
+        if change.old.as_ref().map(|file| &file.hash) == change.new.as_ref().map(|file| &file.hash) {
+            continue;
+        }
+        added += size(&change.new)?;
+        removed += size(&change.old)?;
+    }
+    Ok(format!("+{} bytes / -{} bytes", added, removed))
+}
+
+/// Renders a unified diff for each change, reading blobs from the store.
+/// With `word_diff`, changed lines are shown as [`word_diff_run`] does.
+pub(crate) fn render_patch(objects_path: &Path, changes: &[FileChange], word_diff: bool) -> Result<String> {
+    let mut out = String::new();
+    for change in changes {
+        let is_dir = |file: &Option<FileEntry>| file.as_ref().is_some_and(FileEntry::is_empty_dir);
+        if is_dir(&change.old) || is_dir(&change.new) {
+            out.push_str(&format!("diff --gini a/{0} b/{0}\n", change.display_path()));
+            continue;
+        }
+        let old = match &change.old {
+            Some(file) => read_object_raw(objects_path, &file.hash)?,
+            None => Vec::new(),
+        };
+        let new = match &change.new {
+            Some(file) => read_object_raw(objects_path, &file.hash)?,
+            None => Vec::new(),
+        };
+        out.push_str(&file_patch(change, &old, &new, word_diff, "gini"));
+    }
+    Ok(out)
+}
+
+/// Renders the changes as a patch `git apply` and `patch -p1` accept, with
+/// `diff --git` headers. Git can't hold empty directories, so they are
+/// skipped, and binary files are left out and returned instead.
+pub(crate) fn render_git_patch(objects_path: &Path, changes: &[FileChange]) -> Result<(String, Vec<String>)> {
+    let mut out = String::new();
+    let mut binary = Vec::new();
+    for change in changes {
+        let is_dir = |file: &Option<FileEntry>| file.as_ref().is_some_and(FileEntry::is_empty_dir);
+        if is_dir(&change.old) || is_dir(&change.new) {
+            continue;
+        }
+        let read = |file: &Option<FileEntry>| match file {
+            Some(file) => read_object_raw(objects_path, &file.hash),
+            None => Ok(Vec::new()),
+        };
+        let (old, new) = (read(&change.old)?, read(&change.new)?);
+        if as_text(&old).is_none() || as_text(&new).is_none() {
+            binary.push(change.path.clone());
+            continue;
+        }
+        out.push_str(&file_patch(change, &old, &new, false, "git"));
+    }
+    Ok((out, binary))
+}
+
+/// One file's patch, headed `diff --<tool>`.
+pub(crate) fn file_patch(change: &FileChange, old: &[u8], new: &[u8], word_diff: bool, tool: &str) -> String {
+    let path = &change.path;
+    let old_path = change.old_path.as_ref().unwrap_or(path);
+    let old_label = match change.kind {
+        ChangeKind::Added => "/dev/null".to_string(),
+        _ => format!("a/{}", old_path),
+    };
+    let new_label = match change.kind {
+        ChangeKind::Deleted => "/dev/null".to_string(),
+        _ => format!("b/{}", path),
+    };
+
+    let mut out = format!("diff --{} a/{} b/{}\n", tool, old_path, path);
+    if change.kind == ChangeKind::Renamed {
+        out.push_str(&format!("rename from {}\nrename to {}\n", old_path, path));
+    }
+    match (&change.old, &change.new) {
+        (None, Some(file)) => out.push_str(&format!("new file mode {:o}\n", file.mode)),
+        (Some(file), None) => out.push_str(&format!("deleted file mode {:o}\n", file.mode)),
+        _ => {}
+    }
+    if let (Some(old_file), Some(new_file)) = (&change.old, &change.new) {
+        if old_file.mode != new_file.mode {
+            out.push_str(&format!(
+                "old mode {:o}\nnew mode {:o}\n",
+                old_file.mode, new_file.mode
+            ));
+        }
+        if old == new {
+            return out;
+        }
+    }
+    match (as_text(old), as_text(new)) {
+        (Some(old), Some(new)) => {
+            out.push_str(&format!("--- {}\n+++ {}\n", old_label, new_label));
+            out.push_str(&unified_diff(old, new, word_diff));
+        }
+        _ => {
+            // Say what each side is, e.g. `PNG image, 1204 -> 1350 bytes`.
+            let describe = |content: &[u8]| {
+                describe_binary(content).unwrap_or_else(|| format!("text, {} bytes", content.len()))
+            };
+            let mut sides = Vec::new();
+            if change.kind != ChangeKind::Added {
+                sides.push(describe(old));
+            }
+            if change.kind != ChangeKind::Deleted {
+                sides.push(describe(new));
+            }
+            out.push_str(&format!(
+                "Binary files {} and {} differ ({})\n",
+                old_label,
+                new_label,
+                sides.join(" -> ")
+            ));
+        }
+    }
+    out
+}
+
+/// The lines added going from `old` to `new` that end in whitespace or are
+/// left over from a conflict, for `diff --check`. A CR before the newline
+/// counts as part of the line ending.
+pub(crate) fn check_added_lines(path: &str, old: &str, new: &str) -> Vec<CheckProblem> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let mut problems = Vec::new();
+    let mut line = 0;
+    for edit in diff_lines(&old_lines, &new_lines) {
+        let text = match edit {
+            Edit::Delete(_) => continue,
+            Edit::Keep(_) => {
+                line += 1;
+                continue;
+            }
+            Edit::Insert(text) => text,
+        };
+        line += 1;
+        let problem = if is_conflict_marker(text) {
+            "leftover conflict marker"
+        } else if text.ends_with([' ', '\t']) {
+            "trailing whitespace"
+        } else {
+            continue;
+        };
+        problems.push(CheckProblem { path: path.to_string(), line, problem, text: text.to_string() });
+    }
+    problems
+}
+
+/// Whether a line opens, splits, or closes a conflict as `merge` writes
+/// them: seven `<`, `|`, `=` or `>`, then the end of the line or (for all
+/// but `=`) a space and a label.
+fn is_conflict_marker(line: &str) -> bool {
+    ['<', '|', '=', '>'].into_iter().any(|c| {
+        let Some(rest) = line.strip_prefix(&c.to_string().repeat(7)) else {
+            return false;
+        };
+        rest.is_empty() || (c != '=' && rest.starts_with(' '))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Edit<'a> {
+    Keep(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Computes an edit script turning the lines `old` into `new`. Word diffs
+/// use it on words instead.
+pub(crate) fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut edits: Vec<Edit> = old[..prefix].iter().map(|l| Edit::Keep(l)).collect();
+    let (n, m) = (old_mid.len(), new_mid.len());
+    if n.saturating_mul(m) > MAX_DIFF_CELLS {
+        edits.extend(old_mid.iter().map(|l| Edit::Delete(l)));
+        edits.extend(new_mid.iter().map(|l| Edit::Insert(l)));
+    } else {
+        // lcs[i][j] = length of the LCS of old_mid[i..] and new_mid[j..]
+        let width = m + 1;
+        let mut lcs = vec![0u32; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * width + j] = if old_mid[i] == new_mid[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if old_mid[i] == new_mid[j] {
+                edits.push(Edit::Keep(old_mid[i]));
+                i += 1;
+                j += 1;
+            } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+                edits.push(Edit::Delete(old_mid[i]));
+                i += 1;
+            } else {
+                edits.push(Edit::Insert(new_mid[j]));
+                j += 1;
+            }
+        }
+        edits.extend(old_mid[i..].iter().map(|l| Edit::Delete(l)));
+        edits.extend(new_mid[j..].iter().map(|l| Edit::Insert(l)));
+    }
+    edits.extend(old[old.len() - suffix..].iter().map(|l| Edit::Keep(l)));
+    edits
+}
+
+/// Formats the hunks of a unified diff between two texts (without the
+/// `---`/`+++` header). With `word_diff`, context lines lose their leading
+/// space and each run of changed lines is rendered by [`word_diff_run`].
+pub(crate) fn unified_diff(old: &str, new: &str, word_diff: bool) -> String {
+    // Lines keep their terminators (except for word diffs, which are only
+    // read), so a patch records a missing final newline and CRLF endings.
+    let (old_lines, new_lines): (Vec<&str>, Vec<&str>) = if word_diff {
+        (old.lines().collect(), new.lines().collect())
+    } else {
+        (old.split_inclusive('\n').collect(), new.split_inclusive('\n').collect())
+    };
+    let edits = diff_lines(&old_lines, &new_lines);
+    let positions = edit_positions(&edits);
+    hunk_spans(&edits)
+        .into_iter()
+        .map(|span| render_hunk(&edits, &positions, span, word_diff))
+        .collect()
+}
+
+/// The hunks of the line diff from `old` to `new`, each rendered as
+/// [`unified_diff`] would, for `add -p` to offer one at a time.
+pub(crate) fn diff_hunks(old: &str, new: &str) -> Vec<String> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff_lines(&old_lines, &new_lines);
+    let positions = edit_positions(&edits);
+    hunk_spans(&edits)
+        .into_iter()
+        .map(|span| render_hunk(&edits, &positions, span, false))
+        .collect()
+}
+
+/// `old` with the hunks of its diff to `new` that `selected` picks (in the
+/// order [`diff_hunks`] lists them) applied, and the others left out.
+pub(crate) fn apply_hunks(old: &str, new: &str, selected: &[bool]) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff_lines(&old_lines, &new_lines);
+    let mut spans = hunk_spans(&edits).into_iter().zip(selected.iter().copied()).peekable();
+    let mut out = String::new();
+    for (i, edit) in edits.iter().enumerate() {
+        while spans.next_if(|((_, end), _)| *end <= i).is_some() {}
+        let take = spans.peek().is_some_and(|((start, _), take)| *start <= i && *take);
+        match edit {
+            Edit::Keep(line) => out.push_str(line),
+            Edit::Delete(line) if !take => out.push_str(line),
+            Edit::Insert(line) if take => out.push_str(line),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Line numbers (0-based) in old and new before each edit, and after the
+/// last.
+fn edit_positions(edits: &[Edit]) -> Vec<(usize, usize)> {
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for edit in edits {
+        positions.push((old_pos, new_pos));
+        match edit {
+            Edit::Keep(_) => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Edit::Delete(_) => old_pos += 1,
+            Edit::Insert(_) => new_pos += 1,
+        }
+    }
+    positions.push((old_pos, new_pos));
+    positions
+}
+
+/// The hunks of an edit script as `[start, end)` ranges of it: each run of
+/// changes with [`DIFF_CONTEXT`] unchanged lines on either side, where
+/// changes closer than twice that share a hunk.
+fn hunk_spans(edits: &[Edit]) -> Vec<(usize, usize)> {
+    let is_change = |i: usize| !matches!(edits[i], Edit::Keep(_));
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while let Some(first) = (i..edits.len()).find(|&k| is_change(k)) {
+        let start = first.saturating_sub(DIFF_CONTEXT).max(i);
+        let mut end = first + 1;
+        while let Some(next) = (end..edits.len()).find(|&k| is_change(k)) {
+            if next - end > 2 * DIFF_CONTEXT {
+                break;
+            }
+            end = next + 1;
+        }
+        let hunk_end = (end + DIFF_CONTEXT).min(edits.len());
+        spans.push((start, hunk_end));
+        i = hunk_end;
+    }
+    spans
+}
+
+/// One hunk, from its `@@` header to its last line.
+fn render_hunk(edits: &[Edit], positions: &[(usize, usize)], (start, hunk_end): (usize, usize), word_diff: bool) -> String {
+    let is_change = |i: usize| !matches!(edits[i], Edit::Keep(_));
+    let (old_start, new_start) = positions[start];
+    let (old_stop, new_stop) = positions[hunk_end];
+    let (old_count, new_count) = (old_stop - old_start, new_stop - new_start);
+    let mut out = format!(
+        "@@ -{} +{} @@\n",
+        hunk_range(old_start, old_count),
+        hunk_range(new_start, new_count)
+    );
+    let mut k = start;
+    while k < hunk_end {
+        match edits[k] {
+            Edit::Keep(line) if word_diff => out.push_str(&format!("{}\n", line)),
+            _ if word_diff => {
+                let run_end = (k..hunk_end).find(|&e| !is_change(e)).unwrap_or(hunk_end);
+                out.push_str(&word_diff_run(&edits[k..run_end]));
+                k = run_end;
+                continue;
+            }
+            Edit::Keep(line) => push_patch_line(&mut out, ' ', line),
+            Edit::Delete(line) => push_patch_line(&mut out, '-', line),
+            Edit::Insert(line) => push_patch_line(&mut out, '+', line),
+        }
+        k += 1;
+    }
+    out
+}
+
+/// Appends one line of a hunk, noting when it has no newline as `patch`
+/// and `git apply` expect.
+fn push_patch_line(out: &mut String, prefix: char, line: &str) {
+    out.push(prefix);
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+pub(crate) fn hunk_range(start: usize, count: usize) -> String {
+    // Empty ranges point at the line before the change, as in GNU diff.
+    let first = if count == 0 { start } else { start + 1 };
+    if count == 1 {
+        first.to_string()
+    } else {
+        format!("{},{}", first, count)
+    }
+}
+
+/// Splits text into words, each paired with the whitespace before it, and
+/// returns the whitespace after the last word separately.
+fn split_words(text: &str) -> (Vec<(&str, &str)>, &str) {
+    let mut words = Vec::new();
+    let mut rest = text;
+    loop {
+        let word_start = rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len());
+        if word_start == rest.len() {
+            return (words, rest);
+        }
+        let (space, from_word) = rest.split_at(word_start);
+        let word_end = from_word.find(char::is_whitespace).unwrap_or(from_word.len());
+        let (word, after) = from_word.split_at(word_end);
+        words.push((space, word));
+        rest = after;
+    }
+}
+
+/// Renders a run of deleted and inserted lines word by word, marking
+/// removed words `[-like this-]` and added ones `{+like this+}`. Only the
+/// words are compared, so a reflowed paragraph shows no markup where the
+/// wording is the same; unchanged words keep the new text's spacing.
+pub(crate) fn word_diff_run(edits: &[Edit]) -> String {
+    let mut old_text = String::new();
+    let mut new_text = String::new();
+    for edit in edits {
+        match edit {
+            Edit::Delete(line) => old_text.push_str(&format!("{}\n", line)),
+            Edit::Insert(line) => new_text.push_str(&format!("{}\n", line)),
+            Edit::Keep(_) => {}
+        }
+    }
+    let (old_words, old_trailing) = split_words(&old_text);
+    let (new_words, new_trailing) = split_words(&new_text);
+    let old_list: Vec<&str> = old_words.iter().map(|(_, word)| *word).collect();
+    let new_list: Vec<&str> = new_words.iter().map(|(_, word)| *word).collect();
+
+    // (kind, whitespace before, word) in output order.
+    let mut pieces = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for edit in diff_lines(&old_list, &new_list) {
+        match edit {
+            Edit::Keep(_) => {
+                pieces.push((' ', new_words[j].0, new_words[j].1));
+                i += 1;
+                j += 1;
+            }
+            Edit::Delete(_) => {
+                pieces.push(('-', old_words[i].0, old_words[i].1));
+                i += 1;
+            }
+            Edit::Insert(_) => {
+                pieces.push(('+', new_words[j].0, new_words[j].1));
+                j += 1;
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let mut k = 0;
+    while k < pieces.len() {
+        let kind = pieces[k].0;
+        let end = (k..pieces.len()).find(|&e| pieces[e].0 != kind).unwrap_or(pieces.len());
+        let mut group = pieces[k].2.to_string();
+        for &(_, space, word) in &pieces[k + 1..end] {
+            group.push_str(space);
+            group.push_str(word);
+        }
+        // A replacement reads better as `[-old-]{+new+}`, without a gap.
+        let replaces = kind == '+' && k > 0 && pieces[k - 1].0 == '-';
+        let space = pieces[k].1;
+        if !replaces || space.contains('\n') {
+            out.push_str(space);
+        }
+        match kind {
+            '-' => out.push_str(&format!("[-{}-]", group)),
+            '+' => out.push_str(&format!("{{+{}+}}", group)),
+            _ => out.push_str(&group),
+        }
+        k = end;
+    }
+    out.push_str(if new_text.is_empty() { old_trailing } else { new_trailing });
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,24 @@
+//! Line-ending conversion for `core.autocrlf`: text files are stored with
+//! LF endings and written out with the platform's own. Content that looks
+//! binary (a NUL byte, or not UTF-8) is never converted.
+
+use crate::content::as_text;
+
+/// `content` with its CRLF line endings turned into LF, or `None` if it is
+/// binary or has none to convert.
+pub(crate) fn to_lf(content: &[u8]) -> Option<Vec<u8>> {
+    let text = as_text(content)?;
+    text.contains("\r\n").then(|| text.replace("\r\n", "\n").into_bytes())
+}
+
+/// `content` as it should be written to the working tree: on Windows, text
+/// gets CRLF line endings; everywhere else it is returned as stored.
+pub(crate) fn to_native(content: Vec<u8>) -> Vec<u8> {
+    if !cfg!(windows) {
+        return content;
+    }
+    match as_text(&content) {
+        Some(text) => text.replace("\r\n", "\n").replace('\n', "\r\n").into_bytes(),
+        None => content,
+    }
+}
@@ -0,0 +1,43 @@
+//! The errors a library caller may want to tell apart.
+//!
+//! Operations still return [`anyhow::Result`]; the failures below are
+//! raised as [`GiniError`] values inside it, so they can be matched with
+//! [`anyhow::Error::downcast_ref`] even after context has been added.
+
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum GiniError {
+    /// No `.gini` was found in the directory or any of its parents.
+    #[error("Not a Gini repository.")]
+    NotARepository,
+    /// `init` found a `.gini` already there.
+    #[error("--- .gini already exists.")]
+    AlreadyInitialized,
+    /// The string is not a (possibly abbreviated) hex hash.
+    #[error("Invalid hash: {0} (need at least 4 hex characters)")]
+    InvalidHash(String),
+    /// No stored object has this hash, or starts with this prefix.
+    #[error("Object not found: {0}")]
+    ObjectNotFound(String),
+    /// The prefix matches more than one object.
+    #[error("Ambiguous hash: {prefix} matches {count} objects")]
+    AmbiguousHash { prefix: String, count: usize },
+    /// Nothing with this name or hash could be found; `reason` says why.
+    #[error("Unknown revision '{rev}': {reason}")]
+    UnknownRevision { rev: String, reason: String },
+    /// An object's content no longer matches its hash.
+    #[error("Object {hash} is corrupt: its content hashes to {actual} (run `gini fsck`)")]
+    CorruptObject { hash: String, actual: String },
+    /// Another gini process holds `.gini/lock`.
+    #[error(
+        "Repository is locked by another gini process (pid {pid}). \
+         If none is running, remove the stale lock with --break-lock."
+    )]
+    Locked { pid: String },
+    /// A ref no longer holds the commit an update was based on: something
+    /// else moved it in between. `expected` and `actual` are `none` for a
+    /// ref that doesn't exist.
+    #[error("{name} changed underneath this update (expected {expected}, found {actual}); try again")]
+    StaleRef { name: String, expected: String, actual: String },
+}
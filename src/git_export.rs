@@ -0,0 +1,158 @@
+//! Writing the history out as a `git fast-import` stream, so a project can
+//! move to git with its checkpoints intact.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+
+use crate::commit::{parse_commit_details, parse_commit_tree, CommitDetails};
+use crate::objects::{read_object, read_object_raw};
+use crate::tree::{flatten_tree, FileEntry, MODE_EXECUTABLE, MODE_SYMLINK};
+
+/// Streams every checkpoint reachable from `branches` into `out`, parents
+/// before children, then points each branch and tag (both given as `(name,
+/// commit)`) at its checkpoint. Each checkpoint lists the files it changed
+/// against its first parent. Empty directories are left out, since git
+/// can't hold them. Returns how many checkpoints were written.
+pub(crate) fn write_fast_import(
+    out: &mut dyn Write,
+    objects_path: &Path,
+    branches: &[(String, String)],
+    tags: &[(String, String)],
+) -> Result<usize> {
+    // The ref every commit is written to; the real refs are set at the end.
+    let import_ref = match branches.first() {
+        Some((name, _)) => format!("refs/heads/{}", name),
+        None => return Ok(0),
+    };
+    let mut marks: HashMap<String, usize> = HashMap::new();
+    let mut next_mark = 1;
+    let mut files_of: HashMap<String, BTreeMap<String, FileEntry>> = HashMap::new();
+
+    for commit in parents_first(objects_path, branches.iter().map(|(_, tip)| tip.clone()))? {
+        let content = read_object(objects_path, &commit)?;
+        let details = parse_commit_details(&content)?;
+        let mut files = flatten_tree(objects_path, &parse_commit_tree(&content)?)?;
+        files.retain(|_, file| !file.is_empty_dir());
+        let empty = BTreeMap::new();
+        let parent_files = match details.parent() {
+            Some(parent) => files_of.get(parent).unwrap_or(&empty),
+            None => &empty,
+        };
+
+        let mut modified = Vec::new();
+        for (path, file) in &files {
+            if parent_files.get(path) == Some(file) {
+                continue;
+            }
+            let mark = match marks.get(&file.hash) {
+                Some(&mark) => mark,
+                None => {
+                    let data = read_object_raw(objects_path, &file.hash)?;
+                    write!(out, "blob\nmark :{}\ndata {}\n", next_mark, data.len())?;
+                    out.write_all(&data)?;
+                    out.write_all(b"\n")?;
+                    marks.insert(file.hash.clone(), next_mark);
+                    next_mark += 1;
+                    next_mark - 1
+                }
+            };
+            modified.push((path, file.mode, mark));
+        }
+
+        if details.parents.is_empty() {
+            writeln!(out, "reset {}\n", import_ref)?;
+        }
+        let signature = signature(&details);
+        write!(out, "commit {}\nmark :{}\n", import_ref, next_mark)?;
+        write!(out, "author {}\ncommitter {}\n", signature, signature)?;
+        writeln!(out, "data {}\n{}", details.message.len(), details.message)?;
+        for (i, parent) in details.parents.iter().enumerate() {
+            writeln!(out, "{} :{}", if i == 0 { "from" } else { "merge" }, marks[parent])?;
+        }
+        for path in parent_files.keys().filter(|path| !files.contains_key(*path)) {
+            writeln!(out, "D {}", quote_path(path))?;
+        }
+        for (path, mode, mark) in modified {
+            writeln!(out, "M {:o} :{} {}", git_mode(mode), mark, quote_path(path))?;
+        }
+        out.write_all(b"\n")?;
+        marks.insert(commit.clone(), next_mark);
+        next_mark += 1;
+        files_of.insert(commit, files);
+    }
+
+    for (name, tip) in branches {
+        writeln!(out, "reset refs/heads/{}\nfrom :{}\n", name, marks[tip])?;
+    }
+    for (name, commit) in tags {
+        if let Some(mark) = marks.get(commit) {
+            writeln!(out, "reset refs/tags/{}\nfrom :{}\n", name, mark)?;
+        }
+    }
+    Ok(files_of.len())
+}
+
+/// Every commit reachable from `tips`, each once, with every commit after
+/// all of its parents.
+fn parents_first(objects_path: &Path, tips: impl Iterator<Item = String>) -> Result<Vec<String>> {
+    let mut order = Vec::new();
+    let mut done = std::collections::HashSet::new();
+    for tip in tips {
+        // A commit is pushed once to queue its parents and again, beneath
+        // them, to be emitted after they are.
+        let mut pending = vec![(tip, false)];
+        while let Some((hash, parents_done)) = pending.pop() {
+            if done.contains(&hash) {
+                continue;
+            }
+            if parents_done {
+                done.insert(hash.clone());
+                order.push(hash);
+                continue;
+            }
+            let parents = parse_commit_details(&read_object(objects_path, &hash)?)?.parents;
+            pending.push((hash, true));
+            pending.extend(parents.into_iter().rev().map(|parent| (parent, false)));
+        }
+    }
+    Ok(order)
+}
+
+/// `Name <email> <timestamp> <offset>` as git expects it. An author
+/// recorded without an email gets an empty one.
+fn signature(details: &CommitDetails) -> String {
+    let identity = format!("{} <{}>", details.name, details.email);
+    let offset = details.offset.unwrap_or(0);
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.unsigned_abs() / 60;
+    format!("{} {} {}{:02}{:02}", identity, details.timestamp, sign, minutes / 60, minutes % 60)
+}
+
+/// The file modes git accepts; anything else is stored as a plain file.
+fn git_mode(mode: u32) -> u32 {
+    match mode {
+        MODE_EXECUTABLE | MODE_SYMLINK => mode,
+        _ => 0o100644,
+    }
+}
+
+/// A path as fast-import reads it: as is, unless it starts with a quote or
+/// holds a newline, which call for C-style quoting.
+fn quote_path(path: &str) -> String {
+    if !path.starts_with('"') && !path.contains('\n') {
+        return path.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in path.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
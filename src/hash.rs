@@ -0,0 +1,76 @@
+//! Object hashing. Repositories use SHA-1 unless `core.hashAlgo = sha256`
+//! was set when they were created; the choice is fixed from then on.
+
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+/// The hash function a repository names its objects with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum HashAlgo {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgo {
+    /// Reads a `core.hashAlgo` value.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "sha1" => Some(HashAlgo::Sha1),
+            "sha256" => Some(HashAlgo::Sha256),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            HashAlgo::Sha1 => "sha1",
+            HashAlgo::Sha256 => "sha256",
+        }
+    }
+
+    /// How many hex characters a full hash has.
+    pub(crate) fn hex_len(self) -> usize {
+        match self {
+            HashAlgo::Sha1 => 40,
+            HashAlgo::Sha256 => 64,
+        }
+    }
+}
+
+/// An in-progress hash, for content that arrives in pieces.
+pub(crate) enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub(crate) fn new(algo: HashAlgo) -> Self {
+        match algo {
+            HashAlgo::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgo::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// The hash as lowercase hex.
+    pub(crate) fn finish(self) -> String {
+        match self {
+            Hasher::Sha1(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha256(hasher) => hex::encode(hasher.finalize()),
+        }
+    }
+}
+
+/// Hashes `content` in one go, as lowercase hex.
+pub(crate) fn hash_with(algo: HashAlgo, content: &[u8]) -> String {
+    let mut hasher = Hasher::new(algo);
+    hasher.update(content);
+    hasher.finish()
+}
@@ -0,0 +1,55 @@
+//! User scripts in `.gini/hooks` that run around checkpoints and restores.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::util::gini_dir;
+use crate::HOOKS_DIR;
+
+/// Runs before a checkpoint is recorded; a non-zero exit aborts it.
+pub(crate) const PRE_CHECKPOINT_HOOK: &str = "pre-checkpoint";
+/// Runs after a restore has written the checkpoint's files.
+pub(crate) const POST_RESTORE_HOOK: &str = "post-restore";
+
+/// The hook script called `name`, if one is installed and executable.
+fn find_hook(root_path: &Path, name: &str) -> Option<PathBuf> {
+    let path = gini_dir(root_path).join(HOOKS_DIR).join(name);
+    let metadata = fs::metadata(&path).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return None;
+        }
+    }
+    Some(path)
+}
+
+/// Runs the hook `name` from the project root with `commit` in
+/// `GINI_COMMIT` (empty when there is no commit yet). The hook's output
+/// goes to stderr so it never mixes with gini's own. Missing hooks are
+/// skipped; a hook that fails or exits non-zero is an error.
+pub(crate) fn run_hook(root_path: &Path, name: &str, commit: Option<&str>) -> Result<()> {
+    let Some(path) = find_hook(root_path, name) else {
+        return Ok(());
+    };
+    let status = Command::new(&path)
+        .current_dir(root_path)
+        .env("GINI_COMMIT", commit.unwrap_or(""))
+        .env("GINI_HOOK", name)
+        .stdout(std::io::stderr())
+        .status()
+        .with_context(|| format!("Failed to run the {} hook", name))?;
+    if !status.success() {
+        match status.code() {
+            Some(code) => bail!("The {} hook exited with status {}", name, code),
+            None => bail!("The {} hook was killed by a signal", name),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,209 @@
+//! Gitignore-style exclusion rules for the working tree walk.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::{GITIGNORE_FILE_NAME, IGNORE_FILE_NAME};
+
+/// A single pattern from `.giniignore` or a `.gitignore`.
+#[derive(Debug)]
+pub(crate) struct IgnorePattern {
+    pub(crate) glob: String,
+    pub(crate) negated: bool,
+    pub(crate) dir_only: bool,
+    /// Patterns containing a `/` match against the path relative to `base`;
+    /// the rest match the file name at any depth below it.
+    pub(crate) anchored: bool,
+    /// The root-relative directory, with a trailing `/`, whose ignore file
+    /// the pattern came from; empty for the project root.
+    pub(crate) base: String,
+}
+
+/// Exclusion rules applied while walking the working tree: `.gini` itself,
+/// then gitignore-style patterns from `core.exclude`, from every
+/// `.gitignore` in the tree when `core.useGitignore` is on, and from
+/// `.giniignore`, with later ones winning.
+#[derive(Debug)]
+pub(crate) struct IgnoreRules {
+    pub(crate) root: PathBuf,
+    pub(crate) patterns: Vec<IgnorePattern>,
+}
+
+impl IgnoreRules {
+    pub(crate) fn load(root_path: &Path) -> Result<Self> {
+        let ignore_path = root_path.join(IGNORE_FILE_NAME);
+        let content = if ignore_path.is_file() {
+            fs::read_to_string(&ignore_path)
+                .with_context(|| format!("Failed to read {}", IGNORE_FILE_NAME))?
+        } else {
+            String::new()
+        };
+        let mut rules = Self::parse(root_path, &content);
+        let config = Config::load(root_path)?;
+        let excludes = parse_patterns(&config.excludes().join("\n"), "");
+        let mut git_patterns = excludes.len();
+        rules.patterns.splice(0..0, excludes);
+        if config.use_gitignore(root_path)? {
+            rules.load_gitignores(root_path, "", &mut git_patterns)?;
+        }
+        Ok(rules)
+    }
+
+    /// Adds the patterns of `dir/.gitignore` and of every `.gitignore` in
+    /// the subdirectories that aren't ignored. They go before the
+    /// `.giniignore` patterns, which therefore win, and deeper files come
+    /// after shallower ones, as in git. `git_patterns` is where the next
+    /// ones go: after the `core.exclude` patterns and those loaded so far.
+    fn load_gitignores(&mut self, dir: &Path, base: &str, git_patterns: &mut usize) -> Result<()> {
+        let gitignore_path = dir.join(GITIGNORE_FILE_NAME);
+        if gitignore_path.is_file() {
+            let content = fs::read_to_string(&gitignore_path)
+                .with_context(|| format!("Failed to read {}", gitignore_path.display()))?;
+            let patterns = parse_patterns(&content, base);
+            let count = patterns.len();
+            self.patterns.splice(*git_patterns..*git_patterns, patterns);
+            *git_patterns += count;
+        }
+
+        let mut subdirs = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                subdirs.push(entry.path());
+            }
+        }
+        subdirs.sort();
+        for path in subdirs {
+            if self.is_ignored(&path, true) {
+                continue;
+            }
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let base = format!("{}{}/", base, name);
+            self.load_gitignores(&path, &base, git_patterns)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn parse(root_path: &Path, content: &str) -> Self {
+        IgnoreRules {
+            root: root_path.to_path_buf(),
+            patterns: parse_patterns(content, ""),
+        }
+    }
+
+
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let file_name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => return false,
+        };
+        if file_name == ".gini" {
+            return true;
+        }
+
+        let relative = path
+            .strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        // Later patterns override earlier ones, as in gitignore.
+        let mut ignored = false;
+        for pattern in &self.patterns {
+            if pattern.dir_only && !is_dir {
+                continue;
+            }
+            let Some(below_base) = relative.strip_prefix(pattern.base.as_str()) else {
+                continue;
+            };
+            let subject = if pattern.anchored { below_base } else { file_name };
+            if glob_match(pattern.glob.as_bytes(), subject.as_bytes()) {
+                ignored = !pattern.negated;
+            }
+        }
+        ignored
+    }
+
+    /// Like [`IgnoreRules::is_ignored`] for a root-relative path that was not
+    /// found by walking the tree, so its parent directories are checked too.
+    pub(crate) fn is_ignored_path(&self, relative: &str, is_dir: bool) -> bool {
+        let mut path = self.root.clone();
+        let parts: Vec<&str> = relative.split('/').collect();
+        for (i, part) in parts.iter().enumerate() {
+            path.push(part);
+            let last = i == parts.len() - 1;
+            if self.is_ignored(&path, !last || is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Parses the lines of an ignore file found in the directory `base`.
+fn parse_patterns(content: &str, base: &str) -> Vec<IgnorePattern> {
+    let mut patterns = Vec::new();
+    for line in content.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let glob = line.trim_start_matches('/');
+        if glob.is_empty() {
+            continue;
+        }
+        patterns.push(IgnorePattern {
+            glob: glob.to_string(),
+            negated,
+            dir_only,
+            anchored,
+            base: base.to_string(),
+        });
+    }
+    patterns
+}
+
+/// Matches `text` against a glob where `*` and `?` stop at `/` and `**`
+/// spans any number of directories.
+pub(crate) fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            if let Some(after_slash) = rest.strip_prefix(b"/") {
+                if glob_match(after_slash, text) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(b'*') => {
+            for i in 0..=text.len() {
+                if glob_match(&pattern[1..], &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => {
+            !text.is_empty() && text[0] != b'/' && glob_match(&pattern[1..], &text[1..])
+        }
+        Some(&c) => !text.is_empty() && text[0] == c && glob_match(&pattern[1..], &text[1..]),
+    }
+}
@@ -0,0 +1,83 @@
+//! The staging index, `.gini/index`.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::commit::parse_commit_tree;
+use crate::objects::{is_valid_hash, read_object};
+use crate::refs::get_head_commit;
+use crate::tree::{flatten_tree, FileEntry, MODE_FILE};
+use crate::util::{gini_dir, write_file_atomic};
+use crate::INDEX_FILE_NAME;
+
+/// Returns the files recorded in HEAD's tree, or nothing before the first
+/// checkpoint.
+pub(crate) fn head_files(root_path: &Path) -> Result<BTreeMap<String, FileEntry>> {
+    let objects_path = gini_dir(root_path).join("objects");
+    match get_head_commit(root_path)? {
+        Some(head) => {
+            let tree_hash = parse_commit_tree(&read_object(&objects_path, &head)?)?;
+            flatten_tree(&objects_path, &tree_hash)
+        }
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Reads `.gini/index` (`<mode> <hash> <path>` per line), if staging is in
+/// use. Lines from older versions have no mode and are read as regular files.
+pub(crate) fn load_index(root_path: &Path) -> Result<Option<BTreeMap<String, FileEntry>>> {
+    let index_path = gini_dir(root_path).join(INDEX_FILE_NAME);
+    if !index_path.is_file() {
+        return Ok(None);
+    }
+    let mut index = BTreeMap::new();
+    for line in fs::read_to_string(&index_path)?.lines() {
+        let invalid = || anyhow::anyhow!("Invalid index entry: {}", line);
+        let (first, rest) = line.split_once(' ').ok_or_else(invalid)?;
+        let (mode, hash, path) = if is_valid_hash(first) {
+            (MODE_FILE, first, rest)
+        } else {
+            let (hash, path) = rest.split_once(' ').ok_or_else(invalid)?;
+            let mode = u32::from_str_radix(first, 8).map_err(|_| invalid())?;
+            (mode, hash, path)
+        };
+        if !is_valid_hash(hash) || path.is_empty() {
+            bail!("Invalid index entry: {}", line);
+        }
+        index.insert(
+            path.to_string(),
+            FileEntry {
+                hash: hash.to_string(),
+                mode,
+            },
+        );
+    }
+    Ok(Some(index))
+}
+
+pub(crate) fn save_index(root_path: &Path, index: &BTreeMap<String, FileEntry>) -> Result<()> {
+    let content: String = index
+        .iter()
+        .map(|(path, file)| format!("{:o} {} {}\n", file.mode, file.hash, path))
+        .collect();
+    let index_path = gini_dir(root_path).join(INDEX_FILE_NAME);
+    write_file_atomic(&index_path, content.as_bytes()).context("Failed to write .gini/index")
+}
+
+pub(crate) fn clear_index(root_path: &Path) -> Result<()> {
+    let index_path = gini_dir(root_path).join(INDEX_FILE_NAME);
+    if index_path.exists() {
+        fs::remove_file(index_path)?;
+    }
+    Ok(())
+}
+
+/// True if `path` is `prefix` itself or lies beneath it. An empty prefix
+/// matches everything.
+pub(crate) fn path_matches(path: &str, prefix: &str) -> bool {
+    prefix.is_empty()
+        || path == prefix
+        || (path.starts_with(prefix) && path.as_bytes().get(prefix.len()) == Some(&b'/'))
+}
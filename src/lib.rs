@@ -0,0 +1,4692 @@
+//! The gini checkpoint engine.
+//!
+//! A [`Repository`] wraps a project root containing a `.gini` directory and
+//! exposes the operations behind the `gini` command line: creating and
+//! restoring checkpoints, inspecting history, staging, and maintenance of the
+//! content-addressed object store.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+mod archive;
+mod backup;
+mod blame;
+mod commit;
+mod config;
+mod content;
+mod diff;
+mod eol;
+mod error;
+mod git_export;
+mod hash;
+mod hooks;
+mod ignore;
+mod index;
+mod lock;
+mod merge;
+mod objects;
+mod pack;
+mod pattern;
+mod reflog;
+mod refs;
+mod remote;
+mod stash;
+mod tree;
+mod util;
+mod zip;
+
+use archive::*;
+use backup::*;
+use blame::*;
+use commit::*;
+use config::*;
+use content::*;
+use diff::*;
+use eol::*;
+use git_export::*;
+use hash::*;
+use hooks::*;
+use ignore::*;
+use index::*;
+use lock::*;
+use merge::*;
+use objects::*;
+use pack::*;
+use pattern::*;
+use reflog::*;
+use refs::*;
+use remote::*;
+use stash::*;
+use tree::*;
+use util::*;
+use zip::*;
+
+pub use commit::relative_time;
+pub use config::parse_duration;
+pub use content::describe_binary;
+pub use error::GiniError;
+pub use refs::validate_ref_name;
+pub use util::is_gini_dir;
+
+// --- Constants and Configuration ---
+
+/// The default largest file a checkpoint stores; `core.maxFileSize` overrides it.
+pub const MAX_FILE_SIZE: u64 = 100 * 1024 * 1024; // 100MB limit
+pub const MAX_COMMIT_MESSAGE_LENGTH: usize = 1000;
+/// The length of the longest full hash (SHA-256, in hex).
+pub(crate) const MAX_HASH_LENGTH: usize = 64;
+pub(crate) const IGNORE_FILE_NAME: &str = ".giniignore";
+pub(crate) const GITIGNORE_FILE_NAME: &str = ".gitignore";
+pub(crate) const CONFIG_FILE_NAME: &str = "config";
+pub(crate) const INDEX_FILE_NAME: &str = "index";
+pub(crate) const STAT_CACHE_FILE_NAME: &str = "stat-cache";
+pub(crate) const STASH_FILE_NAME: &str = "stash";
+pub(crate) const MERGE_HEAD_FILE_NAME: &str = "MERGE_HEAD";
+pub(crate) const LOCK_FILE_NAME: &str = "lock";
+pub(crate) const REFLOG_FILE_NAME: &str = "logs/HEAD";
+/// Counts checkpoints since the last automatic gc.
+pub(crate) const AUTO_GC_COUNT_FILE_NAME: &str = "auto-gc-count";
+pub(crate) const HOOKS_DIR: &str = "hooks";
+pub(crate) const PACK_DIR: &str = "pack";
+/// The branch a new repository starts on, unless `init --initial-branch`
+/// names another.
+pub(crate) const DEFAULT_BRANCH: &str = "main";
+pub(crate) const DEFAULT_BACKUP_KEEP: usize = 10;
+pub(crate) const DEFAULT_WRITE_RETRIES: u32 = 3;
+pub(crate) const DEFAULT_AUTO_GC_EVERY: u32 = 50;
+/// 90 days, in seconds.
+pub(crate) const DEFAULT_REFLOG_EXPIRE: u64 = 90 * 24 * 60 * 60;
+/// 2 weeks, in seconds.
+pub(crate) const DEFAULT_PRUNE_EXPIRE: u64 = 14 * 24 * 60 * 60;
+/// How long an automatic gc may spend deleting objects before leaving the
+/// rest for the next checkpoint.
+pub(crate) const AUTO_GC_TIME_LIMIT: Duration = Duration::from_millis(200);
+/// Excluded from checkpoints until `core.exclude` says otherwise. `.gini`
+/// itself is always excluded.
+pub(crate) const DEFAULT_EXCLUDES: [&str; 2] = [".git", "target"];
+
+// --- Public Types ---
+
+/// Options controlling how [`Repository::checkpoint`] builds a commit.
+#[derive(Debug, Default)]
+pub struct CheckpointOptions {
+    /// Record a commit even when the tree is identical to HEAD's.
+    pub allow_empty: bool,
+    /// Re-read every file instead of trusting the stat cache.
+    pub no_cache: bool,
+    /// Leave files over the size limit (`core.maxFileSize`, by default
+    /// [`MAX_FILE_SIZE`]) out of the checkpoint instead of failing.
+    /// `core.excludeLarge` turns this on for every checkpoint.
+    pub skip_large: bool,
+    /// Gitignore-style patterns; when any are given, only matching paths
+    /// (and everything inside matching directories) are recorded, from the
+    /// working tree or from the staged files.
+    pub include: Vec<String>,
+    /// A root-relative file or directory to record on its own: the new
+    /// checkpoint takes everything there from the working tree (or the
+    /// staged files) and everything else from HEAD. Not combined with
+    /// `include`.
+    pub path: Option<String>,
+    /// Called as the working tree walk records each file and finishes each
+    /// directory.
+    pub progress: Option<fn(&Progress)>,
+    /// `Key: value` lines to add at the end of the message.
+    pub trailers: Vec<String>,
+    /// Add a `Signed-off-by:` trailer with the author's name and email.
+    pub signoff: bool,
+    /// Skip the automatic gc `maintenance.autoGc` would otherwise run.
+    pub no_gc: bool,
+    /// Checkpoint even when the working tree is over `core.maxFiles` or
+    /// `core.maxTreeSize`.
+    pub force: bool,
+    /// Told how many files and bytes the working tree walk will record,
+    /// before it starts. Setting it makes the counting pass run even when
+    /// no limit is configured.
+    pub preflight: Option<fn(&TreeTotals)>,
+}
+
+/// What a checkpoint of the working tree is about to record, from the
+/// counting pass that checks `core.maxFiles` and `core.maxTreeSize`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TreeTotals {
+    pub files: usize,
+    /// The files' sizes on disk, added up.
+    pub bytes: u64,
+}
+
+/// A progress report from the working tree walk behind a checkpoint.
+#[derive(Debug)]
+pub struct Progress<'a> {
+    /// The file just recorded, or the directory just finished (`""` for the
+    /// root).
+    pub path: &'a str,
+    pub is_dir: bool,
+    /// Files recorded so far.
+    pub files: usize,
+    /// Bytes read and hashed so far; files the stat cache vouches for are
+    /// not read and do not count.
+    pub bytes_hashed: u64,
+}
+
+/// What [`Repository::checkpoint`] did.
+#[derive(Debug, Default)]
+pub struct CheckpointReport {
+    /// The new commit, or `None` if nothing changed.
+    pub commit: Option<String>,
+    /// Files left out because they were too large (with `skip_large` or
+    /// `core.excludeLarge`), with their sizes in bytes.
+    pub skipped: Vec<(String, u64)>,
+    /// What the automatic gc did, if `maintenance.autoGc` ran one.
+    pub auto_gc: Option<GcReport>,
+}
+
+/// What [`Repository::checkpoint_dry_run`] found a checkpoint would record.
+#[derive(Debug, Default)]
+pub struct CheckpointPreview {
+    /// The root tree the checkpoint would point at. It is not stored.
+    pub tree: String,
+    /// The changes against HEAD, as `(letter, path)` pairs like
+    /// [`Status::staged`].
+    pub changes: Vec<(char, String)>,
+    /// Whether the tree comes from the staged files rather than the
+    /// working tree.
+    pub staged: bool,
+    /// Whether a checkpoint would be made at all; `false` when the tree
+    /// matches HEAD's and nothing calls for an empty one.
+    pub would_commit: bool,
+    /// Files that would be left out for size, with their sizes in bytes.
+    pub skipped: Vec<(String, u64)>,
+}
+
+/// A checkpoint's tree as `snapshot` built it.
+struct Snapshot {
+    tree: String,
+    files: BTreeMap<String, FileEntry>,
+    skipped: Vec<(String, u64)>,
+}
+
+/// A diff written for other tools to apply, from [`Repository::diff_patch`].
+#[derive(Debug)]
+pub struct Patch {
+    pub text: String,
+    /// Paths of changed binary files, which the patch leaves out.
+    pub binary_files: Vec<String>,
+}
+
+/// An added line `gini diff --check` objects to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckProblem {
+    pub path: String,
+    /// The line's number in the new version of the file, from 1.
+    pub line: usize,
+    /// `trailing whitespace` or `leftover conflict marker`.
+    pub problem: &'static str,
+    pub text: String,
+}
+
+/// What [`Repository::squash`] did.
+#[derive(Debug)]
+pub struct SquashReport {
+    /// The checkpoint that replaces the range.
+    pub commit: String,
+    /// How many checkpoints it replaces.
+    pub squashed: usize,
+    /// Later checkpoints made again on top of it, unchanged apart from their
+    /// parent.
+    pub rebuilt: usize,
+    /// Where HEAD is now: `commit`, or the last rebuilt checkpoint.
+    pub head: String,
+}
+
+/// What [`Repository::autosquash`] did.
+#[derive(Debug)]
+pub struct AutosquashReport {
+    /// `fixup!` checkpoints folded into the ones they name.
+    pub folded: usize,
+    /// Checkpoints made again, whether to take a fixup or to sit on a
+    /// rewritten parent.
+    pub rebuilt: usize,
+    /// Where HEAD is now.
+    pub head: String,
+}
+
+/// What [`Repository::restore_merge`] did.
+#[derive(Debug)]
+pub struct RestoreMergeReport {
+    /// Files written or deleted to match the checkpoint, sorted.
+    pub updated: Vec<String>,
+    /// Files with local edits that the checkpoint also changed, sorted. A
+    /// text file holds conflict markers; anything else keeps the local
+    /// version.
+    pub conflicts: Vec<String>,
+    /// The backup taken before anything was written, unless backups were
+    /// turned off.
+    pub backup: Option<PathBuf>,
+}
+
+/// What [`Repository::merge`] did.
+#[derive(Debug)]
+pub enum MergeOutcome {
+    /// The other side was already in HEAD's history; nothing changed.
+    UpToDate,
+    /// HEAD was in the other side's history and simply moved to it.
+    FastForward(String),
+    /// A new merge checkpoint with both sides as parents.
+    Merged(String),
+    /// These files need resolving by hand; no checkpoint was made.
+    Conflicts(Vec<String>),
+}
+
+/// Options controlling which checkpoints [`Repository::log`] shows and how.
+#[derive(Debug, Default)]
+pub struct LogOptions {
+    /// One `<short-hash> <subject>` line per checkpoint.
+    pub oneline: bool,
+    /// Stop after this many checkpoints.
+    pub max_count: Option<usize>,
+    /// Only checkpoints whose author line contains this substring.
+    pub author: Option<String>,
+    /// Only checkpoints made at or after this Unix timestamp.
+    pub since: Option<i64>,
+    /// Only checkpoints made before this Unix timestamp.
+    pub until: Option<i64>,
+    /// Only checkpoints whose message contains this text (or, with `regex`,
+    /// matches this regular expression).
+    pub grep: Option<String>,
+    /// Match `grep` case-insensitively.
+    pub ignore_case: bool,
+    /// Treat `grep` as a regular expression rather than plain text.
+    pub regex: bool,
+    /// Summarize each checkpoint's changed files against its parent.
+    pub stat: bool,
+    /// Total the bytes each checkpoint's changed files added and removed
+    /// against its parent (see [`LogOptions::stat`]).
+    pub stat_bytes: bool,
+    /// Show dates as [`relative_time`] rather than absolute times.
+    pub relative_dates: bool,
+    /// List the oldest checkpoint first. `max_count` still picks the newest.
+    pub reverse: bool,
+    /// Draw the ancestry as a column of `*` and `|` beside the entries.
+    pub graph: bool,
+    /// Print each checkpoint as this template instead, one per line: `%H`
+    /// and `%h` (full and short hash), `%P` and `%p` (parents), `%an`, `%ae`,
+    /// `%ad` and `%at` (author name, email, date and Unix time), `%s`
+    /// (subject), `%n` (newline) and `%%`.
+    pub format: Option<String>,
+    /// Only checkpoints that changed this path (repository-relative),
+    /// following it back across renames.
+    pub follow: Option<String>,
+    /// Only checkpoints that changed something at or under this path
+    /// (repository-relative), compared with their first parent.
+    pub path: Option<String>,
+    /// Walk from every branch, tag and remote branch (plus HEAD and the
+    /// stash) instead of just HEAD, newest checkpoint first.
+    pub all: bool,
+    /// `Some(true)` shows only merges (checkpoints with several parents),
+    /// `Some(false)` only the rest.
+    pub merges: Option<bool>,
+    /// List only the checkpoints after this revision, which must be an
+    /// ancestor of HEAD: the walk stops at the first one it already has.
+    pub since_commit: Option<String>,
+}
+
+impl LogOptions {
+    fn matches(&self, details: &CommitDetails, grep: Option<&TextPattern>) -> bool {
+        self.author
+            .as_deref()
+            .is_none_or(|author| details.author.contains(author))
+            && self.since.is_none_or(|since| details.timestamp >= since)
+            && self.until.is_none_or(|until| details.timestamp < until)
+            && grep.is_none_or(|grep| grep.is_match(&details.message))
+            && self.merges.is_none_or(|merges| details.is_merge() == merges)
+    }
+}
+
+/// One checkpoint in [`Repository::get_commit_history`].
+#[derive(Debug, Clone)]
+pub struct CheckpointSummary {
+    pub hash: String,
+    /// The first line of the message.
+    pub subject: String,
+    /// `Name <email>`.
+    pub author: String,
+    /// Unix timestamp of when the checkpoint was made.
+    pub timestamp: i64,
+}
+
+/// A commit object taken apart, from [`Repository::cat_commit`].
+#[derive(Debug, Clone)]
+pub struct CommitObject {
+    pub hash: String,
+    pub tree: String,
+    /// In order; a merge has more than one.
+    pub parents: Vec<String>,
+    pub author_name: String,
+    /// Empty when the commit records none.
+    pub author_email: String,
+    /// Unix timestamp of when the checkpoint was made.
+    pub timestamp: i64,
+    /// The same moment in ISO 8601, in the author's recorded offset.
+    pub date: String,
+    pub message: String,
+}
+
+impl CommitObject {
+    /// The fields as one JSON object.
+    pub fn to_json(&self) -> String {
+        let parents: Vec<String> = self.parents.iter().map(|parent| json_string(parent)).collect();
+        format!(
+            "{{\"hash\":{},\"tree\":{},\"parents\":[{}],\"author\":{{\"name\":{},\"email\":{}}},\"timestamp\":{},\"date\":{},\"message\":{}}}",
+            json_string(&self.hash),
+            json_string(&self.tree),
+            parents.join(","),
+            json_string(&self.author_name),
+            json_string(&self.author_email),
+            self.timestamp,
+            json_string(&self.date),
+            json_string(&self.message)
+        )
+    }
+}
+
+/// How much storage a path's history takes, from [`Repository::history_size`].
+#[derive(Debug, Clone, Default)]
+pub struct HistorySize {
+    /// Checkpoints that have the path.
+    pub checkpoints: usize,
+    /// Distinct blobs among them; identical versions count once.
+    pub versions: usize,
+    /// The size of those blobs added up.
+    pub bytes: u64,
+}
+
+/// A changed file `gini add -p` can stage, from [`Repository::patch_files`].
+#[derive(Debug)]
+pub struct PatchFile {
+    /// Repository-relative.
+    pub path: String,
+    /// The file's changes since it was staged (or since HEAD), one hunk
+    /// each in unified diff form; `None` when it can only be staged whole:
+    /// a binary file, a link, an empty new file, a deletion, or a file
+    /// whose mode changed.
+    pub hunks: Option<Vec<String>>,
+}
+
+/// Differences between HEAD, the staging index, and the working tree.
+#[derive(Debug, Default)]
+pub struct Status {
+    /// Whether a staging index is in use; without one every change is
+    /// picked up by the next checkpoint.
+    pub has_index: bool,
+    pub staged: Vec<(char, String)>,
+    pub unstaged: Vec<(char, String)>,
+    pub untracked: Vec<String>,
+}
+
+impl Status {
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty() && self.unstaged.is_empty() && self.untracked.is_empty()
+    }
+}
+
+/// What [`Repository::gc`] did.
+#[derive(Debug, Default)]
+pub struct GcReport {
+    /// Objects moved from the legacy flat layout into shard directories.
+    pub migrated: usize,
+    /// Unreachable objects deleted.
+    pub removed: usize,
+    /// Bytes freed by the deletions.
+    pub bytes: u64,
+    /// Objects written into the new pack by `--compress`.
+    pub packed: usize,
+    /// Reflog entries dropped for being older than `gc.reflogExpire`, or,
+    /// with `--aggressive`, for naming a checkpoint that is gone.
+    pub reflog_expired: usize,
+    /// Empty directories (and empty legacy backups) removed by `--aggressive`.
+    pub empty_dirs: usize,
+    /// Backups deleted up front by [`GcOptions::drop_backups`].
+    pub backups_dropped: usize,
+    /// Unreachable objects left alone for being written too recently (see
+    /// [`GcOptions::keep_unreachable`]).
+    pub spared: usize,
+    /// With [`GcOptions::verify`], what the check afterwards found wrong
+    /// with the objects that are left; empty when all is well.
+    pub verify_problems: Option<Vec<String>>,
+}
+
+/// What [`Repository::gc`] and [`Repository::gc_dry_run`] may delete.
+#[derive(Debug, Default)]
+pub struct GcOptions {
+    /// Repack every reachable object into one new pack and delete the
+    /// unreachable objects in older packs too.
+    pub compress: bool,
+    /// Also clear out empty directories, empty legacy backups, and reflog
+    /// entries naming a checkpoint that is gone.
+    pub aggressive: bool,
+    /// Delete every backup first, so objects only a backup kept go as well.
+    pub drop_backups: bool,
+    /// Spare unreachable loose objects written less than this many seconds
+    /// ago; `gc.pruneExpire` (two weeks by default) when unset. Packed
+    /// objects have no age of their own and get no reprieve.
+    pub keep_unreachable: Option<u64>,
+    /// Instead, delete every unreachable loose object written before this
+    /// Unix timestamp, however recent.
+    pub prune_before: Option<i64>,
+    /// Re-hash every reachable object before deleting anything, refusing
+    /// to go on if one is missing or corrupt, and run
+    /// [`Repository::fsck`] afterwards.
+    pub verify: bool,
+}
+
+/// What [`Repository::doctor`] found.
+#[derive(Debug, Default)]
+pub struct DoctorReport {
+    /// Missing scaffolding (directories, HEAD) that `fix` recreates. When
+    /// `fix` was set, these have been repaired.
+    pub repairable: Vec<String>,
+    /// Problems that need a person, such as corrupt objects or a HEAD that
+    /// points at a missing checkpoint. Includes everything [`Repository::fsck`]
+    /// reports.
+    pub problems: Vec<String>,
+}
+
+/// A named checkpoint under `.gini/refs/tags`.
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub name: String,
+    /// The tagged commit.
+    pub commit: String,
+    /// The annotation, for tags created with a message.
+    pub message: Option<String>,
+}
+
+/// One line of [`Repository::blame`] output.
+#[derive(Debug, Clone)]
+pub struct BlameLine {
+    /// The checkpoint that last changed the line.
+    pub commit: String,
+    /// That checkpoint's author, `Name <email>`.
+    pub author: String,
+    /// 1-based line number in the blamed version.
+    pub number: usize,
+    pub text: String,
+}
+
+/// A shelved working tree on the stash stack.
+#[derive(Debug, Clone)]
+pub struct StashEntry {
+    /// The stash commit; its tree is the shelved working tree.
+    pub hash: String,
+    /// The checkpoint the changes were made on.
+    pub base: String,
+    pub message: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: i64,
+}
+
+/// A branch under `.gini/refs/heads`.
+#[derive(Debug, Clone)]
+pub struct Branch {
+    pub name: String,
+    /// The checkpoint the branch points at.
+    pub commit: String,
+    /// Whether HEAD points at this branch.
+    pub current: bool,
+}
+
+/// A remote branch whose tracking ref [`Repository::fetch`] moved.
+#[derive(Debug, Clone)]
+pub struct FetchedBranch {
+    pub name: String,
+    /// Where the tracking ref pointed before, or `None` if it is new.
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// What [`Repository::fetch`] did.
+#[derive(Debug, Clone)]
+pub struct FetchReport {
+    pub remote: String,
+    /// The source repository's root.
+    pub path: PathBuf,
+    /// Objects copied into the local store.
+    pub objects: usize,
+    pub updated: Vec<FetchedBranch>,
+    /// Tracking refs removed because the branch is gone from the remote.
+    pub pruned: Vec<String>,
+}
+
+/// What [`Repository::push`] did.
+#[derive(Debug, Clone)]
+pub struct PushReport {
+    pub remote: String,
+    /// The target repository's root.
+    pub path: PathBuf,
+    pub branch: String,
+    /// Objects copied into the target's store.
+    pub objects: usize,
+    /// Where the target's branch pointed before, or `None` if it is new.
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// What [`Repository::pull`] did.
+#[derive(Debug, Clone)]
+pub struct PullReport {
+    pub fetch: FetchReport,
+    pub branch: String,
+    /// The branch's checkpoint before the pull.
+    pub from: Option<String>,
+    /// The branch's checkpoint after it; equal to `from` when the branch
+    /// was already up to date (or ahead).
+    pub to: String,
+}
+
+/// Disk usage of a `.gini` directory, from [`Repository::storage_usage`].
+#[derive(Debug, Default)]
+pub struct StorageReport {
+    /// Objects reachable from a ref or HEAD.
+    pub reachable_objects: usize,
+    pub reachable_bytes: u64,
+    /// Objects `gc` would delete.
+    pub unreachable_objects: usize,
+    pub unreachable_bytes: u64,
+    pub backups: usize,
+    pub backup_bytes: u64,
+    /// HEAD plus everything under `refs/`.
+    pub ref_bytes: u64,
+    /// Everything else: config, index, stat cache.
+    pub other_bytes: u64,
+}
+
+impl StorageReport {
+    pub fn total_bytes(&self) -> u64 {
+        self.reachable_bytes
+            + self.unreachable_bytes
+            + self.backup_bytes
+            + self.ref_bytes
+            + self.other_bytes
+    }
+}
+
+/// The kinds of object in the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectType {
+    Blob,
+    Tree,
+    Commit,
+    Tag,
+}
+
+impl std::fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ObjectType::Blob => "blob",
+            ObjectType::Tree => "tree",
+            ObjectType::Commit => "commit",
+            ObjectType::Tag => "tag",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One object in the store, from [`Repository::objects`].
+#[derive(Debug, Clone)]
+pub struct ObjectInfo {
+    pub hash: String,
+    /// The type inferred from the object's content.
+    pub kind: ObjectType,
+    /// Bytes on disk.
+    pub size: u64,
+    /// Whether any ref (or HEAD) reaches the object; `gc` deletes the rest.
+    pub reachable: bool,
+    /// The first ref or backup found to reach the object, such as
+    /// `refs/heads/main`, `HEAD`, `stash@{0}` or `backup backup_1700000000`.
+    pub reached_from: Option<String>,
+}
+
+/// How much the object store saves by keeping each distinct file once, from
+/// [`Repository::dedupe_report`].
+#[derive(Debug, Default)]
+pub struct DedupeReport {
+    /// Distinct reachable trees looked at.
+    pub trees: usize,
+    /// Entries in those trees that point at a file (or symlink) blob.
+    pub references: usize,
+    /// Distinct blobs those entries point at.
+    pub blobs: usize,
+    /// Bytes the distinct blobs take.
+    pub stored_bytes: u64,
+    /// Bytes they would take with a copy for every entry.
+    pub undeduplicated_bytes: u64,
+    /// Blobs more than one entry points at, those saving the most first.
+    pub shared: Vec<SharedBlob>,
+}
+
+impl DedupeReport {
+    /// Bytes saved by storing each blob once.
+    pub fn saved_bytes(&self) -> u64 {
+        self.undeduplicated_bytes - self.stored_bytes
+    }
+}
+
+/// One blob in [`DedupeReport::shared`].
+#[derive(Debug, Clone)]
+pub struct SharedBlob {
+    pub hash: String,
+    pub size: u64,
+    /// Tree entries pointing at it.
+    pub references: usize,
+    /// The file name one of those entries gives it.
+    pub name: String,
+}
+
+/// The result of [`Repository::verify_object`].
+#[derive(Debug, Clone)]
+pub struct ObjectCheck {
+    /// The full hash the object is stored under.
+    pub hash: String,
+    /// The hash recomputed from the object's content.
+    pub actual: String,
+    pub kind: ObjectType,
+}
+
+impl ObjectCheck {
+    /// Whether the content still hashes to the name it is stored under.
+    pub fn is_intact(&self) -> bool {
+        self.hash == self.actual
+    }
+}
+
+/// One move of HEAD, from [`Repository::reflog`].
+#[derive(Debug, Clone)]
+pub struct ReflogEntry {
+    /// Where HEAD was before, or `None` if it had no checkpoint yet.
+    pub old: Option<String>,
+    pub new: String,
+    /// When it moved, in Unix time.
+    pub timestamp: u64,
+    /// What moved it, e.g. `checkpoint: Add login page` or `reset: moving to v1.0`.
+    pub reason: String,
+}
+
+/// The result of [`Repository::verify_tree`].
+#[derive(Debug, Clone, Default)]
+pub struct TreeCheck {
+    /// The tree that was checked (a checkpoint's, if given one).
+    pub hash: String,
+    /// Trees read, counting the top one.
+    pub trees: usize,
+    /// File and symlink entries whose objects were found.
+    pub files: usize,
+    /// The first broken link, saying where it is; `None` if nothing is wrong.
+    pub problem: Option<String>,
+}
+
+/// A backup under `.gini/backups`: a manifest naming a snapshot tree, or a
+/// full copy of the working tree for backups made by older versions.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub name: String,
+    pub path: PathBuf,
+    /// The message given to `gini backup --create -m`, if any.
+    pub message: Option<String>,
+    /// The label given with `gini backup --label`, if any.
+    pub label: Option<String>,
+    /// The commit HEAD pointed at when the backup was taken. Backups made by
+    /// older versions do not record it.
+    pub head: Option<String>,
+}
+
+impl Backup {
+    /// When the backup was taken, as a Unix timestamp, read from its name
+    /// (or, once renamed, its manifest's mtime).
+    pub fn timestamp(&self) -> Option<u64> {
+        backup_time(&self.name, &self.path)
+    }
+}
+
+// --- Repository ---
+
+/// A gini project: a working tree whose root contains a `.gini` directory.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    root: PathBuf,
+}
+
+/// Where HEAD ends up after restoring a checkpoint.
+enum HeadUpdate<'a> {
+    /// Move whatever HEAD points at (a branch, or a detached HEAD itself).
+    Advance,
+    Detach,
+    Attach(&'a str),
+    /// Leave HEAD and every branch where they are.
+    Keep,
+}
+
+impl Repository {
+    /// Finds the repository containing the current directory.
+    pub fn discover() -> Result<Self> {
+        Self::discover_from(&std::env::current_dir()?)
+    }
+
+    /// Finds the repository containing `start`, searching upward.
+    pub fn discover_from(start: &Path) -> Result<Self> {
+        let mut current_dir = fs::canonicalize(start)
+            .with_context(|| format!("Cannot access {}", start.display()))?;
+        let mut depth = 0;
+        const MAX_DEPTH: u32 = 100; // Prevent infinite loops
+
+        loop {
+            let gini_path = gini_dir(&current_dir);
+            if gini_path.is_dir() {
+                return Ok(Repository { root: current_dir });
+            }
+            if current_dir.join(".gini").is_file() {
+                bail!(
+                    "{} points at {}, which is not a directory",
+                    current_dir.join(".gini").display(),
+                    gini_path.display()
+                );
+            }
+            if !current_dir.pop() || depth >= MAX_DEPTH {
+                bail!(GiniError::NotARepository);
+            }
+            depth += 1;
+        }
+    }
+
+    /// Opens the repository whose working tree is `work_tree` and whose
+    /// metadata lives in `gini_path` rather than in `work_tree/.gini`.
+    pub fn open_with_gini_dir(work_tree: &Path, gini_path: &Path) -> Result<Self> {
+        let root = fs::canonicalize(work_tree)
+            .with_context(|| format!("Cannot access {}", work_tree.display()))?;
+        if !is_gini_dir(gini_path) {
+            bail!("{} is not a gini metadata directory", gini_path.display());
+        }
+        set_gini_dir(&root, &fs::canonicalize(gini_path)?);
+        Ok(Repository { root })
+    }
+
+    /// Creates an empty repository in `path`.
+    pub fn init(path: &Path) -> Result<Self> {
+        Self::init_in(path, None)
+    }
+
+    /// Like [`Repository::init`], but keeps the metadata in `gini_path` and
+    /// leaves a `.gini` file in `path` that points there.
+    pub fn init_with_gini_dir(path: &Path, gini_path: &Path) -> Result<Self> {
+        Self::init_in(path, Some(gini_path))
+    }
+
+    fn init_in(path: &Path, separate: Option<&Path>) -> Result<Self> {
+        if path.join(".gini").exists() {
+            bail!(GiniError::AlreadyInitialized);
+        }
+        let gini_path = match separate {
+            Some(dir) => {
+                if dir.exists() && fs::read_dir(dir)?.next().is_some() {
+                    bail!("{} already exists and is not empty", dir.display());
+                }
+                if std::path::absolute(dir)?.starts_with(fs::canonicalize(path)?) {
+                    bail!("The metadata directory {} is inside the working tree", dir.display());
+                }
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+                let dir = fs::canonicalize(dir)?;
+                fs::write(path.join(".gini"), format!("gitdir: {}\n", dir.display()))
+                    .context("Failed to write the .gini file")?;
+                dir
+            }
+            None => {
+                let gini_path = path.join(".gini");
+                fs::create_dir(&gini_path)
+                    .context("Failed to create .gini directory")?;
+                gini_path
+            }
+        };
+
+        fs::create_dir(gini_path.join("objects"))
+            .context("Failed to create objects directory")?;
+        fs::create_dir_all(gini_path.join("refs/heads"))
+            .context("Failed to create refs directory")?;
+
+        // Write HEAD file atomically
+        let head_content = format!("ref: {}/{}", HEADS_DIR, DEFAULT_BRANCH);
+        let head_path = gini_path.join("HEAD");
+        write_file_atomic(&head_path, head_content.as_bytes())
+            .context("Failed to write HEAD file")?;
+
+        Ok(Repository {
+            root: fs::canonicalize(path)?,
+        })
+    }
+
+    /// Starts a new repository on branch `name` instead of `main`, and
+    /// records it as `init.defaultBranch`, which `doctor` falls back on when
+    /// HEAD goes missing. Only possible before the first checkpoint.
+    pub fn set_initial_branch(&self, name: &str) -> Result<()> {
+        validate_ref_name(name)?;
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        if get_head_commit(root_path)?.is_some() {
+            bail!("The initial branch can only be chosen before the first checkpoint; use `gini branch -m` to rename it");
+        }
+        attach_head(root_path, name, "init")?;
+        let mut config = Config::load(root_path)?;
+        config.set("init.defaultBranch", name)?;
+        config.save(root_path)
+    }
+
+    /// Like [`Repository::init`], then copies the contents of `template`
+    /// (leaving out any `.gini` in it) into the new working tree. Nothing is
+    /// created if a template file would overwrite one already at `path`.
+    /// `gini_path` keeps the metadata elsewhere, as with
+    /// [`Repository::init_with_gini_dir`].
+    pub fn init_from_template(path: &Path, template: &Path, gini_path: Option<&Path>) -> Result<Self> {
+        if !template.is_dir() {
+            bail!("Template {} is not a directory", template.display());
+        }
+        let template = fs::canonicalize(template)?;
+        if fs::canonicalize(path)?.starts_with(&template) {
+            bail!("The template {} contains the new repository", template.display());
+        }
+        let conflicts = copy_conflicts(&template, path, &[".gini"])?;
+        if !conflicts.is_empty() {
+            bail!("The template would overwrite existing files: {}", conflicts.join(", "));
+        }
+        let repo = Self::init_in(path, gini_path)?;
+        copy_directory_excluding(&template, &repo.root, &[".gini"])
+            .with_context(|| format!("Failed to copy the template {}", template.display()))?;
+        Ok(repo)
+    }
+
+    /// Copies this repository's history (objects, refs, and HEAD) into a new
+    /// repository at `destination` and checks out HEAD there. Backups, the
+    /// staging index, and the config are local and not copied.
+    pub fn clone_to(&self, destination: &Path) -> Result<Repository> {
+        if destination.exists() && fs::read_dir(destination)?.next().is_some() {
+            bail!("Destination {} already exists and is not empty", destination.display());
+        }
+        // Keep the source still while it is copied.
+        let _lock = RepoLock::acquire(&self.root)?;
+        let source = gini_dir(&self.root);
+        let gini_path = gini_dir(destination);
+        fs::create_dir_all(&gini_path)
+            .with_context(|| format!("Failed to create {}", gini_path.display()))?;
+        for name in ["objects", PACK_DIR, "refs", "HEAD"] {
+            if !source.join(name).exists() {
+                continue;
+            }
+            copy_directory_excluding(&source.join(name), &gini_path.join(name), &[])?;
+        }
+        // The objects are only readable under the hash they were named with.
+        let algo = hash_algo(&source.join("objects"))?;
+        if algo != HashAlgo::Sha1 {
+            let mut config = Config::default();
+            config.set("core.hashAlgo", algo.name())?;
+            config.save(destination)?;
+        }
+
+        let clone = Repository {
+            root: fs::canonicalize(destination)?,
+        };
+        if let Some(head) = get_head_commit(&clone.root)? {
+            let objects_path = gini_path.join("objects");
+            let tree_hash = parse_commit_tree(&read_object(&objects_path, &head)?)?;
+            restore_tree(&clone.root, &objects_path, &tree_hash, false)?;
+        }
+        Ok(clone)
+    }
+
+    /// The working tree root (the directory containing `.gini`).
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The directory holding the repository's objects, refs, and config:
+    /// `.gini` in the working tree unless it was put elsewhere.
+    pub fn gini_dir(&self) -> PathBuf {
+        gini_dir(&self.root)
+    }
+
+    /// Converts a user-supplied path into a normalized `/`-separated path
+    /// relative to the repository root. Relative paths are taken from the
+    /// current directory when it lies inside the repository and from the
+    /// repository root otherwise.
+    pub fn relative_path(&self, input: &str) -> Result<String> {
+        repo_relative_path(&self.root, input)
+    }
+
+    /// Snapshots the working tree (or the staged files, if anything is
+    /// staged). No commit is made when the tree is unchanged from HEAD and
+    /// `allow_empty` is not set. A failing `pre-checkpoint` hook aborts it.
+    pub fn checkpoint(&self, message: &str, options: &CheckpointOptions) -> Result<CheckpointReport> {
+        let root_path = self.root.as_path();
+        self.run_pre_checkpoint_hook()?;
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let Snapshot { tree: tree_hash, skipped, .. } = self.snapshot(options, true)?;
+        let parent_hash = get_head_commit(root_path)?;
+        // Resolving a conflicted merge: this checkpoint is the merge.
+        let merge_head = read_merge_head(root_path)?;
+
+        if let Some(parent) = &parent_hash {
+            let parent_tree = parse_commit_tree(&read_object(&objects_path, parent)?)?;
+            if parent_tree == tree_hash && !options.allow_empty && merge_head.is_none() {
+                return Ok(CheckpointReport { commit: None, skipped, auto_gc: None });
+            }
+        }
+
+        let parents: Vec<String> = parent_hash.into_iter().chain(merge_head.clone()).collect();
+        let message = self.finish_message(message, options)?;
+        let reason = format!("checkpoint{}: {}", if merge_head.is_some() { " (merge)" } else { "" }, subject(&message));
+        let commit_hash = self.write_commit(&tree_hash, &parents, &message, &reason)?;
+        if merge_head.is_some() {
+            write_merge_head(root_path, None)?;
+        }
+        // The commit now records whatever was staged, unless --include or
+        // --path left some of it out; then the rest stays staged.
+        if options.include.is_empty() && options.path.is_none() {
+            clear_index(root_path)?;
+        }
+        let auto_gc = match options.no_gc {
+            true => None,
+            false => self.auto_gc().with_context(|| {
+                format!("Checkpoint {} was created, but the automatic gc failed", &commit_hash[..7])
+            })?,
+        };
+        Ok(CheckpointReport {
+            commit: Some(commit_hash),
+            skipped,
+            auto_gc,
+        })
+    }
+
+    /// Works out what [`Repository::checkpoint`] would record with
+    /// `options`, hashing the files without storing anything, taking the
+    /// lock, or running the `pre-checkpoint` hook.
+    pub fn checkpoint_dry_run(&self, options: &CheckpointOptions) -> Result<CheckpointPreview> {
+        let root_path = self.root.as_path();
+        let staged = load_index(root_path)?.is_some();
+        let snapshot = self.snapshot(options, false)?;
+        let head_tree = match get_head_commit(root_path)? {
+            Some(head) => Some(parse_commit_tree(&read_object(&gini_dir(root_path).join("objects"), &head)?)?),
+            None => None,
+        };
+        let changes = detect_renames(diff_file_maps(&head_files(root_path)?, &snapshot.files))
+            .into_iter()
+            .map(|change| (change.kind.letter(), change.display_path()))
+            .collect();
+        let would_commit = head_tree.as_ref() != Some(&snapshot.tree)
+            || options.allow_empty
+            || read_merge_head(root_path)?.is_some();
+        Ok(CheckpointPreview {
+            tree: snapshot.tree,
+            changes,
+            staged,
+            would_commit,
+            skipped: snapshot.skipped,
+        })
+    }
+
+    /// Replaces the HEAD checkpoint with a new one built from the current
+    /// files (or staged files), on HEAD's parent. Without a message, HEAD's
+    /// message is kept. The old checkpoint is left for `gc` to collect.
+    pub fn amend(&self, message: Option<&str>, options: &CheckpointOptions) -> Result<CheckpointReport> {
+        let root_path = self.root.as_path();
+        self.run_pre_checkpoint_hook()?;
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let head = get_head_commit(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("Nothing to amend: no checkpoints yet"))?;
+        let details = parse_commit_details(&read_object(&objects_path, &head)?)?;
+
+        let Snapshot { tree: tree_hash, skipped, .. } = self.snapshot(options, true)?;
+        let message = self.finish_message(message.unwrap_or(&details.message), options)?;
+        let reason = format!("checkpoint (amend): {}", subject(&message));
+        let commit_hash = self.write_commit(&tree_hash, &details.parents, &message, &reason)?;
+        if options.include.is_empty() && options.path.is_none() {
+            clear_index(root_path)?;
+        }
+        let auto_gc = match options.no_gc {
+            true => None,
+            false => self.auto_gc().with_context(|| {
+                format!("Checkpoint {} was created, but the automatic gc failed", &commit_hash[..7])
+            })?,
+        };
+        Ok(CheckpointReport {
+            commit: Some(commit_hash),
+            skipped,
+            auto_gc,
+        })
+    }
+
+    /// Counts a new checkpoint towards `maintenance.autoGc` and, every
+    /// `maintenance.autoGcEvery` checkpoints, deletes unreachable loose
+    /// objects for up to [`AUTO_GC_TIME_LIMIT`]. If time runs out, the next
+    /// checkpoint carries on. The caller holds the lock.
+    fn auto_gc(&self) -> Result<Option<GcReport>> {
+        let root_path = self.root.as_path();
+        let config = Config::load(root_path)?;
+        if !config.auto_gc()? {
+            return Ok(None);
+        }
+        let count_path = gini_dir(root_path).join(AUTO_GC_COUNT_FILE_NAME);
+        let count = fs::read_to_string(&count_path)
+            .ok()
+            .and_then(|count| count.trim().parse::<u32>().ok())
+            .unwrap_or(0)
+            + 1;
+        if count < config.auto_gc_every()? {
+            write_file_atomic(&count_path, count.to_string().as_bytes())?;
+            return Ok(None);
+        }
+
+        let objects_path = gini_dir(root_path).join("objects");
+        let reachable = collect_reachable_objects(root_path)?;
+        let mut report = GcReport::default();
+        let finished = remove_unreachable_objects(
+            &objects_path,
+            &reachable,
+            Some(Instant::now() + AUTO_GC_TIME_LIMIT),
+            keep_unreachable_since(root_path, &GcOptions::default())?,
+            &mut report,
+        )?;
+        let count = if finished { 0 } else { count };
+        write_file_atomic(&count_path, count.to_string().as_bytes())?;
+        Ok(Some(report))
+    }
+
+    /// `message` with the trailers `options` asks for, checked against
+    /// [`MAX_COMMIT_MESSAGE_LENGTH`] once they are in.
+    fn finish_message(&self, message: &str, options: &CheckpointOptions) -> Result<String> {
+        let mut trailers = options.trailers.clone();
+        if options.signoff {
+            let (name, email) = self.identity()?;
+            trailers.push(format!("Signed-off-by: {} <{}>", name, email));
+        }
+        if trailers.is_empty() {
+            return Ok(message.to_string());
+        }
+        let message = append_trailers(message, &trailers)?;
+        if message.len() > MAX_COMMIT_MESSAGE_LENGTH {
+            bail!("Commit message too long (max {} characters)", MAX_COMMIT_MESSAGE_LENGTH);
+        }
+        Ok(message)
+    }
+
+    /// Runs `.gini/hooks/pre-checkpoint`, if installed, with the current HEAD
+    /// in `GINI_COMMIT`. It runs before the lock is taken so the script can
+    /// use gini itself.
+    fn run_pre_checkpoint_hook(&self) -> Result<()> {
+        let head = get_head_commit(&self.root)?;
+        run_hook(&self.root, PRE_CHECKPOINT_HOOK, head.as_deref())
+            .map_err(|e| anyhow::anyhow!("{}; checkpoint aborted", e))
+    }
+
+    /// Records the contents of a tar archive as a new checkpoint on top of
+    /// HEAD. Paths matched by `.giniignore` are left out. The working tree
+    /// and staging index are not touched; restore the checkpoint to see its
+    /// files. Returns the new commit's hash.
+    pub fn import_tar(&self, input: &mut dyn std::io::Read, message: &str) -> Result<String> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let ignore = IgnoreRules::load(root_path)?;
+        let config = Config::load(root_path)?;
+        let max_file_size = config.max_file_size()?;
+        let autocrlf = config.autocrlf()?;
+
+        let mut files: BTreeMap<String, FileEntry> = BTreeMap::new();
+        for member in read_tar(input, max_file_size)? {
+            let is_dir = member.kind == TarMemberKind::Directory;
+            if ignore.is_ignored_path(&member.path, is_dir) {
+                continue;
+            }
+            let file = match member.kind {
+                TarMemberKind::File { executable } => FileEntry {
+                    hash: match autocrlf.then(|| to_lf(&member.content)).flatten() {
+                        Some(content) => hash_and_write_blob(&objects_path, &content, max_file_size)?,
+                        None => hash_and_write_blob(&objects_path, &member.content, max_file_size)?,
+                    },
+                    mode: if executable { MODE_EXECUTABLE } else { MODE_FILE },
+                },
+                TarMemberKind::Directory => FileEntry {
+                    hash: hash_and_write_object(&objects_path, b"")?,
+                    mode: MODE_DIR,
+                },
+                TarMemberKind::Symlink { target } => FileEntry {
+                    hash: hash_and_write_object(&objects_path, &target)?,
+                    mode: MODE_SYMLINK,
+                },
+                TarMemberKind::HardLink { target } => match files.get(&target) {
+                    Some(file) => file.clone(),
+                    None => bail!("Archive links {} to missing {}", member.path, target),
+                },
+            };
+            files.insert(member.path, file);
+        }
+
+        let tree_hash = write_tree_from_files(&objects_path, &files)?;
+        let parent = get_head_commit(root_path)?;
+        self.write_commit(&tree_hash, parent.as_slice(), message, &format!("import-tar: {}", subject(message)))
+    }
+
+    /// Writes the tree for a new checkpoint: the staged files if anything is
+    /// staged, otherwise the whole working tree. Returns the tree hash, the
+    /// files it holds, and any files skipped for size. Unless `write`, the
+    /// hashes are computed but nothing is stored.
+    fn snapshot(&self, options: &CheckpointOptions, write: bool) -> Result<Snapshot> {
+        let root_path = self.root.as_path();
+        let objects_path = gini_dir(root_path).join("objects");
+        // Never record a working tree that a crashed restore left mid-swap.
+        finish_interrupted_replace(root_path)?;
+
+        // Validate objects directory
+        if !objects_path.exists() {
+            bail!("Objects directory not found. Repository may be corrupted.");
+        }
+
+        // `--path` is an anchored include pattern, so reject glob characters.
+        let mut patterns = options.include.clone();
+        if let Some(path) = &options.path {
+            if path.contains(['*', '?']) {
+                bail!("--path cannot contain * or ?; use --include for patterns");
+            }
+            patterns.push(format!("/{}", path));
+        }
+        let include = (!patterns.is_empty()).then(|| IgnoreRules::parse(root_path, &patterns.join("\n")));
+        let write_tree = match write {
+            true => write_tree_from_files,
+            false => hash_tree_from_files,
+        };
+        let (mut tree_hash, mut files, skipped) = match load_index(root_path)? {
+            Some(mut index) => {
+                if let Some(include) = &include {
+                    index.retain(|path, file| include.is_ignored_path(path, file.is_empty_dir()));
+                }
+                (write_tree(&objects_path, &index)?, index, Vec::new())
+            }
+            None => {
+                let config = Config::load(root_path)?;
+                let mut builder = TreeBuilder::new(root_path, !options.no_cache)?;
+                builder.write_objects = write;
+                builder.skip_large = options.skip_large || config.exclude_large()?;
+                builder.progress = options.progress;
+                let filtered = include.is_some();
+                builder.include = include;
+                let (max_files, max_tree_size) = (config.max_files()?, config.max_tree_size()?);
+                if max_files.is_some() || max_tree_size.is_some() || options.preflight.is_some() {
+                    let mut totals = TreeTotals::default();
+                    builder.count(root_path, &mut totals)?;
+                    if let Some(preflight) = options.preflight {
+                        preflight(&totals);
+                    }
+                    if !options.force {
+                        check_tree_limits(&totals, max_files, max_tree_size)?;
+                    }
+                }
+                let tree_hash = match builder.write_tree(root_path) {
+                    Ok(tree_hash) => tree_hash,
+                    Err(e) => {
+                        // Best effort: the original failure is what matters.
+                        let _ = builder.save_progress(root_path);
+                        return Err(e);
+                    }
+                };
+                // A filtered walk only saw some files; the old cache still
+                // holds for the rest.
+                if !filtered && write {
+                    builder.seen.save(root_path)?;
+                }
+                builder.skipped.sort();
+                (tree_hash, builder.files, builder.skipped)
+            }
+        };
+        if !options.include.is_empty() && tree_hash == hash_bytes(&gini_dir(root_path).join("objects"), b"")? {
+            bail!("No files match --include {}", options.include.join(", "));
+        }
+        if let Some(path) = &options.path {
+            let mut merged = head_files(root_path)?;
+            merged.retain(|file_path, _| !path_matches(file_path, path));
+            merged.extend(files);
+            tree_hash = write_tree(&objects_path, &merged)?;
+            files = merged;
+        }
+        Ok(Snapshot { tree: tree_hash, files, skipped })
+    }
+
+    /// Stores a commit for `tree_hash` and moves HEAD to it, logging
+    /// `reason` in the reflog.
+    fn write_commit(&self, tree_hash: &str, parents: &[String], message: &str, reason: &str) -> Result<String> {
+        let commit_hash = self.write_commit_object(tree_hash, parents, message)?;
+        update_head(&self.root, &commit_hash, reason)?;
+        Ok(commit_hash)
+    }
+
+    /// Stores a commit for `tree_hash` without moving HEAD.
+    fn write_commit_object(&self, tree_hash: &str, parents: &[String], message: &str) -> Result<String> {
+        let root_path = self.root.as_path();
+        let parent_line: String = parents.iter().map(|h| format!("parent {}\n", h)).collect();
+
+        let commit_content = format!(
+            "tree {}\n{}author {}\n\n{}",
+            tree_hash,
+            parent_line,
+            self.signature()?,
+            message
+        );
+
+        hash_and_write_object(&gini_dir(root_path).join("objects"), commit_content.as_bytes())
+    }
+
+    /// The author's name and email: from the repo config, then the
+    /// environment, then the user-wide config, then defaults.
+    fn identity(&self) -> Result<(String, String)> {
+        let config = Config::load(&self.root)?;
+        let global = Config::load_global()?;
+        let author_name = config
+            .get("user.name")
+            .map(str::to_string)
+            .or_else(|| std::env::var("GINI_AUTHOR_NAME").ok())
+            .or_else(|| global.get("user.name").map(str::to_string))
+            .unwrap_or_else(|| "Unknown".to_string());
+        let author_email = config
+            .get("user.email")
+            .map(str::to_string)
+            .or_else(|| std::env::var("GINI_AUTHOR_EMAIL").ok())
+            .or_else(|| global.get("user.email").map(str::to_string))
+            .unwrap_or_else(|| "unknown@example.com".to_string());
+        Ok((author_name, author_email))
+    }
+
+    /// The `Name <email> <timestamp> <offset>` identity recorded on new
+    /// commits and tags. The name and email come from [`Self::identity`];
+    /// the offset is `user.timezone` if set, otherwise the machine's local
+    /// offset.
+    fn signature(&self) -> Result<String> {
+        let config = Config::load(&self.root)?;
+        let (author_name, author_email) = self.identity()?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let offset = match config.get("user.timezone") {
+            Some(value) => parse_offset(value)
+                .ok_or_else(|| anyhow::anyhow!("Invalid user.timezone: {}", value))?,
+            None => chrono::Local::now().offset().local_minus_utc(),
+        };
+        Ok(format!(
+            "{} <{}> {} {}",
+            author_name,
+            author_email,
+            timestamp,
+            format_offset(offset)
+        ))
+    }
+
+    /// Replaces the working tree with a checkpoint's contents and moves HEAD
+    /// to it. `rev` is anything [`Repository::resolve_revision`] accepts. The
+    /// previous state is backed up first and the backup's path returned,
+    /// unless `backup` is false or `core.backupOnRestore` is off. Unless
+    /// `force` is set, refuses when files would be lost (see
+    /// [`Repository::files_lost_by_restore`]).
+    ///
+    /// Normally the current branch is moved to the checkpoint; with `detach`
+    /// the branch is left alone and HEAD points straight at it instead.
+    /// Files matching one of the `preserve` globs (see
+    /// [`Repository::preserved_files`]) are kept as they are rather than
+    /// deleted or overwritten. The `post-restore` hook runs once the files
+    /// are written.
+    pub fn restore(
+        &self,
+        rev: &str,
+        force: bool,
+        detach: bool,
+        backup: bool,
+        preserve: &[String],
+    ) -> Result<Option<PathBuf>> {
+        let head = if detach { HeadUpdate::Detach } else { HeadUpdate::Advance };
+        self.restore_commit(rev, force, head, backup, preserve, "restore")
+    }
+
+    /// Replaces the working tree with a checkpoint's contents like
+    /// [`Repository::restore`], but leaves HEAD and the current branch where
+    /// they are. The next checkpoint is then an ordinary child of HEAD that
+    /// records the old state, instead of history moving back to it.
+    pub fn restore_files(&self, rev: &str, force: bool, backup: bool, preserve: &[String]) -> Result<Option<PathBuf>> {
+        self.restore_commit(rev, force, HeadUpdate::Keep, backup, preserve, "restore")
+    }
+
+    /// Writes checkpoint `rev`'s files into `dir`, creating it if needed,
+    /// and leaves the working tree, HEAD and every branch alone. `dir` must
+    /// be outside the working tree, and empty unless `force` is set, in
+    /// which case the checkpoint's files replace whatever is in their way
+    /// and everything else there stays. Returns `dir` made absolute.
+    pub fn restore_into(&self, rev: &str, dir: &Path, force: bool) -> Result<PathBuf> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let commit_hash = resolve_revision(&self.root, rev)?;
+        let tree_hash = parse_commit_tree(&read_object(&objects_path, &commit_hash)?)?;
+        let created = !dir.exists();
+        if !created && !force && fs::read_dir(dir)?.next().is_some() {
+            bail!("{} is not empty; pass --force to extract into it anyway", dir.display());
+        }
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let dir = fs::canonicalize(dir)?;
+        if dir.starts_with(&self.root) {
+            if created {
+                let _ = fs::remove_dir(&dir);
+            }
+            bail!("{} is inside the working tree", dir.display());
+        }
+        restore_tree(&dir, &objects_path, &tree_hash, Config::load(&self.root)?.autocrlf()?)?;
+        Ok(dir)
+    }
+
+    /// Switches to `target`. A branch name restores the branch's checkpoint
+    /// and reattaches HEAD to it; any other revision restores that
+    /// checkpoint with HEAD detached, leaving every branch where it is.
+    /// Backs up and refuses like [`Repository::restore`].
+    pub fn checkout(&self, target: &str, force: bool) -> Result<Option<PathBuf>> {
+        let is_branch = validate_ref_name(target).is_ok()
+            && read_ref(&self.root, &format!("{}/{}", HEADS_DIR, target))?.is_some();
+        if is_branch {
+            self.restore_commit(target, force, HeadUpdate::Attach(target), true, &[], "checkout")
+        } else {
+            self.restore_commit(target, force, HeadUpdate::Detach, true, &[], "checkout")
+        }
+    }
+
+    /// Moves HEAD to `rev` like [`Repository::restore`] (or, with `detach`,
+    /// detaches it there) but keeps local edits: only files that differ
+    /// between HEAD and the checkpoint are touched, and where one of those
+    /// was also edited locally the two changes are merged line by line,
+    /// with conflict markers where they overlap. Untracked files and edits
+    /// to anything the checkpoint didn't change stay as they are. The
+    /// working tree is backed up first, as [`Repository::restore`] decides.
+    pub fn restore_merge(&self, rev: &str, detach: bool, backup: bool) -> Result<RestoreMergeReport> {
+        let root_path = self.root.as_path();
+        let lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        finish_interrupted_replace(root_path)?;
+        let commit_hash = &resolve_revision(root_path, rev)?;
+        let target = flatten_tree(&objects_path, &parse_commit_tree(&read_object(&objects_path, commit_hash)?)?)?;
+        let head = head_files(root_path)?;
+
+        // The local edits are stored so the merge can read them.
+        let mut builder = TreeBuilder::new(root_path, true)?;
+        let working = flatten_tree(&objects_path, &builder.write_tree(root_path)?)?;
+        let merged = merge_files(&objects_path, &head, &working, &target, "local", &commit_hash[..7])?;
+        let changes = diff_file_maps(&working, &merged.working);
+
+        let backup = match backup && Config::load(root_path)?.backup_on_restore()? {
+            true => Some(create_backup(root_path, None)?),
+            false => None,
+        };
+        checkout_changes(root_path, &objects_path, &changes)?;
+        write_merge_head(root_path, None)?;
+        let reason = format!("restore --merge: moving to {}", rev);
+        match detach {
+            true => detach_head(root_path, commit_hash, &reason)?,
+            false => update_head(root_path, commit_hash, &reason)?,
+        }
+        drop(lock);
+        run_hook(root_path, POST_RESTORE_HOOK, Some(commit_hash))
+            .map_err(|e| anyhow::anyhow!("{} (the files were restored)", e))?;
+        let updated = changes
+            .into_iter()
+            .map(|change| change.path)
+            .filter(|path| !merged.conflicts.contains(path))
+            .collect();
+        Ok(RestoreMergeReport {
+            updated,
+            conflicts: merged.conflicts,
+            backup,
+        })
+    }
+
+    /// Every move of HEAD that the reflog still holds, newest first, so
+    /// entry `n` is what `HEAD@{n}` resolves to.
+    pub fn reflog(&self) -> Result<Vec<ReflogEntry>> {
+        read_reflog(&self.root)
+    }
+
+    /// The branch HEAD points at, or `None` when HEAD is detached.
+    pub fn current_branch(&self) -> Result<Option<String>> {
+        head_branch(&self.root)
+    }
+
+    /// Afterwards `.gini/hooks/post-restore`, if installed, runs with the
+    /// restored checkpoint in `GINI_COMMIT`. The reflog names the move
+    /// `<operation>: moving to <rev>`.
+    fn restore_commit(
+        &self,
+        rev: &str,
+        force: bool,
+        head: HeadUpdate<'_>,
+        backup: bool,
+        preserve: &[String],
+        operation: &str,
+    ) -> Result<Option<PathBuf>> {
+        let root_path = self.root.as_path();
+        let lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let commit_hash = &resolve_revision(root_path, rev)?;
+        let preserved = self.preserved_files(preserve)?;
+
+        if !force {
+            let mut lost = self.files_lost_by_restore(commit_hash)?;
+            lost.retain(|path| !preserved.contains(path));
+            if !lost.is_empty() {
+                bail!("{}", describe_lost_files(&lost));
+            }
+        }
+
+        let commit_content = read_object(&objects_path, commit_hash)?;
+        let tree_hash = parse_commit_tree(&commit_content)?;
+
+        // Create backup before destructive operation
+        let config = Config::load(root_path)?;
+        let backup_path = match backup && config.backup_on_restore()? {
+            true => Some(create_backup(root_path, None)?),
+            false => None,
+        };
+
+        let autocrlf = config.autocrlf()?;
+        replace_working_tree(root_path, |staging| {
+            restore_tree(staging, &objects_path, &tree_hash, autocrlf)?;
+            preserved.iter().try_for_each(|path| copy_preserved(root_path, staging, path))
+        })?;
+        write_merge_head(root_path, None)?;
+        let reason = format!("{}: moving to {}", operation, rev);
+        match head {
+            HeadUpdate::Advance => update_head(root_path, commit_hash, &reason)?,
+            HeadUpdate::Detach => detach_head(root_path, commit_hash, &reason)?,
+            HeadUpdate::Attach(branch) => attach_head(root_path, branch, &reason)?,
+            HeadUpdate::Keep => {}
+        }
+        drop(lock);
+        run_hook(root_path, POST_RESTORE_HOOK, Some(commit_hash))
+            .map_err(|e| anyhow::anyhow!("{} (the files were restored)", e))?;
+        Ok(backup_path)
+    }
+
+    /// The files in the working tree, ignored ones included, that match one
+    /// of `globs`, sorted. Globs work like `.giniignore` lines: one without
+    /// a `/` matches the file name at any depth, and one naming a directory
+    /// matches everything beneath it.
+    pub fn preserved_files(&self, globs: &[String]) -> Result<Vec<String>> {
+        let mut preserved = Vec::new();
+        if !globs.is_empty() {
+            let rules = IgnoreRules::parse(&self.root, &globs.join("\n"));
+            collect_preserved(&self.root, &rules, false, &mut preserved)?;
+            preserved.sort();
+        }
+        Ok(preserved)
+    }
+
+    /// Lists working-tree files that restoring `rev` would delete and that no
+    /// checkpoint holds: files missing from its tree that are either
+    /// untracked or changed since HEAD. Ignored files count too, since a
+    /// restore replaces everything outside `.gini` and `.git`.
+    pub fn files_lost_by_restore(&self, rev: &str) -> Result<Vec<String>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let commit_hash = resolve_revision(&self.root, rev)?;
+        let target_tree = parse_commit_tree(&read_object(&objects_path, &commit_hash)?)?;
+        let target = flatten_tree(&objects_path, &target_tree)?;
+        let head = head_files(&self.root)?;
+        Ok(scan_all_files(&self.root)?
+            .into_iter()
+            .filter(|(path, file)| {
+                !file.is_empty_dir() && !target.contains_key(path) && head.get(path) != Some(file)
+            })
+            .map(|(path, _)| path)
+            .collect())
+    }
+
+    /// Previews [`Repository::restore`] (or, with `path`,
+    /// [`Repository::restore_path`]) without touching anything: one
+    /// `(letter, path)` pair per file that would be created (`A`),
+    /// overwritten (`M`) or deleted (`D`). Restoring a single path only
+    /// deletes files HEAD tracks.
+    pub fn restore_preview(&self, rev: &str, path: Option<&str>) -> Result<Vec<(char, String)>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let commit_hash = resolve_revision(&self.root, rev)?;
+        let tree_hash = parse_commit_tree(&read_object(&objects_path, &commit_hash)?)?;
+        let mut target = flatten_tree(&objects_path, &tree_hash)?;
+        let mut working = scan_working_tree(&self.root, true)?;
+        let mut head = BTreeMap::new();
+        if let Some(path) = path {
+            target.retain(|p, _| path_is_within(p, path));
+            working.retain(|p, _| path_is_within(p, path));
+            head = head_files(&self.root)?;
+        }
+        Ok(diff_file_maps(&working, &target)
+            .into_iter()
+            .filter(|change| path.is_none() || change.kind != ChangeKind::Deleted || head.contains_key(&change.path))
+            .map(|change| (change.kind.letter(), change.display_path()))
+            .collect())
+    }
+
+    /// Restores a single file or directory from a checkpoint without cleaning
+    /// the working tree or taking a backup. `relative_path` is relative to the
+    /// repository root. Files under it that HEAD tracks but the checkpoint
+    /// lacks are deleted, so a file removed since then goes again; untracked
+    /// files are left alone.
+    pub fn restore_path(&self, rev: &str, relative_path: &str) -> Result<()> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let commit_hash = &resolve_revision(root_path, rev)?;
+        let commit_content = read_object(&objects_path, commit_hash)?;
+        let tree_hash = parse_commit_tree(&commit_content)?;
+
+        let entry = find_tree_entry(&objects_path, &tree_hash, relative_path)?;
+        let mut gone = head_files(root_path)?;
+        gone.retain(|path, _| path_is_within(path, relative_path));
+        if entry.is_some() {
+            let then = flatten_tree(&objects_path, &tree_hash)?;
+            gone.retain(|path, _| !then.contains_key(path));
+        }
+        let Some(entry) = entry else {
+            if gone.is_empty() {
+                bail!("Path '{}' does not exist in checkpoint {}", relative_path, &commit_hash[..7]);
+            }
+            return checkout_files(root_path, &objects_path, &gone, &BTreeMap::new());
+        };
+        checkout_files(root_path, &objects_path, &gone, &BTreeMap::new())?;
+
+        let target = root_path.join(relative_path);
+        ensure_inside(root_path, &target)?;
+        // Inspect the link itself, not what it points at, so restoring over a
+        // symlink replaces the link rather than writing through it.
+        let existing = fs::symlink_metadata(&target).ok();
+        let existing_dir = existing.as_ref().is_some_and(|m| m.is_dir());
+        match entry.kind {
+            EntryKind::Tree => {
+                if existing.is_some() && !existing_dir {
+                    fs::remove_file(&target)?;
+                }
+                fs::create_dir_all(&target)?;
+                restore_tree(&target, &objects_path, &entry.hash, Config::load(root_path)?.autocrlf()?)?;
+            }
+            EntryKind::Blob | EntryKind::Link => {
+                if existing_dir {
+                    fs::remove_dir_all(&target)?;
+                } else if existing.is_some() {
+                    fs::remove_file(&target)?;
+                }
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let content = read_object_raw(&objects_path, &entry.hash)?;
+                if entry.kind == EntryKind::Link {
+                    create_symlink(&content, &target)?;
+                } else {
+                    write_blob_file(&target, content, entry.mode, Config::load(root_path)?.autocrlf()?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The files in checkpoint `rev` that are gone from the working tree,
+    /// sorted, with a trailing `/` on empty directories: nothing is at
+    /// their path, and nothing but directories above it. These are what
+    /// [`Repository::restore_missing`] writes.
+    pub fn missing_files(&self, rev: &str) -> Result<Vec<String>> {
+        let missing = self.missing_entries(rev)?;
+        Ok(missing.iter().map(|(path, file)| format!("{}{}", path, dir_suffix(file))).collect())
+    }
+
+    /// Writes back the files [`Repository::missing_files`] lists, leaving
+    /// every file that exists alone, however it was edited. Nothing is
+    /// deleted or backed up, and HEAD stays where it is. Returns what was
+    /// restored.
+    pub fn restore_missing(&self, rev: &str) -> Result<Vec<String>> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let missing = self.missing_entries(rev)?;
+        checkout_files(root_path, &gini_dir(root_path).join("objects"), &BTreeMap::new(), &missing)?;
+        Ok(missing.iter().map(|(path, file)| format!("{}{}", path, dir_suffix(file))).collect())
+    }
+
+    /// The entries of checkpoint `rev` whose path is free in the working
+    /// tree. A file standing where a parent directory should be counts as
+    /// being in the way, since writing the entry would delete it.
+    fn missing_entries(&self, rev: &str) -> Result<BTreeMap<String, FileEntry>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let commit_hash = resolve_revision(&self.root, rev)?;
+        let mut files = flatten_tree(&objects_path, &parse_commit_tree(&read_object(&objects_path, &commit_hash)?)?)?;
+        files.retain(|path, _| {
+            let mut current = self.root.clone();
+            for component in Path::new(path).components() {
+                current.push(component);
+                match fs::symlink_metadata(&current) {
+                    Ok(metadata) if metadata.is_dir() => continue,
+                    Ok(_) => return false,
+                    Err(_) => return true,
+                }
+            }
+            false
+        });
+        Ok(files)
+    }
+
+    /// Stages the given paths (files or directories, relative to the current
+    /// directory). Paths that no longer exist in the working tree are staged
+    /// as deletions. Returns the number of index entries changed.
+    pub fn add(&self, paths: &[String]) -> Result<usize> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let mut index = match load_index(root_path)? {
+            Some(index) => index,
+            None => head_files(root_path)?,
+        };
+        let working = scan_working_tree(root_path, true)?;
+        let config = Config::load(root_path)?;
+        let max_file_size = config.max_file_size()?;
+        let autocrlf = config.autocrlf()?;
+
+        let mut changed = 0;
+        for input in paths {
+            let relative = repo_relative_path_or_root(root_path, input)?;
+            let mut matched = false;
+            for (path, file) in working.iter().filter(|(p, _)| path_matches(p, &relative)) {
+                matched = true;
+                if index.get(path) != Some(file) {
+                    // The scan only hashed the file; staging must store the blob.
+                    write_working_object(&objects_path, &root_path.join(path), file, max_file_size, autocrlf)?;
+                    index.insert(path.clone(), file.clone());
+                    changed += 1;
+                }
+            }
+            let removed: Vec<String> = index
+                .keys()
+                .filter(|p| path_matches(p, &relative) && !working.contains_key(*p))
+                .cloned()
+                .collect();
+            for path in removed {
+                matched = true;
+                index.remove(&path);
+                changed += 1;
+            }
+            if !matched {
+                bail!("Path did not match any files: {}", input);
+            }
+        }
+        save_index(root_path, &index)?;
+        Ok(changed)
+    }
+
+    /// The files under `paths` whose working copy differs from what is
+    /// staged (or from HEAD, when nothing is), split into hunks for
+    /// [`Repository::stage_hunks`].
+    pub fn patch_files(&self, paths: &[String]) -> Result<Vec<PatchFile>> {
+        let root_path = self.root.as_path();
+        let index = match load_index(root_path)? {
+            Some(index) => index,
+            None => head_files(root_path)?,
+        };
+        let working = scan_working_tree(root_path, true)?;
+        let mut changed = BTreeSet::new();
+        for input in paths {
+            let relative = repo_relative_path_or_root(root_path, input)?;
+            let mut matched = false;
+            for path in working.keys().chain(index.keys()).filter(|p| path_matches(p, &relative)) {
+                matched = true;
+                if working.get(path) != index.get(path) {
+                    changed.insert(path.clone());
+                }
+            }
+            if !matched {
+                bail!("Path did not match any files: {}", input);
+            }
+        }
+        changed
+            .into_iter()
+            .map(|path| {
+                let hunks = self.patch_sides(&path, &index, &working)?.map(|(old, new)| diff_hunks(&old, &new));
+                Ok(PatchFile { hunks: hunks.filter(|hunks| !hunks.is_empty()), path })
+            })
+            .collect()
+    }
+
+    /// Stages the hunks of `path`'s changes that `selected` picks, in the
+    /// order [`Repository::patch_files`] listed them, leaving the rest
+    /// unstaged. For a file without hunks, one flag says whether to stage
+    /// it whole. Returns whether anything was staged.
+    pub fn stage_hunks(&self, path: &str, selected: &[bool]) -> Result<bool> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let mut index = match load_index(root_path)? {
+            Some(index) => index,
+            None => head_files(root_path)?,
+        };
+        let working = scan_working_tree(root_path, true)?;
+        let hunks = self.patch_sides(path, &index, &working)?.filter(|(old, new)| !diff_hunks(old, new).is_empty());
+        let config = Config::load(root_path)?;
+        let max_file_size = config.max_file_size()?;
+        let staged = match (hunks, working.get(path)) {
+            _ if !selected.contains(&true) => return Ok(false),
+            (Some((old, new)), Some(file)) => {
+                let content = apply_hunks(&old, &new, selected);
+                let hash = hash_and_write_blob(&objects_path, content.as_bytes(), max_file_size)?;
+                FileEntry { hash, mode: file.mode }
+            }
+            (None, Some(file)) => {
+                let autocrlf = config.autocrlf()?;
+                write_working_object(&objects_path, &root_path.join(path), file, max_file_size, autocrlf)?;
+                file.clone()
+            }
+            (_, None) => {
+                index.remove(path);
+                save_index(root_path, &index)?;
+                return Ok(true);
+            }
+        };
+        let changed = index.get(path) != Some(&staged);
+        index.insert(path.to_string(), staged);
+        save_index(root_path, &index)?;
+        Ok(changed)
+    }
+
+    /// The staged (or HEAD) and working versions of `path` as text, LF line
+    /// endings under `core.autocrlf`, or `None` unless both are text files.
+    fn patch_sides(
+        &self,
+        path: &str,
+        index: &BTreeMap<String, FileEntry>,
+        working: &BTreeMap<String, FileEntry>,
+    ) -> Result<Option<(String, String)>> {
+        let is_file = |file: &FileEntry| file.mode != MODE_SYMLINK && !file.is_empty_dir();
+        let Some(new_file) = working.get(path).filter(|file| is_file(file)) else {
+            return Ok(None);
+        };
+        let old = match index.get(path) {
+            Some(file) if !is_file(file) => return Ok(None),
+            Some(file) if file.mode != new_file.mode => return Ok(None),
+            Some(file) => read_object_raw(&gini_dir(&self.root).join("objects"), &file.hash)?,
+            None => Vec::new(),
+        };
+        let mut new = fs::read(self.root.join(path))?;
+        if Config::load(&self.root)?.autocrlf()? {
+            new = to_lf(&new).unwrap_or(new);
+        }
+        match (as_text(&old), as_text(&new)) {
+            (Some(old), Some(new)) => Ok(Some((old.to_string(), new.to_string()))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Unstages the given paths, resetting their index entries to HEAD.
+    pub fn reset_paths(&self, paths: &[String]) -> Result<usize> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let mut index = match load_index(root_path)? {
+            Some(index) => index,
+            None => return Ok(0),
+        };
+        let head = head_files(root_path)?;
+
+        let mut changed = 0;
+        for input in paths {
+            let relative = repo_relative_path_or_root(root_path, input)?;
+            let candidates: BTreeSet<String> = index
+                .keys()
+                .chain(head.keys())
+                .filter(|p| path_matches(p, &relative))
+                .cloned()
+                .collect();
+            for path in candidates {
+                let previous = match head.get(&path) {
+                    Some(hash) => index.insert(path.clone(), hash.clone()),
+                    None => index.remove(&path),
+                };
+                if previous.as_ref() != head.get(&path) {
+                    changed += 1;
+                }
+            }
+        }
+
+        if index == head {
+            clear_index(root_path)?;
+        } else {
+            save_index(root_path, &index)?;
+        }
+        Ok(changed)
+    }
+
+    /// Points the current branch at `rev` and makes the working tree and
+    /// index match it, without taking a backup. Untracked files are kept.
+    /// Returns the tracked paths whose uncommitted changes were discarded.
+    pub fn reset_hard(&self, rev: &str) -> Result<Vec<String>> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let commit_hash = resolve_revision(root_path, rev)?;
+        let target_tree = parse_commit_tree(&read_object(&objects_path, &commit_hash)?)?;
+        let target = flatten_tree(&objects_path, &target_tree)?;
+
+        let head = head_files(root_path)?;
+        let index = load_index(root_path)?;
+        let staged = index.as_ref().unwrap_or(&head);
+        let working = scan_working_tree(root_path, true)?;
+
+        // Staged changes plus edits to tracked files are lost; files that
+        // were never tracked ("added" relative to the index) are kept.
+        let mut discarded = BTreeSet::new();
+        for change in diff_file_maps(&head, staged) {
+            discarded.insert(change.display_path());
+        }
+        for change in diff_file_maps(staged, &working) {
+            if change.kind != ChangeKind::Added {
+                discarded.insert(change.display_path());
+            }
+        }
+
+        // Everything gini knows about: HEAD plus anything staged.
+        let mut tracked = head.clone();
+        tracked.extend(staged.iter().map(|(p, f)| (p.clone(), f.clone())));
+
+        checkout_files(root_path, &objects_path, &tracked, &target)?;
+        update_head(root_path, &commit_hash, &format!("reset: moving to {}", rev))?;
+        clear_index(root_path)?;
+        write_merge_head(root_path, None)?;
+        Ok(discarded.into_iter().collect())
+    }
+
+    /// Points the current branch at `rev`, leaving the working tree and the
+    /// index untouched.
+    pub fn reset_soft(&self, rev: &str) -> Result<()> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let commit_hash = resolve_revision(root_path, rev)?;
+        update_head(root_path, &commit_hash, &format!("reset: moving to {}", rev))
+    }
+
+    /// Records a new checkpoint on top of HEAD that undoes what checkpoint
+    /// `rev` changed relative to its parent, and updates the working tree
+    /// to match. Files changed since `rev` in a way that conflicts with
+    /// undoing it abort the revert, as do uncommitted changes to tracked
+    /// files. Returns the new commit's hash.
+    pub fn revert(&self, rev: &str) -> Result<String> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let head = get_head_commit(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("Nothing to revert: no checkpoints yet"))?;
+        let target = resolve_revision(root_path, rev)?;
+        let target_content = read_object(&objects_path, &target)?;
+        let target_files = flatten_tree(&objects_path, &parse_commit_tree(&target_content)?)?;
+        let details = parse_commit_details(&target_content)?;
+        let parent_files = match details.parent() {
+            Some(parent) => flatten_tree(&objects_path, &parse_commit_tree(&read_object(&objects_path, parent)?)?)?,
+            None => BTreeMap::new(),
+        };
+
+        let head_files = head_files(root_path)?;
+        let working = ensure_clean_for(root_path, &head_files, "revert")?;
+
+        // Each path the checkpoint changed goes back to its parent's version,
+        // provided HEAD still has the checkpoint's version.
+        let mut reverted = head_files.clone();
+        let mut conflicts = Vec::new();
+        for change in diff_file_maps(&parent_files, &target_files) {
+            let current = head_files.get(&change.path);
+            if current == change.old.as_ref() {
+                continue; // already undone
+            }
+            if current != change.new.as_ref() {
+                conflicts.push(change.display_path());
+                continue;
+            }
+            match change.old {
+                Some(file) => reverted.insert(change.path, file),
+                None => reverted.remove(&change.path),
+            };
+        }
+        if !conflicts.is_empty() {
+            bail!(
+                "Cannot revert {}: these files changed since then:\n  {}",
+                &target[..7],
+                conflicts.join("\n  ")
+            );
+        }
+        if reverted == head_files {
+            bail!("Nothing to revert: {} is already undone in HEAD", &target[..7]);
+        }
+        ensure_untracked_kept(&head_files, &working, &reverted, "revert")?;
+
+        let tree_hash = write_tree_from_files(&objects_path, &reverted)?;
+        let subject = details.message.lines().next().unwrap_or("");
+        let message = format!("Revert \"{}\"\n\nThis reverts checkpoint {}.", subject, target);
+        let reason = format!("revert: Revert \"{}\"", subject);
+        let commit_hash = self.write_commit(&tree_hash, std::slice::from_ref(&head), &message, &reason)?;
+        checkout_files(root_path, &objects_path, &head_files, &reverted)?;
+        Ok(commit_hash)
+    }
+
+    /// The checkpoints [`Repository::squash`] would fold together, oldest
+    /// first: `from`, `to`, and every one between them.
+    pub fn squash_range(&self, from: &str, to: &str) -> Result<Vec<String>> {
+        Ok(self.squash_plan(from, to)?.0)
+    }
+
+    /// Replaces checkpoints `from` through `to` (both included) with a single
+    /// one holding `to`'s files, on `from`'s parent, and moves HEAD's branch
+    /// onto it. Checkpoints after `to` are made again on top, with their
+    /// files, authors and messages unchanged, so the working tree stays as it
+    /// is. The old checkpoints are left for `gc`, and the reflog still names
+    /// them. Everything between `from` and HEAD must be one line of history
+    /// without merges.
+    pub fn squash(&self, from: &str, to: &str, message: &str) -> Result<SquashReport> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        if read_merge_head(root_path)?.is_some() {
+            bail!("A merge is in progress; finish it with `gini checkpoint` before squashing");
+        }
+        let (range, later) = self.squash_plan(from, to)?;
+        let first = parse_commit_details(&read_object(&objects_path, &range[0])?)?;
+        let tree_hash = parse_commit_tree(&read_object(&objects_path, &range[range.len() - 1])?)?;
+        let commit = self.write_commit_object(&tree_hash, &first.parents, message)?;
+
+        let mut head = commit.clone();
+        for old in &later {
+            let content = read_object(&objects_path, old)?;
+            let old_parent = parse_commit_details(&content)?
+                .parent()
+                .map(str::to_string)
+                .ok_or_else(|| anyhow::anyhow!("Checkpoint {} has no parent", &old[..7]))?;
+            let content = content.replacen(&format!("\nparent {}\n", old_parent), &format!("\nparent {}\n", head), 1);
+            head = hash_and_write_object(&objects_path, content.as_bytes())?;
+        }
+        // The plan was made from this HEAD; don't rewrite over a newer one.
+        let planned = later.last().or(range.last()).map(String::as_str);
+        update_head_checked(root_path, planned, &head, &format!("squash: {}..{} into {}", from, to, &commit[..7]))?;
+        Ok(SquashReport {
+            commit,
+            squashed: range.len(),
+            rebuilt: later.len(),
+            head,
+        })
+    }
+
+    /// The message `checkpoint --fixup` gives a correction to `rev`:
+    /// `fixup! <its subject>`.
+    pub fn fixup_message(&self, rev: &str) -> Result<String> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let hash = resolve_revision(&self.root, rev)?;
+        let details = parse_commit_details(&read_object(&objects_path, &hash)?)?;
+        Ok(format!("fixup! {}", subject(&details.message)))
+    }
+
+    /// The `fixup!` checkpoints from `from` through HEAD, each with the
+    /// earlier checkpoint it names, oldest first: the `(fixup, target)`
+    /// pairs [`Repository::autosquash`] would fold.
+    pub fn autosquash_plan(&self, from: &str) -> Result<Vec<(String, String)>> {
+        let (range, _) = self.squash_plan(from, "HEAD")?;
+        let mut targets = self.fixup_targets(&range)?;
+        Ok(range
+            .into_iter()
+            .filter_map(|commit| targets.remove(&commit).map(|target| (commit, target)))
+            .collect())
+    }
+
+    /// Folds each `fixup!` checkpoint from `from` through HEAD into the
+    /// nearest earlier checkpoint whose subject (or hash) it names, keeping
+    /// that checkpoint's message and author. Every checkpoint after the first
+    /// one changed is made again on top, with the changes it made applied by
+    /// a three-way merge, so the fixups can move back past them. Fails,
+    /// changing nothing, if any change no longer applies cleanly or the
+    /// result would differ from HEAD's files. Like [`Repository::squash`],
+    /// everything from `from` to HEAD must be one line without merges.
+    pub fn autosquash(&self, from: &str) -> Result<AutosquashReport> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        if read_merge_head(root_path)?.is_some() {
+            bail!("A merge is in progress; finish it with `gini checkpoint` before squashing");
+        }
+        let (range, _) = self.squash_plan(from, "HEAD")?;
+        let fixups = self.fixup_targets(&range)?;
+        if fixups.is_empty() {
+            bail!("No fixup! checkpoints to fold from {} to HEAD", from);
+        }
+        let files_of = |commit: Option<&str>| -> Result<BTreeMap<String, FileEntry>> {
+            match commit {
+                Some(commit) => flatten_tree(&objects_path, &parse_commit_tree(&read_object(&objects_path, commit)?)?),
+                None => Ok(BTreeMap::new()),
+            }
+        };
+
+        let first = parse_commit_details(&read_object(&objects_path, &range[0])?)?;
+        let mut parent = first.parent().map(str::to_string);
+        let mut files = files_of(parent.as_deref())?;
+        let mut rebuilt = 0;
+        for commit in range.iter().filter(|commit| !fixups.contains_key(*commit)) {
+            let content = read_object(&objects_path, commit)?;
+            let details = parse_commit_details(&content)?;
+            let folded: Vec<&String> = range.iter().filter(|fixup| fixups.get(*fixup) == Some(commit)).collect();
+            // Untouched so far: the checkpoint can stay as it is.
+            if folded.is_empty() && details.parent() == parent.as_deref() {
+                files = files_of(Some(commit))?;
+                parent = Some(commit.clone());
+                continue;
+            }
+            for change in std::iter::once(commit).chain(folded) {
+                let base = files_of(parse_commit_details(&read_object(&objects_path, change)?)?.parent())?;
+                let merged = merge_files(&objects_path, &base, &files, &files_of(Some(change))?, "HEAD", change)?;
+                if !merged.conflicts.is_empty() {
+                    bail!(
+                        "Cannot autosquash: {} no longer applies cleanly once the fixups move, in:\n  {}",
+                        &change[..7],
+                        merged.conflicts.join("\n  ")
+                    );
+                }
+                files = merged.files;
+            }
+            let tree_hash = write_tree_from_files(&objects_path, &files)?;
+            let rewritten = rewrite_commit(&content, &tree_hash, parent.as_deref());
+            parent = Some(hash_and_write_object(&objects_path, rewritten.as_bytes())?);
+            rebuilt += 1;
+        }
+
+        let head = parent.expect("the range holds at least one checkpoint that isn't a fixup");
+        if files != head_files(root_path)? {
+            bail!("Cannot autosquash: the result would not match HEAD's files, so nothing was changed");
+        }
+        let planned = range.last().map(String::as_str);
+        update_head_checked(root_path, planned, &head, &format!("squash --autosquash: {}", from))?;
+        Ok(AutosquashReport {
+            folded: fixups.len(),
+            rebuilt,
+            head,
+        })
+    }
+
+    /// Pairs each `fixup!` checkpoint in `range` (oldest first) with the
+    /// nearest earlier one, not itself a fixup, whose subject it repeats or
+    /// whose hash starts with what it names. Fixups naming nothing in the
+    /// range are left out and stay ordinary checkpoints.
+    fn fixup_targets(&self, range: &[String]) -> Result<BTreeMap<String, String>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let mut subjects = Vec::new();
+        for commit in range {
+            let details = parse_commit_details(&read_object(&objects_path, commit)?)?;
+            subjects.push(subject(&details.message).to_string());
+        }
+        let mut targets = BTreeMap::new();
+        for (i, commit) in range.iter().enumerate() {
+            let Some(wanted) = fixup_target(&subjects[i]) else {
+                continue;
+            };
+            let target = (0..i).rev().find(|&j| {
+                !targets.contains_key(&range[j])
+                    && fixup_target(&subjects[j]).is_none()
+                    && (subjects[j] == wanted || (wanted.len() >= 4 && range[j].starts_with(wanted)))
+            });
+            if let Some(j) = target {
+                targets.insert(commit.clone(), range[j].clone());
+            }
+        }
+        Ok(targets)
+    }
+
+    /// The checkpoints `from` through `to`, and those after `to` up to HEAD,
+    /// each oldest first. Fails unless `to` is on HEAD's line of first
+    /// parents, `from` is behind it, and none of them is a merge.
+    fn squash_plan(&self, from: &str, to: &str) -> Result<(Vec<String>, Vec<String>)> {
+        let root_path = self.root.as_path();
+        let objects_path = gini_dir(root_path).join("objects");
+        let from_hash = resolve_revision(root_path, from)?;
+        let to_hash = resolve_revision(root_path, to)?;
+        let mut current = get_head_commit(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("Nothing to squash: no checkpoints yet"))?;
+        let mut later = Vec::new();
+        let mut range = Vec::new();
+        loop {
+            let details = parse_commit_details(&read_object(&objects_path, &current)?)?;
+            if details.is_merge() {
+                bail!("Cannot squash across merge checkpoint {}", &current[..7]);
+            }
+            if range.is_empty() && current != to_hash {
+                later.push(current.clone());
+            } else {
+                range.push(current.clone());
+                if current == from_hash {
+                    break;
+                }
+            }
+            current = match details.parent() {
+                Some(parent) => parent.to_string(),
+                None if range.is_empty() => bail!("{} is not in HEAD's history", to),
+                None => bail!("{} does not come before {} in HEAD's history", from, to),
+            };
+        }
+        if range.len() < 2 {
+            bail!("Nothing to squash: {}..{} is a single checkpoint", from, to);
+        }
+        range.reverse();
+        later.reverse();
+        Ok((range, later))
+    }
+
+    /// Merges `rev` (usually a branch) into HEAD. When one side's history
+    /// contains the other, HEAD just fast-forwards or nothing happens.
+    /// Otherwise each file is merged against the nearest common ancestor
+    /// (see [`merge_files`]) and a checkpoint with both parents is made.
+    ///
+    /// On conflicts, the merged files are written to the working tree with
+    /// conflict markers where the sides disagree, and no checkpoint is made;
+    /// the next `checkpoint` records the resolution as the merge. Like
+    /// `revert`, this refuses to run over uncommitted changes.
+    pub fn merge(&self, rev: &str) -> Result<MergeOutcome> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        if read_merge_head(root_path)?.is_some() {
+            bail!("A merge is already in progress; resolve it and checkpoint, or `gini reset --hard HEAD`");
+        }
+        let head = get_head_commit(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("Nothing to merge into: no checkpoints yet"))?;
+        let theirs = resolve_revision(root_path, rev)?;
+        if is_ancestor(&objects_path, &theirs, &head)? {
+            return Ok(MergeOutcome::UpToDate);
+        }
+
+        let head_files = head_files(root_path)?;
+        let working = ensure_clean_for(root_path, &head_files, "merge")?;
+        let their_files = flatten_tree(&objects_path, &parse_commit_tree(&read_object(&objects_path, &theirs)?)?)?;
+        if is_ancestor(&objects_path, &head, &theirs)? {
+            ensure_untracked_kept(&head_files, &working, &their_files, "merge")?;
+            update_head(root_path, &theirs, &format!("merge {}: fast-forward", rev))?;
+            checkout_files(root_path, &objects_path, &head_files, &their_files)?;
+            return Ok(MergeOutcome::FastForward(theirs));
+        }
+
+        let base_files = match merge_base(&objects_path, &head, &theirs)? {
+            Some(base) => flatten_tree(&objects_path, &parse_commit_tree(&read_object(&objects_path, &base)?)?)?,
+            None => BTreeMap::new(),
+        };
+        let merged = merge_files(&objects_path, &base_files, &head_files, &their_files, "HEAD", rev)?;
+        ensure_untracked_kept(&head_files, &working, &merged.working, "merge")?;
+        if !merged.conflicts.is_empty() {
+            checkout_files(root_path, &objects_path, &head_files, &merged.working)?;
+            write_merge_head(root_path, Some(&theirs))?;
+            return Ok(MergeOutcome::Conflicts(merged.conflicts));
+        }
+
+        let tree_hash = write_tree_from_files(&objects_path, &merged.files)?;
+        let is_branch = validate_ref_name(rev).is_ok()
+            && read_ref(root_path, &format!("{}/{}", HEADS_DIR, rev))?.is_some();
+        let message = if is_branch {
+            format!("Merge branch '{}'", rev)
+        } else {
+            format!("Merge checkpoint {}", &theirs[..7])
+        };
+        let reason = format!("merge {}: {}", rev, message);
+        let commit_hash = self.write_commit(&tree_hash, &[head, theirs], &message, &reason)?;
+        checkout_files(root_path, &objects_path, &head_files, &merged.files)?;
+        Ok(MergeOutcome::Merged(commit_hash))
+    }
+
+    /// Shelves every change in the working tree, untracked files included,
+    /// onto the stash stack and makes the working tree match HEAD again.
+    /// Staged changes are stashed as plain working-tree changes and the
+    /// index is cleared. Without `message` the stash is named after HEAD.
+    pub fn stash(&self, message: Option<&str>) -> Result<StashEntry> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        finish_interrupted_replace(root_path)?;
+        let head = get_head_commit(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("Cannot stash before the first checkpoint"))?;
+        let head_files = head_files(root_path)?;
+
+        let mut builder = TreeBuilder::new(root_path, true)?;
+        let tree_hash = builder.write_tree(root_path)?;
+        builder.seen.save(root_path)?;
+        let working = flatten_tree(&objects_path, &tree_hash)?;
+        let changes = diff_file_maps(&head_files, &working);
+        if changes.is_empty() {
+            bail!("No local changes to stash");
+        }
+
+        let message = match message {
+            Some(message) => message.to_string(),
+            None => {
+                let details = parse_commit_details(&read_object(&objects_path, &head)?)?;
+                let subject = details.message.lines().next().unwrap_or("").to_string();
+                format!("WIP on {}: {}", &head[..7], subject)
+            }
+        };
+        let commit_hash = self.write_commit_object(&tree_hash, std::slice::from_ref(&head), &message)?;
+        let mut stack = read_stash(root_path)?;
+        stack.insert(0, commit_hash.clone());
+        write_stash(root_path, &stack)?;
+
+        checkout_changes(root_path, &objects_path, &diff_file_maps(&working, &head_files))?;
+        clear_index(root_path)?;
+        self.stash_entry(&commit_hash)
+    }
+
+    /// The stash stack, newest first.
+    pub fn stash_list(&self) -> Result<Vec<StashEntry>> {
+        read_stash(&self.root)?
+            .iter()
+            .map(|hash| self.stash_entry(hash))
+            .collect()
+    }
+
+    /// Applies the newest stash on top of the current working tree and
+    /// drops it from the stack. Each file the stash changed must still be as
+    /// it was when the stash was taken (or already match the stash);
+    /// otherwise nothing is written and the stash is kept.
+    pub fn stash_pop(&self) -> Result<StashEntry> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let mut stack = read_stash(root_path)?;
+        let Some(top) = stack.first().cloned() else {
+            bail!("No stash entries to pop");
+        };
+        if load_index(root_path)?.is_some() {
+            bail!("Cannot pop a stash with staged changes; checkpoint or reset them first");
+        }
+        let entry = self.stash_entry(&top)?;
+        let stashed = flatten_tree(&objects_path, &parse_commit_tree(&read_object(&objects_path, &top)?)?)?;
+        let base = flatten_tree(&objects_path, &parse_commit_tree(&read_object(&objects_path, &entry.base)?)?)?;
+        let working = scan_working_tree(root_path, true)?;
+
+        let mut changes = Vec::new();
+        let mut conflicts = Vec::new();
+        for change in diff_file_maps(&base, &stashed) {
+            let current = working.get(&change.path);
+            if current == change.new.as_ref() {
+                continue; // already applied
+            }
+            if current != change.old.as_ref() {
+                conflicts.push(change.display_path());
+                continue;
+            }
+            changes.push(FileChange { old: current.cloned(), ..change });
+        }
+        if !conflicts.is_empty() {
+            bail!(
+                "Cannot pop the stash: these files changed since it was taken:\n  {}",
+                conflicts.join("\n  ")
+            );
+        }
+        checkout_changes(root_path, &objects_path, &changes)?;
+        stack.remove(0);
+        write_stash(root_path, &stack)?;
+        Ok(entry)
+    }
+
+    /// Deletes the newest stash without applying it.
+    pub fn stash_drop(&self) -> Result<StashEntry> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let mut stack = read_stash(root_path)?;
+        let Some(top) = stack.first().cloned() else {
+            bail!("No stash entries to drop");
+        };
+        let entry = self.stash_entry(&top)?;
+        stack.remove(0);
+        write_stash(root_path, &stack)?;
+        Ok(entry)
+    }
+
+    fn stash_entry(&self, hash: &str) -> Result<StashEntry> {
+        let details = parse_commit_details(&read_object(&gini_dir(&self.root).join("objects"), hash)?)?;
+        let base = details
+            .parents
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Stash {} has no base checkpoint", &hash[..7]))?;
+        Ok(StashEntry {
+            hash: hash.to_string(),
+            base,
+            message: details.message,
+            timestamp: details.timestamp,
+        })
+    }
+
+    /// The files in the working tree that neither HEAD nor the index
+    /// tracks, sorted. Ignored files are never listed. Empty directories
+    /// have a trailing `/`.
+    pub fn untracked_files(&self) -> Result<Vec<String>> {
+        Ok(self
+            .untracked_entries()?
+            .iter()
+            .map(|(path, file)| format!("{}{}", path, dir_suffix(file)))
+            .collect())
+    }
+
+    /// Deletes the files [`Repository::untracked_files`] lists, along with
+    /// directories that leaves empty, after taking a backup. Returns what
+    /// was removed.
+    pub fn clean(&self) -> Result<Vec<String>> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let untracked = self.untracked_entries()?;
+        if untracked.is_empty() {
+            return Ok(Vec::new());
+        }
+        create_backup(root_path, Some("Before clean"))?;
+        checkout_files(root_path, &gini_dir(root_path).join("objects"), &untracked, &BTreeMap::new())?;
+        Ok(untracked
+            .iter()
+            .map(|(path, file)| format!("{}{}", path, dir_suffix(file)))
+            .collect())
+    }
+
+    fn untracked_entries(&self) -> Result<BTreeMap<String, FileEntry>> {
+        let head = head_files(&self.root)?;
+        let index = load_index(&self.root)?.unwrap_or_default();
+        Ok(scan_working_tree(&self.root, true)?
+            .into_iter()
+            .filter(|(path, _)| !head.contains_key(path) && !index.contains_key(path))
+            .collect())
+    }
+
+    /// Compares HEAD, the staging index, and the working tree. Files whose
+    /// size and mtime match `.gini/stat-cache` aren't re-read.
+    pub fn status(&self) -> Result<Status> {
+        self.status_with_cache(true)
+    }
+
+    /// Like [`Repository::status`], but hashes every file instead of
+    /// trusting the stat cache.
+    pub fn status_uncached(&self) -> Result<Status> {
+        self.status_with_cache(false)
+    }
+
+    fn status_with_cache(&self, use_cache: bool) -> Result<Status> {
+        let root_path = self.root.as_path();
+        let head = head_files(root_path)?;
+        let working = scan_working_tree(root_path, use_cache)?;
+        let summarize = |changes: Vec<FileChange>| -> Vec<(char, String)> {
+            detect_renames(changes)
+                .into_iter()
+                .map(|c| (c.kind.letter(), c.display_path()))
+                .collect()
+        };
+
+        match load_index(root_path)? {
+            Some(index) => {
+                let untracked = working
+                    .keys()
+                    .filter(|p| !index.contains_key(*p) && !head.contains_key(*p))
+                    .cloned()
+                    .collect();
+                let tracked_working: BTreeMap<String, FileEntry> = working
+                    .into_iter()
+                    .filter(|(p, _)| index.contains_key(p) || head.contains_key(p))
+                    .collect();
+                Ok(Status {
+                    has_index: true,
+                    staged: summarize(diff_file_maps(&head, &index)),
+                    unstaged: summarize(diff_file_maps(&index, &tracked_working)),
+                    untracked,
+                })
+            }
+            None => Ok(Status {
+                has_index: false,
+                unstaged: summarize(diff_file_maps(&head, &working)),
+                ..Status::default()
+            }),
+        }
+    }
+
+    /// Renders the history in the human-readable `gini log` format.
+    /// A detached HEAD's checkpoint is marked `(detached)`.
+    pub fn log(&self, options: &LogOptions) -> Result<String> {
+        let detached_at = match head_branch(&self.root)? {
+            Some(_) => None,
+            None => get_head_commit(&self.root)?,
+        };
+        let objects_path = gini_dir(&self.root).join("objects");
+        let now = chrono::Utc::now().timestamp();
+        let mut entries = Vec::new();
+        for (hash, details) in self.walk_history(options)? {
+            let marker = if detached_at.as_deref() == Some(hash.as_str()) { " (detached)" } else { "" };
+            let stat = if options.stat || options.stat_bytes {
+                let tree_hash = parse_commit_tree(&read_object(&objects_path, &hash)?)?;
+                let changes = changes_from_parent(&objects_path, &tree_hash, details.parent())?;
+                Some(match (options.stat, options.stat_bytes) {
+                    (true, true) => {
+                        format!("{} ({})", render_stat(&changes), render_byte_stat(&objects_path, &changes)?)
+                    }
+                    (true, false) => render_stat(&changes),
+                    _ => render_byte_stat(&objects_path, &changes)?,
+                })
+            } else {
+                None
+            };
+            let date = if options.relative_dates {
+                relative_time(details.timestamp, now)
+            } else {
+                format_timestamp(details.timestamp, details.offset)
+            };
+            if options.oneline || options.format.is_some() {
+                let mut entry = match &options.format {
+                    Some(template) => format_commit(template, &hash, &details, &date)?,
+                    None => format!(
+                        "{}{} {}",
+                        &hash[..7],
+                        marker,
+                        details.message.lines().next().unwrap_or("")
+                    ),
+                };
+                if let Some(stat) = stat {
+                    entry.push_str(&format!("\n    {}", stat));
+                }
+                entries.push(entry);
+                continue;
+            }
+            let merge_line = if details.is_merge() {
+                let parents: Vec<&str> = details.parents.iter().map(|parent| &parent[..7]).collect();
+                format!("Merge: {}\n", parents.join(" "))
+            } else {
+                String::new()
+            };
+            let mut entry = format!(
+                "checkpoint {}{}\n{}Author: {}\nDate:   {}\n\n\t{}\n\n",
+                hash, marker, merge_line, details.author, date, details.message
+            );
+            if let Some(stat) = stat {
+                entry.push_str(&format!(" {}\n\n", stat));
+            }
+            entries.push(entry);
+        }
+        if options.graph {
+            let last = entries.len().saturating_sub(1);
+            for (i, entry) in entries.iter_mut().enumerate() {
+                *entry = graph_entry(entry, i == last);
+            }
+        }
+        let one_per_line = options.oneline || options.format.is_some();
+        Ok(if one_per_line { entries.join("\n") } else { entries.concat() })
+    }
+
+    /// How many checkpoints [`Repository::log`] would list with `options`;
+    /// nothing is formatted.
+    pub fn log_count(&self, options: &LogOptions) -> Result<usize> {
+        Ok(self.walk_history(options)?.len())
+    }
+
+    /// Renders the history as a JSON array, newest checkpoint first.
+    pub fn log_json(&self, options: &LogOptions) -> Result<String> {
+        let mut entries = Vec::new();
+        for (hash, details) in self.walk_history(options)? {
+            let parent = details
+                .parent()
+                .map(json_string)
+                .unwrap_or_else(|| "null".to_string());
+            let parents: Vec<String> = details.parents.iter().map(|parent| json_string(parent)).collect();
+            entries.push(format!(
+                "{{\"hash\":{},\"parent\":{},\"parents\":[{}],\"author\":{},\"timestamp\":{},\"message\":{}}}",
+                json_string(&hash),
+                parent,
+                parents.join(","),
+                json_string(&details.author),
+                details.timestamp,
+                json_string(&details.message)
+            ));
+        }
+        Ok(format!("[{}]", entries.join(",")))
+    }
+
+    /// Follows parents from HEAD, keeping the commits that pass the filters
+    /// and stopping once `max_count` have been collected so long histories
+    /// are not read in full. With `all`, every ref's history is read and
+    /// merged by date instead.
+    fn walk_history(&self, options: &LogOptions) -> Result<Vec<(String, CommitDetails)>> {
+        let root_path = self.root.as_path();
+        let mut history = Vec::new();
+        let mut current_commit_hash: Option<String> =
+            if options.all { None } else { get_head_commit(root_path)? };
+        let grep = options
+            .grep
+            .as_deref()
+            .map(|pattern| TextPattern::new(pattern, options.regex, options.ignore_case))
+            .transpose()?;
+
+        let objects_path = gini_dir(root_path).join("objects");
+        let mut follow = options.follow.clone();
+        // Everything `since_commit` already has; the walk stops at the first
+        // of these, even when it reaches them through a merge.
+        let already_seen = match &options.since_commit {
+            Some(_) if options.all => bail!("--since-commit cannot be combined with --all"),
+            Some(rev) => {
+                let boundary = resolve_revision(root_path, rev)?;
+                let is_behind = match &current_commit_hash {
+                    Some(head) => is_ancestor(&objects_path, &boundary, head)?,
+                    None => false,
+                };
+                if !is_behind {
+                    bail!("{} is not an ancestor of HEAD", rev);
+                }
+                ancestors(&objects_path, &boundary)?
+            }
+            None => HashSet::new(),
+        };
+
+        if options.all {
+            if follow.is_some() {
+                bail!("--follow follows a single line of history and cannot be combined with --all");
+            }
+            let mut tips = Vec::new();
+            for tip in all_ref_tips(root_path)? {
+                tips.push(peel_to_commit(&objects_path, &tip)?);
+            }
+            for (hash, details) in commits_by_date(&objects_path, &tips)? {
+                if options.max_count.is_some_and(|max| history.len() >= max) {
+                    break;
+                }
+                if options.matches(&details, grep.as_ref())
+                    && changed_path(&objects_path, &hash, &details, options.path.as_deref())?
+                {
+                    history.push((hash, details));
+                }
+            }
+        }
+
+        while let Some(hash) = current_commit_hash {
+            if options.max_count.is_some_and(|max| history.len() >= max) || already_seen.contains(&hash) {
+                break;
+            }
+            let commit_content = read_object(&objects_path, &hash)?;
+            let details = parse_commit_details(&commit_content)?;
+            current_commit_hash = details.parent().map(str::to_string);
+            // Checked before the other filters so a rename is followed even
+            // when the checkpoint making it is filtered out.
+            let touched = match follow.as_mut() {
+                Some(path) => {
+                    let tree_hash = parse_commit_tree(&commit_content)?;
+                    follow_path(&objects_path, &tree_hash, details.parent(), path)?
+                }
+                None => true,
+            };
+            if touched
+                && options.matches(&details, grep.as_ref())
+                && changed_path(&objects_path, &hash, &details, options.path.as_deref())?
+            {
+                history.push((hash, details));
+            }
+        }
+        if options.reverse {
+            history.reverse();
+        }
+        Ok(history)
+    }
+
+    /// Summarizes each checkpoint, newest first.
+    pub fn get_commit_history(&self) -> Result<Vec<CheckpointSummary>> {
+        self.commit_history(&LogOptions::default())
+    }
+
+    /// The newest checkpoint in HEAD's history made at or before `time` (a
+    /// Unix timestamp), or `None` if they are all later.
+    pub fn checkpoint_at(&self, time: i64) -> Result<Option<CheckpointSummary>> {
+        Ok(self
+            .get_commit_history()?
+            .into_iter()
+            .filter(|commit| commit.timestamp <= time)
+            .max_by_key(|commit| commit.timestamp))
+    }
+
+    /// Summarizes the checkpoints [`Repository::log`] would list with
+    /// `options`, in the same order. Display options are ignored.
+    pub fn commit_history(&self, options: &LogOptions) -> Result<Vec<CheckpointSummary>> {
+        Ok(self
+            .walk_history(options)?
+            .into_iter()
+            .map(|(hash, details)| CheckpointSummary {
+                subject: details.message.lines().next().unwrap_or("").to_string(),
+                hash,
+                author: details.author,
+                timestamp: details.timestamp,
+            })
+            .collect())
+    }
+
+    /// Describes a single checkpoint: its metadata followed by either its file
+    /// listing or, with `patch`, the diff against its parent.
+    pub fn show(&self, hash: &str, name_only: bool, patch: bool) -> Result<String> {
+        let root_path = self.root.as_path();
+        let objects_path = gini_dir(root_path).join("objects");
+        let hash = resolve_revision(root_path, hash)?;
+        let commit_content = read_object(&objects_path, &hash)?;
+        let tree_hash = parse_commit_tree(&commit_content)
+            .with_context(|| format!("{} is not a checkpoint", hash))?;
+        let details = parse_commit_details(&commit_content)?;
+        let files = flatten_tree(&objects_path, &tree_hash)?;
+
+        if name_only {
+            return Ok(files
+                .iter()
+                .map(|(path, file)| format!("{}{}\n", path, dir_suffix(file)))
+                .collect());
+        }
+
+        let mut out = format!(
+            "checkpoint {}\nAuthor: {}\nDate:   {}\n\n",
+            hash,
+            details.author,
+            format_timestamp(details.timestamp, details.offset)
+        );
+        for line in details.message.lines() {
+            out.push_str(&format!("\t{}\n", line));
+        }
+        out.push('\n');
+
+        if patch {
+            let changes = changes_from_parent(&objects_path, &tree_hash, details.parent())?;
+            out.push_str(&render_patch(&objects_path, &changes, false)?);
+        } else {
+            for (path, file) in &files {
+                out.push_str(&format!("{}  {}{}\n", file.hash, path, dir_suffix(file)));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Compares two checkpoints, rendering unified diffs or, with
+    /// `name_status`, one `<letter>\t<path>` line per changed file. With
+    /// `word_diff`, changed lines in text files are compared word by word and
+    /// shown inline as `[-removed-]{+added+}`.
+    pub fn diff(&self, from: &str, to: &str, name_status: bool, word_diff: bool) -> Result<String> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let old_files = flatten_tree(&objects_path, &self.checkpoint_tree(from)?)?;
+        let new_files = flatten_tree(&objects_path, &self.checkpoint_tree(to)?)?;
+        self.render_diff(&old_files, &new_files, name_status, word_diff)
+    }
+
+    /// Like [`Repository::diff`], but compares HEAD with what is staged for
+    /// the next checkpoint. Fails when nothing is staged.
+    pub fn diff_staged(&self, name_status: bool, word_diff: bool) -> Result<String> {
+        let (head, index) = self.staged_sides()?;
+        self.render_diff(&head, &index, name_status, word_diff)
+    }
+
+    fn render_diff(
+        &self,
+        old_files: &BTreeMap<String, FileEntry>,
+        new_files: &BTreeMap<String, FileEntry>,
+        name_status: bool,
+        word_diff: bool,
+    ) -> Result<String> {
+        let changes = detect_renames(diff_file_maps(old_files, new_files));
+        if name_status {
+            return Ok(render_name_status(&changes));
+        }
+        render_patch(&gini_dir(&self.root).join("objects"), &changes, word_diff)
+    }
+
+    /// The lines added from checkpoint `from` to `to` that end in
+    /// whitespace or hold conflict markers. Binary files are skipped.
+    pub fn diff_check(&self, from: &str, to: &str) -> Result<Vec<CheckProblem>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let old_files = flatten_tree(&objects_path, &self.checkpoint_tree(from)?)?;
+        let new_files = flatten_tree(&objects_path, &self.checkpoint_tree(to)?)?;
+        self.check_changes(&old_files, &new_files, |_, file| read_object_raw(&objects_path, &file.hash))
+    }
+
+    /// Like [`Repository::diff_check`], but for the staged changes.
+    pub fn diff_staged_check(&self) -> Result<Vec<CheckProblem>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let (head, index) = self.staged_sides()?;
+        self.check_changes(&head, &index, |_, file| read_object_raw(&objects_path, &file.hash))
+    }
+
+    /// Like [`Repository::diff_check`], but for the changes in the working
+    /// tree since HEAD, as the next checkpoint would record them.
+    pub fn diff_working_check(&self) -> Result<Vec<CheckProblem>> {
+        let head = head_files(&self.root)?;
+        let working = scan_working_tree(&self.root, true)?;
+        self.check_changes(&head, &working, |path, _| Ok(fs::read(self.root.join(path))?))
+    }
+
+    fn check_changes(
+        &self,
+        old_files: &BTreeMap<String, FileEntry>,
+        new_files: &BTreeMap<String, FileEntry>,
+        read_new: impl Fn(&str, &FileEntry) -> Result<Vec<u8>>,
+    ) -> Result<Vec<CheckProblem>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let mut problems = Vec::new();
+        for change in detect_renames(diff_file_maps(old_files, new_files)) {
+            // Links and directories have no lines, and a rename adds none.
+            let Some(new) = change.new.as_ref().filter(|file| file.mode != MODE_SYMLINK && !file.is_empty_dir()) else {
+                continue;
+            };
+            if change.kind == ChangeKind::Renamed {
+                continue;
+            }
+            let old = match change.old.as_ref().filter(|file| file.mode != MODE_SYMLINK && !file.is_empty_dir()) {
+                Some(file) => read_object_raw(&objects_path, &file.hash)?,
+                None => Vec::new(),
+            };
+            let new = read_new(&change.path, new)?;
+            if let (Some(old), Some(new)) = (as_text(&old), as_text(&new)) {
+                problems.extend(check_added_lines(&change.path, old, new));
+            }
+        }
+        Ok(problems)
+    }
+
+    /// HEAD's files and the staged ones, for the `diff_staged` methods.
+    fn staged_sides(&self) -> Result<(BTreeMap<String, FileEntry>, BTreeMap<String, FileEntry>)> {
+        let Some(index) = load_index(&self.root)? else {
+            bail!("Nothing is staged; stage files with `gini add` first, or compare checkpoints with `gini diff FROM TO`");
+        };
+        Ok((head_files(&self.root)?, index))
+    }
+
+    /// The changes from checkpoint `from` to `to` as a patch file for
+    /// `git apply` or `patch -p1`. Binary files are left out and listed in
+    /// [`Patch::binary_files`], and a note at the top of the patch names them.
+    pub fn diff_patch(&self, from: &str, to: &str) -> Result<Patch> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let old_files = flatten_tree(&objects_path, &self.checkpoint_tree(from)?)?;
+        let new_files = flatten_tree(&objects_path, &self.checkpoint_tree(to)?)?;
+        self.render_git_patch(&old_files, &new_files)
+    }
+
+    /// Like [`Repository::diff_patch`], but for the staged changes, as
+    /// [`Repository::diff_staged`] compares them.
+    pub fn diff_staged_patch(&self) -> Result<Patch> {
+        let (head, index) = self.staged_sides()?;
+        self.render_git_patch(&head, &index)
+    }
+
+    fn render_git_patch(
+        &self,
+        old_files: &BTreeMap<String, FileEntry>,
+        new_files: &BTreeMap<String, FileEntry>,
+    ) -> Result<Patch> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let changes = detect_renames(diff_file_maps(old_files, new_files));
+        let (body, binary_files) = render_git_patch(&objects_path, &changes)?;
+        let mut text = String::new();
+        if !binary_files.is_empty() {
+            text.push_str("Binary files left out of this patch:\n");
+            for path in &binary_files {
+                text.push_str(&format!("  {}\n", path));
+            }
+            text.push('\n');
+        }
+        text.push_str(&body);
+        Ok(Patch { text, binary_files })
+    }
+
+    /// Attributes each line of `path` (root-relative) as of checkpoint `rev`
+    /// to the checkpoint that last changed it. `lines` limits the output to
+    /// an inclusive, 1-based range. Costs one diff per checkpoint that
+    /// changed the file, back to where the wanted lines were all added.
+    pub fn blame(&self, path: &str, rev: &str, lines: Option<(usize, usize)>) -> Result<Vec<BlameLine>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let start = resolve_revision(&self.root, rev)?;
+        let wanted = match lines {
+            Some((first, last)) => first.saturating_sub(1)..last,
+            None => 0..usize::MAX,
+        };
+        let first = wanted.start + 1;
+        let mut authors: HashMap<String, String> = HashMap::new();
+        let mut blamed = Vec::new();
+        for (i, (commit, text)) in blame_file(&objects_path, &start, path, wanted)?.into_iter().enumerate() {
+            let author = match authors.get(&commit) {
+                Some(author) => author.clone(),
+                None => {
+                    let author = parse_commit_details(&read_object(&objects_path, &commit)?)?.author;
+                    authors.insert(commit.clone(), author.clone());
+                    author
+                }
+            };
+            blamed.push(BlameLine { commit, author, number: first + i, text });
+        }
+        if blamed.is_empty() && lines.is_some() {
+            bail!("{} has fewer than {} lines", path, first);
+        }
+        Ok(blamed)
+    }
+
+    /// Lists what checkpoint `rev` changed relative to its parent, one
+    /// `<letter>\t<path>` line per file as in [`Repository::diff`] with
+    /// `name_status`. A checkpoint without a parent lists every file as added.
+    pub fn what_changed(&self, rev: &str) -> Result<String> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let hash = resolve_revision(&self.root, rev)?;
+        let commit_content = read_object(&objects_path, &hash)?;
+        let tree_hash = parse_commit_tree(&commit_content)
+            .with_context(|| format!("{} is not a checkpoint", hash))?;
+        let details = parse_commit_details(&commit_content)?;
+        let changes = changes_from_parent(&objects_path, &tree_hash, details.parent())?;
+        Ok(render_name_status(&changes))
+    }
+
+    /// The newest checkpoint in HEAD's history that changed `path`
+    /// (repository-relative; a file or a directory): added it, edited it,
+    /// or deleted it, compared with its first parent. `None` if no
+    /// checkpoint ever had it. The walk stops at the first match.
+    pub fn last_change(&self, path: &str) -> Result<Option<CheckpointSummary>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        // A checkpoint's details and what it holds at `path`.
+        let load = |hash: &str| -> Result<(CommitDetails, Option<(String, u32)>)> {
+            let content = read_object(&objects_path, hash)?;
+            let entry = find_tree_entry(&objects_path, &parse_commit_tree(&content)?, path)?;
+            Ok((parse_commit_details(&content)?, entry.map(|entry| (entry.hash, entry.mode))))
+        };
+        let Some(mut hash) = get_head_commit(&self.root)? else {
+            return Ok(None);
+        };
+        let (mut details, mut entry) = load(&hash)?;
+        loop {
+            let parent = details.parent().map(str::to_string);
+            let (parent_details, parent_entry) = match &parent {
+                Some(parent) => {
+                    let (parent_details, parent_entry) = load(parent)?;
+                    (Some(parent_details), parent_entry)
+                }
+                None => (None, None),
+            };
+            if entry != parent_entry {
+                return Ok(Some(CheckpointSummary {
+                    subject: details.message.lines().next().unwrap_or("").to_string(),
+                    hash,
+                    author: details.author,
+                    timestamp: details.timestamp,
+                }));
+            }
+            let (Some(parent), Some(parent_details)) = (parent, parent_details) else {
+                return Ok(None);
+            };
+            (hash, details, entry) = (parent, parent_details, parent_entry);
+        }
+    }
+
+    /// Re-hashes every file in the working tree, storing nothing, and
+    /// compares the result with checkpoint `rev`'s tree. Returns the files
+    /// that differ as `(letter, path)` pairs, `A` for one the checkpoint
+    /// doesn't have, `D` for one missing from the working tree (ignore
+    /// rules included) and `M` for different content or mode; none when
+    /// the working tree matches exactly.
+    pub fn verify_restore(&self, rev: &str) -> Result<Vec<(char, String)>> {
+        let tree_hash = self.checkpoint_tree(rev)?;
+        let mut builder = TreeBuilder::new(&self.root, false)?;
+        builder.write_objects = false;
+        if builder.write_tree(&self.root)? == tree_hash {
+            return Ok(Vec::new());
+        }
+        let expected = flatten_tree(&gini_dir(&self.root).join("objects"), &tree_hash)?;
+        Ok(diff_file_maps(&expected, &builder.files)
+            .into_iter()
+            .map(|change| (change.kind.letter(), change.display_path()))
+            .collect())
+    }
+
+    /// Adds up the distinct versions `path` (repository-relative; a file or
+    /// a directory, whose files all count) has had in every checkpoint any
+    /// branch, tag, HEAD or the stash reaches. Each blob is counted once
+    /// however many checkpoints share it, so this is what the path's
+    /// history really costs in the object store.
+    pub fn history_size(&self, path: &str) -> Result<HistorySize> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let mut pending = Vec::new();
+        for tip in all_ref_tips(&self.root)? {
+            pending.push(peel_to_commit(&objects_path, &tip)?);
+        }
+        let mut size = HistorySize::default();
+        let (mut seen, mut trees, mut blobs) = (HashSet::new(), HashSet::new(), HashSet::new());
+        while let Some(hash) = pending.pop() {
+            if !seen.insert(hash.clone()) {
+                continue;
+            }
+            let content = read_object(&objects_path, &hash)?;
+            match find_tree_entry(&objects_path, &parse_commit_tree(&content)?, path)? {
+                Some(entry) if entry.kind == EntryKind::Tree => {
+                    size.checkpoints += 1;
+                    collect_blobs(&objects_path, &entry.hash, &mut trees, &mut blobs)?;
+                }
+                Some(entry) => {
+                    size.checkpoints += 1;
+                    blobs.insert(entry.hash);
+                }
+                None => {}
+            }
+            pending.extend(parse_commit_details(&content)?.parents);
+        }
+        size.versions = blobs.len();
+        for blob in &blobs {
+            size.bytes += read_object_raw(&objects_path, blob)?.len() as u64;
+        }
+        Ok(size)
+    }
+
+    /// Whether restores and checkouts back up the working tree first
+    /// (`core.backupOnRestore`).
+    pub fn backs_up_restores(&self) -> Result<bool> {
+        Config::load(&self.root)?.backup_on_restore()
+    }
+
+    /// Whether restores should be checked with
+    /// [`Repository::verify_restore`] afterwards (`restore.verify`).
+    pub fn verifies_restores(&self) -> Result<bool> {
+        Config::load(&self.root)?.restore_verify()
+    }
+
+    /// A single hash identifying the exact contents of HEAD's tree or, with
+    /// `working`, of the files in the working tree right now, honoring the
+    /// ignore rules but not the staging area (nothing is stored). Trees are content-addressed, so two
+    /// repositories whose files are identical get the same fingerprint.
+    pub fn fingerprint(&self, working: bool) -> Result<String> {
+        if working {
+            hash_working_tree(&self.root)
+        } else {
+            self.checkpoint_tree("HEAD")
+        }
+    }
+
+    /// Resolves a revision to its checkpoint's root tree.
+    fn checkpoint_tree(&self, rev: &str) -> Result<String> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let hash = resolve_revision(&self.root, rev)?;
+        parse_commit_tree(&read_object(&objects_path, &hash)?)
+            .with_context(|| format!("{} is not a checkpoint", hash))
+    }
+
+    /// Resolves a revision (`HEAD`, a tag name, or a possibly abbreviated
+    /// hash) to a full commit hash.
+    pub fn resolve_revision(&self, rev: &str) -> Result<String> {
+        resolve_revision(&self.root, rev)
+    }
+
+    /// Tags the HEAD checkpoint as `name`. With a message, an annotated tag
+    /// object is stored and the ref points at it.
+    pub fn create_tag(&self, name: &str, message: Option<&str>) -> Result<Tag> {
+        validate_ref_name(name)?;
+        let root_path = self.root.as_path();
+        let ref_name = format!("{}/{}", TAGS_DIR, name);
+        if read_ref(root_path, &ref_name)?.is_some() {
+            bail!("Tag already exists: {}", name);
+        }
+        let commit = get_head_commit(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("Nothing to tag: no checkpoints yet"))?;
+
+        let target = match message {
+            Some(message) => {
+                let tag_content = format!(
+                    "object {}\ntype commit\ntag {}\ntagger {}\n\n{}",
+                    commit,
+                    name,
+                    self.signature()?,
+                    message
+                );
+                hash_and_write_object(&gini_dir(root_path).join("objects"), tag_content.as_bytes())?
+            }
+            None => commit.clone(),
+        };
+        let ref_path = gini_dir(root_path).join(&ref_name);
+        fs::create_dir_all(gini_dir(root_path).join(TAGS_DIR))?;
+        write_file_atomic(&ref_path, target.as_bytes())?;
+        Ok(Tag {
+            name: name.to_string(),
+            commit,
+            message: message.map(str::to_string),
+        })
+    }
+
+    /// Lists tags sorted by name.
+    pub fn tags(&self) -> Result<Vec<Tag>> {
+        let root_path = self.root.as_path();
+        let objects_path = gini_dir(root_path).join("objects");
+        let tags_path = gini_dir(root_path).join(TAGS_DIR);
+        let mut tags = Vec::new();
+        if !tags_path.is_dir() {
+            return Ok(tags);
+        }
+        for entry in fs::read_dir(&tags_path)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if validate_ref_name(&name).is_err() {
+                continue;
+            }
+            let Some(target) = read_ref(root_path, &format!("{}/{}", TAGS_DIR, name))? else {
+                continue;
+            };
+            let content = read_object(&objects_path, &target)?;
+            let (commit, message) = match parse_tag(&content) {
+                Some(tag) => (tag.object, Some(tag.message)),
+                None => (target, None),
+            };
+            tags.push(Tag {
+                name,
+                commit,
+                message,
+            });
+        }
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    /// Deletes a tag ref. An annotated tag's object is left for `gc`.
+    pub fn delete_tag(&self, name: &str) -> Result<()> {
+        validate_ref_name(name)?;
+        let ref_path = gini_dir(&self.root).join(TAGS_DIR).join(name);
+        if !ref_path.is_file() {
+            bail!("Tag not found: {}", name);
+        }
+        fs::remove_file(&ref_path).with_context(|| format!("Failed to delete tag: {}", name))
+    }
+
+    /// Creates branch `name` at the HEAD checkpoint. HEAD stays where it is;
+    /// use [`Repository::checkout`] to switch to the new branch.
+    pub fn create_branch(&self, name: &str) -> Result<Branch> {
+        validate_ref_name(name)?;
+        let root_path = self.root.as_path();
+        let ref_name = format!("{}/{}", HEADS_DIR, name);
+        if read_ref(root_path, &ref_name)?.is_some() {
+            bail!("Branch already exists: {}", name);
+        }
+        let commit = get_head_commit(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("Nothing to branch from: no checkpoints yet"))?;
+        fs::create_dir_all(gini_dir(root_path).join(HEADS_DIR))?;
+        write_file_atomic(&gini_dir(root_path).join(&ref_name), commit.as_bytes())?;
+        Ok(Branch {
+            name: name.to_string(),
+            commit,
+            current: false,
+        })
+    }
+
+    /// Lists branches sorted by name. A branch with no checkpoints yet (as
+    /// in a new project) has no ref and is not listed.
+    pub fn branches(&self) -> Result<Vec<Branch>> {
+        let root_path = self.root.as_path();
+        let heads_path = gini_dir(root_path).join(HEADS_DIR);
+        let current = head_branch(root_path)?;
+        let mut branches = Vec::new();
+        if !heads_path.is_dir() {
+            return Ok(branches);
+        }
+        for entry in fs::read_dir(&heads_path)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if validate_ref_name(&name).is_err() {
+                continue;
+            }
+            let Some(commit) = read_ref(root_path, &format!("{}/{}", HEADS_DIR, name))? else {
+                continue;
+            };
+            branches.push(Branch {
+                current: current.as_deref() == Some(name.as_str()),
+                name,
+                commit,
+            });
+        }
+        branches.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(branches)
+    }
+
+    /// Deletes a branch ref. The branch HEAD points at can't be deleted.
+    /// Checkpoints only it reached are left for `gc`.
+    pub fn delete_branch(&self, name: &str) -> Result<()> {
+        validate_ref_name(name)?;
+        if head_branch(&self.root)?.as_deref() == Some(name) {
+            bail!("Cannot delete branch {}: it is checked out", name);
+        }
+        let ref_path = gini_dir(&self.root).join(HEADS_DIR).join(name);
+        if !ref_path.is_file() {
+            bail!("Branch not found: {}", name);
+        }
+        fs::remove_file(&ref_path).with_context(|| format!("Failed to delete branch: {}", name))
+    }
+
+    /// Renames branch `old` to `new`, keeping HEAD on it if it was checked
+    /// out.
+    pub fn rename_branch(&self, old: &str, new: &str) -> Result<()> {
+        validate_ref_name(old)?;
+        validate_ref_name(new)?;
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let heads_path = gini_dir(root_path).join(HEADS_DIR);
+        let is_current = head_branch(root_path)?.as_deref() == Some(old);
+        let old_path = heads_path.join(old);
+        // The current branch of a new project has no ref file yet.
+        if !old_path.is_file() && !is_current {
+            bail!("Branch not found: {}", old);
+        }
+        if heads_path.join(new).is_file() {
+            bail!("Branch already exists: {}", new);
+        }
+        if old_path.is_file() {
+            fs::rename(&old_path, heads_path.join(new))
+                .with_context(|| format!("Failed to rename branch: {}", old))?;
+        }
+        if is_current {
+            attach_head(root_path, new, &format!("branch: renamed {} to {}", old, new))?;
+        }
+        Ok(())
+    }
+
+    /// Copies the branches of the gini repository at `path` into this one:
+    /// any objects missing here are copied over, and each branch is recorded
+    /// as `<remote>/<branch>` under `.gini/refs/remotes`. The path is saved
+    /// as `remote.<remote>.path`, so later fetches can leave it out. The
+    /// working tree and local branches are not touched.
+    pub fn fetch(&self, remote: &str, path: Option<&Path>) -> Result<FetchReport> {
+        let _lock = RepoLock::acquire(&self.root)?;
+        self.fetch_locked(remote, path)
+    }
+
+    fn fetch_locked(&self, remote: &str, path: Option<&Path>) -> Result<FetchReport> {
+        let root_path = self.root.as_path();
+        let source = self.remote_root(remote, path, "fetch")?;
+        let source_repo = Repository { root: source.clone() };
+        let branches = source_repo.branches()?;
+        let tips: Vec<String> = branches.iter().map(|branch| branch.commit.clone()).collect();
+        let objects = copy_missing_objects(
+            &gini_dir(&source).join("objects"),
+            &gini_dir(root_path).join("objects"),
+            &tips,
+        )?;
+
+        let remote_dir = gini_dir(root_path).join(REMOTES_DIR).join(remote);
+        fs::create_dir_all(&remote_dir)?;
+        let mut updated = Vec::new();
+        for branch in &branches {
+            let ref_name = format!("{}/{}/{}", REMOTES_DIR, remote, branch.name);
+            let old = read_ref(root_path, &ref_name)?;
+            if old.as_deref() != Some(branch.commit.as_str()) {
+                write_file_atomic(&remote_dir.join(&branch.name), branch.commit.as_bytes())?;
+                updated.push(FetchedBranch {
+                    name: branch.name.clone(),
+                    old,
+                    new: branch.commit.clone(),
+                });
+            }
+        }
+        let mut pruned = Vec::new();
+        for entry in fs::read_dir(&remote_dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if validate_ref_name(&name).is_ok() && !branches.iter().any(|branch| branch.name == name) {
+                fs::remove_file(remote_dir.join(&name))?;
+                pruned.push(name);
+            }
+        }
+        pruned.sort();
+
+        if path.is_some() {
+            self.remember_remote(remote, &source)?;
+        }
+        Ok(FetchReport {
+            remote: remote.to_string(),
+            path: source,
+            objects,
+            updated,
+            pruned,
+        })
+    }
+
+    /// The root of the repository behind `remote`: `path` if given, else the
+    /// path saved for it. Checks that it is a gini repository other than
+    /// this one.
+    fn remote_root(&self, remote: &str, path: Option<&Path>, action: &str) -> Result<PathBuf> {
+        validate_ref_name(remote)?;
+        let root = match path {
+            Some(path) => fs::canonicalize(path)
+                .with_context(|| format!("Cannot access {}", path.display()))?,
+            None => match Config::load(&self.root)?.get(&format!("remote.{}.path", remote)) {
+                Some(saved) => PathBuf::from(saved),
+                None => bail!("No path known for remote '{}'; pass one, e.g. `gini {} <path>`", remote, action),
+            },
+        };
+        if !gini_dir(&root).is_dir() {
+            bail!("{} is not a gini repository", root.display());
+        }
+        if root == self.root {
+            bail!("Cannot {} a repository to or from itself", action);
+        }
+        Ok(root)
+    }
+
+    /// Saves `root` as the path of `remote` in the config.
+    fn remember_remote(&self, remote: &str, root: &Path) -> Result<()> {
+        let mut config = Config::load(&self.root)?;
+        config.set(&format!("remote.{}.path", remote), &root.to_string_lossy())?;
+        config.save(&self.root)
+    }
+
+    /// Sends the current branch to the gini repository at `path` (or the
+    /// path saved for `remote`): the objects it lacks are copied into its
+    /// store, then its branch of the same name is moved to our checkpoint.
+    /// The update must be a fast-forward unless `force` is set. A branch
+    /// checked out there is refused, since its files would no longer match
+    /// its HEAD.
+    pub fn push(&self, remote: &str, path: Option<&Path>, force: bool) -> Result<PushReport> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let target = self.remote_root(remote, path, "push")?;
+        let _target_lock = RepoLock::acquire(&target)
+            .map_err(|e| anyhow::anyhow!("Cannot lock {}: {}", target.display(), e))?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let branch = head_branch(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("Cannot push with a detached HEAD; check out a branch first"))?;
+        let head = get_head_commit(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("Nothing to push: no checkpoints yet"))?;
+
+        let ref_name = format!("{}/{}", HEADS_DIR, branch);
+        let old = read_ref(&target, &ref_name)?;
+        if head_branch(&target)?.as_deref() == Some(branch.as_str()) && old.is_some() {
+            bail!(
+                "Cannot push to {}: it is checked out in {}, whose files would no longer match. \
+                 Detach HEAD there first (`gini restore --detach HEAD`).",
+                branch,
+                target.display()
+            );
+        }
+        if let Some(old) = &old {
+            if !force && !is_ancestor(&objects_path, old, &head)? {
+                bail!(
+                    "Cannot push: {} in {} has checkpoints you don't have. \
+                     Pull them first, or use --force to overwrite them.",
+                    branch,
+                    target.display()
+                );
+            }
+        }
+
+        let objects = copy_missing_objects(&objects_path, &gini_dir(&target).join("objects"), std::slice::from_ref(&head))?;
+        // The fast-forward check above was against `old`; a branch moved
+        // since then by something that ignores the lock is left alone.
+        update_ref_checked(&target, &ref_name, old.as_deref(), &head)?;
+
+        let remote_dir = gini_dir(root_path).join(REMOTES_DIR).join(remote);
+        fs::create_dir_all(&remote_dir)?;
+        write_file_atomic(&remote_dir.join(&branch), head.as_bytes())?;
+        if path.is_some() {
+            self.remember_remote(remote, &target)?;
+        }
+        Ok(PushReport {
+            remote: remote.to_string(),
+            path: target,
+            branch,
+            objects,
+            old,
+            new: head,
+        })
+    }
+
+    /// Fetches from the remote, then fast-forwards the current branch to the
+    /// remote branch of the same name and updates the working tree to match.
+    /// Fails, leaving the branch alone, if the two have diverged, since
+    /// merging is not supported, or if uncommitted changes are in the way.
+    pub fn pull(&self, remote: &str, path: Option<&Path>) -> Result<PullReport> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let fetch = self.fetch_locked(remote, path)?;
+        let branch = head_branch(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("Cannot pull with a detached HEAD; check out a branch first"))?;
+        let target = read_ref(root_path, &format!("{}/{}/{}", REMOTES_DIR, remote, branch))?
+            .ok_or_else(|| anyhow::anyhow!("Remote '{}' has no branch '{}'", remote, branch))?;
+        let head = get_head_commit(root_path)?;
+        let report = |to: String| PullReport {
+            fetch: fetch.clone(),
+            branch: branch.clone(),
+            from: head.clone(),
+            to,
+        };
+
+        if let Some(head) = &head {
+            if is_ancestor(&objects_path, &target, head)? {
+                return Ok(report(head.clone()));
+            }
+            if !is_ancestor(&objects_path, head, &target)? {
+                bail!(
+                    "Cannot pull: {} and {}/{} have diverged, and merging is not supported yet. \
+                     Restore or branch from one of them to carry on.",
+                    branch,
+                    remote,
+                    branch
+                );
+            }
+        }
+
+        let head_files = head_files(root_path)?;
+        let working = ensure_clean_for(root_path, &head_files, "pull")?;
+        let target_files = flatten_tree(&objects_path, &parse_commit_tree(&read_object(&objects_path, &target)?)?)?;
+        ensure_untracked_kept(&head_files, &working, &target_files, "pull")?;
+        checkout_files(root_path, &objects_path, &head_files, &target_files)?;
+        fs::create_dir_all(gini_dir(root_path).join(HEADS_DIR))?;
+        update_head(root_path, &target, &format!("pull {}: fast-forward", remote))?;
+        Ok(report(target))
+    }
+
+    /// Writes the files of checkpoint `rev` to `out` as a tar archive. Paths
+    /// are relative to the repository root, modes and symlinks are kept, and
+    /// every entry carries the checkpoint's time. The working tree is not
+    /// touched.
+    pub fn export_tar(&self, rev: &str, out: &mut dyn std::io::Write) -> Result<()> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let hash = resolve_revision(&self.root, rev)?;
+        let commit_content = read_object(&objects_path, &hash)?;
+        let tree_hash = parse_commit_tree(&commit_content)?;
+        let timestamp = parse_commit_details(&commit_content)?.timestamp;
+        write_tar(out, &objects_path, &tree_hash, timestamp.max(0) as u64)
+    }
+
+    /// Writes the files of checkpoint `rev` to `out` as a zip archive, dated
+    /// the checkpoint's time in the author's time zone.
+    pub fn export_zip(&self, rev: &str, out: &mut dyn std::io::Write) -> Result<()> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let hash = resolve_revision(&self.root, rev)?;
+        let commit_content = read_object(&objects_path, &hash)?;
+        let tree_hash = parse_commit_tree(&commit_content)?;
+        let details = parse_commit_details(&commit_content)?;
+        let local_time = details.timestamp + details.offset.unwrap_or(0) as i64;
+        write_zip(out, &objects_path, &tree_hash, local_time)
+    }
+
+    /// Writes the history of every branch to `out` as a `git fast-import`
+    /// stream (see [`write_fast_import`]), with tags pointing at the same
+    /// checkpoints. The current branch is written first. Returns how many
+    /// checkpoints were exported.
+    pub fn export_git(&self, out: &mut dyn std::io::Write) -> Result<usize> {
+        let root_path = self.root.as_path();
+        let objects_path = gini_dir(root_path).join("objects");
+        let current = head_branch(root_path)?;
+        let mut branches = Vec::new();
+        let mut tags = Vec::new();
+        for (name, tip) in named_ref_tips(root_path)? {
+            if let Some(branch) = name.strip_prefix("refs/heads/") {
+                branches.push((branch.to_string(), tip));
+            } else if let Some(tag) = name.strip_prefix("refs/tags/") {
+                tags.push((tag.to_string(), peel_to_commit(&objects_path, &tip)?));
+            }
+        }
+        branches.sort_by_key(|(name, _)| Some(name) != current.as_ref());
+        if branches.is_empty() {
+            bail!("Nothing to export: no checkpoints yet");
+        }
+        write_fast_import(out, &objects_path, &branches, &tags)
+    }
+
+    /// Writes every checkpoint in the history into a folder of its own under
+    /// `output`, named `<short-hash>-<subject>`, and returns the folder names,
+    /// newest first. `output` must be new or empty and outside the working
+    /// tree; nothing in the repository is changed.
+    pub fn archive_history(&self, output: &Path) -> Result<Vec<String>> {
+        let created = !output.exists();
+        if !created && fs::read_dir(output)?.next().is_some() {
+            bail!("Output {} already exists and is not empty", output.display());
+        }
+        fs::create_dir_all(output).with_context(|| format!("Failed to create {}", output.display()))?;
+        let output = fs::canonicalize(output)?;
+        if output.starts_with(&self.root) {
+            if created {
+                let _ = fs::remove_dir(&output);
+            }
+            bail!("Output {} is inside the working tree", output.display());
+        }
+
+        let objects_path = gini_dir(&self.root).join("objects");
+        let autocrlf = Config::load(&self.root)?.autocrlf()?;
+        let mut folders = Vec::new();
+        for checkpoint in self.get_commit_history()? {
+            let slug = slug(&checkpoint.subject);
+            let name = if slug.is_empty() {
+                checkpoint.hash[..7].to_string()
+            } else {
+                format!("{}-{}", &checkpoint.hash[..7], slug)
+            };
+            let target = output.join(&name);
+            fs::create_dir(&target).with_context(|| format!("Failed to create {}", target.display()))?;
+            let tree_hash = parse_commit_tree(&read_object(&objects_path, &checkpoint.hash)?)?;
+            restore_tree(&target, &objects_path, &tree_hash, autocrlf)?;
+            folders.push(name);
+        }
+        Ok(folders)
+    }
+
+    /// Every ref under `.gini/refs` as `(hash, name)`, such as
+    /// `refs/heads/main`, sorted by name. Tags are listed as stored, so an
+    /// annotated tag shows its tag object. Without a filter, HEAD comes
+    /// first, resolved to its checkpoint; `heads` and `tags` limit the list
+    /// to branches, tags, or both, and leave HEAD out.
+    pub fn show_ref(&self, heads: bool, tags: bool) -> Result<Vec<(String, String)>> {
+        let filtered = heads || tags;
+        let wanted = |name: &str| {
+            !filtered
+                || (heads && name.starts_with(&format!("{}/", HEADS_DIR)))
+                || (tags && name.starts_with(&format!("{}/", TAGS_DIR)))
+        };
+        let tips = named_ref_tips(&self.root)?;
+        let mut refs: Vec<(String, String)> = tips
+            .iter()
+            .filter(|(name, _)| name == "HEAD" && !filtered)
+            .map(|(name, hash)| (hash.clone(), name.clone()))
+            .collect();
+        refs.extend(
+            tips.into_iter()
+                .filter(|(name, _)| name.starts_with("refs/") && wanted(name))
+                .map(|(name, hash)| (hash, name)),
+        );
+        Ok(refs)
+    }
+
+    /// Lists the files recorded in HEAD's tree, sorted by path, one per
+    /// line, optionally followed by each blob's hash.
+    pub fn ls_files(&self, with_hash: bool) -> Result<String> {
+        Ok(head_files(&self.root)?
+            .iter()
+            .map(|(path, file)| {
+                if with_hash {
+                    format!("{}{} {}\n", path, dir_suffix(file), file.hash)
+                } else {
+                    format!("{}{}\n", path, dir_suffix(file))
+                }
+            })
+            .collect())
+    }
+
+    /// Draws checkpoint `rev`'s files as an indented tree, like the `tree`
+    /// command: entries in the order the tree stores them, directories with
+    /// a trailing `/`, symlinks as `name -> target`, and a count of
+    /// directories and files at the end. `sizes` and
+    /// `hashes` put each file's size in bytes and short hash in brackets
+    /// before its name.
+    pub fn tree_listing(&self, rev: &str, sizes: bool, hashes: bool) -> Result<String> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let hash = resolve_revision(&self.root, rev)?;
+        let mut out = format!("{}\n", &hash[..7]);
+        let mut counts = (0, 0);
+        let columns = (sizes, hashes);
+        draw_tree(&objects_path, &self.checkpoint_tree(&hash)?, "", columns, &mut out, &mut counts)?;
+        let plural = |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+        out.push_str(&format!(
+            "\n{}, {}\n",
+            plural(counts.0, "directory", "directories"),
+            plural(counts.1, "file", "files")
+        ));
+        Ok(out)
+    }
+
+    /// Reads an object's raw content by (possibly abbreviated) hash.
+    pub fn cat_file(&self, hash: &str) -> Result<Vec<u8>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        read_object_raw(&objects_path, &resolve_hash(&objects_path, hash)?)
+    }
+
+    /// Parses the commit `rev` names into its fields, checking first that
+    /// it is a well-formed commit: a tree, valid parents, and an author
+    /// with a timestamp.
+    pub fn cat_commit(&self, rev: &str) -> Result<CommitObject> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let hash = resolve_revision(&self.root, rev)?;
+        let content = read_object_raw(&objects_path, &hash)?;
+        let text = match std::str::from_utf8(&content) {
+            Ok(text) if text.starts_with("tree ") => text,
+            _ => bail!("{} is a {}, not a checkpoint", &hash[..7], classify_object(&content)),
+        };
+        validate_commit(text).map_err(|e| anyhow::anyhow!("{} is a malformed commit: {}", &hash[..7], e))?;
+        let details = parse_commit_details(text)?;
+        Ok(CommitObject {
+            tree: parse_commit_tree(text)?,
+            parents: details.parents,
+            author_name: details.name,
+            author_email: details.email,
+            timestamp: details.timestamp,
+            date: iso_timestamp(details.timestamp, details.offset),
+            message: details.message,
+            hash,
+        })
+    }
+
+    /// Reads the file at `path` (root-relative) as recorded in checkpoint
+    /// `rev`, byte for byte. A symlink gives its target.
+    pub fn file_at(&self, rev: &str, path: &str) -> Result<Vec<u8>> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let hash = resolve_revision(&self.root, rev)?;
+        let tree_hash = self.checkpoint_tree(&hash)?;
+        match find_tree_entry(&objects_path, &tree_hash, path)? {
+            Some(entry) if entry.kind != EntryKind::Tree => read_object_raw(&objects_path, &entry.hash),
+            Some(_) => bail!("{} is a directory in checkpoint {}", path, &hash[..7]),
+            None => bail!("Path '{}' does not exist in checkpoint {}", path, &hash[..7]),
+        }
+    }
+
+    /// Works out what kind of object a hash names.
+    pub fn object_type(&self, hash: &str) -> Result<ObjectType> {
+        Ok(classify_object(&self.cat_file(hash)?))
+    }
+
+    /// Re-hashes a single object and reports whether it still matches the
+    /// name it is stored under.
+    pub fn verify_object(&self, hash: &str) -> Result<ObjectCheck> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let hash = resolve_hash(&objects_path, hash)?;
+        let content = read_object_unchecked(&objects_path, &hash)?;
+        Ok(ObjectCheck {
+            actual: hash_bytes(&objects_path, &content)?,
+            kind: classify_object(&content),
+            hash,
+        })
+    }
+
+    /// Checks one tree and everything beneath it, without re-hashing the
+    /// rest of the store: every tree must be intact and parse, and every
+    /// entry must name an object that exists. Stops at the first problem.
+    /// Given a checkpoint (by hash, or as any revision), checks its tree.
+    pub fn verify_tree(&self, hash: &str) -> Result<TreeCheck> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let hash = match resolve_hash(&objects_path, hash) {
+            Ok(hash) => hash,
+            Err(_) => resolve_revision(&self.root, hash)?,
+        };
+        let content = read_object_unchecked(&objects_path, &hash)?;
+        let tree_hash = match classify_object(&content) {
+            ObjectType::Commit => parse_commit_tree(&String::from_utf8_lossy(&content))?,
+            _ => hash,
+        };
+        let mut check = TreeCheck::default();
+        check.problem = verify_subtree(&objects_path, &tree_hash, "", &mut check)?;
+        check.hash = tree_hash;
+        Ok(check)
+    }
+
+    /// Deletes every object not reachable from a ref, HEAD, a reflog entry
+    /// younger than `gc.reflogExpire`, a backup, or the staging index; older
+    /// reflog entries are dropped. Objects still in the legacy flat layout
+    /// are moved into their shards first.
+    ///
+    /// With `drop_backups`, every backup is deleted before reachability is
+    /// worked out, so the files only a backup held go too. Unreachable loose
+    /// objects written within `keep_unreachable` (or `gc.pruneExpire`)
+    /// survive, so a checkpoint running alongside keeps the objects it has
+    /// written but not yet pointed HEAD at; `prune_before` moves that line
+    /// to a fixed time instead.
+    ///
+    /// With `compress`, every reachable object is then written into a single
+    /// new pack under `.gini/pack`, replacing any older packs (and dropping
+    /// the unreachable objects in them), and the loose copies are deleted.
+    ///
+    /// With `aggressive`, the hollow structure left behind is cleared up
+    /// too: empty directories under `.gini/objects` and `.gini/refs` (but
+    /// not `refs/heads`), empty legacy backups, and reflog entries naming a
+    /// checkpoint that is no longer in the store.
+    pub fn gc(&self, options: &GcOptions) -> Result<GcReport> {
+        let root_path = self.root.as_path();
+        let _lock = RepoLock::acquire(root_path)?;
+        let objects_path = gini_dir(root_path).join("objects");
+        let migrated = migrate_flat_objects(&objects_path)?;
+        if options.verify {
+            let problems = verify_objects(&objects_path, &collect_reachable_objects(root_path)?);
+            if !problems.is_empty() {
+                let mut message = format!("{} reachable object(s) failed verification; nothing was deleted:", problems.len());
+                for problem in &problems {
+                    message.push_str(&format!("\n  {}", problem));
+                }
+                bail!("{}", message);
+            }
+        }
+        let reflog_expired = expire_reflog(root_path, reflog_cutoff(root_path)?)?;
+        let backups_dropped = match options.drop_backups {
+            true => remove_all_backups(root_path)?,
+            false => 0,
+        };
+        let reachable = collect_reachable_objects(root_path)?;
+
+        let mut report = GcReport {
+            migrated,
+            reflog_expired,
+            backups_dropped,
+            ..GcReport::default()
+        };
+        let keep_since = keep_unreachable_since(root_path, options)?;
+        remove_unreachable_objects(&objects_path, &reachable, None, keep_since, &mut report)?;
+        let GcOptions { compress, aggressive, .. } = *options;
+        if aggressive {
+            let gini_path = gini_dir(root_path);
+            report.reflog_expired += retain_reflog(root_path, |entry| object_exists(&objects_path, &entry.new))?;
+            report.empty_dirs += remove_empty_dirs(&objects_path, &[])?;
+            report.empty_dirs += remove_empty_dirs(&gini_path.join("refs"), &[gini_path.join(HEADS_DIR)])?;
+            report.empty_dirs += remove_empty_backups(root_path)?;
+        }
+        if compress {
+            for (hash, length) in list_packed(&objects_path)? {
+                if !reachable.contains(&hash) {
+                    report.bytes += length;
+                    report.removed += 1;
+                }
+            }
+            let mut hashes: Vec<String> = reachable.iter().cloned().collect();
+            hashes.sort();
+            let pack = write_pack(&objects_path, &hashes)?;
+            remove_packs_except(&objects_path, pack.as_deref())?;
+            for (hash, path) in list_objects(&objects_path)? {
+                if reachable.contains(&hash) {
+                    fs::remove_file(&path).with_context(|| format!("Failed to remove object: {}", hash))?;
+                }
+            }
+            report.packed = hashes.len();
+        }
+        if options.verify {
+            let mut problems = verify_objects(&objects_path, &reachable);
+            problems.extend(self.fsck()?);
+            report.verify_problems = Some(problems);
+        }
+        Ok(report)
+    }
+
+    /// Writes every object, packed or loose and reachable or not, into one
+    /// new pack, replacing the old packs, and deletes the loose copies.
+    /// Unlike `gc --compress`, nothing is thrown away. Returns how many
+    /// objects the pack holds.
+    pub fn repack(&self) -> Result<usize> {
+        let _lock = RepoLock::acquire(&self.root)?;
+        let objects_path = gini_dir(&self.root).join("objects");
+        migrate_flat_objects(&objects_path)?;
+        let loose = list_objects(&objects_path)?;
+        let mut hashes: BTreeSet<String> = loose.iter().map(|(hash, _)| hash.clone()).collect();
+        hashes.extend(list_packed(&objects_path)?.into_iter().map(|(hash, _)| hash));
+        let hashes: Vec<String> = hashes.into_iter().collect();
+        let pack = write_pack(&objects_path, &hashes)?;
+        remove_packs_except(&objects_path, pack.as_deref())?;
+        for (hash, path) in loose {
+            fs::remove_file(&path).with_context(|| format!("Failed to remove object: {}", hash))?;
+        }
+        Ok(hashes.len())
+    }
+
+    /// Undoes packing: every packed object is written back as a loose
+    /// object, checked against its hash, and the packs are deleted. Returns
+    /// how many objects were unpacked.
+    pub fn unpack(&self) -> Result<usize> {
+        let _lock = RepoLock::acquire(&self.root)?;
+        unpack_all(&gini_dir(&self.root).join("objects"))
+    }
+
+    /// Measures how much disk the `.gini` directory uses and where it goes.
+    /// Read-only.
+    pub fn storage_usage(&self) -> Result<StorageReport> {
+        let gini_path = gini_dir(&self.root);
+        let reachable = collect_reachable_objects(&self.root)?;
+        let mut report = StorageReport::default();
+        let objects_path = gini_path.join("objects");
+        let mut sizes = Vec::new();
+        for (hash, path) in list_objects(&objects_path)? {
+            sizes.push((hash, fs::metadata(&path)?.len()));
+        }
+        sizes.extend(list_packed(&objects_path)?);
+        for (hash, size) in sizes {
+            if reachable.contains(&hash) {
+                report.reachable_objects += 1;
+                report.reachable_bytes += size;
+            } else {
+                report.unreachable_objects += 1;
+                report.unreachable_bytes += size;
+            }
+        }
+
+        let backups = list_backups(&self.root)?;
+        report.backups = backups.len();
+        report.backup_bytes = disk_usage(&gini_path.join("backups"))?;
+        report.ref_bytes = disk_usage(&gini_path.join("HEAD"))? + disk_usage(&gini_path.join("refs"))?;
+        let counted = report.reachable_bytes
+            + report.unreachable_bytes
+            + report.backup_bytes
+            + report.ref_bytes;
+        report.other_bytes = disk_usage(&gini_path)?.saturating_sub(counted);
+        Ok(report)
+    }
+
+    /// Lists every object in the store, sorted by hash, with its type, size
+    /// and whether it is reachable. Reads every object, so it is meant for
+    /// debugging rather than everyday use.
+    pub fn objects(&self) -> Result<Vec<ObjectInfo>> {
+        let reachable = reachable_objects_by_source(&self.root)?;
+        let objects_path = gini_dir(&self.root).join("objects");
+        let mut hashes: BTreeSet<String> =
+            list_objects(&objects_path)?.into_iter().map(|(hash, _)| hash).collect();
+        hashes.extend(list_packed(&objects_path)?.into_iter().map(|(hash, _)| hash));
+        let mut objects = Vec::new();
+        for hash in hashes {
+            let content = read_object_raw(&objects_path, &hash)?;
+            objects.push(ObjectInfo {
+                kind: classify_object(&content),
+                size: content.len() as u64,
+                reachable: reachable.contains_key(&hash),
+                reached_from: reachable.get(&hash).cloned(),
+                hash,
+            });
+        }
+        objects.sort_by(|a, b| a.hash.cmp(&b.hash));
+        Ok(objects)
+    }
+
+    /// Counts, across every distinct reachable tree, how many entries point
+    /// at each blob, and how many bytes storing each blob once saves over a
+    /// copy per entry. Read-only; reads every reachable tree.
+    pub fn dedupe_report(&self) -> Result<DedupeReport> {
+        let objects_path = gini_dir(&self.root).join("objects");
+        let trees = reachable_trees(&self.root)?;
+        let mut counts: HashMap<String, (usize, String)> = HashMap::new();
+        let mut report = DedupeReport {
+            trees: trees.len(),
+            ..DedupeReport::default()
+        };
+        for tree_hash in &trees {
+            for entry in read_tree(&objects_path, tree_hash)? {
+                if entry.kind == EntryKind::Tree {
+                    continue;
+                }
+                report.references += 1;
+                counts.entry(entry.hash).or_insert((0, entry.name)).0 += 1;
+            }
+        }
+        report.blobs = counts.len();
+        for (hash, (references, name)) in counts {
+            let size = read_object_raw(&objects_path, &hash)?.len() as u64;
+            report.stored_bytes += size;
+            report.undeduplicated_bytes += size * references as u64;
+            if references > 1 {
+                report.shared.push(SharedBlob { hash, size, references, name });
+            }
+        }
+        report.shared.sort_by(|a, b| {
+            let saved = |blob: &SharedBlob| blob.size * (blob.references as u64 - 1);
+            saved(b).cmp(&saved(a)).then_with(|| a.hash.cmp(&b.hash))
+        });
+        Ok(report)
+    }
+
+    /// Lists the objects [`Repository::gc`] would delete with `options`,
+    /// sorted by hash, without deleting anything (not even the backups
+    /// `drop_backups` names). Packed objects are only deleted when
+    /// repacking, so they are only listed with `compress`.
+    pub fn gc_dry_run(&self, options: &GcOptions) -> Result<Vec<ObjectInfo>> {
+        let reachable = match options.drop_backups {
+            true => collect_reachable_without_backups(&self.root)?,
+            false => collect_reachable_objects(&self.root)?,
+        };
+        let objects_path = gini_dir(&self.root).join("objects");
+        let keep_since = keep_unreachable_since(&self.root, options)?;
+        let mut hashes = BTreeSet::new();
+        for (hash, path) in list_objects(&objects_path)? {
+            if !modified_since(&path, keep_since) {
+                hashes.insert(hash);
+            }
+        }
+        if options.compress {
+            hashes.extend(list_packed(&objects_path)?.into_iter().map(|(hash, _)| hash));
+        }
+        let mut objects = Vec::new();
+        for hash in hashes.into_iter().filter(|hash| !reachable.contains(hash)) {
+            let content = read_object_raw(&objects_path, &hash)?;
+            objects.push(ObjectInfo {
+                kind: classify_object(&content),
+                size: content.len() as u64,
+                reachable: false,
+                reached_from: None,
+                hash,
+            });
+        }
+        Ok(objects)
+    }
+
+    /// Checks every stored object against its hash and every reachable tree
+    /// for dangling or mistyped entries. Returns a description of each problem.
+    pub fn fsck(&self) -> Result<Vec<String>> {
+        let root_path = self.root.as_path();
+        let objects_path = gini_dir(root_path).join("objects");
+        let mut problems = Vec::new();
+
+        for (name, path) in list_objects(&objects_path)? {
+            let content = fs::read(&path)
+                .with_context(|| format!("Failed to read object: {}", name))?;
+            let actual = hash_bytes(&objects_path, &content)?;
+            if actual != name {
+                problems.push(format!(
+                    "corrupt object {}: expected hash {}, actual {}",
+                    name, name, actual
+                ));
+            }
+        }
+        for (name, _) in list_packed(&objects_path)? {
+            match read_packed(&objects_path, &name) {
+                Ok(Some(content)) => {
+                    let actual = hash_bytes(&objects_path, &content)?;
+                    if actual != name {
+                        problems.push(format!("corrupt packed object {}: actual hash {}", name, actual));
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => problems.push(format!("unreadable packed object {}: {}", name, e)),
+            }
+        }
+
+        let mut checked = HashSet::new();
+        for tip in all_ref_tips(root_path)? {
+            let mut pending = match peel_to_commit(&objects_path, &tip) {
+                Ok(commit) => vec![commit],
+                Err(e) => {
+                    problems.push(format!("bad ref target {}: {}", tip, e));
+                    Vec::new()
+                }
+            };
+            while let Some(hash) = pending.pop() {
+                if !checked.insert(hash.clone()) {
+                    continue;
+                }
+                let commit_content = match read_object(&objects_path, &hash) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        problems.push(format!("bad commit {}: {}", hash, e));
+                        continue;
+                    }
+                };
+                match parse_commit_tree(&commit_content) {
+                    Ok(tree_hash) => {
+                        fsck_tree(&objects_path, &tree_hash, &mut checked, &mut problems)
+                    }
+                    Err(e) => problems.push(format!("bad commit {}: {}", hash, e)),
+                }
+                match parse_commit_details(&commit_content) {
+                    Ok(details) => pending.extend(details.parents),
+                    Err(e) => problems.push(format!("bad commit {}: {}", hash, e)),
+                }
+            }
+        }
+        for (name, path) in list_backups(root_path)? {
+            match read_backup_tree(&path) {
+                Ok(Some(tree_hash)) => fsck_tree(&objects_path, &tree_hash, &mut checked, &mut problems),
+                Ok(None) => {}
+                Err(e) => problems.push(format!("bad backup {}: {}", name, e)),
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Checks that `.gini` has the layout gini expects (`objects`,
+    /// `refs/heads`, a readable HEAD that resolves) and runs
+    /// [`Repository::fsck`]. Read-only unless `fix` is set, in which case
+    /// missing directories and a missing HEAD are recreated; nothing that
+    /// exists is ever changed.
+    pub fn doctor(&self, fix: bool) -> Result<DoctorReport> {
+        let gini_path = gini_dir(&self.root);
+        let _lock = if fix { Some(RepoLock::acquire(&self.root)?) } else { None };
+        let mut report = DoctorReport::default();
+
+        for dir in ["objects", HEADS_DIR] {
+            let path = gini_path.join(dir);
+            if path.is_dir() {
+                continue;
+            }
+            if path.exists() {
+                report.problems.push(format!(".gini/{} is not a directory", dir));
+            } else if fix {
+                fs::create_dir_all(&path).with_context(|| format!("Failed to create .gini/{}", dir))?;
+                report.repairable.push(format!("created missing .gini/{}", dir));
+            } else {
+                report.repairable.push(format!(".gini/{} is missing", dir));
+            }
+        }
+
+        let head_path = gini_path.join("HEAD");
+        if !head_path.exists() {
+            // Point it at the branch `init` would have made, or else any branch.
+            let mut branches: Vec<String> = fs::read_dir(gini_path.join(HEADS_DIR))
+                .map(|entries| {
+                    entries
+                        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                        .collect()
+                })
+                .unwrap_or_default();
+            branches.sort();
+            let default_branch = Config::load(&self.root)?
+                .get("init.defaultBranch")
+                .unwrap_or(DEFAULT_BRANCH)
+                .to_string();
+            let branch = if branches.is_empty() || branches.contains(&default_branch) {
+                default_branch
+            } else {
+                branches.remove(0)
+            };
+            if fix {
+                write_file_atomic(&head_path, format!("ref: {}/{}", HEADS_DIR, branch).as_bytes())
+                    .context("Failed to write HEAD")?;
+                report.repairable.push(format!("created missing HEAD pointing at branch {}", branch));
+            } else {
+                report.repairable.push(format!("HEAD is missing (it would point at branch {})", branch));
+            }
+        }
+
+        if head_path.exists() {
+            match get_head_commit(&self.root) {
+                Ok(Some(head)) if !object_exists(&gini_path.join("objects"), &head) => {
+                    report.problems.push(format!("HEAD points at missing checkpoint {}", head));
+                }
+                Ok(_) => {}
+                Err(e) => report.problems.push(format!("HEAD is unreadable: {}", e)),
+            }
+        }
+        if let Err(e) = Config::load(&self.root) {
+            report.problems.push(format!("config is unreadable: {}", e));
+        }
+        if let Err(e) = load_index(&self.root) {
+            report.problems.push(format!("index is unreadable: {}", e));
+        }
+        if gini_path.join("objects").is_dir() {
+            match self.fsck() {
+                Ok(problems) => report.problems.extend(problems),
+                Err(e) => report.problems.push(format!("fsck failed: {}", e)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Every `key = value` pair in `.gini/config`, sorted by key.
+    pub fn config_values(&self) -> Result<Vec<(String, String)>> {
+        Ok(Config::load(&self.root)?.values.into_iter().collect())
+    }
+
+    /// The value of `key` in `.gini/config`, if set.
+    pub fn get_config(&self, key: &str) -> Result<Option<String>> {
+        Ok(Config::load(&self.root)?.get(key).map(str::to_string))
+    }
+
+    /// Sets a value in `.gini/config`.
+    pub fn set_config(&self, key: &str, value: &str) -> Result<()> {
+        let root_path = self.root.as_path();
+        if key == "user.timezone" && parse_offset(value).is_none() {
+            bail!("Invalid user.timezone: {} (expected an offset like +0100 or -0800)", value);
+        }
+        if key == "core.maxFileSize" && parse_size(value).is_none() {
+            bail!("Invalid core.maxFileSize: {} (expected a size like 500M)", value);
+        }
+        if key == "backup.maxAge" && parse_duration(value).is_none() {
+            bail!("Invalid backup.maxAge: {} (expected a duration like 30d)", value);
+        }
+        if key == "core.maxFiles" && value.parse::<usize>().is_err() {
+            bail!("Invalid core.maxFiles: {} (expected a number of files)", value);
+        }
+        if key == "core.maxTreeSize" && parse_size(value).is_none() {
+            bail!("Invalid core.maxTreeSize: {} (expected a size like 2G)", value);
+        }
+        if key == "gc.pruneExpire" && parse_duration(value).is_none() {
+            bail!("Invalid gc.pruneExpire: {} (expected a duration like 2w)", value);
+        }
+        if key == "gc.reflogExpire" && parse_duration(value).is_none() {
+            bail!("Invalid gc.reflogExpire: {} (expected a duration like 90d)", value);
+        }
+        if key == "core.useGitignore" && parse_bool(value).is_none() {
+            bail!("Invalid core.useGitignore: {} (expected true or false)", value);
+        }
+        if key == "core.excludeLarge" && parse_bool(value).is_none() {
+            bail!("Invalid core.excludeLarge: {} (expected true or false)", value);
+        }
+        if key == "core.autocrlf" && parse_bool(value).is_none() {
+            bail!("Invalid core.autocrlf: {} (expected true or false)", value);
+        }
+        if key == "core.verifyOnRead" && parse_bool(value).is_none() {
+            bail!("Invalid core.verifyOnRead: {} (expected true or false)", value);
+        }
+        if key == "core.backupOnRestore" && parse_bool(value).is_none() {
+            bail!("Invalid core.backupOnRestore: {} (expected true or false)", value);
+        }
+        if key == "restore.verify" && parse_bool(value).is_none() {
+            bail!("Invalid restore.verify: {} (expected true or false)", value);
+        }
+        if key == "core.writeRetries" && value.parse::<u32>().is_err() {
+            bail!("Invalid core.writeRetries: {} (expected a whole number)", value);
+        }
+        if key == "init.defaultBranch" && validate_ref_name(value).is_err() {
+            bail!("Invalid init.defaultBranch: {} (use letters, digits, '.', '_' and '-')", value);
+        }
+        if key == "maintenance.autoGc" && parse_bool(value).is_none() {
+            bail!("Invalid maintenance.autoGc: {} (expected true or false)", value);
+        }
+        if key == "maintenance.autoGcEvery" && !value.parse::<u32>().is_ok_and(|every| every > 0) {
+            bail!("Invalid maintenance.autoGcEvery: {} (expected a positive number)", value);
+        }
+        let objects_path = gini_dir(root_path).join("objects");
+        if key == "core.hashAlgo" {
+            let Some(algo) = HashAlgo::parse(value) else {
+                bail!("Invalid core.hashAlgo: {} (expected sha1 or sha256)", value);
+            };
+            let has_objects =
+                !list_objects(&objects_path)?.is_empty() || !list_packed(&objects_path)?.is_empty();
+            if has_objects && algo != hash_algo(&objects_path)? {
+                bail!("Invalid core.hashAlgo: it can only be changed before the first checkpoint");
+            }
+        }
+        let mut config = Config::load(root_path)?;
+        config.set(key, value)?;
+        config.save(root_path)?;
+        forget_store_settings(&objects_path);
+        Ok(())
+    }
+
+    /// Removes all but the newest `keep` backups (`backup.keep` in the config
+    /// when not given), and any older than `backup.maxAge`. Returns how many
+    /// were deleted.
+    pub fn prune_backups(&self, keep: Option<usize>) -> Result<usize> {
+        let root_path = self.root.as_path();
+        apply_backup_retention(root_path, keep)
+    }
+
+    /// Removes every backup taken before `before` (a Unix timestamp),
+    /// regardless of the retention policy. Returns how many were deleted.
+    pub fn prune_backups_before(&self, before: u64) -> Result<usize> {
+        prune_backups_before(&self.root, before)
+    }
+
+    /// Lists the backups, newest first.
+    pub fn backups(&self) -> Result<Vec<Backup>> {
+        Ok(list_backups(&self.root)?
+            .into_iter()
+            .map(|(name, path)| Backup {
+                message: read_backup_message(&path),
+                label: read_backup_label(&path),
+                head: read_backup_head(&path),
+                name,
+                path,
+            })
+            .collect())
+    }
+
+    /// Finds a backup by its directory name.
+    pub fn backup(&self, name: &str) -> Result<Backup> {
+        self.backups()?
+            .into_iter()
+            .find(|backup| backup.name == name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown backup: {}", name))
+    }
+
+    /// Renames a backup (and its message, label and HEAD sidecars) to
+    /// `new_name`.
+    pub fn move_backup(&self, name: &str, new_name: &str) -> Result<Backup> {
+        let _lock = RepoLock::acquire(&self.root)?;
+        let backup = self.backup(name)?;
+        let path = move_backup(&backup.path, new_name)?;
+        Ok(Backup { name: new_name.to_string(), path, ..backup })
+    }
+
+    /// Labels a backup, replacing any label it had. An empty label removes
+    /// it.
+    pub fn label_backup(&self, name: &str, label: &str) -> Result<()> {
+        let _lock = RepoLock::acquire(&self.root)?;
+        let backup = self.backup(name)?;
+        let label = label.trim();
+        if label.contains('\n') {
+            bail!("A backup label must fit on one line");
+        }
+        label_backup(&backup.path, (!label.is_empty()).then_some(label))
+    }
+
+    /// Lists the files in a backup, one `<size>  <path>` line each.
+    pub fn show_backup(&self, name: &str) -> Result<String> {
+        let backup = self.backup(name)?;
+        Ok(backup_files(&self.root, &backup.path)?
+            .into_iter()
+            .map(|(path, size)| format!("{:>10}  {}\n", size, path))
+            .collect())
+    }
+
+    /// The changes restoring a backup would make to the working tree, as
+    /// `<letter>\t<path>` lines.
+    pub fn diff_backup(&self, name: &str) -> Result<String> {
+        let backup = self.backup(name)?;
+        // Backups keep files byte for byte, so neither `core.autocrlf` nor the
+        // stat cache (whose hashes may be converted ones) may be used here.
+        let mut builder = TreeBuilder::new(&self.root, false)?;
+        builder.write_objects = false;
+        builder.autocrlf = false;
+        builder.max_file_size = u64::MAX;
+        builder.write_tree(&self.root)?;
+        let working = builder.files;
+        let saved = match read_backup_tree(&backup.path)? {
+            // Snapshots hold ignored files too, which a diff leaves out.
+            Some(tree_hash) => {
+                let ignore = IgnoreRules::load(&self.root)?;
+                flatten_tree(&gini_dir(&self.root).join("objects"), &tree_hash)?
+                    .into_iter()
+                    .filter(|(path, entry)| !ignore.is_ignored_path(path, entry.is_empty_dir()))
+                    .collect()
+            }
+            None => {
+                let mut builder = TreeBuilder::new(&backup.path, false)?;
+                builder.write_objects = false;
+                // Hashed the way this repository names its objects.
+                builder.objects_path = gini_dir(&self.root).join("objects");
+                builder.write_tree(&backup.path)?;
+                builder.files
+            }
+        };
+        Ok(render_name_status(&diff_file_maps(&working, &saved)))
+    }
+
+    /// Snapshots the working tree into a new backup without touching
+    /// anything else. Returns the backup's path.
+    pub fn create_backup(&self, message: Option<&str>) -> Result<PathBuf> {
+        let _lock = RepoLock::acquire(&self.root)?;
+        create_backup(&self.root, message)
+    }
+
+    /// Replaces the working tree with the contents of a backup.
+    pub fn restore_backup(&self, backup: &Backup) -> Result<()> {
+        let _lock = RepoLock::acquire(&self.root)?;
+        restore_from_backup(&self.root, &backup.path)
+    }
+
+    /// Rolls back to the newest backup, usually the one the last restore took:
+    /// its files replace the working tree and HEAD moves back to the commit it
+    /// recorded. Returns the backup used, or `None` if there are none.
+    pub fn undo(&self) -> Result<Option<Backup>> {
+        let Some(backup) = self.backups()?.into_iter().next() else {
+            return Ok(None);
+        };
+        let _lock = RepoLock::acquire(&self.root)?;
+        restore_from_backup(&self.root, &backup.path)?;
+        if let Some(head) = &backup.head {
+            update_head(&self.root, head, &format!("undo: back to backup {}", backup.name))?;
+        }
+        Ok(Some(backup))
+    }
+
+    /// Deletes a `.gini/lock` left behind by a gini process that crashed or
+    /// was killed. Returns whether a lock was present.
+    pub fn break_lock(&self) -> Result<bool> {
+        break_lock(&self.root)
+    }
+}
+
+/// The error shown when a restore would delete unsaved files.
+pub fn describe_lost_files(lost: &[String]) -> String {
+    let mut message = format!(
+        "Restoring would delete {} file(s) not saved in any checkpoint:",
+        lost.len()
+    );
+    for path in lost {
+        message.push_str(&format!("\n  {}", path));
+    }
+    message.push_str("\nCheckpoint or move them first, or rerun with --force.");
+    message
+}
+
+/// What a checkpoint with tree `tree_hash` changed relative to `parent`;
+/// without a parent, every file counts as added.
+fn changes_from_parent(objects_path: &Path, tree_hash: &str, parent: Option<&str>) -> Result<Vec<FileChange>> {
+    let parent_files = match parent {
+        Some(parent) => {
+            let parent_tree = parse_commit_tree(&read_object(objects_path, parent)?)?;
+            flatten_tree(objects_path, &parent_tree)?
+        }
+        None => BTreeMap::new(),
+    };
+    let changes = diff_file_maps(&parent_files, &flatten_tree(objects_path, tree_hash)?);
+    Ok(detect_renames(changes))
+}
+
+/// Every commit reachable from `tips`, each once, newest first. Commits
+/// made in the same second keep the order they were found in, so a child
+/// still comes before its parent.
+fn commits_by_date(objects_path: &Path, tips: &[String]) -> Result<Vec<(String, CommitDetails)>> {
+    let mut seen = HashSet::new();
+    let mut commits = Vec::new();
+    for tip in tips {
+        let mut pending = vec![tip.clone()];
+        while let Some(hash) = pending.pop() {
+            if !seen.insert(hash.clone()) {
+                continue;
+            }
+            let details = parse_commit_details(&read_object(objects_path, &hash)?)?;
+            // Pushed in reverse so the first parent is walked first.
+            pending.extend(details.parents.iter().rev().cloned());
+            commits.push((hash, details));
+        }
+    }
+    commits.sort_by_key(|(_, details)| std::cmp::Reverse(details.timestamp));
+    Ok(commits)
+}
+
+/// Fails if a checkpoint would record more than `core.maxFiles` files or
+/// `core.maxTreeSize` bytes.
+fn check_tree_limits(totals: &TreeTotals, max_files: Option<usize>, max_tree_size: Option<u64>) -> Result<()> {
+    let hint = "check your ignore rules, or pass --force to checkpoint anyway";
+    if let Some(max) = max_files.filter(|&max| totals.files > max) {
+        bail!(
+            "This checkpoint would record {} files, more than core.maxFiles ({}); {}",
+            totals.files,
+            max,
+            hint
+        );
+    }
+    if let Some(max) = max_tree_size.filter(|&max| totals.bytes > max) {
+        bail!(
+            "This checkpoint would record {} bytes, more than core.maxTreeSize ({}); {}",
+            totals.bytes,
+            max,
+            hint
+        );
+    }
+    Ok(())
+}
+
+/// The first line of a commit message.
+fn subject(message: &str) -> &str {
+    message.lines().next().unwrap_or("")
+}
+
+/// A checkpoint subject as a folder name: lowercase ASCII letters and
+/// digits, each run of anything else becoming one `-`, cut to 40 characters.
+fn slug(subject: &str) -> String {
+    let mut slug = String::new();
+    for c in subject.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= 40 {
+            break;
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Whether a checkpoint with tree `tree_hash` changed `path` relative to
+/// `parent`. If it renamed the file there, `path` becomes the name in the
+/// parent so the walk keeps following it.
+fn follow_path(objects_path: &Path, tree_hash: &str, parent: Option<&str>, path: &mut String) -> Result<bool> {
+    let (current, previous) = hashes_at_path(objects_path, tree_hash, parent, path)?;
+    if current.is_some() && previous.is_none() {
+        let renamed_from = changes_from_parent(objects_path, tree_hash, parent)?
+            .into_iter()
+            .find(|change| change.kind == ChangeKind::Renamed && change.path == *path)
+            .and_then(|change| change.old_path);
+        if let Some(old_path) = renamed_from {
+            *path = old_path;
+        }
+    }
+    Ok(current != previous)
+}
+
+/// Whether checkpoint `hash` changed anything at or under `path`; always
+/// true without a path.
+fn changed_path(objects_path: &Path, hash: &str, details: &CommitDetails, path: Option<&str>) -> Result<bool> {
+    let Some(path) = path else {
+        return Ok(true);
+    };
+    let tree_hash = parse_commit_tree(&read_object(objects_path, hash)?)?;
+    let (current, previous) = hashes_at_path(objects_path, &tree_hash, details.parent(), path)?;
+    Ok(current != previous)
+}
+
+/// The hash of whatever is at `path` (a file or a whole subtree) in tree
+/// `tree_hash` and in `parent`'s tree. They differ exactly when the
+/// checkpoint changed something there.
+fn hashes_at_path(
+    objects_path: &Path,
+    tree_hash: &str,
+    parent: Option<&str>,
+    path: &str,
+) -> Result<(Option<String>, Option<String>)> {
+    let current = find_tree_entry(objects_path, tree_hash, path)?.map(|entry| entry.hash);
+    let previous = match parent {
+        Some(parent) => {
+            let parent_tree = parse_commit_tree(&read_object(objects_path, parent)?)?;
+            find_tree_entry(objects_path, &parent_tree, path)?.map(|entry| entry.hash)
+        }
+        None => None,
+    };
+    Ok((current, previous))
+}
+
+/// Infers an object's type from whether its content parses, since objects
+/// carry no type header.
+fn classify_object(content: &[u8]) -> ObjectType {
+    let Ok(text) = std::str::from_utf8(content) else {
+        return ObjectType::Blob;
+    };
+    if text.starts_with("tree ") && parse_commit_tree(text).is_ok() {
+        ObjectType::Commit
+    } else if parse_tag(text).is_some() {
+        ObjectType::Tag
+    } else if !text.is_empty() && parse_tree(text).is_ok() {
+        ObjectType::Tree
+    } else {
+        ObjectType::Blob
+    }
+}
+
+/// Appends the entries of one tree to a [`Repository::tree_listing`],
+/// each line starting with `indent`, and counts them into
+/// `(directories, files)`.
+fn draw_tree(
+    objects_path: &Path,
+    tree_hash: &str,
+    indent: &str,
+    (sizes, hashes): (bool, bool),
+    out: &mut String,
+    counts: &mut (usize, usize),
+) -> Result<()> {
+    let entries = read_tree(objects_path, tree_hash)?;
+    let last = entries.len().saturating_sub(1);
+    for (i, entry) in entries.iter().enumerate() {
+        let (branch, below) = if i == last { ("└── ", "    ") } else { ("├── ", "│   ") };
+        out.push_str(indent);
+        out.push_str(branch);
+        if entry.kind == EntryKind::Tree {
+            counts.0 += 1;
+            out.push_str(&format!("{}/\n", entry.name));
+            draw_tree(objects_path, &entry.hash, &format!("{}{}", indent, below), (sizes, hashes), out, counts)?;
+            continue;
+        }
+        counts.1 += 1;
+        let content = read_object_raw(objects_path, &entry.hash)?;
+        let mut columns = Vec::new();
+        if sizes {
+            columns.push(format!("{:>10}", content.len()));
+        }
+        if hashes {
+            columns.push(entry.hash[..7].to_string());
+        }
+        if !columns.is_empty() {
+            out.push_str(&format!("[{}]  ", columns.join("  ")));
+        }
+        match entry.kind {
+            EntryKind::Link => out.push_str(&format!("{} -> {}\n", entry.name, String::from_utf8_lossy(&content))),
+            _ => out.push_str(&format!("{}\n", entry.name)),
+        }
+    }
+    Ok(())
+}
+
+/// Draws the `log --graph` column in front of one entry: `*` on its first
+/// line, then `|` down to the next entry (blank after the last one).
+/// History is linear, so a single column is enough.
+fn graph_entry(entry: &str, last: bool) -> String {
+    let mut out = String::new();
+    for (i, line) in entry.split_inclusive('\n').enumerate() {
+        let (text, newline) = match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        };
+        let column = match (i, last) {
+            (0, _) => "*",
+            (_, true) => " ",
+            (_, false) => "|",
+        };
+        out.push_str(format!("{} {}", column, text).trim_end());
+        out.push_str(newline);
+    }
+    out
+}
+
+/// Writes just the paths in `changes`, taking each from its `old` to its
+/// `new` side, and leaves every other file alone.
+fn checkout_changes(root_path: &Path, objects_path: &Path, changes: &[FileChange]) -> Result<()> {
+    let side = |pick: fn(&FileChange) -> Option<&FileEntry>| -> BTreeMap<String, FileEntry> {
+        changes
+            .iter()
+            .filter_map(|change| pick(change).map(|file| (change.path.clone(), file.clone())))
+            .collect()
+    };
+    let current = side(|change| change.old.as_ref());
+    let target = side(|change| change.new.as_ref());
+    checkout_files(root_path, objects_path, &current, &target)
+}
+
+/// Refuses to `action` (an operation that rewrites tracked files in place)
+/// while anything is staged or a tracked file has uncommitted changes.
+/// Returns the scanned working tree.
+fn ensure_clean_for(
+    root_path: &Path,
+    head_files: &BTreeMap<String, FileEntry>,
+    action: &str,
+) -> Result<BTreeMap<String, FileEntry>> {
+    if load_index(root_path)?.is_some() {
+        bail!("Cannot {} with staged changes; checkpoint or reset them first", action);
+    }
+    let working = scan_working_tree(root_path, true)?;
+    let dirty: Vec<String> = diff_file_maps(head_files, &working)
+        .into_iter()
+        .filter(|change| change.kind != ChangeKind::Added)
+        .map(|change| change.display_path())
+        .collect();
+    if !dirty.is_empty() {
+        bail!("Cannot {} with uncommitted changes to:\n  {}", action, dirty.join("\n  "));
+    }
+    Ok(working)
+}
+
+/// Refuses to `action` when moving from `head_files` to `target` would
+/// write over an untracked file in `working`.
+fn ensure_untracked_kept(
+    head_files: &BTreeMap<String, FileEntry>,
+    working: &BTreeMap<String, FileEntry>,
+    target: &BTreeMap<String, FileEntry>,
+    action: &str,
+) -> Result<()> {
+    let overwritten: Vec<&str> = target
+        .iter()
+        .filter(|(path, file)| {
+            !head_files.contains_key(*path) && working.get(*path).is_some_and(|w| w != *file)
+        })
+        .map(|(path, _)| path.as_str())
+        .collect();
+    if !overwritten.is_empty() {
+        bail!(
+            "Cannot {}: it would overwrite these untracked files:\n  {}",
+            action,
+            overwritten.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+fn fsck_tree(
+    objects_path: &Path,
+    tree_hash: &str,
+    checked: &mut HashSet<String>,
+    problems: &mut Vec<String>,
+) {
+    if !checked.insert(tree_hash.to_string()) {
+        return;
+    }
+    let entries = match read_tree(objects_path, tree_hash) {
+        Ok(entries) => entries,
+        Err(e) => {
+            problems.push(format!("bad tree {}: {}", tree_hash, e));
+            return;
+        }
+    };
+    for entry in entries {
+        match entry.kind {
+            EntryKind::Tree => fsck_tree(objects_path, &entry.hash, checked, problems),
+            EntryKind::Blob | EntryKind::Link => {
+                if !object_exists(objects_path, &entry.hash) {
+                    problems.push(format!(
+                        "missing blob {} (entry '{}' in tree {})",
+                        entry.hash, entry.name, tree_hash
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Checks the tree `tree_hash`, found at `path` (`""` for the top), and the
+/// trees beneath it for [`Repository::verify_tree`]. Returns the first
+/// problem found.
+fn verify_subtree(objects_path: &Path, tree_hash: &str, path: &str, check: &mut TreeCheck) -> Result<Option<String>> {
+    let place = if path.is_empty() { "top-level tree".to_string() } else { format!("{}/", path) };
+    let content = match read_object_unchecked(objects_path, tree_hash) {
+        Ok(content) => content,
+        Err(e) => return Ok(Some(format!("{}: tree {} can't be read: {}", place, tree_hash, e))),
+    };
+    let actual = hash_bytes(objects_path, &content)?;
+    if actual != tree_hash {
+        return Ok(Some(format!("{}: tree {} is corrupt (its content hashes to {})", place, tree_hash, actual)));
+    }
+    let entries = match std::str::from_utf8(&content) {
+        Ok(text) => parse_tree(text),
+        Err(_) => Err(anyhow::anyhow!("not valid UTF-8")),
+    };
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => return Ok(Some(format!("{}: tree {} doesn't parse: {}", place, tree_hash, e))),
+    };
+    check.trees += 1;
+    for entry in entries {
+        let entry_path = if path.is_empty() { entry.name.clone() } else { format!("{}/{}", path, entry.name) };
+        match entry.kind {
+            EntryKind::Tree => {
+                if let Some(problem) = verify_subtree(objects_path, &entry.hash, &entry_path, check)? {
+                    return Ok(Some(problem));
+                }
+            }
+            EntryKind::Blob | EntryKind::Link if !object_exists(objects_path, &entry.hash) => {
+                let kind = if entry.kind == EntryKind::Link { "symlink" } else { "blob" };
+                return Ok(Some(format!(
+                    "{}: {} {} is missing (listed in tree {})",
+                    entry_path, kind, entry.hash, tree_hash
+                )));
+            }
+            EntryKind::Blob | EntryKind::Link => check.files += 1,
+        }
+    }
+    Ok(None)
+}
+
+/// Adds the files under `dir` to `preserved` (as root-relative paths) that
+/// `rules` match, or all of them when `matched` says `dir` itself did.
+fn collect_preserved(dir: &Path, rules: &IgnoreRules, matched: bool, preserved: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if dir == rules.root && path.file_name().is_some_and(|name| name == ".gini" || name == ".git") {
+            continue;
+        }
+        let is_dir = fs::symlink_metadata(&path)?.is_dir();
+        let matched = matched || rules.is_ignored(&path, is_dir);
+        if is_dir {
+            collect_preserved(&path, rules, matched, preserved)?;
+        } else if matched {
+            let relative = path.strip_prefix(&rules.root).unwrap_or(&path);
+            preserved.push(relative.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    Ok(())
+}
+
+/// Copies the preserved file at `relative` from the working tree into the
+/// new tree being assembled in `staging`, over whatever the checkpoint put
+/// there. Symlinks are copied as links.
+fn copy_preserved(root_path: &Path, staging: &Path, relative: &str) -> Result<()> {
+    let from = root_path.join(relative);
+    let to = staging.join(relative);
+    clear_path_for(staging, &to, false)?;
+    if fs::symlink_metadata(&from)?.is_symlink() {
+        create_symlink(fs::read_link(&from)?.as_os_str().as_encoded_bytes(), &to)
+    } else {
+        fs::copy(&from, &to).with_context(|| format!("Failed to keep {}", relative))?;
+        Ok(())
+    }
+}
+
+/// Adds every blob beneath tree `tree_hash` to `blobs`, skipping the
+/// trees already in `trees`.
+fn collect_blobs(
+    objects_path: &Path,
+    tree_hash: &str,
+    trees: &mut HashSet<String>,
+    blobs: &mut HashSet<String>,
+) -> Result<()> {
+    if !trees.insert(tree_hash.to_string()) {
+        return Ok(());
+    }
+    for entry in read_tree(objects_path, tree_hash)? {
+        match entry.kind {
+            EntryKind::Tree => collect_blobs(objects_path, &entry.hash, trees, blobs)?,
+            EntryKind::Blob | EntryKind::Link => {
+                blobs.insert(entry.hash);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Re-hashes each of `hashes`, loose or packed, describing every one that
+/// is missing or doesn't match its name, sorted.
+fn verify_objects(objects_path: &Path, hashes: &HashSet<String>) -> Vec<String> {
+    let mut problems: Vec<String> = hashes
+        .iter()
+        .filter_map(|hash| match read_object_unchecked(objects_path, hash) {
+            Ok(content) => match hash_bytes(objects_path, &content) {
+                Ok(actual) if actual == *hash => None,
+                Ok(actual) => Some(format!("corrupt reachable object {}: actual hash {}", hash, actual)),
+                Err(e) => Some(format!("unreadable reachable object {}: {}", hash, e)),
+            },
+            Err(e) => Some(format!("missing reachable object {}: {}", hash, e)),
+        })
+        .collect();
+    problems.sort();
+    problems
+}
+
+/// From when unreachable objects are spared: [`GcOptions::prune_before`],
+/// or [`GcOptions::keep_unreachable`] (falling back to `gc.pruneExpire`)
+/// before now.
+fn keep_unreachable_since(root_path: &Path, options: &GcOptions) -> Result<SystemTime> {
+    if let Some(timestamp) = options.prune_before {
+        return Ok(UNIX_EPOCH + Duration::from_secs(timestamp.max(0) as u64));
+    }
+    let seconds = match options.keep_unreachable {
+        Some(seconds) => seconds,
+        None => Config::load(root_path)?.prune_expire()?,
+    };
+    Ok(SystemTime::now().checked_sub(Duration::from_secs(seconds)).unwrap_or(UNIX_EPOCH))
+}
+
+/// Whether the file at `path` was last written at or after `since`. A file
+/// whose time can't be read counts as old.
+fn modified_since(path: &Path, since: SystemTime) -> bool {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).is_ok_and(|time| time >= since)
+}
+
+/// Deletes the loose objects not in `reachable`, adding them to `report`.
+/// Objects written since `keep_since` are spared. With a `deadline`, stops
+/// once it passes. Returns whether it got through them all.
+fn remove_unreachable_objects(
+    objects_path: &Path,
+    reachable: &HashSet<String>,
+    deadline: Option<Instant>,
+    keep_since: SystemTime,
+    report: &mut GcReport,
+) -> Result<bool> {
+    for (hash, path) in list_objects(objects_path)? {
+        if reachable.contains(&hash) {
+            continue;
+        }
+        if modified_since(&path, keep_since) {
+            report.spared += 1;
+            continue;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Ok(false);
+        }
+        report.bytes += fs::metadata(&path)?.len();
+        fs::remove_file(&path).with_context(|| format!("Failed to remove object: {}", hash))?;
+        report.removed += 1;
+    }
+    Ok(true)
+}
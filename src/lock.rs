@@ -0,0 +1,52 @@
+//! The repository lock, `.gini/lock`, held while a command mutates the store.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::GiniError;
+use crate::util::gini_dir;
+use crate::LOCK_FILE_NAME;
+
+/// An exclusive hold on `.gini/lock`, released when dropped, including when
+/// the command fails partway.
+#[derive(Debug)]
+pub(crate) struct RepoLock {
+    path: PathBuf,
+}
+
+impl RepoLock {
+    /// Creates the lock file, failing if another process already holds it.
+    pub(crate) fn acquire(root_path: &Path) -> Result<Self> {
+        let path = gini_dir(root_path).join(LOCK_FILE_NAME);
+        let mut file = match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let holder = fs::read_to_string(&path).unwrap_or_default();
+                bail!(GiniError::Locked { pid: holder.trim().to_string() });
+            }
+            Err(e) => return Err(e).context("Failed to create .gini/lock"),
+        };
+        // The pid is informational only; it helps decide if a lock is stale.
+        write!(file, "{}", std::process::id())?;
+        Ok(RepoLock { path })
+    }
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Removes a lock left behind by a process that died. Returns whether there
+/// was one.
+pub(crate) fn break_lock(root_path: &Path) -> Result<bool> {
+    let path = gini_dir(root_path).join(LOCK_FILE_NAME);
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e).context("Failed to remove .gini/lock"),
+    }
+}
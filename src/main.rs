@@ -1,40 +1,38 @@
-/*
-
-[package]
-name = "gini"
-version = "0.2.0"
-edition = "2021"
-author = "Somendra somendra830@gmail.com"
-about = "A simple CLI checkpoint system"
-
-[dependencies]
-clap = { version = "4.4.8", features = ["derive"] }
-anyhow = "1.0.75"
-dialoguer = "0.11.0"
-sha1 = "0.10.6"
-hex = "0.4.3"
-flate2 = "1.0.28"
-chrono = "0.4"
-*/
-
-
-
+//! Command-line front end for the gini checkpoint engine. All repository
+//! logic lives in the library crate; this file only parses arguments,
+//! drives the interactive prompts, and prints results.
 
 use anyhow::{bail, Context, Result};
 use clap::{Parser, Subcommand};
-use sha1::{Digest, Sha1};
-use std::collections::BTreeMap;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, Select};
+use gini::{
+    describe_binary, describe_lost_files, is_gini_dir, parse_duration, relative_time, CheckpointOptions, FetchReport, GcOptions, LogOptions,
+    validate_ref_name, GiniError, MergeOutcome, Progress, Repository, Status, TreeTotals, MAX_COMMIT_MESSAGE_LENGTH,
+};
+use std::collections::HashSet;
 use std::fs;
-use std::io::stdin;
+use std::io::{stdin, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
-use chrono;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Set by `--quiet`.
+static QUIET: AtomicBool = AtomicBool::new(false);
 
-// --- Constants and Configuration ---
+fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
 
-const MAX_FILE_SIZE: u64 = 100 * 1024 * 1024; // 100MB limit
-const MAX_COMMIT_MESSAGE_LENGTH: usize = 1000;
-const HASH_LENGTH: usize = 40;
+/// Prints an informational `gini: ...` message, unless `--quiet` was given.
+/// Warnings, prompts, and command output always use `println!`.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
 
 // --- CLI Definition ---
 
@@ -42,6 +40,28 @@ const HASH_LENGTH: usize = 40;
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Operate on the repository at this path instead of the current
+    /// directory (also read from `GINI_DIR`).
+    #[arg(long, global = true, value_name = "PATH")]
+    repo: Option<PathBuf>,
+    /// Keep the repository's objects, refs, and config in this directory
+    /// instead of `.gini` in the working tree (also read from `GINI_DIR`
+    /// when that names such a directory).
+    #[arg(long, global = true, value_name = "DIR")]
+    gini_dir: Option<PathBuf>,
+    /// Remove a stale `.gini/lock` left by a crashed gini before running.
+    #[arg(long, global = true)]
+    break_lock: bool,
+    /// Never color output (also disabled by `NO_COLOR` or when not on a terminal).
+    #[arg(long, global = true)]
+    no_color: bool,
+    /// Print only warnings, errors, and essential output such as new hashes.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Say which repository root commands operate on, and report each file
+    /// and directory, with running totals, as a checkpoint records them.
+    #[arg(short, long, global = true)]
+    verbose: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -49,24 +69,670 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Initialize a new Gini repository.
-    Init,
+    Init {
+        /// Copy this directory's contents into the new working tree.
+        #[arg(long, value_name = "DIR")]
+        template: Option<PathBuf>,
+        /// Name objects with this hash function; it can't be changed later.
+        #[arg(long, value_name = "ALGO", value_parser = ["sha1", "sha256"])]
+        hash_algo: Option<String>,
+        /// Start on this branch instead of `main` (also read from `GINI_DEFAULT_BRANCH`).
+        #[arg(short = 'b', long, value_name = "NAME")]
+        initial_branch: Option<String>,
+    },
+    /// Record the contents of a tar archive as a new checkpoint.
+    ImportTar {
+        /// The archive to read.
+        file: PathBuf,
+        #[arg(short, long)]
+        message: String,
+    },
+    /// Copy a repository, with its full history, into a new directory.
+    Clone {
+        /// The repository to copy.
+        source: PathBuf,
+        /// Where to create the copy; must not exist or be empty.
+        destination: PathBuf,
+    },
+    /// Copy the branches of another local repository into `<remote>/<branch>` refs.
+    Fetch {
+        /// The repository to fetch from; defaults to the path saved for the remote.
+        path: Option<PathBuf>,
+        /// The name to track the repository under.
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
+    /// Send the current branch to another local repository.
+    Push {
+        /// The repository to push to; defaults to the path saved for the remote.
+        path: Option<PathBuf>,
+        /// The name to track the repository under.
+        #[arg(long, default_value = "origin")]
+        remote: String,
+        /// Overwrite the target branch even if it has checkpoints you don't.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Fetch, then fast-forward the current branch to the remote's.
+    Pull {
+        /// The repository to pull from; defaults to the path saved for the remote.
+        path: Option<PathBuf>,
+        /// The name to track the repository under.
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
     /// Create a new checkpoint with a message.
     #[command(alias = "c")]
     Checkpoint {
+        /// The checkpoint message; without it (or --message-file), $EDITOR is
+        /// opened on `commit.template`.
         #[arg(short, long)]
-        message: String,
+        message: Option<String>,
+        /// Read the message from a file, or from stdin with `-`.
+        #[arg(long, value_name = "PATH", conflicts_with = "message")]
+        message_file: Option<PathBuf>,
+        /// Replace the last checkpoint instead of adding a new one. Keeps its
+        /// message unless -m is given.
+        #[arg(long)]
+        amend: bool,
+        /// Mark this checkpoint as a correction to an earlier one, to fold
+        /// into it later with `gini squash --autosquash`.
+        #[arg(long, value_name = "REV", conflicts_with_all = ["message", "message_file", "amend"])]
+        fixup: Option<String>,
+        /// Create the checkpoint even if nothing changed since HEAD.
+        #[arg(long)]
+        allow_empty: bool,
+        /// Re-read and re-hash every file instead of reusing cached hashes.
+        #[arg(long)]
+        no_cache: bool,
+        /// Warn about and leave out files over the size limit instead of failing.
+        #[arg(long)]
+        skip_large: bool,
+        /// Record only paths matching this gitignore-style pattern (repeatable).
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+        /// Record only this file or directory (e.g. `.` for the current one);
+        /// everything else is kept as it is in HEAD.
+        #[arg(long, conflicts_with = "include")]
+        path: Option<String>,
+        /// End the message with a `Signed-off-by:` line for the author.
+        #[arg(short, long)]
+        signoff: bool,
+        /// Add a `Key: value` line at the end of the message (repeatable).
+        #[arg(long, value_name = "KEY: VALUE")]
+        trailer: Vec<String>,
+        /// Don't run the automatic gc that `maintenance.autoGc` schedules.
+        #[arg(long)]
+        no_gc: bool,
+        /// Print only the new hash on stdout; warnings go to stderr.
+        #[arg(long)]
+        quiet_hash: bool,
+        /// Checkpoint even past `core.maxFiles` or `core.maxTreeSize`.
+        #[arg(short, long)]
+        force: bool,
+        /// List what the checkpoint would record and the tree hash it would
+        /// have, without storing anything.
+        #[arg(long, conflicts_with_all = ["amend", "quiet_hash"])]
+        dry_run: bool,
+    },
+    /// Keep watching the working tree and checkpoint each time edits settle.
+    Watch {
+        /// How long files must stay unchanged before a checkpoint, e.g. `30s`
+        /// or `2m`.
+        #[arg(long, default_value = "5s", value_parser = parse_interval)]
+        interval: Duration,
     },
     /// Restore the project to a previous checkpoint.
     #[command(alias = "r")]
-    Restore,
+    Restore {
+        /// Checkpoint to restore (hash or tag); prompts for one when omitted.
+        rev: Option<String>,
+        /// Restore the last checkpoint made at or before this time:
+        /// `2024-03-01` (the end of that day) or `2024-03-01 14:30`.
+        #[arg(long, value_name = "DATE", value_parser = parse_moment, conflicts_with = "rev")]
+        at: Option<i64>,
+        /// Restore only this file or directory, leaving everything else untouched.
+        #[arg(long)]
+        path: Option<String>,
+        /// Restore even if untracked or changed files would be deleted.
+        #[arg(long, conflicts_with = "path")]
+        force: bool,
+        /// Leave the branch where it is and detach HEAD at the checkpoint.
+        #[arg(long, conflicts_with = "path")]
+        detach: bool,
+        /// Write the checkpoint's files but leave HEAD and the branch where they are.
+        #[arg(long, alias = "mixed", conflicts_with_all = ["path", "detach"])]
+        no_move_head: bool,
+        /// Keep local edits: change only the files that differ between HEAD
+        /// and the checkpoint, merging any you edited too.
+        #[arg(long, conflicts_with_all = ["path", "force", "no_move_head", "dry_run"])]
+        merge: bool,
+        /// Write the checkpoint's files into this directory instead, leaving
+        /// the working tree and HEAD alone. With --force, the directory may
+        /// already hold files.
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["path", "detach", "no_move_head", "merge", "dry_run"])]
+        into: Option<PathBuf>,
+        /// List the files that would be created, overwritten or deleted, and stop.
+        #[arg(long)]
+        dry_run: bool,
+        /// Don't ask before overwriting files.
+        #[arg(short, long)]
+        yes: bool,
+        /// Overwrite the working tree without backing it up first, whatever
+        /// core.backupOnRestore says.
+        #[arg(long, conflicts_with_all = ["path", "into", "dry_run"])]
+        no_backup: bool,
+        /// Write back only the checkpoint's files that are missing from the
+        /// working tree, leaving every existing file, HEAD and the branch alone.
+        #[arg(long, conflicts_with_all = ["path", "force", "detach", "no_move_head", "merge", "into", "no_backup"])]
+        only_missing: bool,
+        /// Keep working-tree files matching this glob as they are, neither
+        /// deleted nor overwritten by the checkpoint's version. Repeatable.
+        #[arg(long, value_name = "GLOB", conflicts_with_all = ["path", "merge", "into", "only_missing"])]
+        preserve: Vec<String>,
+        /// Print the numbered checkpoint list the prompt would offer, and stop.
+        #[arg(long, conflicts_with_all = ["rev", "at", "path", "force", "detach", "no_move_head", "merge", "into", "dry_run", "yes", "no_backup", "only_missing", "preserve"])]
+        list: bool,
+    },
+    /// Switch to a branch, or detach HEAD at any other checkpoint.
+    Checkout {
+        /// Branch name, or a checkpoint (hash or tag) to inspect.
+        target: String,
+        /// Switch even if untracked or changed files would be deleted.
+        #[arg(long)]
+        force: bool,
+        /// List the files that would be created, overwritten or deleted, and stop.
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// List all checkpoints in the project's history.
     #[command(alias = "l")]
-    Log,
+    Log {
+        /// Emit the history as a JSON array.
+        #[arg(long)]
+        json: bool,
+        /// Print each checkpoint as `<short-hash> <subject>`.
+        #[arg(long, conflicts_with = "json")]
+        oneline: bool,
+        /// Show at most this many checkpoints.
+        #[arg(short = 'n', long = "max-count", value_name = "N")]
+        max_count: Option<usize>,
+        /// Show only checkpoints whose author contains this text.
+        #[arg(long)]
+        author: Option<String>,
+        /// Show only checkpoints made on or after this date (YYYY-MM-DD).
+        #[arg(long, value_name = "DATE", value_parser = parse_date)]
+        since: Option<chrono::NaiveDate>,
+        /// Show only checkpoints made on or before this date (YYYY-MM-DD).
+        #[arg(long, value_name = "DATE", value_parser = parse_date)]
+        until: Option<chrono::NaiveDate>,
+        /// Show only checkpoints whose message contains this text.
+        #[arg(long, value_name = "PATTERN")]
+        grep: Option<String>,
+        /// Match --grep case-insensitively.
+        #[arg(short = 'i', long, requires = "grep")]
+        ignore_case: bool,
+        /// Treat --grep as a regular expression.
+        #[arg(long, requires = "grep")]
+        regex: bool,
+        /// Show dates as `relative` ("2 days ago") or as absolute `iso` times.
+        #[arg(long, value_name = "FORMAT", value_parser = ["relative", "iso"], default_value = "relative")]
+        date: String,
+        /// Count the files each checkpoint added, modified and deleted.
+        #[arg(long, conflicts_with = "json")]
+        stat: bool,
+        /// Total the bytes each checkpoint added and removed, as
+        /// `+X bytes / -Y bytes`.
+        #[arg(long, conflicts_with = "json")]
+        stat_bytes: bool,
+        /// Show the oldest checkpoint first.
+        #[arg(long)]
+        reverse: bool,
+        /// Draw the ancestry beside the entries.
+        #[arg(long, conflicts_with_all = ["json", "reverse"])]
+        graph: bool,
+        /// Browse the checkpoints in a menu and act on the one you pick.
+        #[arg(long, conflicts_with_all = ["json", "oneline", "stat", "stat_bytes", "graph", "format"])]
+        interactive: bool,
+        /// Print each checkpoint with a template: %H/%h hash, %P/%p parents,
+        /// %an author, %ae email, %ad date, %at Unix time, %s subject, %n, %%.
+        #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["json", "oneline"])]
+        format: Option<String>,
+        /// Show only checkpoints that changed this file, following renames.
+        #[arg(long, value_name = "PATH")]
+        follow: Option<String>,
+        /// Show only checkpoints that changed something in this file or directory.
+        #[arg(long, value_name = "PATH", conflicts_with = "follow")]
+        path: Option<String>,
+        /// Include checkpoints reachable from any branch or tag, not just HEAD.
+        #[arg(long, conflicts_with_all = ["graph", "follow"])]
+        all: bool,
+        /// Show only merge checkpoints (those with more than one parent).
+        #[arg(long, conflicts_with = "no_merges")]
+        merges: bool,
+        /// Hide merge checkpoints.
+        #[arg(long)]
+        no_merges: bool,
+        /// Show only checkpoints made after this one (a hash, tag, or branch
+        /// that HEAD descends from).
+        #[arg(long, value_name = "REV", conflicts_with = "all")]
+        since_commit: Option<String>,
+        /// Print only how many checkpoints match, for scripts.
+        #[arg(long, conflicts_with_all = ["json", "oneline", "stat", "stat_bytes", "graph", "interactive", "format", "reverse"])]
+        count: bool,
+    },
+    /// Show where HEAD has been, newest first.
+    Reflog,
     /// Restore from a backup.
     #[command(alias = "b")]
-    Backup,
+    Backup {
+        /// Take a backup of the working tree now instead of restoring.
+        #[arg(long, conflicts_with = "prune")]
+        create: bool,
+        /// Note to store with the backup.
+        #[arg(short, long, requires = "create")]
+        message: Option<String>,
+        /// Delete old backups according to the retention policy instead of restoring.
+        #[arg(long)]
+        prune: bool,
+        /// Number of backups to keep when pruning (overrides `backup.keep`).
+        #[arg(long, requires = "prune")]
+        keep: Option<usize>,
+        /// When pruning, instead delete every backup taken before this date (YYYY-MM-DD).
+        #[arg(long, value_name = "DATE", requires = "prune", conflicts_with = "keep", value_parser = parse_date)]
+        before: Option<chrono::NaiveDate>,
+        /// Don't ask before deleting backups with --before.
+        #[arg(short, long, requires = "before")]
+        yes: bool,
+        /// Label a backup so it is easy to spot later; an empty label removes it.
+        #[arg(long, num_args = 2, value_names = ["NAME", "LABEL"], conflicts_with_all = ["create", "prune"])]
+        label: Vec<String>,
+    },
+    /// List the files in a backup without restoring it.
+    ShowBackup {
+        /// Backup name, e.g. backup_1700000000.
+        name: String,
+        /// Instead list what restoring the backup would change.
+        #[arg(long)]
+        diff: bool,
+    },
+    /// Rename a backup.
+    MoveBackup {
+        /// Backup name, e.g. backup_1700000000.
+        name: String,
+        /// The name to give it: letters, digits, `-` and `_`.
+        new_name: String,
+    },
+    /// Roll back the last restore using the backup it took.
+    Undo,
+    /// Shelve uncommitted changes and clean the working tree back to HEAD.
+    Stash {
+        /// `push` (the default) shelves the changes, `pop` applies and drops
+        /// the newest stash, `list` shows the stack, `drop` discards the newest.
+        #[arg(value_parser = ["push", "pop", "list", "drop"], default_value = "push")]
+        action: String,
+        /// Describe the stash; defaults to HEAD's hash and subject.
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// Stage files for the next checkpoint.
+    Add {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Go through each changed hunk and choose which to stage.
+        #[arg(short, long)]
+        patch: bool,
+    },
+    /// Unstage files, or with `--hard`/`--soft` move the current branch.
+    Reset {
+        /// Discard all changes and make the working tree match REV (no backup).
+        #[arg(long, conflicts_with = "soft")]
+        hard: bool,
+        /// Move the current branch to REV, keeping the working tree and index.
+        #[arg(long)]
+        soft: bool,
+        /// Paths to unstage, or with `--hard`/`--soft` the checkpoint to
+        /// reset to (default HEAD).
+        #[arg(value_name = "PATHS|REV")]
+        targets: Vec<String>,
+    },
+    /// Combine another branch's checkpoints into the current one.
+    Merge {
+        /// The branch (or any checkpoint) to merge into HEAD.
+        rev: String,
+    },
+    /// Fold a run of checkpoints into one, rewriting the history after it.
+    Squash {
+        /// The first and last checkpoints to fold together, both included.
+        #[arg(value_name = "FROM..TO", value_parser = parse_range, required_unless_present = "autosquash")]
+        range: Option<(String, String)>,
+        /// The message for the combined checkpoint.
+        #[arg(short, long, required_unless_present = "autosquash")]
+        message: Option<String>,
+        /// Instead, fold each `fixup!` checkpoint from FROM through HEAD into
+        /// the checkpoint it names, keeping that one's message.
+        #[arg(long, value_name = "FROM", conflicts_with_all = ["range", "message"])]
+        autosquash: Option<String>,
+        /// Don't ask for confirmation.
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Record a new checkpoint that undoes an earlier one.
+    Revert {
+        /// The checkpoint to undo (hash or tag).
+        rev: String,
+    },
+    /// List untracked files, or with --force delete them.
+    Clean {
+        /// Delete the files (a backup is taken first).
+        #[arg(short, long)]
+        force: bool,
+        /// Only list what would be deleted (the default).
+        #[arg(short = 'n', long, conflicts_with = "force")]
+        dry_run: bool,
+    },
+    /// Show changes since the last checkpoint. Exits with status 1 when
+    /// there are any.
+    #[command(alias = "s")]
+    Status {
+        /// Print stable `XY path` lines for scripts: X is the staged change,
+        /// Y the unstaged one (`A`, `M`, `D`, `R` or a space), `??` untracked.
+        #[arg(long)]
+        porcelain: bool,
+        /// Exit with status 0 even when there are changes.
+        #[arg(long)]
+        no_exit_code: bool,
+        /// Hash every file instead of trusting cached sizes and modification times.
+        #[arg(long)]
+        no_cache: bool,
+    },
+    /// Show a checkpoint's metadata and the files it contains.
+    Show {
+        /// Commit hash (may be abbreviated).
+        hash: String,
+        /// Print only the file paths.
+        #[arg(long)]
+        name_only: bool,
+        /// Show the changes against the parent checkpoint.
+        #[arg(long, conflicts_with = "name_only")]
+        patch: bool,
+    },
+    /// List tags, or tag the current checkpoint.
+    Tag {
+        /// Name of the tag to create (or delete with `-d`).
+        name: Option<String>,
+        /// Store an annotated tag with this message.
+        #[arg(short, long, requires = "name", conflicts_with = "delete")]
+        message: Option<String>,
+        /// Delete the named tag.
+        #[arg(short, long, requires = "name")]
+        delete: bool,
+    },
+    /// List branches, or create, delete or rename one.
+    Branch {
+        /// Branch to create at the current checkpoint (or delete with `-d`,
+        /// or rename with `-m`).
+        name: Option<String>,
+        /// The new name, with `-m`.
+        #[arg(requires = "rename")]
+        new_name: Option<String>,
+        /// Delete the named branch.
+        #[arg(short, long, requires = "name", conflicts_with = "rename")]
+        delete: bool,
+        /// Rename branch NAME to NEW_NAME.
+        #[arg(short = 'm', long = "move", requires = "new_name")]
+        rename: bool,
+    },
+    /// Show the changes between two checkpoints.
+    Diff {
+        /// The older checkpoint (hash, may be abbreviated).
+        #[arg(required_unless_present_any = ["staged", "check"], conflicts_with = "staged", requires = "to")]
+        from: Option<String>,
+        /// The newer checkpoint (hash, may be abbreviated).
+        #[arg(required_unless_present_any = ["staged", "check"])]
+        to: Option<String>,
+        /// Compare the staged files with HEAD instead of two checkpoints.
+        #[arg(long, visible_alias = "cached")]
+        staged: bool,
+        /// Print only `A`/`M`/`D` and the path of each changed file.
+        #[arg(long)]
+        name_status: bool,
+        /// Compare changed lines word by word and mark edits inline.
+        #[arg(long, conflicts_with = "name_status")]
+        word_diff: bool,
+        /// Write a patch for `git apply` or `patch -p1` to this file instead
+        /// of printing the diff. Binary files are left out.
+        #[arg(short, long, value_name = "FILE", conflicts_with_all = ["name_status", "word_diff"])]
+        output: Option<PathBuf>,
+        /// Instead report added lines with trailing whitespace or conflict
+        /// markers, and exit non-zero if there are any. Without FROM, TO or
+        /// --staged, checks the working tree against HEAD.
+        #[arg(long, conflicts_with_all = ["name_status", "word_diff", "output"])]
+        check: bool,
+    },
+    /// Show which checkpoint last changed each line of a file.
+    Blame {
+        /// The file, relative to the current directory.
+        path: String,
+        /// Blame the file as of this checkpoint.
+        #[arg(long, default_value = "HEAD")]
+        rev: String,
+        /// Only these lines, e.g. `-L 10,20`.
+        #[arg(short = 'L', value_name = "START,END", value_parser = parse_line_range)]
+        lines: Option<(usize, usize)>,
+    },
+    /// Show the last checkpoint that changed a file or directory.
+    LastChange {
+        /// The file or directory, relative to the current directory.
+        path: String,
+    },
+    /// Report how much storage a file's distinct versions take across history.
+    HistorySize {
+        /// The file or directory, relative to the current directory.
+        path: String,
+    },
+    /// Check that the working tree exactly matches a checkpoint, re-hashing
+    /// every file.
+    VerifyRestore {
+        /// The checkpoint the working tree should match.
+        #[arg(default_value = "HEAD")]
+        rev: String,
+    },
+    /// List the files a checkpoint changed since its parent.
+    Whatchanged {
+        /// The checkpoint to look at.
+        #[arg(default_value = "HEAD")]
+        rev: String,
+    },
+    /// Write a checkpoint's files to a tar (or zip) archive.
+    Export {
+        /// Checkpoint to export (hash, tag, or HEAD).
+        rev: String,
+        /// Path of the archive to create.
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Write a zip archive instead of tar; implied by a `.zip` output.
+        #[arg(long)]
+        zip: bool,
+    },
+    /// Write the whole history as a `git fast-import` stream.
+    ExportGit {
+        /// File to write the stream to; standard output when omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write every checkpoint into its own folder, for browsing without gini.
+    ArchiveHistory {
+        /// Directory to create the folders in; must not exist or be empty.
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// List every ref with the hash it points at.
+    ShowRef {
+        /// Only branches.
+        #[arg(long)]
+        heads: bool,
+        /// Only tags.
+        #[arg(long)]
+        tags: bool,
+    },
+    /// List the files tracked at HEAD.
+    LsFiles {
+        /// Append each file's blob hash.
+        #[arg(long)]
+        with_hash: bool,
+    },
+    /// Draw a checkpoint's files as an indented tree.
+    Tree {
+        /// The checkpoint to draw.
+        #[arg(default_value = "HEAD")]
+        rev: String,
+        /// Show each file's size in bytes.
+        #[arg(long)]
+        size: bool,
+        /// Show each file's short blob hash.
+        #[arg(long)]
+        hash: bool,
+    },
+    /// Print the raw contents of an object.
+    CatFile {
+        /// Object hash (may be abbreviated).
+        hash: String,
+        /// Print the object's type instead of its contents.
+        #[arg(short = 't', long = "type", conflicts_with = "size")]
+        show_type: bool,
+        /// Print the object's size in bytes instead of its contents.
+        #[arg(short, long)]
+        size: bool,
+    },
+    /// Print a commit object's fields: tree, parents, author, date, message.
+    CatCommit {
+        /// Commit hash (may be abbreviated), tag, or branch.
+        rev: String,
+        /// `text` for one field per line, or `json` for tooling.
+        #[arg(long, alias = "pretty", value_name = "FORMAT", value_parser = ["text", "json"], default_value = "text")]
+        format: String,
+    },
+    /// Print a file as it was in a checkpoint, without restoring it.
+    Cat {
+        /// `<rev>:<path>`, with the path from the project root; or just the
+        /// path when `--rev` is given.
+        spec: String,
+        /// Checkpoint to read from (hash, tag, or branch); the path is then
+        /// taken from the current directory.
+        #[arg(long)]
+        rev: Option<String>,
+    },
+    /// Re-hash one object and check it matches its name.
+    Verify {
+        /// Object hash (may be abbreviated).
+        hash: String,
+    },
+    /// Check that a tree and everything under it can be read, without a full fsck.
+    VerifyTree {
+        /// Tree or checkpoint hash (may be abbreviated), or a revision such as HEAD.
+        hash: String,
+    },
+    /// Show how much disk the repository's `.gini` directory uses.
+    Size,
+    /// Summarize where you are: branch, latest checkpoint, and counts.
+    Info,
+    /// Print a hash identifying the exact contents of HEAD's tree.
+    Fingerprint {
+        /// Fingerprint the working tree as it is now instead.
+        #[arg(long)]
+        working: bool,
+    },
+    /// Summarize the object store by type, or list every object.
+    Objects {
+        /// `dedupe-report` shows how much storing each file once saves,
+        /// and which files are shared the most.
+        #[arg(value_parser = ["dedupe-report"], conflicts_with_all = ["list", "kind", "unreachable"])]
+        action: Option<String>,
+        /// How many shared files `dedupe-report` lists.
+        #[arg(long, default_value_t = 10, requires = "action")]
+        top: usize,
+        /// List each object as `<hash> <type> <size>`, marking unreachable ones.
+        #[arg(long)]
+        list: bool,
+        /// Only objects of this type.
+        #[arg(long = "type", value_name = "TYPE", value_parser = ["blob", "tree", "commit", "tag"])]
+        kind: Option<String>,
+        /// Only objects no ref reaches (what `gc` would delete).
+        #[arg(long)]
+        unreachable: bool,
+        /// List the largest objects first instead of sorting by hash.
+        #[arg(long, requires = "list")]
+        by_size: bool,
+    },
+    /// Delete objects that are no longer reachable from any ref.
+    Gc {
+        /// Also repack every remaining object into a single pack file.
+        #[arg(long)]
+        compress: bool,
+        /// List what would be deleted without deleting it. With --verbose,
+        /// list every object and what reaches it.
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        /// Also remove empty directories and empty backups, and reflog
+        /// entries for checkpoints that are gone.
+        #[arg(long, conflicts_with = "dry_run")]
+        aggressive: bool,
+        /// Keep every object a backup holds (the default).
+        #[arg(long)]
+        keep_backups: bool,
+        /// Delete every backup first, so objects only backups hold go too.
+        #[arg(long, conflicts_with = "keep_backups")]
+        drop_backups: bool,
+        /// Spare unreachable objects written in the last N days, instead of
+        /// gc.pruneExpire (two weeks unless set).
+        #[arg(long, value_name = "N")]
+        keep_unreachable_days: Option<u64>,
+        /// Delete every unreachable object written before DATE (YYYY-MM-DD,
+        /// optionally with HH:MM), however recent; with no DATE, before now.
+        #[arg(
+            long,
+            value_name = "DATE",
+            value_parser = parse_prune_cutoff,
+            num_args = 0..=1,
+            default_missing_value = "now",
+            conflicts_with = "keep_unreachable_days"
+        )]
+        prune_now: Option<i64>,
+        /// Re-hash every reachable object first and stop if one is missing
+        /// or corrupt, then check the whole store like fsck afterwards.
+        #[arg(long, conflicts_with = "dry_run")]
+        verify: bool,
+    },
+    /// Pack every object into one pack file, or with --loose unpack them all.
+    Repack {
+        /// Write every packed object back as a loose file and delete the packs.
+        #[arg(long)]
+        loose: bool,
+    },
+    /// Verify the integrity of the object store.
+    Fsck,
+    /// Check the repository layout as well as its objects.
+    #[command(alias = "repair")]
+    Doctor {
+        /// Recreate missing directories and HEAD.
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Get or set a repository configuration value (e.g. `user.name`).
+    Config {
+        /// The key to read, or with VALUE, to set.
+        #[arg(required_unless_present = "list")]
+        key: Option<String>,
+        #[arg(allow_hyphen_values = true)]
+        value: Option<String>,
+        /// Print every `key = value` pair.
+        #[arg(short, long, conflicts_with = "key")]
+        list: bool,
+    },
 }
 
+
 // --- Main Application Logic ---
 
 fn main() -> Result<()> {
@@ -79,616 +745,2107 @@ fn main() -> Result<()> {
 }
 
 fn run() -> Result<()> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+
+    if let Commands::Checkpoint { message, message_file: Some(path), .. } = &mut cli.command {
+        *message = Some(read_message_file(path)?);
+    }
     
     // Validate input
-    if let Commands::Checkpoint { ref message } = cli.command {
-        if message.is_empty() {
-            bail!("Commit message cannot be empty");
+    if let Commands::Checkpoint { message: Some(ref message), .. }
+    | Commands::ImportTar { ref message, .. }
+    | Commands::Squash { message: Some(ref message), .. } = cli.command
+    {
+        validate_message(message)?;
+    }
+
+    // `GINI_DIR` names the metadata directory itself when it holds one, and
+    // the repository (like `--repo`) otherwise.
+    let env_dir = std::env::var_os("GINI_DIR").map(PathBuf::from);
+    let (env_gini_dir, env_repo) = match env_dir {
+        Some(path) if is_gini_dir(&path) => (Some(path), None),
+        path => (None, path),
+    };
+    let gini_dir = cli.gini_dir.or(env_gini_dir);
+    let start = match cli.repo.or(env_repo) {
+        Some(path) => path,
+        None => std::env::current_dir()?,
+    };
+
+    if let Commands::Init { template, hash_algo, initial_branch } = &cli.command {
+        let initial_branch = initial_branch.clone().or_else(|| std::env::var("GINI_DEFAULT_BRANCH").ok());
+        if let Some(name) = &initial_branch {
+            validate_ref_name(name)?;
+        }
+        let repo = match (template, &gini_dir) {
+            (Some(template), gini_dir) => Repository::init_from_template(&start, template, gini_dir.as_deref())?,
+            (None, Some(gini_dir)) => Repository::init_with_gini_dir(&start, gini_dir)?,
+            (None, None) => Repository::init(&start)?,
+        };
+        if let Some(algo) = hash_algo {
+            repo.set_config("core.hashAlgo", algo)?;
         }
-        if message.len() > MAX_COMMIT_MESSAGE_LENGTH {
-            bail!("Commit message too long (max {} characters)", MAX_COMMIT_MESSAGE_LENGTH);
+        if let Some(name) = &initial_branch {
+            repo.set_initial_branch(name)?;
         }
+        info!("gini: Initialized empty .gini project in {}", repo.root().display());
+        return Ok(());
+    }
+
+    if let Commands::Clone { source, destination } = &cli.command {
+        let source = Repository::discover_from(source)
+            .with_context(|| format!("{} is not a gini repository", source.display()))?;
+        let clone = source.clone_to(destination)?;
+        info!("gini: Cloned {} into {}", source.root().display(), clone.root().display());
+        return Ok(());
     }
 
-    if !matches!(cli.command, Commands::Init) {
-        ensure_initialized()?;
+    let repo = match &gini_dir {
+        Some(gini_dir) => Repository::open_with_gini_dir(&start, gini_dir)?,
+        None => open_repository(&start),
+    };
+    if cli.verbose {
+        eprintln!("gini: repository root: {}", repo.root().display());
+    }
+    let painter = Painter::new(cli.no_color);
+    if cli.break_lock && repo.break_lock()? {
+        info!("gini: Removed stale lock.");
     }
 
     match cli.command {
-        Commands::Init => {
-            init()?;
+        Commands::Init { .. } | Commands::Clone { .. } => unreachable!("handled above"),
+        Commands::Checkpoint {
+            message, amend, fixup, allow_empty, no_cache, skip_large, include, path, signoff, trailer, no_gc, quiet_hash, force,
+            dry_run, ..
+        } => {
+            if quiet_hash {
+                QUIET.store(true, Ordering::Relaxed);
+            }
+            let message = match fixup {
+                Some(rev) => Some(repo.fixup_message(&rev)?),
+                None => message,
+            };
+            let options = CheckpointOptions {
+                allow_empty,
+                no_cache,
+                skip_large,
+                include,
+                path: path.map(|path| repo.relative_path(&path)).transpose()?,
+                progress: cli.verbose.then_some(print_progress as fn(&Progress)),
+                trailers: trailer,
+                signoff,
+                no_gc,
+                force,
+                preflight: cli.verbose.then_some(print_preflight as fn(&TreeTotals)),
+            };
+            if dry_run {
+                return print_checkpoint_preview(&repo, &options);
+            }
+            let report = match (amend, message) {
+                (true, message) => repo.amend(message.as_deref(), &options)?,
+                (false, Some(message)) => repo.checkpoint(&message, &options)?,
+                (false, None) => {
+                    let message = edit_message(&repo)?;
+                    validate_message(&message)?;
+                    repo.checkpoint(&message, &options)?
+                }
+            };
+            match report.commit {
+                // Scripts running with --quiet still need the new hash.
+                Some(commit_hash) if is_quiet() => println!("{}", commit_hash),
+                Some(commit_hash) if amend => {
+                    println!("gini: Amended checkpoint, new hash: {}", commit_hash)
+                }
+                Some(commit_hash) => {
+                    println!("gini: Checkpoint created with hash: {}", commit_hash)
+                }
+                None => info!("gini: Nothing to checkpoint, working tree matches HEAD."),
+            }
+            // Keep stdout to the bare hash for scripts capturing it.
+            warn_skipped(&report.skipped, quiet_hash);
+            if let Some(gc) = report.auto_gc.filter(|gc| gc.removed > 0) {
+                info!("gini: Auto gc removed {} unreachable object(s), freeing {}.", gc.removed, format_bytes(gc.bytes));
+            }
+        }
+        Commands::Watch { interval } => watch(&repo, interval)?,
+        Commands::Restore { list: true, .. } => {
+            for (number, commit) in repo.get_commit_history()?.iter().enumerate() {
+                println!("{}\t{}\t{}\t{}", number + 1, commit.hash, &commit.hash[..7], commit.subject);
+            }
+        }
+        Commands::Restore {
+            rev,
+            at,
+            path,
+            force,
+            detach,
+            no_move_head,
+            merge,
+            into,
+            dry_run,
+            yes,
+            no_backup,
+            only_missing,
+            preserve,
+            ..
+        } => {
+            let options = RestoreOptions {
+                force,
+                detach,
+                no_move_head,
+                merge,
+                into,
+                dry_run,
+                yes,
+                no_backup,
+                only_missing,
+                preserve,
+            };
+            let rev = match at {
+                Some(time) => {
+                    let Some(commit) = repo.checkpoint_at(time)? else {
+                        bail!("No checkpoint in HEAD's history was made on or before {}", format_moment(time));
+                    };
+                    info!(
+                        "gini: The last checkpoint by {} is {} ({}, {}).",
+                        format_moment(time),
+                        &commit.hash[..7],
+                        commit.subject,
+                        format_moment(commit.timestamp)
+                    );
+                    Some(commit.hash)
+                }
+                None => rev,
+            };
+            restore_checkpoint_tui(&repo, rev.as_deref(), path.as_deref(), options)?;
         }
-        Commands::Checkpoint { message } => {
-            let commit_hash = checkpoint(&message)?;
-            println!("gini: Checkpoint created with hash: {}", commit_hash);
+        Commands::Checkout { target, dry_run: true, .. } => {
+            print_restore_preview(&repo, &target, None, &[])?;
         }
-        Commands::Restore => {
-            restore_checkpoint_tui()?;
+        Commands::Checkout { target, force, .. } => {
+            if let Some(backup_path) = repo.checkout(&target, force)? {
+                info!("gini: Created backup at {:?}", backup_path);
+            }
+            match repo.current_branch()? {
+                Some(branch) => info!("gini: Switched to branch {}.", branch),
+                None => {
+                    let head = repo.resolve_revision("HEAD")?;
+                    info!("gini: HEAD is now detached at {}.", &head[..7]);
+                }
+            }
+        }
+        Commands::Reflog => {
+            let entries = repo.reflog()?;
+            if entries.is_empty() {
+                info!("gini: The reflog is empty.");
+            }
+            let now = chrono::Utc::now().timestamp();
+            for (n, entry) in entries.iter().enumerate() {
+                println!(
+                    "{} HEAD@{{{}}}: {} ({})",
+                    &entry.new[..7],
+                    n,
+                    entry.reason,
+                    relative_time(entry.timestamp as i64, now)
+                );
+            }
         }
-        Commands::Log => {
-            let log_output = log()?;
+        Commands::Log {
+            json, oneline, max_count, author, since, until, grep, ignore_case, regex, stat, stat_bytes, date, reverse,
+            graph, interactive, format, follow, path, all, merges, no_merges, since_commit, count,
+        } => {
+            let options = LogOptions {
+                oneline,
+                max_count,
+                author,
+                since: since.map(start_of_day),
+                // `--until` includes the whole day.
+                until: until.and_then(|date| date.succ_opt()).map(start_of_day),
+                grep,
+                ignore_case,
+                regex,
+                stat,
+                stat_bytes,
+                relative_dates: date == "relative",
+                reverse,
+                graph,
+                format,
+                follow: follow.map(|path| repo.relative_path(&path)).transpose()?,
+                // The whole repository is no filter at all.
+                path: path
+                    .map(|path| repo.relative_path(&path))
+                    .transpose()?
+                    .filter(|path| !path.is_empty()),
+                all,
+                merges: (merges || no_merges).then_some(merges),
+                since_commit,
+            };
+            if interactive {
+                return browse_log(&repo, &options, painter);
+            }
+            if count {
+                println!("{}", repo.log_count(&options)?);
+                return Ok(());
+            }
+            let log_output = if json {
+                repo.log_json(&options)?
+            } else if options.format.is_some() {
+                repo.log(&options)?
+            } else if oneline {
+                painter.oneline_log(&repo.log(&options)?)
+            } else {
+                painter.headers(&repo.log(&options)?)
+            };
             println!("{}", log_output);
         }
-        Commands::Backup => {
-            restore_backup_tui()?;
+        Commands::Backup { create, message, prune, keep, before, yes, label } => {
+            if let [name, label] = label.as_slice() {
+                repo.label_backup(name, label)?;
+                match label.trim().is_empty() {
+                    true => info!("gini: Removed the label from {}.", name),
+                    false => info!("gini: Labeled {} \"{}\".", name, label.trim()),
+                }
+            } else if create {
+                let backup_path = repo.create_backup(message.as_deref())?;
+                info!("gini: Created backup at {:?}", backup_path);
+            } else if let Some(date) = before {
+                let cutoff = start_of_day(date).max(0) as u64;
+                let doomed = repo
+                    .backups()?
+                    .into_iter()
+                    .filter(|backup| backup.timestamp().is_some_and(|timestamp| timestamp < cutoff))
+                    .count();
+                if doomed == 0 {
+                    info!("gini: No backups before {}.", date);
+                    return Ok(());
+                }
+                let prompt = format!("Delete {} backup(s) taken before {}?", doomed, date);
+                if !yes && !confirm(&prompt)? {
+                    info!("gini: Prune cancelled.");
+                    return Ok(());
+                }
+                let removed = repo.prune_backups_before(cutoff)?;
+                info!("gini: Pruned {} backup(s) taken before {}.", removed, date);
+            } else if prune {
+                let removed = repo.prune_backups(keep)?;
+                info!("gini: Pruned {} old backup(s).", removed);
+            } else {
+                restore_backup_tui(&repo)?;
+            }
+        }
+        Commands::Stash { action, message } if message.is_some() && action != "push" => {
+            bail!("--message only applies to `gini stash push`");
+        }
+        Commands::Stash { action, message } => match action.as_str() {
+            "pop" => {
+                let entry = repo.stash_pop()?;
+                info!("gini: Applied and dropped stash {}: {}", &entry.hash[..7], subject(&entry.message));
+            }
+            "list" => {
+                for entry in repo.stash_list()? {
+                    println!("{} {}", &entry.hash[..7], subject(&entry.message));
+                }
+            }
+            "drop" => {
+                let entry = repo.stash_drop()?;
+                info!("gini: Dropped stash {}: {}", &entry.hash[..7], subject(&entry.message));
+            }
+            _ => {
+                let entry = repo.stash(message.as_deref())?;
+                info!("gini: Stashed changes as {}: {}", &entry.hash[..7], subject(&entry.message));
+            }
+        },
+        Commands::ShowBackup { name, diff } => {
+            let output = if diff { painter.diff(&repo.diff_backup(&name)?) } else { repo.show_backup(&name)? };
+            print!("{}", output);
+        }
+        Commands::MoveBackup { name, new_name } => {
+            let backup = repo.move_backup(&name, &new_name)?;
+            info!("gini: Renamed backup {} to {}.", name, backup.name);
+        }
+        Commands::Undo => {
+            let Some(backup) = repo.backups()?.into_iter().next() else {
+                info!("gini: No backups found; nothing to undo.");
+                return Ok(());
+            };
+            info!("gini: Undoing with backup {}.", backup.name);
+            if !confirm_overwrite()? {
+                info!("gini: Undo cancelled.");
+                return Ok(());
+            }
+            repo.undo()?;
+            match &backup.head {
+                Some(head) => info!("gini: Restored files and HEAD ({}) from {}.", &head[..7], backup.name),
+                None => info!("gini: Restored files from {}.", backup.name),
+            }
+        }
+        Commands::Add { paths, patch: true } => {
+            let staged = add_patch(&repo, &paths, painter)?;
+            if staged > 0 {
+                info!("gini: Staged changes to {} file(s).", staged);
+            }
+        }
+        Commands::Add { paths, .. } => {
+            let changed = repo.add(&paths)?;
+            info!("gini: Staged {} change(s).", changed);
+        }
+        Commands::Reset { hard, soft, targets } => {
+            if hard || soft {
+                let rev = match targets.as_slice() {
+                    [] => "HEAD",
+                    [rev] => rev.as_str(),
+                    _ => bail!("--hard and --soft take a single checkpoint"),
+                };
+                if hard {
+                    let discarded = repo.reset_hard(rev)?;
+                    if !discarded.is_empty() {
+                        println!("gini: warning: discarded uncommitted changes to:");
+                        for path in &discarded {
+                            println!("  {}", path);
+                        }
+                    }
+                } else {
+                    repo.reset_soft(rev)?;
+                }
+                let head = repo.resolve_revision("HEAD")?;
+                info!("gini: HEAD is now at {}.", &head[..7]);
+            } else {
+                if targets.is_empty() {
+                    bail!("Nothing specified to unstage; pass paths, or --hard/--soft");
+                }
+                let changed = repo.reset_paths(&targets)?;
+                info!("gini: Unstaged {} change(s).", changed);
+            }
+        }
+        Commands::Fetch { path, remote } => {
+            print_fetch_report(&repo.fetch(&remote, path.as_deref())?);
+        }
+        Commands::Push { path, remote, force } => {
+            let report = repo.push(&remote, path.as_deref(), force)?;
+            match &report.old {
+                Some(old) if *old == report.new => {
+                    info!("gini: {} is already up to date in {}.", report.branch, report.path.display())
+                }
+                Some(old) => info!(
+                    "gini: Pushed {} to {} ({}..{}, {} objects).",
+                    report.branch,
+                    report.path.display(),
+                    &old[..7],
+                    &report.new[..7],
+                    report.objects
+                ),
+                None => info!(
+                    "gini: Pushed new branch {} to {} ({} objects).",
+                    report.branch,
+                    report.path.display(),
+                    report.objects
+                ),
+            }
+        }
+        Commands::Pull { path, remote } => {
+            let report = repo.pull(&remote, path.as_deref())?;
+            print_fetch_report(&report.fetch);
+            if report.from.as_deref() == Some(report.to.as_str()) {
+                info!("gini: {} is already up to date.", report.branch);
+            } else {
+                info!("gini: Fast-forwarded {} to {}.", report.branch, &report.to[..7]);
+            }
+        }
+        Commands::Merge { rev } => match repo.merge(&rev)? {
+            MergeOutcome::UpToDate => info!("gini: Already up to date with {}.", rev),
+            MergeOutcome::FastForward(commit_hash) => {
+                info!("gini: Fast-forwarded to {} ({}).", rev, &commit_hash[..7]);
+            }
+            MergeOutcome::Merged(commit_hash) => {
+                if is_quiet() {
+                    println!("{}", commit_hash);
+                }
+                info!("gini: Merged {} in new checkpoint {}.", rev, commit_hash);
+            }
+            MergeOutcome::Conflicts(conflicts) => {
+                for path in &conflicts {
+                    eprintln!("gini: Conflict in {}", path);
+                }
+                bail!("Automatic merge failed; fix the conflicts above, then `gini checkpoint` to finish the merge");
+            }
+        },
+        Commands::Squash { autosquash: Some(from), yes, .. } => {
+            let plan = repo.autosquash_plan(&from)?;
+            if plan.is_empty() {
+                bail!("No fixup! checkpoints to fold from {} to HEAD", from);
+            }
+            let prompt = format!(
+                "Fold {} fixup checkpoint(s) into the ones they name? This rewrites history.",
+                plan.len()
+            );
+            if !yes && !confirm(&prompt)? {
+                info!("gini: Squash cancelled.");
+                return Ok(());
+            }
+            for (fixup, target) in &plan {
+                info!("gini: Folding {} into {}", &fixup[..7], &target[..7]);
+            }
+            let report = repo.autosquash(&from)?;
+            if is_quiet() {
+                println!("{}", report.head);
+            }
+            info!(
+                "gini: Folded {} fixup(s), rebuilding {} checkpoint(s); HEAD is now {}.",
+                report.folded,
+                report.rebuilt,
+                &report.head[..7]
+            );
+            info!("gini: The old checkpoints stay in `gini reflog` until gc expires them.");
         }
+        Commands::Squash { range, message, yes, .. } => {
+            let ((from, to), message) = (
+                range.expect("clap requires FROM..TO without --autosquash"),
+                message.expect("clap requires -m without --autosquash"),
+            );
+            let range = repo.squash_range(&from, &to)?;
+            let prompt = format!(
+                "Squash {} checkpoints ({}..{}) into one? This rewrites history.",
+                range.len(),
+                &range[0][..7],
+                &range[range.len() - 1][..7]
+            );
+            if !yes && !confirm(&prompt)? {
+                info!("gini: Squash cancelled.");
+                return Ok(());
+            }
+            let report = repo.squash(&from, &to, &message)?;
+            if is_quiet() {
+                println!("{}", report.commit);
+            }
+            info!("gini: Squashed {} checkpoints into {}.", report.squashed, &report.commit[..7]);
+            if report.rebuilt > 0 {
+                info!("gini: Rebuilt {} later checkpoint(s); HEAD is now {}.", report.rebuilt, &report.head[..7]);
+            }
+            info!("gini: The old checkpoints stay in `gini reflog` until gc expires them.");
+        }
+        Commands::Revert { rev } => {
+            let commit_hash = repo.revert(&rev)?;
+            if is_quiet() {
+                println!("{}", commit_hash);
+            }
+            info!("gini: Reverted {} in new checkpoint {}.", rev, commit_hash);
+        }
+        Commands::Clean { force: true, .. } => {
+            let removed = repo.clean()?;
+            for path in &removed {
+                info!("gini: Removed {}", path);
+            }
+            if removed.is_empty() {
+                info!("gini: No untracked files.");
+            }
+        }
+        Commands::Clean { .. } => {
+            let untracked = repo.untracked_files()?;
+            for path in &untracked {
+                println!("Would remove {}", path);
+            }
+            if untracked.is_empty() {
+                info!("gini: No untracked files.");
+            } else {
+                info!("gini: Run `gini clean --force` to delete them.");
+            }
+        }
+        Commands::Status { porcelain, no_exit_code, no_cache } => {
+            let status = if no_cache { repo.status_uncached()? } else { repo.status()? };
+            if porcelain {
+                print_porcelain_status(&status);
+            } else {
+                if repo.current_branch()?.is_none() {
+                    let head = repo.resolve_revision("HEAD")?;
+                    info!("gini: HEAD is detached at {}.", &head[..7]);
+                }
+                print_status(&status, painter);
+            }
+            if !status.is_clean() && !no_exit_code {
+                std::process::exit(1);
+            }
+        }
+        Commands::Show { hash, name_only, patch } => {
+            print!("{}", painter.diff(&repo.show(&hash, name_only, patch)?));
+        }
+        Commands::Tag { name, message, delete } => match name {
+            Some(name) if delete => {
+                repo.delete_tag(&name)?;
+                info!("gini: Deleted tag {}.", name);
+            }
+            Some(name) => {
+                let tag = repo.create_tag(&name, message.as_deref())?;
+                info!("gini: Tagged {} as {}.", &tag.commit[..7], tag.name);
+            }
+            None => {
+                for tag in repo.tags()? {
+                    println!("{}", tag.name);
+                }
+            }
+        },
+        Commands::Branch { name, new_name, delete, rename } => match (name, new_name) {
+            (Some(old), Some(new)) if rename => {
+                repo.rename_branch(&old, &new)?;
+                info!("gini: Renamed branch {} to {}.", old, new);
+            }
+            (Some(name), _) if delete => {
+                repo.delete_branch(&name)?;
+                info!("gini: Deleted branch {}.", name);
+            }
+            (Some(name), _) => {
+                let branch = repo.create_branch(&name)?;
+                info!("gini: Created branch {} at {}.", branch.name, &branch.commit[..7]);
+            }
+            (None, _) => {
+                if repo.current_branch()?.is_none() {
+                    let head = repo.resolve_revision("HEAD")?;
+                    println!("* (detached at {})", &head[..7]);
+                }
+                for branch in repo.branches()? {
+                    let marker = if branch.current { '*' } else { ' ' };
+                    println!("{} {}", marker, branch.name);
+                }
+            }
+        },
+        Commands::Blame { path, rev, lines } => {
+            let blamed = repo.blame(&repo.relative_path(&path)?, &rev, lines)?;
+            let name = |author: &str| author.split(" <").next().unwrap_or(author).to_string();
+            let name_width = blamed.iter().map(|line| name(&line.author).chars().count()).max().unwrap_or(0);
+            let number_width = blamed.last().map_or(1, |line| line.number.to_string().len());
+            for line in &blamed {
+                println!(
+                    "{} {:<name_width$} {:>number_width$} | {}",
+                    painter.paint(Color::Yellow, &line.commit[..7]),
+                    name(&line.author),
+                    line.number,
+                    line.text,
+                );
+            }
+        }
+        Commands::LastChange { path } => {
+            let Some(commit) = repo.last_change(&repo.relative_path(&path)?)? else {
+                bail!("No checkpoint in HEAD's history ever had {}", path);
+            };
+            if is_quiet() {
+                println!("{}", commit.hash);
+                return Ok(());
+            }
+            println!("{}", painter.headers(&format!("checkpoint {}", commit.hash)));
+            println!("Author: {}", commit.author);
+            println!("Date:   {}\n", format_moment(commit.timestamp));
+            println!("\t{}", commit.subject);
+        }
+        Commands::HistorySize { path } => {
+            let size = repo.history_size(&repo.relative_path(&path)?)?;
+            if size.checkpoints == 0 {
+                bail!("No checkpoint ever had {}", path);
+            }
+            if is_quiet() {
+                println!("{}", size.bytes);
+                return Ok(());
+            }
+            println!(
+                "{}: {} distinct version(s) across {} checkpoint(s), {} in total.",
+                path,
+                size.versions,
+                size.checkpoints,
+                format_bytes(size.bytes)
+            );
+        }
+        Commands::VerifyRestore { rev } => {
+            let hash = repo.resolve_revision(&rev)?;
+            verify_restore(&repo, &hash)?;
+            info!("gini: The working tree matches checkpoint {}.", &hash[..7]);
+        }
+        Commands::Whatchanged { rev } => {
+            print!("{}", painter.diff(&repo.what_changed(&rev)?));
+        }
+        Commands::Diff { from, to, staged, check: true, .. } => {
+            let problems = match (from, to) {
+                (Some(from), Some(to)) => repo.diff_check(&from, &to)?,
+                _ if staged => repo.diff_staged_check()?,
+                _ => repo.diff_working_check()?,
+            };
+            for problem in &problems {
+                println!("{}:{}: {}.", problem.path, problem.line, problem.problem);
+                println!("+{}", problem.text);
+            }
+            if !problems.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Diff { from, to, staged, output: Some(output), .. } => {
+            let patch = match (from, to) {
+                (Some(from), Some(to)) => repo.diff_patch(&from, &to)?,
+                _ => {
+                    debug_assert!(staged, "clap requires FROM and TO without --staged");
+                    repo.diff_staged_patch()?
+                }
+            };
+            fs::write(&output, &patch.text).with_context(|| format!("Failed to write {}", output.display()))?;
+            for path in &patch.binary_files {
+                println!("gini: warning: left {} out of the patch (binary file)", path);
+            }
+            info!("gini: Wrote patch to {}.", output.display());
+        }
+        Commands::Diff { from, to, staged, name_status, word_diff, .. } => {
+            let output = match (from, to) {
+                (Some(from), Some(to)) => repo.diff(&from, &to, name_status, word_diff)?,
+                _ => {
+                    debug_assert!(staged, "clap requires FROM and TO without --staged");
+                    repo.diff_staged(name_status, word_diff)?
+                }
+            };
+            print!("{}", if word_diff { painter.word_diff(&output) } else { painter.diff(&output) });
+        }
+        Commands::ImportTar { file, message } => {
+            let archive = fs::File::open(&file)
+                .with_context(|| format!("Failed to open {}", file.display()))?;
+            let commit_hash = repo.import_tar(&mut std::io::BufReader::new(archive), &message)?;
+            if is_quiet() {
+                println!("{}", commit_hash);
+            }
+            info!("gini: Imported {} as checkpoint {}", file.display(), commit_hash);
+            info!("gini: The working tree is unchanged; run `gini reset --hard` to check it out.");
+        }
+        Commands::Export { rev, output, zip } => {
+            let zip = zip || output.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+            let file = fs::File::create(&output)
+                .with_context(|| format!("Failed to create {}", output.display()))?;
+            let mut writer = std::io::BufWriter::new(file);
+            match zip {
+                true => repo.export_zip(&rev, &mut writer)?,
+                false => repo.export_tar(&rev, &mut writer)?,
+            }
+            writer.flush()?;
+            info!("gini: Exported {} to {}", rev, output.display());
+        }
+        Commands::ExportGit { output: Some(output) } => {
+            let file = fs::File::create(&output)
+                .with_context(|| format!("Failed to create {}", output.display()))?;
+            let mut writer = std::io::BufWriter::new(file);
+            let count = repo.export_git(&mut writer)?;
+            writer.flush()?;
+            info!("gini: Exported {} checkpoint(s) to {}", count, output.display());
+            info!("--- Import them with: git init && git fast-import < {}", output.display());
+        }
+        Commands::ExportGit { output: None } => {
+            let mut writer = std::io::BufWriter::new(std::io::stdout().lock());
+            repo.export_git(&mut writer)?;
+            writer.flush()?;
+        }
+        Commands::ArchiveHistory { output } => {
+            let folders = repo.archive_history(&output)?;
+            info!("gini: Wrote {} checkpoint(s) to {}", folders.len(), output.display());
+        }
+        Commands::ShowRef { heads, tags } => {
+            let refs = repo.show_ref(heads, tags)?;
+            if refs.is_empty() {
+                bail!("No refs found");
+            }
+            for (hash, name) in refs {
+                println!("{} {}", hash, name);
+            }
+        }
+        Commands::LsFiles { with_hash } => {
+            print!("{}", repo.ls_files(with_hash)?);
+        }
+        Commands::Tree { rev, size, hash } => print!("{}", repo.tree_listing(&rev, size, hash)?),
+        Commands::CatCommit { rev, format } => {
+            let commit = repo.cat_commit(&rev)?;
+            if format == "json" {
+                println!("{}", commit.to_json());
+            } else {
+                println!("hash    {}", commit.hash);
+                println!("tree    {}", commit.tree);
+                for parent in &commit.parents {
+                    println!("parent  {}", parent);
+                }
+                println!("author  {}", commit.author_name);
+                println!("email   {}", commit.author_email);
+                println!("date    {} ({})", commit.date, commit.timestamp);
+                println!("\n{}", commit.message);
+            }
+        }
+        Commands::CatFile { hash, show_type, size } => {
+            if show_type {
+                println!("{}", repo.object_type(&hash)?);
+            } else if size {
+                println!("{}", repo.cat_file(&hash)?.len());
+            } else {
+                let content = repo.cat_file(&hash)?;
+                // Binary bytes would only garble the terminal; say what they are instead.
+                match describe_binary(&content) {
+                    Some(description) if std::io::stdout().is_terminal() => {
+                        println!("gini: {} is {}; redirect the output to save it", hash, description);
+                    }
+                    _ => std::io::stdout().write_all(&content)?,
+                }
+            }
+        }
+        Commands::Cat { spec, rev } => {
+            let (rev, path) = match rev {
+                Some(rev) => (rev, repo.relative_path(&spec)?),
+                None => match spec.split_once(':') {
+                    Some((rev, path)) => (rev.to_string(), path.trim_matches('/').to_string()),
+                    None => bail!("Expected <rev>:<path>, or a path with --rev"),
+                },
+            };
+            let content = repo.file_at(&rev, &path)?;
+            match describe_binary(&content) {
+                Some(description) if std::io::stdout().is_terminal() => {
+                    println!("gini: {} is {}; redirect the output to save it", path, description);
+                }
+                _ => std::io::stdout().write_all(&content)?,
+            }
+        }
+        Commands::Verify { hash } => {
+            let check = repo.verify_object(&hash)?;
+            if !check.is_intact() {
+                println!("gini: {} {} is corrupt", check.kind, check.hash);
+                println!("  expected: {}", check.hash);
+                println!("  actual:   {}", check.actual);
+                bail!("object {} failed verification", check.hash);
+            }
+            info!("gini: {} {} is intact", check.kind, check.hash);
+        }
+        Commands::VerifyTree { hash } => {
+            let check = repo.verify_tree(&hash)?;
+            if let Some(problem) = &check.problem {
+                println!("gini: {}", problem);
+                bail!("tree {} failed verification", check.hash);
+            }
+            info!(
+                "gini: tree {} is intact ({} trees, {} files)",
+                check.hash, check.trees, check.files
+            );
+        }
+        Commands::Fingerprint { working } => {
+            println!("{}", repo.fingerprint(working)?);
+        }
+        Commands::Info => {
+            let history = repo.get_commit_history()?;
+            let storage = repo.storage_usage()?;
+            let status = repo.status()?;
+            println!("root:         {}", repo.root().display());
+            match repo.current_branch()? {
+                Some(branch) => println!("branch:       {}", branch),
+                None => println!("branch:       (detached HEAD)"),
+            }
+            match history.first() {
+                Some(head) => println!("checkpoint:   {} {}", &head.hash[..7], head.subject),
+                None => println!("checkpoint:   (none yet)"),
+            }
+            println!("checkpoints:  {}", history.len());
+            println!("objects:      {}", storage.reachable_objects + storage.unreachable_objects);
+            println!("backups:      {}", storage.backups);
+            let changed = status.staged.len() + status.unstaged.len() + status.untracked.len();
+            if status.is_clean() {
+                println!("working tree: clean");
+            } else {
+                println!("working tree: {} change(s); run `gini status` for details", changed);
+            }
+        }
+        Commands::Size => {
+            let report = repo.storage_usage()?;
+            let total_objects = report.reachable_objects + report.unreachable_objects;
+            println!(
+                "objects:  {:>10}  ({} objects; {} unreachable, {})",
+                format_bytes(report.reachable_bytes + report.unreachable_bytes),
+                total_objects,
+                report.unreachable_objects,
+                format_bytes(report.unreachable_bytes)
+            );
+            println!("backups:  {:>10}  ({} backups)", format_bytes(report.backup_bytes), report.backups);
+            println!("refs:     {:>10}", format_bytes(report.ref_bytes));
+            println!("other:    {:>10}", format_bytes(report.other_bytes));
+            println!("total:    {:>10}", format_bytes(report.total_bytes()));
+            if report.unreachable_objects > 0 {
+                info!("gini: Run `gini gc` to free {}.", format_bytes(report.unreachable_bytes));
+            }
+        }
+        Commands::Objects { action: Some(_), top, .. } => {
+            let report = repo.dedupe_report()?;
+            println!(
+                "{} file entries in {} trees point at {} distinct blobs.",
+                report.references, report.trees, report.blobs
+            );
+            println!("stored:          {:>10}", format_bytes(report.stored_bytes));
+            println!("without dedupe:  {:>10}", format_bytes(report.undeduplicated_bytes));
+            println!("saved:           {:>10}", format_bytes(report.saved_bytes()));
+            if !report.shared.is_empty() {
+                println!();
+                println!("Most shared ({} of {} shared blobs):", report.shared.len().min(top), report.shared.len());
+                for blob in report.shared.iter().take(top) {
+                    println!(
+                        "  {}  {:>4}x {:>10}  saves {:>10}  {}",
+                        &blob.hash[..7],
+                        blob.references,
+                        format_bytes(blob.size),
+                        format_bytes(blob.size * (blob.references as u64 - 1)),
+                        blob.name
+                    );
+                }
+            }
+        }
+        Commands::Objects { list, kind, unreachable, by_size, .. } => {
+            let mut objects: Vec<_> = repo
+                .objects()?
+                .into_iter()
+                .filter(|object| kind.as_deref().is_none_or(|kind| object.kind.to_string() == kind))
+                .filter(|object| !unreachable || !object.reachable)
+                .collect();
+            if objects.is_empty() {
+                info!("gini: No objects match.");
+            } else if list {
+                if by_size {
+                    objects.sort_by_key(|object| std::cmp::Reverse(object.size));
+                }
+                for object in &objects {
+                    println!(
+                        "{} {:<6} {:>10}{}",
+                        object.hash,
+                        object.kind,
+                        object.size,
+                        if object.reachable { "" } else { "  unreachable" }
+                    );
+                }
+            } else {
+                for name in ["commit", "tree", "blob", "tag"] {
+                    let of_kind: Vec<_> = objects.iter().filter(|o| o.kind.to_string() == name).collect();
+                    if of_kind.is_empty() {
+                        continue;
+                    }
+                    let bytes: u64 = of_kind.iter().map(|o| o.size).sum();
+                    let orphans = of_kind.iter().filter(|o| !o.reachable).count();
+                    println!(
+                        "{:<8} {:>6} objects  {:>10}  ({} unreachable)",
+                        format!("{}s:", name),
+                        of_kind.len(),
+                        format_bytes(bytes),
+                        orphans
+                    );
+                }
+            }
+        }
+        Commands::Gc { compress, dry_run: true, drop_backups, keep_unreachable_days, prune_now, .. } => {
+            let options = GcOptions {
+                compress,
+                drop_backups,
+                keep_unreachable: keep_unreachable_days.map(|days| days.saturating_mul(24 * 60 * 60)),
+                prune_before: prune_now,
+                ..GcOptions::default()
+            };
+            let doomed = repo.gc_dry_run(&options)?;
+            let doomed_hashes: HashSet<&str> = doomed.iter().map(|object| object.hash.as_str()).collect();
+            let shown = if cli.verbose { repo.objects()? } else { doomed.clone() };
+            for object in &shown {
+                let note = match &object.reached_from {
+                    Some(source) => format!("reachable from {}", source),
+                    None if doomed_hashes.contains(object.hash.as_str()) => "unreachable".to_string(),
+                    None => "unreachable, but packed (only --compress removes it)".to_string(),
+                };
+                println!("{} {:<6} {:>10}  {}", object.hash, object.kind, object.size, note);
+            }
+            info!(
+                "gini: Would remove {} unreachable objects ({} bytes).",
+                doomed.len(),
+                doomed.iter().map(|object| object.size).sum::<u64>()
+            );
+        }
+        Commands::Gc { compress, aggressive, drop_backups, keep_unreachable_days, prune_now, verify, .. } => {
+            let options = GcOptions {
+                compress,
+                aggressive,
+                drop_backups,
+                keep_unreachable: keep_unreachable_days.map(|days| days.saturating_mul(24 * 60 * 60)),
+                prune_before: prune_now,
+                verify,
+            };
+            let report = repo.gc(&options)?;
+            if report.migrated > 0 {
+                info!("gini: Moved {} objects into the sharded layout.", report.migrated);
+            }
+            if drop_backups {
+                info!("gini: Deleted {} backups.", report.backups_dropped);
+            }
+            if report.reflog_expired > 0 {
+                info!("gini: Dropped {} reflog entries.", report.reflog_expired);
+            }
+            info!(
+                "gini: Removed {} unreachable objects ({} bytes freed).",
+                report.removed, report.bytes
+            );
+            if report.spared > 0 {
+                info!(
+                    "gini: Kept {} unreachable objects written too recently (gc.pruneExpire); --prune-now deletes them.",
+                    report.spared
+                );
+            }
+            if aggressive {
+                info!("gini: Removed {} empty directories.", report.empty_dirs);
+            }
+            if compress {
+                info!("gini: Packed {} objects.", report.packed);
+            }
+            if let Some(problems) = &report.verify_problems {
+                for problem in problems {
+                    println!("{}", problem);
+                }
+                if !problems.is_empty() {
+                    bail!("gc --verify found {} problem(s) after pruning", problems.len());
+                }
+                info!("gini: Verified: every reachable object is present and intact.");
+            }
+        }
+        Commands::Repack { loose: true } => {
+            let unpacked = repo.unpack()?;
+            info!("gini: Unpacked {} objects; the store holds only loose objects now.", unpacked);
+        }
+        Commands::Repack { loose: false } => {
+            let packed = repo.repack()?;
+            info!("gini: Packed {} objects.", packed);
+        }
+        Commands::Fsck => {
+            let problems = repo.fsck()?;
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            if !problems.is_empty() {
+                bail!("fsck found {} problem(s)", problems.len());
+            }
+            info!("gini: No problems found.");
+        }
+        Commands::Doctor { fix } => {
+            let report = repo.doctor(fix)?;
+            for repair in &report.repairable {
+                if fix {
+                    info!("gini: Fixed: {}", repair);
+                } else {
+                    println!("{} (run `gini doctor --fix`)", repair);
+                }
+            }
+            for problem in &report.problems {
+                println!("{}", problem);
+            }
+            let remaining = report.problems.len() + if fix { 0 } else { report.repairable.len() };
+            if remaining > 0 {
+                bail!("doctor found {} problem(s)", remaining);
+            }
+            if report.repairable.is_empty() {
+                info!("gini: No problems found.");
+            } else {
+                info!("gini: No problems remain.");
+            }
+        }
+        Commands::Config { key, value, list } => match (key, value) {
+            (Some(key), Some(value)) => {
+                repo.set_config(&key, &value)?;
+                info!("gini: Set {} = {}", key, value);
+            }
+            (Some(key), None) => match repo.get_config(&key)? {
+                Some(value) => println!("{}", value),
+                None => bail!("{} is not set", key),
+            },
+            (None, _) => {
+                debug_assert!(list, "clap requires a key without --list");
+                for (key, value) in repo.config_values()? {
+                    println!("{} = {}", key, value);
+                }
+            }
+        },
     }
 
     Ok(())
 }
 
-/// Restores the project state from a selected checkpoint using a TUI.
-fn restore_checkpoint_tui() -> Result<()> {
-    let commits = get_commit_history()?;
-    
-    if commits.is_empty() {
-        println!("gini: No checkpoints found to restore.");
-        return Ok(());
-    }
+fn parse_date(value: &str) -> Result<chrono::NaiveDate, String> {
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("expected a date like 2024-01-31, got '{}'", value))
+}
 
-    // Display available checkpoints
-    println!("gini: Available checkpoints:");
-    for (i, (hash, msg)) in commits.iter().enumerate() {
-        println!("  {}. {} - {}", i + 1, &hash[..7], msg);
+/// `watch --interval`: a duration like `30s`, `2m` or a bare number of
+/// seconds, at least one second.
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    match parse_duration(value) {
+        Some(seconds) if seconds > 0 => Ok(Duration::from_secs(seconds)),
+        _ => Err(format!("expected a duration like 30s or 2m, got '{}'", value)),
     }
+}
 
-    // Simple text-based selection
-    println!("\ngini: Enter checkpoint number to restore (1-{}):", commits.len());
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input)?;
-    
-    let selection: usize = input.trim().parse()
-        .map_err(|_| anyhow::anyhow!("Invalid selection"))?;
-    
-    if selection < 1 || selection > commits.len() {
-        bail!("Invalid selection: must be between 1 and {}", commits.len());
+/// `squash`'s `FROM..TO`.
+fn parse_range(value: &str) -> Result<(String, String), String> {
+    match value.split_once("..") {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok((from.to_string(), to.to_string())),
+        _ => Err(format!("expected FROM..TO like a1b2c3d..HEAD, got '{}'", value)),
     }
+}
 
-    let (hash_to_restore, _) = &commits[selection - 1];
-    
-    // Safety confirmation
-    println!("gini: This will overwrite your current files. Type 'yes' to continue:");
-    let mut confirm = String::new();
-    std::io::stdin().read_line(&mut confirm)?;
-    
-    if confirm.trim().to_lowercase() != "yes" {
-        println!("gini: Restore cancelled.");
-        return Ok(());
+/// `blame -L`: `START,END` (inclusive, 1-based) or a single line number.
+fn parse_line_range(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("expected START,END like 10,20, got '{}'", value);
+    let (start, end) = value.split_once(',').unwrap_or((value, value));
+    let start: usize = start.trim().parse().map_err(|_| invalid())?;
+    let end: usize = end.trim().parse().map_err(|_| invalid())?;
+    if start == 0 || end < start {
+        return Err(invalid());
     }
+    Ok((start, end))
+}
 
-    println!("gini: Restoring to checkpoint {}...", hash_to_restore);
-    restore(hash_to_restore)?;
-    println!("gini: Successfully restored project state.");
+/// The first line of a commit message.
+fn subject(message: &str) -> &str {
+    message.lines().next().unwrap_or("")
+}
 
-    Ok(())
+/// The Unix timestamp of local midnight at the start of `date`.
+fn start_of_day(date: chrono::NaiveDate) -> i64 {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+    midnight
+        .and_local_timezone(chrono::Local)
+        .earliest()
+        .map(|time| time.timestamp())
+        .unwrap_or_else(|| midnight.and_utc().timestamp())
 }
 
-/// Restores the project state from a backup using a TUI.
-fn restore_backup_tui() -> Result<()> {
-    let root_path = find_repo_root()?;
-    let backup_dir = root_path.join(".gini/backups");
-    
-    if !backup_dir.exists() {
-        println!("gini: No backups found.");
-        return Ok(());
+/// `restore --at`: a local date, meaning the end of that day, or a date
+/// and time. Returns the Unix timestamp.
+fn parse_moment(value: &str) -> Result<i64, String> {
+    let invalid = || format!("expected a date like 2024-03-01 or 2024-03-01 14:30, got '{}'", value);
+    let moment = match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => date.and_hms_opt(23, 59, 59).expect("23:59:59 is a valid time"),
+        Err(_) => ["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"]
+            .iter()
+            .find_map(|format| chrono::NaiveDateTime::parse_from_str(value, format).ok())
+            .ok_or_else(invalid)?,
+    };
+    Ok(moment
+        .and_local_timezone(chrono::Local)
+        .latest()
+        .map(|time| time.timestamp())
+        .unwrap_or_else(|| moment.and_utc().timestamp()))
+}
+
+/// `gc --prune-now`: `now`, a local date, meaning the start of that day, or
+/// a date and time. Returns the Unix timestamp.
+fn parse_prune_cutoff(value: &str) -> Result<i64, String> {
+    if value == "now" {
+        return Ok(chrono::Local::now().timestamp());
     }
-    
-    let mut backups = Vec::new();
-    for entry in fs::read_dir(&backup_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_dir() {
-            let name = path.file_name().unwrap().to_str().unwrap();
-            if name.starts_with("backup_") {
-                backups.push((name.to_string(), path));
-            }
+    match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => Ok(start_of_day(date)),
+        Err(_) => parse_moment(value),
+    }
+}
+
+/// A Unix timestamp as local `YYYY-MM-DD HH:MM`.
+fn format_moment(timestamp: i64) -> String {
+    match chrono::DateTime::from_timestamp(timestamp, 0) {
+        Some(time) => time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// Opens the repository containing `start`, exiting with a hint to run
+/// `gini init` when there is none.
+fn open_repository(start: &Path) -> Repository {
+    match Repository::discover_from(start) {
+        Ok(repo) => repo,
+        Err(e) if matches!(e.downcast_ref(), Some(GiniError::NotARepository)) => {
+            eprintln!("gini: No .gini project found in this directory.\n--- Run `gini init` first.");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("gini: error: {}", e);
+            std::process::exit(1);
         }
     }
-    
-    if backups.is_empty() {
-        println!("gini: No backups found.");
-        return Ok(());
+}
+
+fn validate_message(message: &str) -> Result<()> {
+    if message.is_empty() {
+        bail!("Commit message cannot be empty");
     }
-    
-    // Sort backups by timestamp (newest first)
-    backups.sort_by(|a, b| b.0.cmp(&a.0));
-    
-    // Display available backups
-    println!("gini: Available backups:");
-    for (i, (name, path)) in backups.iter().enumerate() {
-        let metadata = fs::metadata(path)?;
-        let modified = metadata.modified()?;
-        let datetime: chrono::DateTime<chrono::Local> = chrono::DateTime::from(modified);
-        println!("  {}. {} (created: {})", i + 1, name, datetime.format("%Y-%m-%d %H:%M:%S"));
+    if message.len() > MAX_COMMIT_MESSAGE_LENGTH {
+        bail!("Commit message too long (max {} characters)", MAX_COMMIT_MESSAGE_LENGTH);
     }
+    Ok(())
+}
 
-    // Simple text-based selection
-    println!("\ngini: Enter backup number to restore (1-{}):", backups.len());
-    let mut input = String::new();
-    stdin().read_line(&mut input)?;
-    
-    let selection: usize = input.trim().parse()
-        .map_err(|_| anyhow::anyhow!("Invalid selection"))?;
-    
-    if selection < 1 || selection > backups.len() {
-        bail!("Invalid selection: must be between 1 and {}", backups.len());
+/// The file the checkpoint message is edited in, inside `.gini`.
+const MESSAGE_EDIT_FILE: &str = "CHECKPOINT_MSG";
+
+/// Asks for a checkpoint message in `$VISUAL` or `$EDITOR`, starting from
+/// the `commit.template` file (relative to the project root) if one is
+/// configured. Lines starting with `#` are dropped, and an empty result
+/// aborts.
+fn edit_message(repo: &Repository) -> Result<String> {
+    let template = match repo.get_config("commit.template")? {
+        Some(path) => {
+            let path = repo.root().join(path);
+            fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read commit.template {}", path.display()))?
+        }
+        None => String::new(),
+    };
+    let edit_path = repo.gini_dir().join(MESSAGE_EDIT_FILE);
+    fs::write(
+        &edit_path,
+        format!(
+            "{}\n# Enter the checkpoint message. Lines starting with '#' are ignored,\n\
+             # and an empty message aborts the checkpoint.\n",
+            template.trim_end()
+        ),
+    )
+    .with_context(|| format!("Failed to write {}", edit_path.display()))?;
+
+    let editor = std::env::var("VISUAL")
+        .ok()
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    // Editors are often given with arguments, e.g. `code --wait`.
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(&edit_path)
+        .status()
+        .with_context(|| format!("Failed to start the editor '{}'; set $EDITOR or pass -m", editor))?;
+    if !status.success() {
+        bail!("The editor '{}' exited with {}; checkpoint aborted", editor, status);
     }
 
-    let (_, backup_path) = &backups[selection - 1];
-    
-    // Safety confirmation
-    println!("gini: This will overwrite your current files. Type 'yes' to continue:");
-    let mut confirm = String::new();
-    stdin().read_line(&mut confirm)?;
-    
-    if confirm.trim().to_lowercase() != "yes" {
-        println!("gini: Restore cancelled.");
-        return Ok(());
+    let edited = fs::read_to_string(&edit_path)
+        .with_context(|| format!("Failed to read {}", edit_path.display()))?;
+    let message = edited
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let message = message.trim_matches('\n').to_string();
+    if message.is_empty() {
+        bail!("Aborting checkpoint: the message is empty");
     }
+    Ok(message)
+}
 
-    println!("gini: Restoring from backup {}...", backup_path.file_name().unwrap().to_str().unwrap());
-    restore_from_backup(&root_path, backup_path)?;
-    println!("gini: Successfully restored from backup.");
+/// Reads a checkpoint message from `path`, or from stdin when it is `-`.
+/// Trailing newlines, which editors and `echo` add, are dropped.
+fn read_message_file(path: &Path) -> Result<String> {
+    let mut message = String::new();
+    if path == Path::new("-") {
+        stdin()
+            .read_to_string(&mut message)
+            .context("Failed to read the message from stdin")?;
+    } else {
+        message = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+    }
+    Ok(message.trim_end_matches(['\n', '\r']).to_string())
+}
 
-    Ok(())
+/// The `restore` flags that shape what happens once a checkpoint is chosen.
+#[derive(Debug, Clone)]
+struct RestoreOptions {
+    force: bool,
+    detach: bool,
+    no_move_head: bool,
+    merge: bool,
+    into: Option<PathBuf>,
+    dry_run: bool,
+    yes: bool,
+    no_backup: bool,
+    only_missing: bool,
+    preserve: Vec<String>,
 }
 
-fn restore_from_backup(root_path: &Path, backup_path: &Path) -> Result<()> {
-    // Clean current working directory (excluding .gini)
-    clean_working_directory(root_path)?;
-    
-    // Copy backup contents to root
-    copy_directory_excluding(backup_path, root_path, &[".gini"])?;
-    
-    Ok(())
+/// Offers each hunk of the changes under `paths` in turn, git `add -p`
+/// style, and stages the ones picked. Returns how many files had
+/// something staged.
+fn add_patch(repo: &Repository, paths: &[String], painter: Painter) -> Result<usize> {
+    let files = repo.patch_files(paths)?;
+    if files.is_empty() {
+        info!("gini: No unstaged changes.");
+        return Ok(0);
+    }
+    let mut staged = 0;
+    for file in files {
+        let header = format!("diff --gini a/{0} b/{0}\n", file.path);
+        let Some(hunks) = &file.hunks else {
+            print!("{}", painter.diff(&header));
+            let answer = ask_hunk(&format!("Stage all of {} (it can't be split) [y,n,q]? ", file.path))?;
+            if answer == 'q' {
+                break;
+            }
+            if repo.stage_hunks(&file.path, &[matches!(answer, 'y' | 'a')])? {
+                staged += 1;
+            }
+            continue;
+        };
+        let mut selected = Vec::with_capacity(hunks.len());
+        let mut quit = false;
+        for (i, hunk) in hunks.iter().enumerate() {
+            print!("{}", painter.diff(&format!("{}{}", header, hunk)));
+            let prompt = format!("({}/{}) Stage this hunk [y,n,q,a,d]? ", i + 1, hunks.len());
+            match ask_hunk(&prompt)? {
+                'y' => selected.push(true),
+                'n' => selected.push(false),
+                'a' => selected.resize(hunks.len(), true),
+                'd' => selected.resize(hunks.len(), false),
+                _ => quit = true,
+            }
+            if quit || selected.len() == hunks.len() {
+                break;
+            }
+        }
+        if repo.stage_hunks(&file.path, &selected)? {
+            staged += 1;
+        }
+        if quit {
+            break;
+        }
+    }
+    Ok(staged)
 }
 
-// --- Core VCS Functions ---
+/// Asks one `add -p` question until it gets an answer it knows. The end
+/// of input counts as `q`.
+fn ask_hunk(prompt: &str) -> Result<char> {
+    loop {
+        print!("{}", prompt);
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        if stdin().read_line(&mut answer)? == 0 {
+            println!();
+            return Ok('q');
+        }
+        match answer.trim() {
+            "y" | "n" | "q" | "a" | "d" => return Ok(answer.trim().chars().next().unwrap_or('q')),
+            _ => println!("y - stage this hunk, n - skip it, q - quit, a - stage the rest of the file, d - skip the rest of the file"),
+        }
+    }
+}
 
-pub fn init() -> Result<()> {
-    let gini_path = Path::new(".gini");
-    if gini_path.exists() {
-        bail!("--- .gini already exists.");
+/// Lists what `checkpoint --dry-run` found: the changes against HEAD and
+/// the tree they would make.
+fn print_checkpoint_preview(repo: &Repository, options: &CheckpointOptions) -> Result<()> {
+    let preview = repo.checkpoint_dry_run(options)?;
+    let source = if preview.staged { "the staged files" } else { "the working tree" };
+    if !preview.would_commit {
+        let verb = if preview.staged { "match" } else { "matches" };
+        info!("gini: Nothing to checkpoint, {} {} HEAD.", source, verb);
+    } else {
+        info!("gini: A checkpoint of {} would record:", source);
+        for (letter, path) in &preview.changes {
+            println!("{}\t{}", letter, path);
+        }
+        match is_quiet() {
+            true => println!("{}", preview.tree),
+            false => println!("gini: Tree: {}", preview.tree),
+        }
     }
-    
-    // Create directory structure atomically
-    fs::create_dir(gini_path)
-        .context("Failed to create .gini directory")?;
-    fs::create_dir(gini_path.join("objects"))
-        .context("Failed to create objects directory")?;
-    fs::create_dir_all(gini_path.join("refs/heads"))
-        .context("Failed to create refs directory")?;
-    
-    // Write HEAD file atomically
-    let head_content = "ref: refs/heads/main";
-    let head_path = gini_path.join("HEAD");
-    write_file_atomic(&head_path, head_content.as_bytes())
-        .context("Failed to write HEAD file")?;
-    
-    println!(
-        "gini: Initialized empty .gini project in {}",
-        std::env::current_dir()?.display()
-    );
+    warn_skipped(&preview.skipped, false);
     Ok(())
 }
 
-pub fn ensure_initialized() -> Result<()> {
-    if find_repo_root().is_err() {
-        eprintln!("gini: No .gini project found in this directory.\n--- Run `gini init` first.");
-        std::process::exit(1);
+/// Warns about files a checkpoint left out for size, on stderr when
+/// stdout is kept for the hash.
+fn warn_skipped(skipped: &[(String, u64)], to_stderr: bool) {
+    if skipped.is_empty() {
+        return;
+    }
+    let width = skipped.iter().map(|(path, _)| path.chars().count()).max().unwrap_or(0);
+    let mut warning = format!("gini: warning: left out {} file(s) over the size limit:", skipped.len());
+    for (path, size) in skipped {
+        warning.push_str(&format!("\n  {:<width$}  {:>10}", path, format_bytes(*size)));
+    }
+    match to_stderr {
+        true => eprintln!("{}", warning),
+        false => println!("{}", warning),
+    }
+}
+
+/// Prints what restoring `rev` (or just `path` from it) would do to the
+/// working tree, leaving out the files `preserve` keeps.
+fn print_restore_preview(repo: &Repository, rev: &str, path: Option<&str>, preserve: &[String]) -> Result<()> {
+    let preserved = repo.preserved_files(preserve)?;
+    let mut changes = repo.restore_preview(rev, path)?;
+    changes.retain(|(_, path)| !preserved.contains(path));
+    let hash = repo.resolve_revision(rev)?;
+    if changes.is_empty() {
+        info!("gini: Restoring {} would not change any files.", &hash[..7]);
+        return Ok(());
+    }
+    info!("gini: Restoring {} would:", &hash[..7]);
+    for (letter, path) in changes {
+        let action = match letter {
+            'A' => "create",
+            'D' => "delete",
+            _ => "overwrite",
+        };
+        println!("  {:<9}  {}", action, path);
     }
     Ok(())
 }
 
-pub fn checkpoint(message: &str) -> Result<String> {
-    let root_path = find_repo_root()?;
-    let objects_path = root_path.join(".gini/objects");
-    
-    // Validate objects directory
-    if !objects_path.exists() {
-        bail!("Objects directory not found. Repository may be corrupted.");
+/// Restores the project state (or just `path`) from `rev`, or from a
+/// checkpoint selected in a TUI when no revision is given.
+fn restore_checkpoint_tui(
+    repo: &Repository,
+    rev: Option<&str>,
+    path: Option<&str>,
+    options: RestoreOptions,
+) -> Result<()> {
+    let RestoreOptions { force, detach, no_move_head, merge, into, dry_run, yes, no_backup, only_missing, preserve } =
+        options;
+    let relative = path.map(|p| repo.relative_path(p)).transpose()?;
+    let hash_to_restore = match rev {
+        Some(rev) => repo.resolve_revision(rev)?,
+        None => match select_checkpoint(repo)? {
+            Some(hash) => hash,
+            None => return Ok(()),
+        },
+    };
+
+    if only_missing {
+        let short = &hash_to_restore[..7];
+        if dry_run {
+            let missing = repo.missing_files(&hash_to_restore)?;
+            if missing.is_empty() {
+                info!("gini: No files of checkpoint {} are missing.", short);
+                return Ok(());
+            }
+            info!("gini: Restoring the missing files of {} would:", short);
+            for path in missing {
+                println!("  {:<9}  {}", "create", path);
+            }
+            return Ok(());
+        }
+        let restored = repo.restore_missing(&hash_to_restore)?;
+        for path in &restored {
+            info!("gini: Restored {}", path);
+        }
+        match restored.len() {
+            0 => info!("gini: No files of checkpoint {} are missing.", short),
+            count => info!("gini: Restored {} missing file(s) from checkpoint {}.", count, short),
+        }
+        return Ok(());
+    }
+
+    if dry_run {
+        return print_restore_preview(repo, &hash_to_restore, relative.as_deref(), &preserve);
+    }
+
+    if let Some(relative) = relative {
+        if repo.root().join(&relative).exists() && !yes && !confirm_overwrite()? {
+            info!("gini: Restore cancelled.");
+            return Ok(());
+        }
+        repo.restore_path(&hash_to_restore, &relative)?;
+        if fs::symlink_metadata(repo.root().join(&relative)).is_ok() {
+            info!("gini: Restored {} from checkpoint {}.", relative, &hash_to_restore[..7]);
+        } else {
+            info!("gini: Deleted {}, which checkpoint {} does not have.", relative, &hash_to_restore[..7]);
+        }
+        return Ok(());
     }
     
-    let tree_hash = write_tree(&root_path, &objects_path)?;
-    let parent_hash = get_head_commit(&root_path)?;
-    
-    // Get author info from environment or use defaults
-    let author_name = std::env::var("GINI_AUTHOR_NAME")
-        .unwrap_or_else(|_| "Unknown".to_string());
-    let author_email = std::env::var("GINI_AUTHOR_EMAIL")
-        .unwrap_or_else(|_| "unknown@example.com".to_string());
-    
-    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    if let Some(dir) = into {
+        let dir = repo.restore_into(&hash_to_restore, &dir, force)?;
+        info!("gini: Wrote checkpoint {} into {}.", &hash_to_restore[..7], dir.display());
+        return Ok(());
+    }
 
-    let parent_line = parent_hash
-        .map(|h| format!("parent {}\n", h))
-        .unwrap_or_default();
+    if merge {
+        let report = repo.restore_merge(&hash_to_restore, detach, !no_backup)?;
+        if let Some(backup) = &report.backup {
+            info!("gini: Created backup at {:?}", backup);
+        }
+        info!(
+            "gini: Restored checkpoint {}, updating {} file(s) and keeping local edits.",
+            &hash_to_restore[..7],
+            report.updated.len()
+        );
+        if !report.conflicts.is_empty() {
+            for path in &report.conflicts {
+                eprintln!("gini: Conflict in {}", path);
+            }
+            bail!("Your edits to the files above overlap the checkpoint's changes; fix them by hand");
+        }
+        return Ok(());
+    }
 
-    let commit_content = format!(
-        "tree {}\n{}author {} <{}> {} +0530\n\n{}",
-        tree_hash, parent_line, author_name, author_email, timestamp, message
-    );
+    // Refuse before prompting rather than after the user has said yes.
+    if !force {
+        let preserved = repo.preserved_files(&preserve)?;
+        let mut lost = repo.files_lost_by_restore(&hash_to_restore)?;
+        lost.retain(|path| !preserved.contains(path));
+        if !lost.is_empty() {
+            bail!("{}", describe_lost_files(&lost));
+        }
+    }
 
-    let commit_hash = hash_and_write_object(&objects_path, commit_content.as_bytes())?;
-    update_head(&root_path, &commit_hash)?;
-    Ok(commit_hash)
-}
+    // Safety confirmation, sterner when there's no backup to fall back on.
+    let confirmed = if yes {
+        true
+    } else if no_backup || !repo.backs_up_restores()? {
+        confirm(
+            "No backup will be taken (--no-backup or core.backupOnRestore = false): \
+             your current files will be overwritten for good. Continue?",
+        )?
+    } else {
+        confirm_overwrite()?
+    };
+    if !confirmed {
+        info!("gini: Restore cancelled.");
+        return Ok(());
+    }
 
-pub fn restore(commit_hash: &str) -> Result<()> {
-    // Validate commit hash
-    if !is_valid_hash(commit_hash) {
-        bail!("Invalid commit hash: {}", commit_hash);
+    info!("gini: Restoring to checkpoint {}...", hash_to_restore);
+    // Already checked above.
+    let backup_path = if no_move_head {
+        repo.restore_files(&hash_to_restore, true, !no_backup, &preserve)?
+    } else {
+        repo.restore(&hash_to_restore, true, detach, !no_backup, &preserve)?
+    };
+    if let Some(backup_path) = backup_path {
+        info!("gini: Created backup at {:?}", backup_path);
     }
-    
-    let root_path = find_repo_root()?;
-    let objects_path = root_path.join(".gini/objects");
-    
-    // Verify commit exists
-    let commit_path = objects_path.join(commit_hash);
-    if !commit_path.exists() {
-        bail!("Commit not found: {}", commit_hash);
+    if repo.verifies_restores()? {
+        verify_restore(repo, &hash_to_restore)?;
+    }
+    info!("gini: Successfully restored project state.");
+    if no_move_head {
+        info!("gini: HEAD was left where it was; checkpoint to record these files on top of it.");
+    } else if detach {
+        info!("gini: HEAD is now detached at {}.", &hash_to_restore[..7]);
     }
-    
-    let commit_content = read_object(&objects_path, commit_hash)?;
-    let tree_hash = parse_commit_tree(&commit_content)?;
 
-    // Create backup before destructive operation
-    create_backup(&root_path)?;
-    
-    clean_working_directory(&root_path)?;
-    restore_tree(&root_path, &objects_path, &tree_hash)?;
-    update_head(&root_path, commit_hash)?;
     Ok(())
 }
 
-pub fn log() -> Result<String> {
-    let root_path = find_repo_root()?;
-    let mut history = String::new();
-    let mut current_commit_hash: Option<String> = get_head_commit(&root_path)?;
-
-    while let Some(hash) = current_commit_hash {
-        let commit_content = read_object(&root_path.join(".gini/objects"), &hash)?;
-        let (parent, author, message) = parse_commit_details(&commit_content)?;
-        history.push_str(&format!(
-            "checkpoint {}\nAuthor: {}\n\n\t{}\n\n",
-            hash, author, message
-        ));
-        current_commit_hash = parent;
+/// Fails, listing the differences, unless the working tree matches
+/// checkpoint `hash` exactly.
+fn verify_restore(repo: &Repository, hash: &str) -> Result<()> {
+    let mismatches = repo.verify_restore(hash)?;
+    if mismatches.is_empty() {
+        return Ok(());
     }
-    Ok(history)
+    for (letter, path) in &mismatches {
+        let problem = match letter {
+            'A' => "extra",
+            'D' => "missing",
+            _ => "differs",
+        };
+        println!("  {:<7}  {}", problem, path);
+    }
+    bail!("The working tree doesn't match checkpoint {}: {} file(s) differ", &hash[..7], mismatches.len())
 }
 
-pub fn get_commit_history() -> Result<Vec<(String, String)>> {
-    let root_path = find_repo_root()?;
-    let mut history = Vec::new();
-    let mut current_commit_hash: Option<String> = get_head_commit(&root_path)?;
+/// Lets the user pick a checkpoint from the history, printing why when
+/// nothing is picked.
+fn select_checkpoint(repo: &Repository) -> Result<Option<String>> {
+    let commits = repo.get_commit_history()?;
+    
+    if commits.is_empty() {
+        info!("gini: No checkpoints found to restore.");
+        return Ok(None);
+    }
 
-    while let Some(hash) = current_commit_hash {
-        let commit_content = read_object(&root_path.join(".gini/objects"), &hash)?;
-        let (parent, _, message) = parse_commit_details(&commit_content)?;
-        history.push((hash, message.lines().next().unwrap_or("").to_string()));
-        current_commit_hash = parent;
+    let items: Vec<String> = commits
+        .iter()
+        .map(|commit| {
+            format!(
+                "{} - {} ({})",
+                &commit.hash[..7],
+                commit.subject,
+                relative_time(commit.timestamp, chrono::Utc::now().timestamp())
+            )
+        })
+        .collect();
+    match select_item("Select a checkpoint to restore", "checkpoint", &items)? {
+        Some(selection) => Ok(Some(commits[selection].hash.clone())),
+        None => {
+            info!("gini: Restore cancelled.");
+            Ok(None)
+        }
     }
-    Ok(history)
 }
 
-// --- Internal Helper Functions ---
+/// `log --interactive`: a menu of checkpoints, each opening a menu of
+/// things to do with it, until the user backs out of the list.
+fn browse_log(repo: &Repository, options: &LogOptions, painter: Painter) -> Result<()> {
+    if !is_interactive() {
+        bail!("log --interactive needs a terminal");
+    }
+    let commits = repo.commit_history(options)?;
+    if commits.is_empty() {
+        info!("gini: No checkpoints to browse.");
+        return Ok(());
+    }
+    let now = chrono::Utc::now().timestamp();
+    let items: Vec<String> = commits
+        .iter()
+        .map(|commit| {
+            format!("{}  {:<14}  {}", &commit.hash[..7], relative_time(commit.timestamp, now), commit.subject)
+        })
+        .collect();
+    let actions = ["Show details and diff", "List files", "Print full hash", "Restore this checkpoint", "Back"];
 
-fn find_repo_root() -> Result<PathBuf> {
-    let mut current_dir = std::env::current_dir()?;
-    let mut depth = 0;
-    const MAX_DEPTH: u32 = 100; // Prevent infinite loops
-    
+    let mut selected = 0;
     loop {
-        if current_dir.join(".gini").is_dir() {
-            return Ok(current_dir);
-        }
-        if !current_dir.pop() || depth >= MAX_DEPTH {
-            bail!("Not a Gini repository.");
+        let Some(choice) = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Checkpoints (Esc to quit)")
+            .items(&items)
+            .default(selected)
+            .interact_opt()?
+        else {
+            return Ok(());
+        };
+        selected = choice;
+        let hash = &commits[choice].hash;
+        loop {
+            let action = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("{} {}", &hash[..7], commits[choice].subject))
+                .items(&actions)
+                .default(0)
+                .interact_opt()?;
+            match action {
+                Some(0) => print!("{}", painter.diff(&repo.show(hash, false, true)?)),
+                Some(1) => print!("{}", painter.diff(&repo.show(hash, true, false)?)),
+                Some(2) => println!("{}", hash),
+                Some(3) => {
+                    let options = RestoreOptions {
+                        force: false,
+                        detach: false,
+                        no_move_head: false,
+                        merge: false,
+                        into: None,
+                        dry_run: false,
+                        yes: false,
+                        no_backup: false,
+                        only_missing: false,
+                        preserve: Vec::new(),
+                    };
+                    return restore_checkpoint_tui(repo, Some(hash), None, options);
+                }
+                _ => break,
+            }
         }
-        depth += 1;
     }
 }
 
-fn is_valid_hash(hash: &str) -> bool {
-    hash.len() == HASH_LENGTH && hash.chars().all(|c| c.is_ascii_hexdigit())
+/// Watches the working tree until interrupted, and once no file has changed
+/// for `interval` after an edit, checkpoints whatever changed. Changes
+/// already there when watching starts count as one.
+fn watch(repo: &Repository, interval: Duration) -> Result<()> {
+    use notify::{EventKind, RecursiveMode, Watcher};
+    use std::sync::mpsc::RecvTimeoutError;
+
+    let (sender, events) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).context("Failed to watch the working tree")?;
+    watcher
+        .watch(repo.root(), RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", repo.root().display()))?;
+    // Reads, and gini's own writes under `.gini` (or git's), aren't edits.
+    let (gini_dir, git_dir) = (repo.gini_dir(), repo.root().join(".git"));
+    let is_edit = |event: &notify::Event| {
+        !matches!(event.kind, EventKind::Access(_))
+            && event.paths.iter().any(|path| !path.starts_with(&gini_dir) && !path.starts_with(&git_dir))
+    };
+
+    let mut settle_at = (!repo.status()?.is_clean()).then(|| Instant::now() + interval);
+    info!(
+        "gini: Watching {}; checkpointing after {}s without changes. Press Ctrl-C to stop.",
+        repo.root().display(),
+        interval.as_secs()
+    );
+    loop {
+        let event = match settle_at {
+            Some(at) => events.recv_timeout(at.saturating_duration_since(Instant::now())),
+            None => events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match event {
+            Ok(Ok(event)) => {
+                if is_edit(&event) {
+                    settle_at = Some(Instant::now() + interval);
+                }
+                continue;
+            }
+            // Events may have been dropped, so assume something changed.
+            Ok(Err(e)) => {
+                eprintln!("gini: warning: watching the working tree: {}", e);
+                settle_at = Some(Instant::now() + interval);
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => bail!("Stopped receiving file change events"),
+        }
+        settle_at = None;
+        // A file that vanishes mid-scan, say, is worth another look later
+        // rather than the end of watching.
+        let status = match repo.status() {
+            Ok(status) => status,
+            Err(e) => {
+                eprintln!("gini: warning: could not check for changes: {}", e);
+                settle_at = Some(Instant::now() + interval);
+                continue;
+            }
+        };
+        if status.is_clean() {
+            continue;
+        }
+        let message = watch_message(&status);
+        // Keep watching through a failed checkpoint, e.g. while another
+        // gini command holds the lock; the next change tries again.
+        match repo.checkpoint(&message, &CheckpointOptions::default()) {
+            Ok(report) => match report.commit {
+                Some(commit_hash) if is_quiet() => println!("{}", commit_hash),
+                Some(commit_hash) => println!(
+                    "gini: {} Checkpoint {}: {}",
+                    chrono::Local::now().format("%H:%M:%S"),
+                    &commit_hash[..7],
+                    message
+                ),
+                None => {}
+            },
+            Err(e) => eprintln!("gini: warning: automatic checkpoint failed: {}", e),
+        }
+    }
 }
 
-fn write_file_atomic(path: &Path, content: &[u8]) -> Result<()> {
-    let temp_path = path.with_extension("tmp");
-    fs::write(&temp_path, content)?;
-    fs::rename(temp_path, path)?;
-    Ok(())
+/// The message for a `gini watch` checkpoint: how many paths changed, and
+/// the first few of them in order.
+fn watch_message(status: &Status) -> String {
+    let paths: std::collections::BTreeSet<&str> = status
+        .staged
+        .iter()
+        .chain(&status.unstaged)
+        .map(|(_, path)| path.as_str())
+        .chain(status.untracked.iter().map(String::as_str))
+        .collect();
+    let mut listed = paths.iter().take(3).copied().collect::<Vec<_>>().join(", ");
+    if paths.len() > 3 {
+        listed.push_str(", ...");
+    }
+    format!("Auto-checkpoint: {} file(s) changed ({})", paths.len(), listed)
 }
 
-fn create_backup(root_path: &Path) -> Result<()> {
-    let backup_dir = root_path.join(".gini/backups");
-    fs::create_dir_all(&backup_dir)?;
-    
-    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-    let backup_path = backup_dir.join(format!("backup_{}", timestamp));
-    
-    // Copy current state to backup
-    copy_directory_excluding(root_path, &backup_path, &[".gini"])?;
-    println!("gini: Created backup at {:?}", backup_path);
-    Ok(())
+fn print_fetch_report(report: &FetchReport) {
+    info!("gini: Fetched {} objects from {}.", report.objects, report.path.display());
+    for branch in &report.updated {
+        match &branch.old {
+            Some(old) => info!("  {}/{}: {}..{}", report.remote, branch.name, &old[..7], &branch.new[..7]),
+            None => info!("  {}/{}: new branch at {}", report.remote, branch.name, &branch.new[..7]),
+        }
+    }
+    for name in &report.pruned {
+        info!("  {}/{}: deleted", report.remote, name);
+    }
 }
 
-fn copy_directory_excluding(src: &Path, dst: &Path, exclude: &[&str]) -> Result<()> {
-    if src.is_file() {
-        fs::copy(src, dst)?;
+/// Restores the project state from a backup using a TUI.
+fn restore_backup_tui(repo: &Repository) -> Result<()> {
+    let backups = repo.backups()?;
+    
+    if backups.is_empty() {
+        info!("gini: No backups found.");
         return Ok(());
     }
     
-    fs::create_dir_all(dst)?;
-    
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let path = entry.path();
-        let name = path.file_name().unwrap().to_str().unwrap();
-        
-        if exclude.contains(&name) {
-            continue;
+    let mut items = Vec::new();
+    for backup in &backups {
+        let metadata = fs::metadata(&backup.path)?;
+        let modified = metadata.modified()?;
+        let datetime: chrono::DateTime<chrono::Local> = chrono::DateTime::from(modified);
+        let mut item = backup.name.clone();
+        if let Some(label) = &backup.label {
+            item.push_str(&format!(" [{}]", label));
         }
-        
-        let dst_path = dst.join(name);
-        if path.is_dir() {
-            copy_directory_excluding(&path, &dst_path, exclude)?;
-        } else {
-            fs::copy(&path, &dst_path)?;
+        item.push_str(&format!(" (created: {})", datetime.format("%Y-%m-%d %H:%M:%S")));
+        if let Some(message) = &backup.message {
+            item.push_str(&format!(" - {}", message));
         }
+        items.push(item);
     }
-    Ok(())
-}
+    let selection = match select_item("Select a backup to restore", "backup", &items)? {
+        Some(selection) => selection,
+        None => {
+            info!("gini: Restore cancelled.");
+            return Ok(());
+        }
+    };
 
-fn hash_and_write_object(objects_path: &Path, content: &[u8]) -> Result<String> {
-    // Check file size limit
-    if content.len() as u64 > MAX_FILE_SIZE {
-        bail!("File too large (max {} bytes)", MAX_FILE_SIZE);
-    }
+    let backup = &backups[selection];
     
-    let mut hasher = Sha1::new();
-    hasher.update(content);
-    let hash_string = hex::encode(hasher.finalize());
-    
-    // Validate hash format
-    if !is_valid_hash(&hash_string) {
-        bail!("Generated invalid hash: {}", hash_string);
+    // Safety confirmation
+    if !confirm_overwrite()? {
+        info!("gini: Restore cancelled.");
+        return Ok(());
     }
-    
-    let object_file_path = objects_path.join(&hash_string);
 
-    if !object_file_path.exists() {
-        let temp_path = object_file_path.with_extension("tmp");
-        fs::write(&temp_path, content)?;
-        fs::rename(temp_path, &object_file_path)?;
+    info!("gini: Restoring from backup {}...", backup.name);
+    repo.restore_backup(backup)?;
+    info!("gini: Successfully restored from backup.");
+
+    Ok(())
+}
+
+/// `--verbose` progress, on stderr so it never mixes with command output.
+fn print_preflight(totals: &TreeTotals) {
+    eprintln!("gini: about to record {} files ({})", totals.files, format_bytes(totals.bytes));
+}
+
+fn print_progress(progress: &Progress) {
+    if !progress.is_dir {
+        eprintln!("gini: recording {}", progress.path);
+        return;
     }
-    Ok(hash_string)
+    let dir = if progress.path.is_empty() { "." } else { progress.path };
+    eprintln!(
+        "gini: finished {}/ ({} files, {} hashed so far)",
+        dir,
+        progress.files,
+        format_bytes(progress.bytes_hashed)
+    );
 }
 
-fn read_object(objects_path: &Path, hash: &str) -> Result<String> {
-    // Validate hash
-    if !is_valid_hash(hash) {
-        bail!("Invalid hash format: {}", hash);
+/// A byte count in the largest binary unit that keeps it at least 1.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
     }
-    
-    let path = objects_path.join(hash);
-    if !path.exists() {
-        bail!("Object not found: {}", hash);
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
     }
-    
-    let content = fs::read_to_string(&path)
-        .with_context(|| format!("Failed to read object: {}", hash))?;
-    Ok(content)
+    format!("{:.1} {}", value, UNITS[unit])
 }
 
-fn write_tree(dir_path: &Path, objects_path: &Path) -> Result<String> {
-    let mut entries = BTreeMap::new();
-    
-    for entry in fs::read_dir(dir_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        let file_name = path.file_name()
-            .and_then(|n| n.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid filename"))?;
-
-        if [".gini", ".git", "target"].contains(&file_name) {
-            continue;
+fn print_status(status: &Status, painter: Painter) {
+    if status.is_clean() {
+        info!("gini: Nothing changed since the last checkpoint.");
+        return;
+    }
+    if !status.has_index {
+        println!("Changes since last checkpoint:");
+        for (letter, path) in &status.unstaged {
+            println!("  {}  {}", painter.change_letter(*letter), path);
         }
+        return;
+    }
 
-        if path.is_dir() {
-            let sub_tree_hash = write_tree(&path, objects_path)?;
-            entries.insert(file_name.to_string(), format!("tree {}", sub_tree_hash));
-        } else {
-            // Check file size before reading
-            let metadata = fs::metadata(&path)?;
-            if metadata.len() > MAX_FILE_SIZE {
-                bail!("File too large: {} (max {} bytes)", path.display(), MAX_FILE_SIZE);
+    let sections = [
+        ("Changes staged for checkpoint:", &status.staged),
+        ("Changes not staged:", &status.unstaged),
+    ];
+    for (title, changes) in sections {
+        if !changes.is_empty() {
+            println!("{}", title);
+            for (letter, path) in changes {
+                println!("  {}  {}", painter.change_letter(*letter), path);
             }
-            
-            let content = fs::read(&path)?;
-            let blob_hash = hash_and_write_object(objects_path, &content)?;
-            entries.insert(file_name.to_string(), format!("blob {}", blob_hash));
         }
     }
-    
-    let tree_content = entries
-        .iter()
-        .map(|(name, entry)| format!("{}  {}", entry, name))
-        .collect::<Vec<_>>()
-        .join("\n");
-    hash_and_write_object(objects_path, tree_content.as_bytes())
+    if !status.untracked.is_empty() {
+        println!("Untracked files:");
+        for path in &status.untracked {
+            println!("  {} {}", painter.paint(Color::Red, "??"), path);
+        }
+    }
 }
 
-fn restore_tree(target_dir: &Path, objects_path: &Path, tree_hash: &str) -> Result<()> {
-    if !is_valid_hash(tree_hash) {
-        bail!("Invalid tree hash: {}", tree_hash);
+/// `status --porcelain`: one `XY path` line per changed path, sorted by
+/// path, where X is the staged change and Y the unstaged one. Without a
+/// staging index every change is unstaged.
+fn print_porcelain_status(status: &Status) {
+    let mut lines: std::collections::BTreeMap<&str, (char, char)> = Default::default();
+    for (letter, path) in &status.staged {
+        lines.entry(path).or_insert((' ', ' ')).0 = *letter;
     }
-    
-    let tree_content = read_object(objects_path, tree_hash)?;
-    
-    for line in tree_content.lines() {
-        let parts: Vec<_> = line.split_whitespace().collect();
-        if parts.len() != 3 {
-            bail!("Invalid tree entry format: {}", line);
-        }
-        
-        let (obj_type, hash, name) = (parts[0], parts[1], parts[2]);
-        
-        // Validate object type
-        if obj_type != "tree" && obj_type != "blob" {
-            bail!("Invalid object type: {}", obj_type);
-        }
-        
-        // Validate hash
-        if !is_valid_hash(hash) {
-            bail!("Invalid hash in tree: {}", hash);
-        }
-        
-        // Validate filename
-        if name.is_empty() || name.contains('/') || name.contains('\\') {
-            bail!("Invalid filename in tree: {}", name);
-        }
-        
-        let path = target_dir.join(name);
-
-        if obj_type == "tree" {
-            fs::create_dir_all(&path)?;
-            restore_tree(&path, objects_path, hash)?;
-        } else {
-            let blob_content = read_object_raw(objects_path, hash)?;
-            fs::write(path, blob_content)?;
-        }
+    for (letter, path) in &status.unstaged {
+        lines.entry(path).or_insert((' ', ' ')).1 = *letter;
+    }
+    for path in &status.untracked {
+        lines.insert(path, ('?', '?'));
+    }
+    for (path, (staged, unstaged)) in lines {
+        println!("{}{} {}", staged, unstaged, path);
     }
-    Ok(())
 }
 
-fn read_object_raw(objects_path: &Path, hash: &str) -> Result<Vec<u8>> {
-    if !is_valid_hash(hash) {
-        bail!("Invalid hash format: {}", hash);
+// --- Colors ---
+
+/// ANSI escape codes for the few styles gini uses.
+#[derive(Clone, Copy)]
+enum Color {
+    Bold = 1,
+    Red = 31,
+    Green = 32,
+    Yellow = 33,
+    Cyan = 36,
+}
+
+/// Colors command output when it is going to a terminal. All coloring goes
+/// through here so every command looks the same and honors `--no-color`.
+#[derive(Clone, Copy)]
+struct Painter {
+    enabled: bool,
+}
+
+impl Painter {
+    fn new(no_color: bool) -> Self {
+        // https://no-color.org: any non-empty value disables color.
+        let env_disabled = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Painter {
+            enabled: !no_color && !env_disabled && std::io::stdout().is_terminal(),
+        }
     }
-    
-    let path = objects_path.join(hash);
-    if !path.exists() {
-        bail!("Object not found: {}", hash);
+
+    fn paint(self, color: Color, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", color as u8, text)
+        } else {
+            text.to_string()
+        }
     }
-    
-    let content = fs::read(&path)
-        .with_context(|| format!("Failed to read object: {}", hash))?;
-    Ok(content)
-}
-
-fn clean_working_directory(root_path: &Path) -> Result<()> {
-    for entry in fs::read_dir(root_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        let file_name = path.file_name()
-            .and_then(|n| n.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid filename"))?;
-            
-        if file_name != ".gini" && file_name != ".git" {
-            if path.is_dir() {
-                fs::remove_dir_all(&path)?;
-            } else {
-                fs::remove_file(&path)?;
+
+    /// Green for added, red for deleted, yellow for anything else.
+    fn change_letter(self, letter: char) -> String {
+        let color = match letter {
+            'A' => Color::Green,
+            'D' => Color::Red,
+            _ => Color::Yellow,
+        };
+        self.paint(color, &letter.to_string())
+    }
+
+    /// `<short-hash> <subject>` lines from `log --oneline`.
+    fn oneline_log(self, text: &str) -> String {
+        map_lines(text, |line| {
+            // `--graph` draws `* ` before each entry and `| ` below it.
+            if line.starts_with('|') {
+                return line.to_string();
             }
-        }
+            let (graph, line) = match line.strip_prefix("* ") {
+                Some(rest) => ("* ", rest),
+                None => ("", line),
+            };
+            let painted = match line.split_once(' ') {
+                // `--stat` summaries are indented under their checkpoint.
+                _ if line.starts_with(' ') => line.to_string(),
+                Some((hash, subject)) => format!("{} {}", self.paint(Color::Yellow, hash), subject),
+                None => self.paint(Color::Yellow, line),
+            };
+            format!("{}{}", graph, painted)
+        })
     }
-    Ok(())
-}
 
-fn get_head_commit(root_path: &Path) -> Result<Option<String>> {
-    let head_path = root_path.join(".gini/HEAD");
-    if !head_path.exists() {
-        return Ok(None);
+    /// The `checkpoint <hash>` and `Author:` header lines of `log` and
+    /// `show`; everything else is left alone.
+    fn headers(self, text: &str) -> String {
+        let mut after_checkpoint = false;
+        map_lines(text, |line| {
+            let was_after_checkpoint = std::mem::take(&mut after_checkpoint);
+            // `log --graph` puts `* ` before the checkpoint line and `| ` (or,
+            // for the last entry, two spaces) before the rest.
+            let (graph, rest) = match line.strip_prefix("* ") {
+                Some(rest) => ("* ", rest),
+                None => ("", line),
+            };
+            if let Some(hash) = rest.strip_prefix("checkpoint ") {
+                let hash = hash.strip_suffix(" (detached)").unwrap_or(hash);
+                if matches!(hash.len(), 40 | 64) && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    after_checkpoint = true;
+                    return format!("{}{}", graph, self.paint(Color::Yellow, rest));
+                }
+            }
+            if !was_after_checkpoint {
+                return line.to_string();
+            }
+            let (graph, rest) = ["| ", "  ", ""]
+                .into_iter()
+                .find_map(|prefix| line.strip_prefix(prefix).map(|rest| (prefix, rest)))
+                .unwrap_or(("", line));
+            match rest.strip_prefix("Author: ") {
+                Some(author) => format!("{}Author: {}", graph, self.paint(Color::Cyan, author)),
+                None => line.to_string(),
+            }
+        })
     }
-    
-    let head_content = fs::read_to_string(&head_path)?;
-    if let Some(ref_path_str) = head_content.strip_prefix("ref: ") {
-        let ref_path = root_path.join(".gini").join(ref_path_str.trim());
-        if ref_path.exists() {
-            let content = fs::read_to_string(&ref_path)?;
-            let hash = content.trim();
-            if is_valid_hash(hash) {
-                Ok(Some(hash.to_string()))
+
+    /// Output of `diff` and `show`: checkpoint headers, `<letter>\t<path>`
+    /// lines, and unified diffs.
+    fn diff(self, text: &str) -> String {
+        let text = self.headers(text);
+        let mut in_patch = false;
+        map_lines(&text, |line| {
+            if line.starts_with("diff --gini ") {
+                in_patch = true;
+                return self.paint(Color::Bold, line);
+            }
+            if !in_patch {
+                let mut chars = line.chars();
+                return match (chars.next(), chars.next()) {
+                    (Some(letter), Some('\t')) if letter.is_ascii_uppercase() => {
+                        format!("{}{}", self.change_letter(letter), &line[1..])
+                    }
+                    _ => line.to_string(),
+                };
+            }
+            if line.starts_with("+++ ") || line.starts_with("--- ") {
+                self.paint(Color::Bold, line)
+            } else if line.starts_with("@@") {
+                self.paint(Color::Cyan, line)
+            } else if line.starts_with('+') {
+                self.paint(Color::Green, line)
+            } else if line.starts_with('-') {
+                self.paint(Color::Red, line)
             } else {
-                bail!("Invalid hash in ref file: {}", hash);
+                line.to_string()
             }
-        } else {
-            Ok(None)
-        }
-    } else if head_content.len() == HASH_LENGTH {
-        let hash = head_content.trim();
-        if is_valid_hash(hash) {
-            Ok(Some(hash.to_string()))
-        } else {
-            bail!("Invalid hash in HEAD: {}", hash);
-        }
-    } else {
-        bail!("Invalid HEAD format")
+        })
     }
-}
 
-fn update_head(root_path: &Path, commit_hash: &str) -> Result<()> {
-    if !is_valid_hash(commit_hash) {
-        bail!("Invalid commit hash: {}", commit_hash);
+    /// Output of `diff --word-diff`: file headers as in [`Painter::diff`],
+    /// then `[-removed-]` words in red and `{+added+}` words in green. A
+    /// marked run may span lines, so one left open carries over.
+    fn word_diff(self, text: &str) -> String {
+        const MARKERS: [(&str, &str, Color); 2] = [("[-", "-]", Color::Red), ("{+", "+}", Color::Green)];
+        let mut in_hunk = false;
+        let mut open: Option<(&str, Color)> = None;
+        map_lines(text, |line| {
+            if open.is_none() {
+                if line.starts_with("diff --gini ") {
+                    in_hunk = false;
+                    return self.paint(Color::Bold, line);
+                }
+                if line.starts_with("@@") {
+                    in_hunk = true;
+                    return self.paint(Color::Cyan, line);
+                }
+                if !in_hunk {
+                    let is_header = line.starts_with("+++ ") || line.starts_with("--- ");
+                    return if is_header { self.paint(Color::Bold, line) } else { line.to_string() };
+                }
+            }
+            let mut out = String::new();
+            let mut rest = line;
+            // Where to look for the close marker: past a marker just opened.
+            let mut skip = 0;
+            while !rest.is_empty() {
+                match open {
+                    None => {
+                        let next = MARKERS
+                            .iter()
+                            .filter_map(|&(start, close, color)| rest.find(start).map(|at| (at, close, color)))
+                            .min_by_key(|&(at, _, _)| at);
+                        match next {
+                            Some((at, close, color)) => {
+                                out.push_str(&rest[..at]);
+                                rest = &rest[at..];
+                                open = Some((close, color));
+                                skip = 2;
+                            }
+                            None => {
+                                out.push_str(rest);
+                                rest = "";
+                            }
+                        }
+                    }
+                    Some((close, color)) => match rest[skip..].find(close) {
+                        Some(end) => {
+                            let end = skip + end + close.len();
+                            out.push_str(&self.paint(color, &rest[..end]));
+                            rest = &rest[end..];
+                            open = None;
+                            skip = 0;
+                        }
+                        None => {
+                            out.push_str(&self.paint(color, rest));
+                            rest = "";
+                        }
+                    },
+                }
+            }
+            out
+        })
     }
-    
-    let head_path = root_path.join(".gini/HEAD");
-    let head_content = fs::read_to_string(&head_path)?;
-    let ref_path_str = head_content
-        .strip_prefix("ref: ")
-        .ok_or_else(|| anyhow::anyhow!("Detached HEAD not supported for updates"))?;
-    let ref_path = root_path.join(".gini").join(ref_path_str.trim());
-    
-    // Write atomically
-    write_file_atomic(&ref_path, commit_hash.as_bytes())?;
-    Ok(())
 }
 
-fn parse_commit_tree(commit_content: &str) -> Result<String> {
-    let tree_line = commit_content
-        .lines()
-        .find(|line| line.starts_with("tree "))
-        .ok_or_else(|| anyhow::anyhow!("Could not find tree in commit object"))?;
-    
-    let parts: Vec<_> = tree_line.split_whitespace().collect();
-    if parts.len() != 2 {
-        bail!("Invalid tree line format: {}", tree_line);
+/// Rewrites each line of `text`, keeping its line endings.
+fn map_lines(text: &str, mut f: impl FnMut(&str) -> String) -> String {
+    text.split_inclusive('\n')
+        .map(|line| match line.strip_suffix('\n') {
+            Some(line) => f(line) + "\n",
+            None => f(line),
+        })
+        .collect()
+}
+
+// --- Prompt Helpers ---
+
+/// Arrow-key menus only work on a real terminal; piped input falls back to
+/// the numbered text prompts.
+fn is_interactive() -> bool {
+    stdin().is_terminal() && std::io::stdout().is_terminal()
+}
+
+/// Lets the user pick one of `items`, returning its index or `None` if the
+/// selection was cancelled.
+fn select_item(prompt: &str, noun: &str, items: &[String]) -> Result<Option<usize>> {
+    if is_interactive() {
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(items)
+            .default(0)
+            .interact_opt()?;
+        return Ok(selection);
     }
-    
-    let hash = parts[1];
-    if !is_valid_hash(hash) {
-        bail!("Invalid tree hash in commit: {}", hash);
+
+    println!("gini: Available {}s:", noun);
+    for (i, item) in items.iter().enumerate() {
+        println!("  {}. {}", i + 1, item);
     }
+
+    // Simple text-based selection
+    println!("\ngini: Enter {} number to restore (1-{}):", noun, items.len());
+    let mut input = String::new();
+    stdin().read_line(&mut input)?;
+    
+    let selection: usize = input.trim().parse()
+        .map_err(|_| anyhow::anyhow!("Invalid selection"))?;
     
-    Ok(hash.to_string())
+    if selection < 1 || selection > items.len() {
+        bail!("Invalid selection: must be between 1 and {}", items.len());
+    }
+    Ok(Some(selection - 1))
 }
 
-fn parse_commit_details(commit_content: &str) -> Result<(Option<String>, String, String)> {
-    let mut parent = None;
-    let mut author = String::new();
-    let mut message_lines = Vec::new();
-    let mut in_message = false;
+/// Asks before a working-tree overwrite; defaults to no.
+fn confirm_overwrite() -> Result<bool> {
+    confirm("This will overwrite your current files. Continue?")
+}
 
-    for line in commit_content.lines() {
-        if in_message {
-            message_lines.push(line);
-            continue;
-        }
-        if line.starts_with("parent ") {
-            let parts: Vec<_> = line.split_whitespace().collect();
-            if parts.len() == 2 && is_valid_hash(parts[1]) {
-                parent = Some(parts[1].to_string());
-            } else {
-                bail!("Invalid parent line: {}", line);
-            }
-        } else if line.starts_with("author ") {
-            author = line.strip_prefix("author ").unwrap().to_string();
-        } else if line.is_empty() {
-            in_message = true;
-        }
+/// Asks a yes/no question; defaults to no. Without a terminal the answer
+/// has to be typed out as `yes`.
+fn confirm(prompt: &str) -> Result<bool> {
+    if is_interactive() {
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .default(false)
+            .interact()?;
+        return Ok(confirmed);
     }
-    Ok((parent, author, message_lines.join("\n")))
+
+    println!("gini: {} Type 'yes' to confirm:", prompt);
+    let mut confirm = String::new();
+    stdin().read_line(&mut confirm)?;
+    Ok(confirm.trim().to_lowercase() == "yes")
 }
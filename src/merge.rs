@@ -0,0 +1,227 @@
+//! `gini merge`: combining two lines of history file by file, and line by
+//! line within text files both sides changed.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::content::as_text;
+use crate::diff::{diff_lines, Edit};
+use crate::objects::{hash_and_write_object, read_object_raw};
+use crate::tree::{FileEntry, MODE_SYMLINK};
+
+/// What merging two sides' files against their common base produced.
+pub(crate) struct MergedFiles {
+    /// The files to record in the merge checkpoint when nothing conflicted.
+    pub(crate) files: BTreeMap<String, FileEntry>,
+    /// The files to leave in the working tree: `files`, except that a
+    /// conflicted text file holds conflict markers.
+    pub(crate) working: BTreeMap<String, FileEntry>,
+    /// The paths that need resolving by hand, sorted.
+    pub(crate) conflicts: Vec<String>,
+}
+
+/// Merges `theirs` into `ours`. A path only one side changed takes that
+/// side's version. When both changed a text file, their edits are
+/// combined, and overlapping edits become a conflict marked with
+/// `ours_label` and `theirs_label`. Any other change on both sides (to a
+/// binary file or symlink, or an edit against a deletion) is a conflict
+/// that leaves our version, or the surviving one, in the working tree.
+pub(crate) fn merge_files(
+    objects_path: &Path,
+    base: &BTreeMap<String, FileEntry>,
+    ours: &BTreeMap<String, FileEntry>,
+    theirs: &BTreeMap<String, FileEntry>,
+    ours_label: &str,
+    theirs_label: &str,
+) -> Result<MergedFiles> {
+    let paths: BTreeSet<&String> = base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+    let mut files = BTreeMap::new();
+    let mut working = BTreeMap::new();
+    let mut conflicts = Vec::new();
+    for path in paths {
+        let (b, o, t) = (base.get(path), ours.get(path), theirs.get(path));
+        let resolved = if o == t || b == t {
+            o.cloned()
+        } else if b == o {
+            t.cloned()
+        } else {
+            match merge_file(objects_path, b, o, t, ours_label, theirs_label)? {
+                (merged, false) => Some(merged),
+                (marked, true) => {
+                    conflicts.push(path.clone());
+                    working.insert(path.clone(), marked);
+                    continue;
+                }
+            }
+        };
+        if let Some(file) = resolved {
+            files.insert(path.clone(), file.clone());
+            working.insert(path.clone(), file);
+        }
+    }
+    Ok(MergedFiles { files, working, conflicts })
+}
+
+/// Merges one path both sides changed. Returns the file to use and
+/// whether it conflicted.
+fn merge_file(
+    objects_path: &Path,
+    base: Option<&FileEntry>,
+    ours: Option<&FileEntry>,
+    theirs: Option<&FileEntry>,
+    ours_label: &str,
+    theirs_label: &str,
+) -> Result<(FileEntry, bool)> {
+    let (ours, theirs) = match (ours, theirs) {
+        (Some(ours), Some(theirs)) => (ours, theirs),
+        // Changed on one side, deleted on the other: keep the change.
+        (Some(file), None) | (None, Some(file)) => return Ok((file.clone(), true)),
+        (None, None) => unreachable!("a path deleted on both sides is not a conflict"),
+    };
+    let mergeable = |file: &FileEntry| file.mode != MODE_SYMLINK && !file.is_empty_dir();
+    if !mergeable(ours) || !mergeable(theirs) || base.is_some_and(|base| !mergeable(base)) {
+        return Ok((ours.clone(), true));
+    }
+    let base_content = match base {
+        Some(base) => read_object_raw(objects_path, &base.hash)?,
+        None => Vec::new(),
+    };
+    let ours_content = read_object_raw(objects_path, &ours.hash)?;
+    let theirs_content = read_object_raw(objects_path, &theirs.hash)?;
+    let (Some(base_text), Some(ours_text), Some(theirs_text)) =
+        (as_text(&base_content), as_text(&ours_content), as_text(&theirs_content))
+    else {
+        return Ok((ours.clone(), true));
+    };
+
+    let (merged, conflicted) = merge_text(base_text, ours_text, theirs_text, ours_label, theirs_label);
+    // A mode change on one side carries over like any other change.
+    let mode = match base {
+        Some(base) if base.mode == ours.mode => theirs.mode,
+        _ => ours.mode,
+    };
+    let hash = hash_and_write_object(objects_path, merged.as_bytes())?;
+    Ok((FileEntry { hash, mode }, conflicted))
+}
+
+/// One side's change to the base: lines `start..end` replaced by `lines`.
+struct Hunk<'a> {
+    start: usize,
+    end: usize,
+    lines: Vec<&'a str>,
+}
+
+/// The changes turning `base` into `other`, in order.
+fn hunks<'a>(base: &[&'a str], other: &[&'a str]) -> Vec<Hunk<'a>> {
+    let mut hunks = Vec::new();
+    let mut current: Option<Hunk> = None;
+    let mut i = 0;
+    for edit in diff_lines(base, other) {
+        match edit {
+            Edit::Keep(_) => {
+                hunks.extend(current.take());
+                i += 1;
+            }
+            Edit::Delete(_) => {
+                current.get_or_insert(Hunk { start: i, end: i, lines: Vec::new() }).end = i + 1;
+                i += 1;
+            }
+            Edit::Insert(line) => {
+                current.get_or_insert(Hunk { start: i, end: i, lines: Vec::new() }).lines.push(line);
+            }
+        }
+    }
+    hunks.extend(current);
+    hunks
+}
+
+/// `base[start..end]` with `hunks` (which all fall inside it) applied.
+fn apply<'a>(base: &[&'a str], start: usize, end: usize, hunks: &[&Hunk<'a>]) -> Vec<&'a str> {
+    let mut out = Vec::new();
+    let mut cursor = start;
+    for hunk in hunks {
+        out.extend(&base[cursor..hunk.start]);
+        out.extend(&hunk.lines);
+        cursor = hunk.end;
+    }
+    out.extend(&base[cursor..end]);
+    out
+}
+
+/// Three-way merges text line by line. Edits from one side, and identical
+/// edits from both, are applied; edits to the same lines (or insertions at
+/// the same place) that differ are written out between conflict markers.
+/// Returns the text and whether anything conflicted.
+pub(crate) fn merge_text(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    ours_label: &str,
+    theirs_label: &str,
+) -> (String, bool) {
+    // Lines keep their terminators so a change to the final newline merges
+    // like any other.
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let ours = hunks(&base, &ours_lines);
+    let theirs = hunks(&base, &theirs_lines);
+
+    let mut out = String::new();
+    let mut conflicted = false;
+    let mut pos = 0;
+    let (mut a, mut b) = (0, 0);
+    while a < ours.len() || b < theirs.len() {
+        let start = match (ours.get(a), theirs.get(b)) {
+            (Some(o), Some(t)) => o.start.min(t.start),
+            (Some(o), None) => o.start,
+            (None, Some(t)) => t.start,
+            (None, None) => break,
+        };
+        // Gather every hunk from either side that overlaps the region.
+        let mut end = start;
+        let (mut from_ours, mut from_theirs) = (Vec::new(), Vec::new());
+        loop {
+            if let Some(hunk) = ours.get(a).filter(|h| h.start < end || h.start == start) {
+                end = end.max(hunk.end);
+                from_ours.push(hunk);
+                a += 1;
+            } else if let Some(hunk) = theirs.get(b).filter(|h| h.start < end || h.start == start) {
+                end = end.max(hunk.end);
+                from_theirs.push(hunk);
+                b += 1;
+            } else {
+                break;
+            }
+        }
+
+        out.extend(base[pos..start].iter().copied());
+        let our_version = apply(&base, start, end, &from_ours);
+        let their_version = apply(&base, start, end, &from_theirs);
+        if from_theirs.is_empty() || our_version == their_version {
+            out.extend(our_version);
+        } else if from_ours.is_empty() {
+            out.extend(their_version);
+        } else {
+            conflicted = true;
+            out.push_str(&format!("<<<<<<< {}\n", ours_label));
+            push_block(&mut out, &our_version);
+            out.push_str("=======\n");
+            push_block(&mut out, &their_version);
+            out.push_str(&format!(">>>>>>> {}\n", theirs_label));
+        }
+        pos = end;
+    }
+    out.extend(base[pos..].iter().copied());
+    (out, conflicted)
+}
+
+/// Appends one side of a conflict, ending it with a newline so the next
+/// marker starts its own line.
+fn push_block(out: &mut String, lines: &[&str]) {
+    out.extend(lines.iter().copied());
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
@@ -0,0 +1,460 @@
+//! The content-addressed object store under `.gini/objects`. Objects are
+//! stored loose, one file each, until `gini gc --compress` moves them into a
+//! pack (see [`crate::pack`]); reads look in both places.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use crate::config::Config;
+use crate::eol::to_lf;
+use crate::error::GiniError;
+use crate::hash::{hash_with, HashAlgo, Hasher};
+use crate::pack::{is_packed, list_packed, read_packed};
+use crate::{CONFIG_FILE_NAME, MAX_HASH_LENGTH};
+
+/// Whether `hash` is a full SHA-1 or SHA-256 hash in hex.
+pub(crate) fn is_valid_hash(hash: &str) -> bool {
+    [HashAlgo::Sha1, HashAlgo::Sha256].iter().any(|algo| hash.len() == algo.hex_len())
+        && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// The name `content` gets in the store at `objects_path`.
+pub(crate) fn hash_bytes(objects_path: &Path, content: &[u8]) -> Result<String> {
+    Ok(hash_with(hash_algo(objects_path)?, content))
+}
+
+/// The hash function the store at `objects_path` uses (`core.hashAlgo`).
+pub(crate) fn hash_algo(objects_path: &Path) -> Result<HashAlgo> {
+    Ok(store_settings(objects_path)?.hash_algo)
+}
+
+pub(crate) fn hash_and_write_object(objects_path: &Path, content: &[u8]) -> Result<String> {
+    let hash_string = hash_bytes(objects_path, content)?;
+    
+    if !object_exists(objects_path, &hash_string) {
+        write_object_file(objects_path, &hash_string, content)?;
+    }
+    Ok(hash_string)
+}
+
+/// Stores file content as a blob, failing if it is larger than `max_size`
+/// (`core.maxFileSize`).
+pub(crate) fn hash_and_write_blob(objects_path: &Path, content: &[u8], max_size: u64) -> Result<String> {
+    if content.len() as u64 > max_size {
+        bail!("File too large (max {} bytes)", max_size);
+    }
+    hash_and_write_object(objects_path, content)
+}
+
+/// Stores content that is already known by its hash, such as an object
+/// copied from another repository, after checking that the two still match.
+pub(crate) fn write_raw_object(objects_path: &Path, hash: &str, content: &[u8]) -> Result<()> {
+    if hash_bytes(objects_path, content)? != hash {
+        bail!("Object {} is corrupt in the source repository (run `gini fsck` there)", hash);
+    }
+    if object_exists(objects_path, hash) {
+        return Ok(());
+    }
+    write_object_file(objects_path, hash, content)
+}
+
+/// Writes `content` out as a loose object named `hash`, even if a pack
+/// already holds it, after checking that the two still match. Returns
+/// whether a file was written, i.e. it wasn't loose already.
+pub(crate) fn write_loose_object(objects_path: &Path, hash: &str, content: &[u8]) -> Result<bool> {
+    if hash_bytes(objects_path, content)? != hash {
+        bail!("Object {} is corrupt, so nothing was unpacked (run `gini fsck`)", hash);
+    }
+    if locate_object(objects_path, hash).is_some() {
+        return Ok(false);
+    }
+    write_object_file(objects_path, hash, content)?;
+    Ok(true)
+}
+
+/// Writes a loose object through a temporary file, retrying transient
+/// failures (see [`retry_transient`]). The temporary name is unique, so
+/// threads storing the same content at once don't trip over each other.
+fn write_object_file(objects_path: &Path, hash: &str, content: &[u8]) -> Result<()> {
+    let temp_path = incoming_temp_path(objects_path);
+    let result = retry_transient(objects_path, || {
+        let object_file_path = prepare_object_path(objects_path, hash)?;
+        fs::write(&temp_path, content)?;
+        fs::rename(&temp_path, &object_file_path)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// A fresh `incoming_<pid>_<n>.tmp` path in the store, distinct from every
+/// other writer's in this process or any other.
+fn incoming_temp_path(objects_path: &Path) -> PathBuf {
+    static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    objects_path.join(format!(
+        "incoming_{}_{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Whether an I/O error is the kind a slow or flaky disk (a network
+/// filesystem, say) may not repeat if the operation is simply tried again.
+fn is_transient(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    const EIO: i32 = 5;
+    matches!(
+        e.kind(),
+        ErrorKind::Interrupted | ErrorKind::TimedOut | ErrorKind::WouldBlock | ErrorKind::ResourceBusy
+    ) || e.raw_os_error() == Some(EIO)
+}
+
+/// Runs `op`, and while it fails with a transient I/O error, runs it again
+/// up to `core.writeRetries` more times, waiting twice as long before each
+/// retry (starting at 50ms). Object writes are idempotent, so repeating
+/// one is always safe.
+fn retry_transient<T>(objects_path: &Path, mut op: impl FnMut() -> Result<T>) -> Result<T> {
+    let retries = store_settings(objects_path)?.write_retries;
+    let mut delay = std::time::Duration::from_millis(50);
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(e) if attempt < retries && e.downcast_ref().is_some_and(is_transient) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Objects are sharded by the first two hex characters of their hash:
+/// `.gini/objects/ab/cdef...`.
+pub(crate) fn object_path(objects_path: &Path, hash: &str) -> PathBuf {
+    objects_path.join(&hash[..2]).join(&hash[2..])
+}
+
+/// Finds an object on disk, falling back to the pre-sharding flat layout.
+pub(crate) fn locate_object(objects_path: &Path, hash: &str) -> Option<PathBuf> {
+    let sharded = object_path(objects_path, hash);
+    if sharded.is_file() {
+        return Some(sharded);
+    }
+    let flat = objects_path.join(hash);
+    flat.is_file().then_some(flat)
+}
+
+/// Whether the object is stored, loose or in a pack.
+pub(crate) fn object_exists(objects_path: &Path, hash: &str) -> bool {
+    locate_object(objects_path, hash).is_some() || is_packed(objects_path, hash)
+}
+
+/// Returns the sharded path for a new object, creating its shard directory.
+pub(crate) fn prepare_object_path(objects_path: &Path, hash: &str) -> Result<PathBuf> {
+    let path = object_path(objects_path, hash);
+    if let Some(shard) = path.parent() {
+        fs::create_dir_all(shard)?;
+    }
+    Ok(path)
+}
+
+/// Lists every stored object as `(hash, path)`, sharded or flat.
+pub(crate) fn list_objects(objects_path: &Path) -> Result<Vec<(String, PathBuf)>> {
+    list_objects_in(objects_path, None)
+}
+
+/// Like `list_objects`, but only descends into the given shard if one is
+/// named. Flat objects are always included.
+pub(crate) fn list_objects_in(objects_path: &Path, shard: Option<&str>) -> Result<Vec<(String, PathBuf)>> {
+    let mut objects = Vec::new();
+    for entry in fs::read_dir(objects_path)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = match entry.file_name().to_str() {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        if path.is_file() {
+            if is_valid_hash(&name) {
+                objects.push((name, path));
+            }
+            continue;
+        }
+        let is_shard = name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit());
+        if !is_shard || shard.is_some_and(|s| s != name) {
+            continue;
+        }
+        for object in fs::read_dir(&path)? {
+            let object = object?;
+            if let Some(rest) = object.file_name().to_str() {
+                let hash = format!("{}{}", name, rest);
+                if is_valid_hash(&hash) && object.path().is_file() {
+                    objects.push((hash, object.path()));
+                }
+            }
+        }
+    }
+    objects.sort();
+    Ok(objects)
+}
+
+/// Moves objects stored in the legacy flat layout into their shards.
+pub(crate) fn migrate_flat_objects(objects_path: &Path) -> Result<usize> {
+    let mut moved = 0;
+    for entry in fs::read_dir(objects_path)? {
+        let entry = entry?;
+        let name = match entry.file_name().to_str() {
+            Some(name) if is_valid_hash(name) => name.to_string(),
+            _ => continue,
+        };
+        let target = prepare_object_path(objects_path, &name)?;
+        if target.exists() {
+            fs::remove_file(entry.path())?;
+        } else {
+            fs::rename(entry.path(), &target)?;
+        }
+        moved += 1;
+    }
+    Ok(moved)
+}
+
+/// Size of the chunks `hash_and_write_file` reads at a time.
+pub(crate) const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Stores a file as a blob without loading it into memory: the content is
+/// fed to the hasher and copied to a temporary object file in one pass,
+/// then renamed into place (or discarded if the object already exists).
+/// Produces the same hash as `hash_and_write_object` on the file's bytes.
+/// Fails if the file turns out to be larger than `max_size`.
+pub(crate) fn hash_and_write_file(objects_path: &Path, path: &Path, max_size: u64) -> Result<String> {
+    let temp_path = incoming_temp_path(objects_path);
+
+    let algo = hash_algo(objects_path)?;
+    // A retry starts over from the beginning of the file.
+    let result = retry_transient(objects_path, || -> Result<String> {
+        let mut source = fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut temp_file = fs::File::create(&temp_path)?;
+        let mut hasher = Hasher::new(algo);
+        let mut buffer = vec![0u8; STREAM_CHUNK_SIZE];
+        let mut total: u64 = 0;
+        loop {
+            let read = source.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            total += read as u64;
+            if total > max_size {
+                bail!("File too large: {} (max {} bytes)", path.display(), max_size);
+            }
+            hasher.update(&buffer[..read]);
+            temp_file.write_all(&buffer[..read])?;
+        }
+        temp_file.sync_all()?;
+        Ok(hasher.finish())
+    });
+
+    let hash_string = match result {
+        Ok(hash) => hash,
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+    };
+
+    if object_exists(objects_path, &hash_string) {
+        fs::remove_file(&temp_path)?;
+    } else {
+        retry_transient(objects_path, || {
+            Ok(fs::rename(&temp_path, prepare_object_path(objects_path, &hash_string)?)?)
+        })?;
+    }
+    Ok(hash_string)
+}
+
+/// Like [`hash_and_write_file`], or [`hash_file`] when `write` is false,
+/// but with CRLF line endings in text turned into LF first (see
+/// `core.autocrlf`). The file is read whole so it can be converted.
+pub(crate) fn hash_normalized_file(objects_path: &Path, path: &Path, max_size: u64, write: bool) -> Result<String> {
+    let content = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if content.len() as u64 > max_size {
+        bail!("File too large: {} (max {} bytes)", path.display(), max_size);
+    }
+    let content = to_lf(&content).unwrap_or(content);
+    if write {
+        hash_and_write_object(objects_path, &content)
+    } else {
+        hash_bytes(objects_path, &content)
+    }
+}
+
+/// Computes a file's blob hash for the store at `objects_path` without
+/// storing it.
+pub(crate) fn hash_file(objects_path: &Path, path: &Path) -> Result<String> {
+    let mut source = fs::File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Hasher::new(hash_algo(objects_path)?);
+    let mut buffer = vec![0u8; STREAM_CHUNK_SIZE];
+    loop {
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Reads a commit, tree or tag object as text. gini writes these as UTF-8,
+/// so anything else means the object is corrupt or is a blob; blobs hold
+/// arbitrary bytes and must go through [`read_object_raw`] instead.
+pub(crate) fn read_object(objects_path: &Path, hash: &str) -> Result<String> {
+    let content = read_object_raw(objects_path, hash)?;
+    String::from_utf8(content).map_err(|_| {
+        anyhow::anyhow!(
+            "Object {} is not valid UTF-8, so it is not a commit, tree or tag \
+             (if it should be, it is corrupt; run `gini fsck`)",
+            hash
+        )
+    })
+}
+
+/// Reads an object's bytes exactly as stored. This is the only way blobs are
+/// read. With `core.verifyOnRead` set, the bytes are re-hashed and a
+/// mismatch with the object's name is an error.
+pub(crate) fn read_object_raw(objects_path: &Path, hash: &str) -> Result<Vec<u8>> {
+    let content = read_object_unchecked(objects_path, hash)?;
+    if store_settings(objects_path)?.verify_on_read {
+        let actual = hash_bytes(objects_path, &content)?;
+        if actual != hash {
+            bail!(GiniError::CorruptObject { hash: hash.to_string(), actual });
+        }
+    }
+    Ok(content)
+}
+
+/// Like [`read_object_raw`], but never re-hashes, for callers that check
+/// the content themselves.
+pub(crate) fn read_object_unchecked(objects_path: &Path, hash: &str) -> Result<Vec<u8>> {
+    if !is_valid_hash(hash) {
+        bail!(GiniError::InvalidHash(hash.to_string()));
+    }
+    
+    let path = match locate_object(objects_path, hash) {
+        Some(path) => path,
+        None => match read_packed(objects_path, hash)? {
+            Some(content) => return Ok(content),
+            None => bail!(GiniError::ObjectNotFound(hash.to_string())),
+        },
+    };
+    
+    let content = fs::read(&path)
+        .with_context(|| format!("Failed to read object: {}", hash))?;
+    Ok(content)
+}
+
+/// The config settings that govern reading and writing objects.
+#[derive(Clone, Copy)]
+struct StoreSettings {
+    hash_algo: HashAlgo,
+    verify_on_read: bool,
+    write_retries: u32,
+}
+
+static STORE_SETTINGS: OnceLock<Mutex<HashMap<PathBuf, StoreSettings>>> = OnceLock::new();
+
+/// Makes the next object operation on the store at `objects_path` read
+/// its settings from the config again, after they were changed.
+pub(crate) fn forget_store_settings(objects_path: &Path) {
+    if let Some(cache) = STORE_SETTINGS.get() {
+        cache.lock().unwrap_or_else(|e| e.into_inner()).remove(objects_path);
+    }
+}
+
+/// The [`StoreSettings`] from the store's config, looked up once per store
+/// for the whole process.
+fn store_settings(objects_path: &Path) -> Result<StoreSettings> {
+    let mut cache = STORE_SETTINGS.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(&settings) = cache.get(objects_path) {
+        return Ok(settings);
+    }
+    let config_path = objects_path.parent().unwrap_or(objects_path).join(CONFIG_FILE_NAME);
+    let config = match fs::read_to_string(&config_path) {
+        Ok(content) => Config::parse(&content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+        Err(e) => return Err(e).context("Failed to read .gini/config"),
+    };
+    let settings = StoreSettings {
+        hash_algo: config.hash_algo()?,
+        verify_on_read: config.verify_on_read()?,
+        write_retries: config.write_retries()?,
+    };
+    cache.insert(objects_path.to_path_buf(), settings);
+    Ok(settings)
+}
+
+/// Expands an abbreviated object hash (at least 4 hex characters) to the
+/// unique full hash it identifies.
+pub(crate) fn resolve_hash(objects_path: &Path, prefix: &str) -> Result<String> {
+    if prefix.len() < 4
+        || prefix.len() > MAX_HASH_LENGTH
+        || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        bail!(GiniError::InvalidHash(prefix.to_string()));
+    }
+    let prefix = prefix.to_ascii_lowercase();
+    if is_valid_hash(&prefix) && object_exists(objects_path, &prefix) {
+        return Ok(prefix);
+    }
+
+    // Only the matching shard (plus any legacy flat objects) needs scanning.
+    let mut matches: Vec<String> = list_objects_in(objects_path, Some(&prefix[..2]))?
+        .into_iter()
+        .map(|(hash, _)| hash)
+        .chain(list_packed(objects_path)?.into_iter().map(|(hash, _)| hash))
+        .filter(|hash| hash.starts_with(&prefix))
+        .collect();
+    matches.sort();
+    matches.dedup();
+    match matches.len() {
+        0 => bail!(GiniError::ObjectNotFound(prefix)),
+        1 => Ok(matches.remove(0)),
+        count => bail!(GiniError::AmbiguousHash { prefix, count }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn concurrent_writes_of_one_object_all_succeed() {
+        let dir = TempDir::new().unwrap();
+        let objects_path = dir.path().join("objects");
+        fs::create_dir_all(&objects_path).unwrap();
+        let content = b"same content\n";
+        let hash = hash_bytes(&objects_path, content).unwrap();
+
+        // Threads hashing identical files race to store the same object.
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..200 {
+                        write_object_file(&objects_path, &hash, content).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(fs::read(object_path(&objects_path, &hash)).unwrap(), content);
+    }
+}
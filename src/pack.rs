@@ -0,0 +1,225 @@
+//! Pack files: many objects concatenated into `.gini/pack/pack-<hash>.gini`,
+//! with a `pack-<hash>.idx` beside it mapping each object to its place.
+//!
+//! The index is text, one `<object hash> <offset> <length>` line per object.
+//! A pack only counts once its index exists, so the index is written last
+//! and removed first. Packs are named after the hash of their contents and
+//! never change, which lets parsed indexes be cached for the whole process.
+
+use anyhow::{bail, Context, Result};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::objects::{hash_bytes, is_valid_hash, read_object_unchecked, write_loose_object};
+use crate::PACK_DIR;
+
+const PACK_EXTENSION: &str = "gini";
+const INDEX_EXTENSION: &str = "idx";
+
+/// A parsed pack index.
+struct PackIndex {
+    data_path: PathBuf,
+    /// Object hash to `(offset, length)` within the pack.
+    entries: HashMap<String, (u64, u64)>,
+}
+
+/// The pack directory that belongs to an object store.
+pub(crate) fn pack_dir(objects_path: &Path) -> PathBuf {
+    objects_path.parent().unwrap_or(objects_path).join(PACK_DIR)
+}
+
+/// The index files of every pack, sorted by name.
+fn index_paths(objects_path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = pack_dir(objects_path);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let is_index = path.extension().is_some_and(|ext| ext == INDEX_EXTENSION)
+            && path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("pack-"));
+        if is_index {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn parse_index(index_path: &Path) -> Result<PackIndex> {
+    let content = fs::read_to_string(index_path)
+        .with_context(|| format!("Failed to read pack index {}", index_path.display()))?;
+    let mut entries = HashMap::new();
+    for (number, line) in content.lines().enumerate() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        let parsed = match fields.as_slice() {
+            [hash, offset, length] if is_valid_hash(hash) => offset
+                .parse::<u64>()
+                .ok()
+                .zip(length.parse::<u64>().ok())
+                .map(|location| (hash.to_string(), location)),
+            _ => None,
+        };
+        match parsed {
+            Some((hash, location)) => {
+                entries.insert(hash, location);
+            }
+            None => bail!("Corrupt pack index {}: bad line {}", index_path.display(), number + 1),
+        }
+    }
+    Ok(PackIndex {
+        data_path: index_path.with_extension(PACK_EXTENSION),
+        entries,
+    })
+}
+
+/// Every pack index of the store, parsed once per process.
+fn load_indexes(objects_path: &Path) -> Result<Vec<Arc<PackIndex>>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, Arc<PackIndex>>>> = OnceLock::new();
+    let paths = index_paths(objects_path)?;
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    let mut indexes = Vec::new();
+    for path in paths {
+        let index = match cache.get(&path) {
+            Some(index) => index.clone(),
+            None => {
+                let index = Arc::new(parse_index(&path)?);
+                cache.insert(path, index.clone());
+                index
+            }
+        };
+        indexes.push(index);
+    }
+    Ok(indexes)
+}
+
+/// Whether any pack holds `hash`.
+pub(crate) fn is_packed(objects_path: &Path, hash: &str) -> bool {
+    load_indexes(objects_path)
+        .map(|indexes| indexes.iter().any(|index| index.entries.contains_key(hash)))
+        .unwrap_or(false)
+}
+
+/// Reads `hash` out of whichever pack holds it, or `None` if none does.
+pub(crate) fn read_packed(objects_path: &Path, hash: &str) -> Result<Option<Vec<u8>>> {
+    for index in load_indexes(objects_path)? {
+        let Some(&(offset, length)) = index.entries.get(hash) else {
+            continue;
+        };
+        let mut file = fs::File::open(&index.data_path)
+            .with_context(|| format!("Failed to open pack {}", index.data_path.display()))?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut content = vec![0u8; length as usize];
+        file.read_exact(&mut content)
+            .with_context(|| format!("Failed to read object {} from {}", hash, index.data_path.display()))?;
+        return Ok(Some(content));
+    }
+    Ok(None)
+}
+
+/// Every packed object as `(hash, length)`, sorted by hash. An object in
+/// several packs is listed once.
+pub(crate) fn list_packed(objects_path: &Path) -> Result<Vec<(String, u64)>> {
+    let mut objects: Vec<(String, u64)> = load_indexes(objects_path)?
+        .iter()
+        .flat_map(|index| index.entries.iter().map(|(hash, &(_, length))| (hash.clone(), length)))
+        .collect();
+    objects.sort();
+    objects.dedup_by(|a, b| a.0 == b.0);
+    Ok(objects)
+}
+
+/// Writes `hashes` (read from loose objects or existing packs) into one new
+/// pack and returns its file name, or `None` if there is nothing to pack.
+/// Every object is re-hashed on the way in so corruption is never copied
+/// into a pack. The caller must hold the repository lock.
+pub(crate) fn write_pack(objects_path: &Path, hashes: &[String]) -> Result<Option<String>> {
+    if hashes.is_empty() {
+        return Ok(None);
+    }
+    let dir = pack_dir(objects_path);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let temp_data = dir.join("incoming.tmp");
+    let temp_index = dir.join("incoming-idx.tmp");
+
+    let result = (|| -> Result<String> {
+        let mut data = fs::File::create(&temp_data)?;
+        let mut hasher = Sha1::new();
+        let mut index = String::new();
+        let mut offset = 0u64;
+        for hash in hashes {
+            let content = read_object_unchecked(objects_path, hash)?;
+            if hash_bytes(objects_path, &content)? != *hash {
+                bail!("Object {} is corrupt, so it was not packed (run `gini fsck`)", hash);
+            }
+            data.write_all(&content)?;
+            hasher.update(&content);
+            index.push_str(&format!("{} {} {}\n", hash, offset, content.len()));
+            offset += content.len() as u64;
+        }
+        data.sync_all()?;
+        fs::write(&temp_index, index)?;
+        Ok(format!("pack-{}", hex::encode(hasher.finalize())))
+    })();
+
+    let name = match result {
+        Ok(name) => name,
+        Err(e) => {
+            let _ = fs::remove_file(&temp_data);
+            let _ = fs::remove_file(&temp_index);
+            return Err(e);
+        }
+    };
+    fs::rename(&temp_data, dir.join(format!("{}.{}", name, PACK_EXTENSION)))?;
+    fs::rename(&temp_index, dir.join(format!("{}.{}", name, INDEX_EXTENSION)))?;
+    Ok(Some(name))
+}
+
+/// Writes every packed object back out as a loose object, checking each
+/// against its hash, then deletes the packs. A corrupt object stops it
+/// before any pack is deleted. Returns how many objects were written (ones
+/// already loose too are skipped). The caller must hold the repository
+/// lock.
+pub(crate) fn unpack_all(objects_path: &Path) -> Result<usize> {
+    let mut unpacked = 0;
+    for (hash, _) in list_packed(objects_path)? {
+        let content = read_packed(objects_path, &hash)?
+            .ok_or_else(|| anyhow::anyhow!("Object {} vanished from its pack", hash))?;
+        if write_loose_object(objects_path, &hash, &content)? {
+            unpacked += 1;
+        }
+    }
+    remove_packs_except(objects_path, None)?;
+    Ok(unpacked)
+}
+
+/// Deletes every pack except `keep`, index first. Returns how many went.
+pub(crate) fn remove_packs_except(objects_path: &Path, keep: Option<&str>) -> Result<usize> {
+    let mut removed = 0;
+    for index_path in index_paths(objects_path)? {
+        let is_kept = index_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .is_some_and(|stem| Some(stem) == keep);
+        if is_kept {
+            continue;
+        }
+        fs::remove_file(&index_path)
+            .with_context(|| format!("Failed to remove {}", index_path.display()))?;
+        let data_path = index_path.with_extension(PACK_EXTENSION);
+        if data_path.exists() {
+            fs::remove_file(&data_path)
+                .with_context(|| format!("Failed to remove {}", data_path.display()))?;
+        }
+        removed += 1;
+    }
+    Ok(removed)
+}
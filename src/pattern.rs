@@ -0,0 +1,67 @@
+//! Text patterns for searching commit messages: plain substrings, or
+//! regular expressions in the `regex` crate's syntax.
+
+use anyhow::Result;
+use regex::{Regex, RegexBuilder};
+
+/// A compiled search pattern.
+#[derive(Debug, Clone)]
+pub(crate) enum TextPattern {
+    Substring { needle: String, ignore_case: bool },
+    Regex(Regex),
+}
+
+impl TextPattern {
+    pub(crate) fn new(pattern: &str, regex: bool, ignore_case: bool) -> Result<Self> {
+        if regex {
+            let regex = RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .map_err(|e| anyhow::anyhow!("Invalid regex '{}': {}", pattern, e))?;
+            return Ok(TextPattern::Regex(regex));
+        }
+        let needle = if ignore_case { pattern.to_lowercase() } else { pattern.to_string() };
+        Ok(TextPattern::Substring { needle, ignore_case })
+    }
+
+    /// Whether the pattern occurs anywhere in `text`.
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        match self {
+            TextPattern::Substring { needle, ignore_case: true } => {
+                text.to_lowercase().contains(needle.as_str())
+            }
+            TextPattern::Substring { needle, ignore_case: false } => text.contains(needle.as_str()),
+            TextPattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commit::parse_commit_details;
+    use crate::LogOptions;
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        let error = TextPattern::new("(unclosed", true, false).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid regex '(unclosed'"));
+    }
+
+    #[test]
+    fn log_regex_honours_ignore_case() {
+        let details = parse_commit_details("author A <a@example.com> 0 +0000\n\nFix the parser").unwrap();
+        let matches = |ignore_case: bool| {
+            let options = LogOptions {
+                grep: Some("^fix".to_string()),
+                regex: true,
+                ignore_case,
+                ..Default::default()
+            };
+            let grep = TextPattern::new("^fix", options.regex, options.ignore_case).unwrap();
+            options.matches(&details, Some(&grep))
+        };
+        assert!(!matches(false));
+        assert!(matches(true));
+    }
+}
@@ -0,0 +1,89 @@
+//! The reflog, `.gini/logs/HEAD`: one line for every move of HEAD, so a
+//! checkpoint that a reset or amend left behind can still be found.
+//!
+//! Each line is `<old> <new> <timestamp>\t<reason>`, oldest first, where
+//! `<old>` is all zeros when HEAD had no checkpoint before.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::objects::is_valid_hash;
+use crate::util::{gini_dir, write_file_atomic};
+use crate::{ReflogEntry, REFLOG_FILE_NAME};
+
+/// Records that HEAD moved from `old` to `new` because of `reason`
+/// (e.g. `reset: moving to v1.0`).
+pub(crate) fn append_reflog(root_path: &Path, old: Option<&str>, new: &str, reason: &str) -> Result<()> {
+    let path = gini_dir(root_path).join(REFLOG_FILE_NAME);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let old = old.map_or_else(|| "0".repeat(new.len()), str::to_string);
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let reason = reason.replace(['\n', '\r'], " ");
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context("Failed to open the reflog")?;
+    writeln!(file, "{} {} {}\t{}", old, new, timestamp, reason).context("Failed to write the reflog")?;
+    Ok(())
+}
+
+/// Reads the reflog, newest first. Lines that don't parse are skipped.
+pub(crate) fn read_reflog(root_path: &Path) -> Result<Vec<ReflogEntry>> {
+    let path = gini_dir(root_path).join(REFLOG_FILE_NAME);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let mut entries: Vec<ReflogEntry> = fs::read_to_string(&path)?.lines().filter_map(parse_line).collect();
+    entries.reverse();
+    Ok(entries)
+}
+
+fn parse_line(line: &str) -> Option<ReflogEntry> {
+    let (head, reason) = line.split_once('\t')?;
+    let mut fields = head.split(' ');
+    let (old, new, timestamp) = (fields.next()?, fields.next()?, fields.next()?.parse().ok()?);
+    if !is_valid_hash(new) || fields.next().is_some() {
+        return None;
+    }
+    Some(ReflogEntry {
+        old: (is_valid_hash(old) && old.bytes().any(|b| b != b'0')).then(|| old.to_string()),
+        new: new.to_string(),
+        timestamp,
+        reason: reason.to_string(),
+    })
+}
+
+/// Drops the entries older than `cutoff` (a Unix timestamp). Returns how
+/// many went.
+pub(crate) fn expire_reflog(root_path: &Path, cutoff: u64) -> Result<usize> {
+    retain_reflog(root_path, |entry| entry.timestamp >= cutoff)
+}
+
+/// Keeps only the entries `keep` accepts; lines that don't parse go too.
+/// Returns how many went.
+pub(crate) fn retain_reflog(root_path: &Path, keep: impl Fn(&ReflogEntry) -> bool) -> Result<usize> {
+    let path = gini_dir(root_path).join(REFLOG_FILE_NAME);
+    if !path.is_file() {
+        return Ok(0);
+    }
+    let content = fs::read_to_string(&path)?;
+    let kept: Vec<&str> = content
+        .lines()
+        .filter(|line| parse_line(line).is_some_and(|entry| keep(&entry)))
+        .collect();
+    let expired = content.lines().count() - kept.len();
+    if expired > 0 {
+        let mut rewritten = kept.join("\n");
+        if !rewritten.is_empty() {
+            rewritten.push('\n');
+        }
+        write_file_atomic(&path, rewritten.as_bytes())?;
+    }
+    Ok(expired)
+}
@@ -0,0 +1,505 @@
+//! HEAD, branch and tag refs, revision names, and reachability.
+
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+
+use crate::backup::{list_backups, read_backup_tree};
+use crate::commit::{parse_commit_details, parse_commit_tree, parse_tag};
+use crate::error::GiniError;
+use crate::config::Config;
+use crate::index::load_index;
+use crate::objects::{is_valid_hash, object_exists, read_object, read_object_raw, resolve_hash};
+use crate::reflog::{append_reflog, read_reflog};
+use crate::stash::read_stash;
+use crate::tree::{read_tree, EntryKind};
+use crate::util::{gini_dir, write_file_atomic};
+use crate::MERGE_HEAD_FILE_NAME;
+
+pub(crate) fn get_head_commit(root_path: &Path) -> Result<Option<String>> {
+    let head_path = gini_dir(root_path).join("HEAD");
+    if !head_path.exists() {
+        return Ok(None);
+    }
+    
+    let head_content = fs::read_to_string(&head_path)?;
+    if let Some(ref_path_str) = head_content.strip_prefix("ref: ") {
+        let ref_path = gini_dir(root_path).join(ref_path_str.trim());
+        if ref_path.exists() {
+            let content = fs::read_to_string(&ref_path)?;
+            let hash = content.trim();
+            if is_valid_hash(hash) {
+                Ok(Some(hash.to_string()))
+            } else {
+                bail!("Invalid hash in ref file: {}", hash);
+            }
+        } else {
+            Ok(None)
+        }
+    } else if is_valid_hash(head_content.trim()) {
+        Ok(Some(head_content.trim().to_string()))
+    } else {
+        bail!("Invalid HEAD format")
+    }
+}
+
+/// Moves HEAD to `commit_hash`: the branch HEAD points at is updated, or
+/// when HEAD is detached, HEAD itself. The move is logged in the reflog
+/// with `reason`.
+pub(crate) fn update_head(root_path: &Path, commit_hash: &str, reason: &str) -> Result<()> {
+    let old = get_head_commit(root_path)?;
+    update_head_checked(root_path, old.as_deref(), commit_hash, reason)
+}
+
+/// Like [`update_head`], but only if HEAD is still at `expected` (`None`
+/// for no checkpoint yet); otherwise fails with [`GiniError::StaleRef`]
+/// and changes nothing. Together with the repository lock this keeps a
+/// move made by someone else from being silently overwritten.
+pub(crate) fn update_head_checked(
+    root_path: &Path,
+    expected: Option<&str>,
+    commit_hash: &str,
+    reason: &str,
+) -> Result<()> {
+    if !is_valid_hash(commit_hash) {
+        bail!("Invalid commit hash: {}", commit_hash);
+    }
+    let old = get_head_commit(root_path)?;
+    check_unchanged("HEAD", expected, old.as_deref())?;
+
+    let head_path = gini_dir(root_path).join("HEAD");
+    let head_content = fs::read_to_string(&head_path)?;
+    let target = match head_content.strip_prefix("ref: ") {
+        Some(ref_path_str) => gini_dir(root_path).join(ref_path_str.trim()),
+        None => head_path,
+    };
+    
+    // Write atomically
+    write_file_atomic(&target, commit_hash.as_bytes())?;
+    append_reflog(root_path, old.as_deref(), commit_hash, reason)
+}
+
+/// Directory of branch refs, relative to `.gini`.
+pub(crate) const HEADS_DIR: &str = "refs/heads";
+
+/// The branch HEAD points at, or `None` when HEAD is detached.
+pub(crate) fn head_branch(root_path: &Path) -> Result<Option<String>> {
+    let head_content = fs::read_to_string(gini_dir(root_path).join("HEAD"))?;
+    Ok(head_content
+        .strip_prefix("ref: ")
+        .map(|ref_name| ref_name.trim())
+        .map(|ref_name| ref_name.strip_prefix("refs/heads/").unwrap_or(ref_name).to_string()))
+}
+
+/// Points HEAD straight at a commit rather than at a branch, logging the
+/// move with `reason`.
+pub(crate) fn detach_head(root_path: &Path, commit_hash: &str, reason: &str) -> Result<()> {
+    if !is_valid_hash(commit_hash) {
+        bail!("Invalid commit hash: {}", commit_hash);
+    }
+    let old = get_head_commit(root_path)?;
+    write_file_atomic(&gini_dir(root_path).join("HEAD"), commit_hash.as_bytes())?;
+    append_reflog(root_path, old.as_deref(), commit_hash, reason)
+}
+
+/// Points HEAD back at `branch`, logging the move with `reason` once the
+/// branch has a checkpoint.
+pub(crate) fn attach_head(root_path: &Path, branch: &str, reason: &str) -> Result<()> {
+    let old = get_head_commit(root_path)?;
+    let ref_name = format!("{}/{}", HEADS_DIR, branch);
+    write_file_atomic(&gini_dir(root_path).join("HEAD"), format!("ref: {}", ref_name).as_bytes())?;
+    match get_head_commit(root_path)? {
+        Some(new) => append_reflog(root_path, old.as_deref(), &new, reason),
+        None => Ok(()),
+    }
+}
+
+/// Directory of tag refs, relative to `.gini`.
+pub(crate) const TAGS_DIR: &str = "refs/tags";
+
+/// Directory of remote-tracking refs, `<remote>/<branch>`, relative to `.gini`.
+pub(crate) const REMOTES_DIR: &str = "refs/remotes";
+
+/// Branch and tag names become file names under `.gini/refs`, so they are
+/// kept to a conservative character set.
+pub fn validate_ref_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('-')
+        && !name.starts_with('.')
+        && !name.ends_with(".lock")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !valid {
+        bail!("Invalid name: '{}' (use letters, digits, '.', '_' and '-')", name);
+    }
+    Ok(())
+}
+
+/// Points `ref_name` (e.g. `refs/heads/main`) at `commit_hash` if it still
+/// holds `expected` (`None` for a ref that doesn't exist yet); otherwise
+/// fails with [`GiniError::StaleRef`] and changes nothing.
+pub(crate) fn update_ref_checked(
+    root_path: &Path,
+    ref_name: &str,
+    expected: Option<&str>,
+    commit_hash: &str,
+) -> Result<()> {
+    if !is_valid_hash(commit_hash) {
+        bail!("Invalid commit hash: {}", commit_hash);
+    }
+    check_unchanged(ref_name, expected, read_ref(root_path, ref_name)?.as_deref())?;
+    let path = gini_dir(root_path).join(ref_name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_file_atomic(&path, commit_hash.as_bytes())
+}
+
+fn check_unchanged(name: &str, expected: Option<&str>, actual: Option<&str>) -> Result<()> {
+    if expected != actual {
+        bail!(GiniError::StaleRef {
+            name: name.to_string(),
+            expected: expected.map_or("none", |hash| &hash[..hash.len().min(7)]).to_string(),
+            actual: actual.map_or("none", |hash| &hash[..hash.len().min(7)]).to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Reads the hash stored in a ref file, if the ref exists.
+pub(crate) fn read_ref(root_path: &Path, ref_name: &str) -> Result<Option<String>> {
+    let ref_path = gini_dir(root_path).join(ref_name);
+    if !ref_path.is_file() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&ref_path)?;
+    let hash = content.trim();
+    if !is_valid_hash(hash) {
+        bail!("Invalid hash in ref {}: {}", ref_name, hash);
+    }
+    Ok(Some(hash.to_string()))
+}
+
+/// The checkpoint a conflicted merge is bringing in, kept in
+/// `.gini/MERGE_HEAD` until the checkpoint that resolves it.
+pub(crate) fn read_merge_head(root_path: &Path) -> Result<Option<String>> {
+    read_ref(root_path, MERGE_HEAD_FILE_NAME)
+}
+
+/// Records the checkpoint a conflicted merge is bringing in, or with
+/// `None` forgets it.
+pub(crate) fn write_merge_head(root_path: &Path, hash: Option<&str>) -> Result<()> {
+    let path = gini_dir(root_path).join(MERGE_HEAD_FILE_NAME);
+    match hash {
+        Some(hash) => write_file_atomic(&path, format!("{}\n", hash).as_bytes()),
+        None if path.exists() => Ok(fs::remove_file(&path)?),
+        None => Ok(()),
+    }
+}
+
+/// Follows an annotated tag to the commit it names. Any other object hash
+/// is returned unchanged.
+pub(crate) fn peel_to_commit(objects_path: &Path, hash: &str) -> Result<String> {
+    // The hash may name a blob, so don't assume the content is text.
+    let content = read_object_raw(objects_path, hash)?;
+    let tag = std::str::from_utf8(&content).ok().and_then(parse_tag);
+    Ok(tag.map_or_else(|| hash.to_string(), |tag| tag.object))
+}
+
+/// Resolves a revision name to a commit hash. `HEAD`, then `HEAD@{n}`
+/// (where HEAD was `n` moves ago, from the reflog), then tag names, then
+/// branch names, then remote-tracking `<remote>/<branch>` names, then
+/// (possibly abbreviated) object hashes are tried in that order.
+pub(crate) fn resolve_revision(root_path: &Path, rev: &str) -> Result<String> {
+    let objects_path = gini_dir(root_path).join("objects");
+    if rev == "HEAD" {
+        return get_head_commit(root_path)?
+            .ok_or_else(|| anyhow::anyhow!("HEAD does not point at a checkpoint yet"));
+    }
+    if let Some(n) = rev.strip_prefix("HEAD@{").and_then(|rest| rest.strip_suffix('}')) {
+        let n: usize = n.parse().map_err(|_| GiniError::UnknownRevision {
+            rev: rev.to_string(),
+            reason: "expected HEAD@{<number>}".to_string(),
+        })?;
+        let entries = read_reflog(root_path)?;
+        let entry = entries.get(n).ok_or_else(|| GiniError::UnknownRevision {
+            rev: rev.to_string(),
+            reason: format!("the reflog has only {} entries", entries.len()),
+        })?;
+        return Ok(entry.new.clone());
+    }
+    let named = if validate_ref_name(rev).is_ok() {
+        match read_ref(root_path, &format!("{}/{}", TAGS_DIR, rev))? {
+            Some(hash) => Some(hash),
+            None => read_ref(root_path, &format!("{}/{}", HEADS_DIR, rev))?,
+        }
+    } else {
+        match rev.split_once('/') {
+            Some((remote, branch))
+                if validate_ref_name(remote).is_ok() && validate_ref_name(branch).is_ok() =>
+            {
+                read_ref(root_path, &format!("{}/{}", REMOTES_DIR, rev))?
+            }
+            _ => None,
+        }
+    };
+    let hash = match named {
+        Some(hash) => hash,
+        None => resolve_hash(&objects_path, rev)
+            .map_err(|e| GiniError::UnknownRevision { rev: rev.to_string(), reason: e.to_string() })?,
+    };
+    peel_to_commit(&objects_path, &hash)
+}
+
+/// Whether `ancestor` is `descendant` itself or somewhere in its history.
+pub(crate) fn is_ancestor(objects_path: &Path, ancestor: &str, descendant: &str) -> Result<bool> {
+    let mut pending = vec![descendant.to_string()];
+    let mut seen = HashSet::new();
+    while let Some(hash) = pending.pop() {
+        if hash == ancestor {
+            return Ok(true);
+        }
+        if seen.insert(hash.clone()) {
+            pending.extend(parse_commit_details(&read_object(objects_path, &hash)?)?.parents);
+        }
+    }
+    Ok(false)
+}
+
+/// `commit` and every checkpoint in its history, through all parents.
+pub(crate) fn ancestors(objects_path: &Path, commit: &str) -> Result<HashSet<String>> {
+    let mut ancestors = HashSet::new();
+    let mut pending = vec![commit.to_string()];
+    while let Some(hash) = pending.pop() {
+        if ancestors.insert(hash.clone()) {
+            pending.extend(parse_commit_details(&read_object(objects_path, &hash)?)?.parents);
+        }
+    }
+    Ok(ancestors)
+}
+
+/// The nearest checkpoint that is in the history of both `a` and `b`, or
+/// `None` when they share no history. "Nearest" means the first one met
+/// walking back from `b` breadth-first.
+pub(crate) fn merge_base(objects_path: &Path, a: &str, b: &str) -> Result<Option<String>> {
+    let ancestors_of_a = ancestors(objects_path, a)?;
+    let mut queue = VecDeque::from([b.to_string()]);
+    let mut seen = HashSet::new();
+    while let Some(hash) = queue.pop_front() {
+        if ancestors_of_a.contains(&hash) {
+            return Ok(Some(hash));
+        }
+        if seen.insert(hash.clone()) {
+            queue.extend(parse_commit_details(&read_object(objects_path, &hash)?)?.parents);
+        }
+    }
+    Ok(None)
+}
+
+/// Returns each ref under `.gini/refs` (by its path, e.g.
+/// `refs/heads/main`) with the hash it points at, sorted by name, followed
+/// by HEAD and the stash entries (`stash@{0}` is the newest). Tag refs may
+/// name annotated tag objects rather than commits.
+pub(crate) fn named_ref_tips(root_path: &Path) -> Result<Vec<(String, String)>> {
+    fn walk(dir: &Path, prefix: &str, tips: &mut Vec<(String, String)>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = format!("{}/{}", prefix, path.file_name().unwrap_or_default().to_string_lossy());
+            if path.is_dir() {
+                walk(&path, &name, tips)?;
+            } else if path.extension().is_none() {
+                let content = fs::read_to_string(&path)?;
+                let hash = content.trim();
+                if is_valid_hash(hash) {
+                    tips.push((name, hash.to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    let mut tips = Vec::new();
+    let refs_path = gini_dir(root_path).join("refs");
+    if refs_path.is_dir() {
+        walk(&refs_path, "refs", &mut tips)?;
+    }
+    tips.sort();
+    if let Some(head) = get_head_commit(root_path)? {
+        tips.push(("HEAD".to_string(), head));
+    }
+    if let Some(merge_head) = read_merge_head(root_path)? {
+        tips.push((MERGE_HEAD_FILE_NAME.to_string(), merge_head));
+    }
+    for (i, hash) in read_stash(root_path)?.into_iter().enumerate() {
+        tips.push((format!("stash@{{{}}}", i), hash));
+    }
+    Ok(tips)
+}
+
+/// Returns the hashes that every ref under `.gini/refs`, plus HEAD and the
+/// stash, points at. Tag refs may name annotated tag objects rather than
+/// commits.
+pub(crate) fn all_ref_tips(root_path: &Path) -> Result<Vec<String>> {
+    let mut tips: Vec<String> = named_ref_tips(root_path)?.into_iter().map(|(_, hash)| hash).collect();
+    tips.sort();
+    tips.dedup();
+    Ok(tips)
+}
+
+/// Marks every commit, tree, and blob reachable from any ref, reflog entry
+/// younger than `gc.reflogExpire`, backup, or the staging index, each with
+/// the first of them found to reach it: a ref name as in
+/// [`named_ref_tips`], `HEAD@{n}`, `backup <name>`, or `index`.
+pub(crate) fn reachable_objects_by_source(root_path: &Path) -> Result<HashMap<String, String>> {
+    Ok(walk_reachable(root_path, true)?.0)
+}
+
+/// Every distinct tree [`reachable_objects_by_source`] reaches.
+pub(crate) fn reachable_trees(root_path: &Path) -> Result<Vec<String>> {
+    Ok(walk_reachable(root_path, true)?.1)
+}
+
+/// The walk behind [`reachable_objects_by_source`]: every reachable object
+/// with its source, and the trees among them. Backups only count when
+/// `with_backups` is set.
+fn walk_reachable(root_path: &Path, with_backups: bool) -> Result<(HashMap<String, String>, Vec<String>)> {
+    fn mark_tree(
+        objects_path: &Path,
+        tree_hash: &str,
+        source: &str,
+        seen: &mut HashMap<String, String>,
+        trees: &mut Vec<String>,
+    ) -> Result<()> {
+        if seen.contains_key(tree_hash) {
+            return Ok(());
+        }
+        seen.insert(tree_hash.to_string(), source.to_string());
+        trees.push(tree_hash.to_string());
+        for entry in read_tree(objects_path, tree_hash)? {
+            match entry.kind {
+                EntryKind::Tree => mark_tree(objects_path, &entry.hash, source, seen, trees)?,
+                EntryKind::Blob | EntryKind::Link => {
+                    seen.entry(entry.hash).or_insert_with(|| source.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    let objects_path = gini_dir(root_path).join("objects");
+    let mut seen = HashMap::new();
+    let mut trees = Vec::new();
+    let mut tips = named_ref_tips(root_path)?;
+    // Commits HEAD was at recently stay, so the reflog can bring them back.
+    let cutoff = reflog_cutoff(root_path)?;
+    for (n, entry) in read_reflog(root_path)?.into_iter().enumerate() {
+        if entry.timestamp >= cutoff && object_exists(&objects_path, &entry.new) {
+            tips.push((format!("HEAD@{{{}}}", n), entry.new));
+        }
+    }
+    for (name, tip) in tips {
+        let commit = peel_to_commit(&objects_path, &tip)?;
+        if commit != tip {
+            // An annotated tag is an object of its own, kept alongside its commit.
+            seen.entry(tip).or_insert_with(|| name.clone());
+        }
+        let mut pending = vec![commit];
+        while let Some(hash) = pending.pop() {
+            if seen.contains_key(&hash) {
+                continue;
+            }
+            seen.insert(hash.clone(), name.clone());
+            let commit_content = read_object(&objects_path, &hash)?;
+            mark_tree(&objects_path, &parse_commit_tree(&commit_content)?, &name, &mut seen, &mut trees)?;
+            pending.extend(parse_commit_details(&commit_content)?.parents);
+        }
+    }
+    let backups = if with_backups { list_backups(root_path)? } else { Vec::new() };
+    for (name, path) in backups {
+        if let Some(tree_hash) = read_backup_tree(&path)? {
+            mark_tree(&objects_path, &tree_hash, &format!("backup {}", name), &mut seen, &mut trees)?;
+        }
+    }
+    // Staged files that no checkpoint records yet.
+    for (_, file) in load_index(root_path)?.unwrap_or_default() {
+        seen.entry(file.hash).or_insert_with(|| "index".to_string());
+    }
+    Ok((seen, trees))
+}
+
+/// Reflog entries older than this Unix timestamp no longer keep their
+/// commits from `gc`: `gc.reflogExpire` ago.
+pub(crate) fn reflog_cutoff(root_path: &Path) -> Result<u64> {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+    Ok(now.saturating_sub(Config::load(root_path)?.reflog_expire()?))
+}
+
+/// Marks every commit, tree, and blob reachable from any ref, recent
+/// reflog entry, backup, or the staging index.
+pub(crate) fn collect_reachable_objects(root_path: &Path) -> Result<HashSet<String>> {
+    Ok(reachable_objects_by_source(root_path)?.into_keys().collect())
+}
+
+/// Like [`collect_reachable_objects`], as if there were no backups.
+pub(crate) fn collect_reachable_without_backups(root_path: &Path) -> Result<HashSet<String>> {
+    Ok(walk_reachable(root_path, false)?.0.into_keys().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CheckpointOptions, Repository};
+    use tempfile::TempDir;
+
+    /// A repository with two checkpoints, returned oldest first.
+    fn repo_with_two_checkpoints() -> (TempDir, Repository, String, String) {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut commits = Vec::new();
+        for content in ["one\n", "two\n"] {
+            fs::write(dir.path().join("file.txt"), content).unwrap();
+            let report = repo.checkpoint(content.trim(), &CheckpointOptions::default()).unwrap();
+            commits.push(report.commit.unwrap());
+        }
+        let second = commits.pop().unwrap();
+        let first = commits.pop().unwrap();
+        (dir, repo, first, second)
+    }
+
+    fn is_stale_ref(error: &anyhow::Error) -> bool {
+        matches!(error.downcast_ref::<GiniError>(), Some(GiniError::StaleRef { .. }))
+    }
+
+    #[test]
+    fn update_head_checked_refuses_a_moved_head() {
+        let (_dir, repo, first, second) = repo_with_two_checkpoints();
+        let root_path = repo.root();
+        let read = get_head_commit(root_path).unwrap();
+        assert_eq!(read.as_deref(), Some(second.as_str()));
+
+        // Someone else moves HEAD between our read and our update.
+        update_head(root_path, &first, "concurrent").unwrap();
+
+        let error = update_head_checked(root_path, read.as_deref(), &second, "ours").unwrap_err();
+        assert!(is_stale_ref(&error), "unexpected error: {}", error);
+        assert_eq!(get_head_commit(root_path).unwrap().as_deref(), Some(first.as_str()));
+    }
+
+    #[test]
+    fn update_ref_checked_refuses_a_moved_ref() {
+        let (_dir, repo, first, second) = repo_with_two_checkpoints();
+        let root_path = repo.root();
+        let ref_name = "refs/heads/topic";
+        update_ref_checked(root_path, ref_name, None, &second).unwrap();
+        let read = read_ref(root_path, ref_name).unwrap();
+
+        // The ref moves on after we read it.
+        update_ref_checked(root_path, ref_name, read.as_deref(), &first).unwrap();
+
+        let error = update_ref_checked(root_path, ref_name, read.as_deref(), &second).unwrap_err();
+        assert!(is_stale_ref(&error), "unexpected error: {}", error);
+        assert_eq!(read_ref(root_path, ref_name).unwrap().as_deref(), Some(first.as_str()));
+    }
+}
@@ -0,0 +1,72 @@
+//! Fetching from another gini repository on the local filesystem. A remote
+//! is just a path, remembered as `remote.<name>.path` in the config; its
+//! branches are mirrored under `.gini/refs/remotes/<name>`.
+
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::commit::{parse_commit_details, parse_commit_tree};
+use crate::objects::{hash_algo, object_exists, read_object, read_object_raw, write_raw_object};
+use crate::tree::{read_tree, EntryKind};
+
+/// Copies every object reachable from `tips` in `source` that `dest` is
+/// missing, and returns how many were copied.
+///
+/// A commit that `dest` already has is taken to come with its whole history,
+/// so the walk stops there. To keep that true if the copy is interrupted,
+/// objects are written children first: blobs and subtrees before their tree,
+/// a tree before its commit, and parents before children.
+pub(crate) fn copy_missing_objects(source: &Path, dest: &Path, tips: &[String]) -> Result<usize> {
+    let (source_algo, dest_algo) = (hash_algo(source)?, hash_algo(dest)?);
+    if source_algo != dest_algo {
+        bail!(
+            "Cannot copy {} objects into a repository that uses {}",
+            source_algo.name(),
+            dest_algo.name()
+        );
+    }
+    let mut copied = HashSet::new();
+    for tip in tips {
+        // A commit is pushed once to queue its parents and again, beneath
+        // them, to be copied after they are.
+        let mut pending = vec![(tip.clone(), false)];
+        while let Some((hash, parents_done)) = pending.pop() {
+            if object_exists(dest, &hash) || copied.contains(&hash) {
+                continue;
+            }
+            let content = read_object(source, &hash)?;
+            if parents_done {
+                copy_tree(source, dest, &parse_commit_tree(&content)?, &mut copied)?;
+                copy_object(source, dest, &hash, &mut copied)?;
+                continue;
+            }
+            let parents = parse_commit_details(&content)?.parents;
+            pending.push((hash, true));
+            pending.extend(parents.into_iter().map(|parent| (parent, false)));
+        }
+    }
+    Ok(copied.len())
+}
+
+fn copy_tree(source: &Path, dest: &Path, tree_hash: &str, copied: &mut HashSet<String>) -> Result<()> {
+    if object_exists(dest, tree_hash) || copied.contains(tree_hash) {
+        return Ok(());
+    }
+    for entry in read_tree(source, tree_hash)? {
+        match entry.kind {
+            EntryKind::Tree => copy_tree(source, dest, &entry.hash, copied)?,
+            EntryKind::Blob | EntryKind::Link => copy_object(source, dest, &entry.hash, copied)?,
+        }
+    }
+    copy_object(source, dest, tree_hash, copied)
+}
+
+fn copy_object(source: &Path, dest: &Path, hash: &str, copied: &mut HashSet<String>) -> Result<()> {
+    if object_exists(dest, hash) || copied.contains(hash) {
+        return Ok(());
+    }
+    write_raw_object(dest, hash, &read_object_raw(source, hash)?)?;
+    copied.insert(hash.to_string());
+    Ok(())
+}
@@ -0,0 +1,44 @@
+//! The stash stack, `.gini/stash`: shelved working trees saved as commits
+//! whose parent is the checkpoint they were taken on.
+//!
+//! The file lists one commit hash per line, oldest first, so the top of the
+//! stack is the last line.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::objects::is_valid_hash;
+use crate::util::{gini_dir, write_file_atomic};
+use crate::STASH_FILE_NAME;
+
+/// The stashed commits, newest first.
+pub(crate) fn read_stash(root_path: &Path) -> Result<Vec<String>> {
+    let path = gini_dir(root_path).join(STASH_FILE_NAME);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let mut stack = Vec::new();
+    for line in fs::read_to_string(&path)?.lines() {
+        if !is_valid_hash(line) {
+            bail!("Invalid stash entry: {}", line);
+        }
+        stack.push(line.to_string());
+    }
+    stack.reverse();
+    Ok(stack)
+}
+
+/// Replaces the stack with `stack` (newest first); an empty stack removes
+/// the file.
+pub(crate) fn write_stash(root_path: &Path, stack: &[String]) -> Result<()> {
+    let path = gini_dir(root_path).join(STASH_FILE_NAME);
+    if stack.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        return Ok(());
+    }
+    let content: String = stack.iter().rev().map(|hash| format!("{}\n", hash)).collect();
+    write_file_atomic(&path, content.as_bytes())
+}
@@ -0,0 +1,1002 @@
+//! Tree objects: reading, writing, and walking the working tree.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::eol::to_native;
+use crate::ignore::IgnoreRules;
+use crate::objects::{
+    hash_and_write_file, hash_and_write_object, hash_bytes, hash_file, hash_normalized_file,
+    is_valid_hash, object_exists, read_object, read_object_raw,
+};
+use crate::util::{create_symlink, gini_dir, read_link_bytes, write_file_atomic};
+use crate::{Progress, TreeTotals, STAT_CACHE_FILE_NAME};
+
+/// The kind of object a tree entry points at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntryKind {
+    Blob,
+    Tree,
+    /// A symlink; the blob holds the link target.
+    Link,
+}
+
+/// Mode recorded for regular files, as in git.
+pub(crate) const MODE_FILE: u32 = 0o100644;
+/// Mode recorded for files with an execute bit set.
+pub(crate) const MODE_EXECUTABLE: u32 = 0o100755;
+/// Mode used for symlinks in flattened file maps; `link` tree lines carry
+/// no mode of their own.
+pub(crate) const MODE_SYMLINK: u32 = 0o120000;
+/// Mode of subtrees. In flattened file maps it marks an empty directory,
+/// whose hash is that of the empty tree.
+pub(crate) const MODE_DIR: u32 = 0o40000;
+
+/// One line of a tree object: `blob <hash> <mode>  <name>`,
+/// `link <hash>  <name>`, or `tree <hash>  <name>`. Blob lines written before modes were recorded
+/// have no mode and are read as [`MODE_FILE`].
+#[derive(Debug, Clone)]
+pub(crate) struct TreeEntry {
+    pub(crate) kind: EntryKind,
+    pub(crate) hash: String,
+    pub(crate) mode: u32,
+    pub(crate) name: String,
+}
+
+impl TreeEntry {
+    fn to_line(&self) -> String {
+        match self.kind {
+            EntryKind::Blob => format!("blob {} {:o}  {}", self.hash, self.mode, self.name),
+            EntryKind::Tree => format!("tree {}  {}", self.hash, self.name),
+            EntryKind::Link => format!("link {}  {}", self.hash, self.name),
+        }
+    }
+}
+
+/// A file as recorded in a tree: its blob hash and mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileEntry {
+    pub(crate) hash: String,
+    pub(crate) mode: u32,
+}
+
+impl FileEntry {
+    /// True for the placeholder entry standing in for an empty directory.
+    pub(crate) fn is_empty_dir(&self) -> bool {
+        self.mode == MODE_DIR
+    }
+}
+
+/// Serializes tree entries (keyed, and so sorted, by name) into a tree object.
+fn format_tree(entries: &BTreeMap<String, TreeEntry>) -> String {
+    entries
+        .values()
+        .map(TreeEntry::to_line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub(crate) fn parse_tree(tree_content: &str) -> Result<Vec<TreeEntry>> {
+    let mut entries = Vec::new();
+    for line in tree_content.lines() {
+        let parts: Vec<_> = line.split_whitespace().collect();
+        let (obj_type, hash, mode, name) = match parts[..] {
+            [obj_type, hash, name] => (obj_type, hash, None, name),
+            [obj_type, hash, mode, name] => (obj_type, hash, Some(mode), name),
+            _ => bail!("Invalid tree entry format: {}", line),
+        };
+
+        // Validate object type
+        let kind = match obj_type {
+            "tree" => EntryKind::Tree,
+            "blob" => EntryKind::Blob,
+            "link" => EntryKind::Link,
+            _ => bail!("Invalid object type: {}", obj_type),
+        };
+
+        // Validate hash
+        if !is_valid_hash(hash) {
+            bail!("Invalid hash in tree: {}", hash);
+        }
+
+        let mode = match mode {
+            Some(mode) => u32::from_str_radix(mode, 8)
+                .map_err(|_| anyhow::anyhow!("Invalid mode in tree: {}", mode))?,
+            None if kind == EntryKind::Link => MODE_SYMLINK,
+            None => MODE_FILE,
+        };
+
+        // Validate filename: one plain path component that can't climb out
+        // of the directory it is restored into or land in `.gini`.
+        let is_plain = !name.contains(['/', '\\', '\0']) && !matches!(name, "" | "." | ".." | ".gini");
+        if !is_plain {
+            bail!("Invalid filename in tree: {}", name);
+        }
+
+        entries.push(TreeEntry {
+            kind,
+            hash: hash.to_string(),
+            mode,
+            name: name.to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+pub(crate) fn read_tree(objects_path: &Path, tree_hash: &str) -> Result<Vec<TreeEntry>> {
+    if !is_valid_hash(tree_hash) {
+        bail!("Invalid tree hash: {}", tree_hash);
+    }
+    parse_tree(&read_object(objects_path, tree_hash)?)
+}
+
+/// Writes a tree's files into `target_dir`, replacing anything in the way,
+/// with native line endings if `autocrlf` is set (see [`write_blob_file`]).
+/// Nothing is written outside `target_dir`.
+pub(crate) fn restore_tree(target_dir: &Path, objects_path: &Path, tree_hash: &str, autocrlf: bool) -> Result<()> {
+    restore_subtree(target_dir, target_dir, objects_path, tree_hash, autocrlf)
+}
+
+fn restore_subtree(root_path: &Path, dir: &Path, objects_path: &Path, tree_hash: &str, autocrlf: bool) -> Result<()> {
+    for entry in read_tree(objects_path, tree_hash)? {
+        let path = dir.join(&entry.name);
+        clear_path_for(root_path, &path, entry.kind == EntryKind::Tree)?;
+
+        match entry.kind {
+            EntryKind::Tree => {
+                fs::create_dir_all(&path)?;
+                restore_subtree(root_path, &path, objects_path, &entry.hash, autocrlf)?;
+            }
+            EntryKind::Blob => {
+                let blob_content = read_object_raw(objects_path, &entry.hash)?;
+                write_blob_file(&path, blob_content, entry.mode, autocrlf)?;
+            }
+            EntryKind::Link => {
+                create_symlink(&read_object_raw(objects_path, &entry.hash)?, &path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Moves the working tree from the `current` set of tracked files to
+/// `target`: files tracked in `current` but absent from `target` are
+/// deleted (along with directories this leaves empty), and every file in
+/// `target` is written out, honoring `core.autocrlf`. Untracked files are
+/// left alone.
+pub(crate) fn checkout_files(
+    root_path: &Path,
+    objects_path: &Path,
+    current: &BTreeMap<String, FileEntry>,
+    target: &BTreeMap<String, FileEntry>,
+) -> Result<()> {
+    let autocrlf = Config::load(root_path)?.autocrlf()?;
+    for (path, file) in current {
+        if target.contains_key(path) {
+            continue;
+        }
+        let full_path = root_path.join(path);
+        ensure_inside(root_path, &full_path)?;
+        match fs::symlink_metadata(&full_path) {
+            Ok(metadata) if metadata.is_dir() => {
+                // Only an empty directory placeholder; keep it if it gained files.
+                if file.is_empty_dir() {
+                    let _ = fs::remove_dir(&full_path);
+                }
+            }
+            Ok(_) => fs::remove_file(&full_path)?,
+            Err(_) => continue,
+        }
+        remove_empty_parents(root_path, &full_path);
+    }
+
+    for (path, file) in target {
+        let full_path = root_path.join(path);
+        clear_path_for(root_path, &full_path, file.is_empty_dir())?;
+        if file.is_empty_dir() {
+            fs::create_dir_all(&full_path)?;
+            continue;
+        }
+        let content = read_object_raw(objects_path, &file.hash)?;
+        if file.mode == MODE_SYMLINK {
+            create_symlink(&content, &full_path)?;
+        } else {
+            write_blob_file(&full_path, content, file.mode, autocrlf)?;
+        }
+    }
+    Ok(())
+}
+
+/// Makes room to create `path` (a directory if `want_dir`): every ancestor
+/// must be a real directory, so files or symlinks in the way are removed,
+/// as is anything of the wrong kind at `path` itself. Fails if `path` would
+/// still end up outside `root_path`.
+pub(crate) fn clear_path_for(root_path: &Path, path: &Path, want_dir: bool) -> Result<()> {
+    let relative = path.strip_prefix(root_path).unwrap_or(path);
+    let mut current = root_path.to_path_buf();
+    for component in relative.parent().into_iter().flat_map(Path::components) {
+        current.push(component);
+        if fs::symlink_metadata(&current).is_ok_and(|m| !m.is_dir()) {
+            fs::remove_file(&current)?;
+        }
+    }
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() && !want_dir => fs::remove_dir_all(path)?,
+        Ok(metadata) if !metadata.is_dir() && (want_dir || metadata.is_symlink()) => {
+            fs::remove_file(path)?
+        }
+        _ => {}
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    ensure_inside(root_path, path)
+}
+
+/// Fails unless the directory `path` sits in, with symlinks resolved, is
+/// `root_path` or below it. Checked before anything a tree names is written
+/// or deleted, so a crafted tree can't reach outside the working tree.
+pub(crate) fn ensure_inside(root_path: &Path, path: &Path) -> Result<()> {
+    let root = fs::canonicalize(root_path)
+        .with_context(|| format!("Failed to resolve {}", root_path.display()))?;
+    // The parent may not exist yet; its nearest existing ancestor decides.
+    let mut dir = path.parent();
+    while let Some(candidate) = dir {
+        if fs::symlink_metadata(candidate).is_ok() {
+            break;
+        }
+        dir = candidate.parent();
+    }
+    let Some(dir) = dir else {
+        return Ok(());
+    };
+    let resolved = fs::canonicalize(dir).with_context(|| format!("Failed to resolve {}", dir.display()))?;
+    if !resolved.starts_with(&root) {
+        bail!("Refusing to write outside {}: {}", root_path.display(), path.display());
+    }
+    Ok(())
+}
+
+/// Removes the directories above `path` that are left empty, stopping at
+/// the repository root.
+fn remove_empty_parents(root_path: &Path, path: &Path) {
+    let mut current = path.parent();
+    while let Some(dir) = current {
+        if dir == root_path || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
+
+/// Reads the mode to record for a file from its metadata. Only the execute
+/// bit is tracked; platforms without one always record [`MODE_FILE`].
+pub(crate) fn file_mode(metadata: &fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 != 0 {
+            return MODE_EXECUTABLE;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    MODE_FILE
+}
+
+/// Writes a blob out as a regular file with the given mode. With
+/// `autocrlf` (`core.autocrlf`), text gets the platform's line endings.
+pub(crate) fn write_blob_file(path: &Path, content: Vec<u8>, mode: u32, autocrlf: bool) -> Result<()> {
+    let content = if autocrlf { to_native(content) } else { content };
+    fs::write(path, content)?;
+    apply_mode(path, mode)
+}
+
+/// Sets or clears a restored file's execute bits to match `mode`, keeping
+/// its other permission bits. A no-op where there are no execute bits.
+pub(crate) fn apply_mode(path: &Path, mode: u32) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let current = fs::metadata(path)?.permissions().mode();
+        let updated = if mode == MODE_EXECUTABLE {
+            // Grant execute to whoever can read, as git does.
+            current | ((current & 0o444) >> 2)
+        } else {
+            current & !0o111
+        };
+        if updated != current {
+            fs::set_permissions(path, fs::Permissions::from_mode(updated))?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
+}
+
+/// Maps every file path in a tree (relative, `/`-separated) to its blob hash
+/// and mode. Empty directories appear as [`MODE_DIR`] entries so that they
+/// survive a round trip through the index.
+pub(crate) fn flatten_tree(objects_path: &Path, tree_hash: &str) -> Result<BTreeMap<String, FileEntry>> {
+    fn walk(
+        objects_path: &Path,
+        tree_hash: &str,
+        prefix: &str,
+        files: &mut BTreeMap<String, FileEntry>,
+    ) -> Result<()> {
+        let entries = read_tree(objects_path, tree_hash)?;
+        if entries.is_empty() && !prefix.is_empty() {
+            files.insert(
+                prefix.trim_end_matches('/').to_string(),
+                FileEntry {
+                    hash: tree_hash.to_string(),
+                    mode: MODE_DIR,
+                },
+            );
+        }
+        for entry in entries {
+            let path = format!("{}{}", prefix, entry.name);
+            match entry.kind {
+                EntryKind::Tree => walk(objects_path, &entry.hash, &format!("{}/", path), files)?,
+                EntryKind::Blob | EntryKind::Link => {
+                    files.insert(
+                        path,
+                        FileEntry {
+                            hash: entry.hash,
+                            mode: entry.mode,
+                        },
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = BTreeMap::new();
+    walk(objects_path, tree_hash, "", &mut files)?;
+    Ok(files)
+}
+
+/// Looks up a `/`-separated path inside a tree.
+pub(crate) fn find_tree_entry(
+    objects_path: &Path,
+    tree_hash: &str,
+    path: &str,
+) -> Result<Option<TreeEntry>> {
+    let mut current_tree = tree_hash.to_string();
+    let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+    while let Some(component) = components.next() {
+        let entry = match read_tree(objects_path, &current_tree)?
+            .into_iter()
+            .find(|entry| entry.name == component)
+        {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if components.peek().is_none() {
+            return Ok(Some(entry));
+        }
+        if entry.kind != EntryKind::Tree {
+            return Ok(None);
+        }
+        current_tree = entry.hash;
+    }
+    Ok(None)
+}
+
+/// Writes the tree objects for a flat path -> file map and returns the root
+/// tree hash. An empty-directory entry is dropped if files beneath it are
+/// also present.
+pub(crate) fn write_tree_from_files(objects_path: &Path, files: &BTreeMap<String, FileEntry>) -> Result<String> {
+    build_tree_from_files(objects_path, files, true)
+}
+
+/// The hash [`write_tree_from_files`] would return, without storing
+/// anything.
+pub(crate) fn hash_tree_from_files(objects_path: &Path, files: &BTreeMap<String, FileEntry>) -> Result<String> {
+    build_tree_from_files(objects_path, files, false)
+}
+
+fn build_tree_from_files(objects_path: &Path, files: &BTreeMap<String, FileEntry>, write: bool) -> Result<String> {
+    let store = |content: &[u8]| match write {
+        true => hash_and_write_object(objects_path, content),
+        false => hash_bytes(objects_path, content),
+    };
+    fn write_level(store: &dyn Fn(&[u8]) -> Result<String>, files: &[(&str, &FileEntry)]) -> Result<String> {
+        let mut entries = BTreeMap::new();
+        let mut i = 0;
+        while i < files.len() {
+            let (path, file) = files[i];
+            match path.split_once('/') {
+                None if file.is_empty_dir() => {
+                    let empty_tree = store(b"")?;
+                    entries.insert(path.to_string(), tree_entry(path, empty_tree));
+                    i += 1;
+                }
+                None => {
+                    let kind = if file.mode == MODE_SYMLINK {
+                        EntryKind::Link
+                    } else {
+                        EntryKind::Blob
+                    };
+                    entries.insert(
+                        path.to_string(),
+                        TreeEntry {
+                            kind,
+                            hash: file.hash.clone(),
+                            mode: file.mode,
+                            name: path.to_string(),
+                        },
+                    );
+                    i += 1;
+                }
+                Some((dir, _)) => {
+                    let mut children = Vec::new();
+                    while i < files.len() {
+                        match files[i].0.split_once('/') {
+                            Some((d, rest)) if d == dir => children.push((rest, files[i].1)),
+                            _ => break,
+                        }
+                        i += 1;
+                    }
+                    let sub_tree_hash = write_level(store, &children)?;
+                    entries.insert(dir.to_string(), tree_entry(dir, sub_tree_hash));
+                }
+            }
+        }
+        store(format_tree(&entries).as_bytes())
+    }
+
+    let files: Vec<(&str, &FileEntry)> = files.iter().map(|(p, f)| (p.as_str(), f)).collect();
+    write_level(&store, &files)
+}
+
+fn tree_entry(name: &str, hash: String) -> TreeEntry {
+    TreeEntry {
+        kind: EntryKind::Tree,
+        hash,
+        mode: MODE_DIR,
+        name: name.to_string(),
+    }
+}
+
+/// Walks the working tree and writes its blobs and trees to the store.
+pub(crate) struct TreeBuilder {
+    pub(crate) root_path: PathBuf,
+    pub(crate) objects_path: PathBuf,
+    pub(crate) ignore: IgnoreRules,
+    /// Blob hashes from the previous checkpoint, keyed by path; files whose
+    /// size and mtime still match are not re-read.
+    pub(crate) cache: StatCache,
+    /// Stat information gathered during this walk.
+    pub(crate) seen: StatCache,
+    /// Every file visited during this walk.
+    pub(crate) files: BTreeMap<String, FileEntry>,
+    /// When false, hashes are computed but nothing is stored.
+    pub(crate) write_objects: bool,
+    /// Files larger than this are rejected (see `core.maxFileSize`).
+    pub(crate) max_file_size: u64,
+    /// Store text files with LF line endings (see `core.autocrlf`).
+    pub(crate) autocrlf: bool,
+    /// Leave files over `max_file_size` out of the tree instead of failing.
+    pub(crate) skip_large: bool,
+    /// When set, only paths these patterns match (or that sit inside a
+    /// matching directory) are recorded.
+    pub(crate) include: Option<IgnoreRules>,
+    /// Paths left out because of `skip_large`, with their sizes in bytes.
+    pub(crate) skipped: Vec<(String, u64)>,
+    /// Told about each recorded file and finished directory.
+    pub(crate) progress: Option<fn(&Progress)>,
+    /// Files recorded so far, for progress reports.
+    pub(crate) files_done: usize,
+    /// Bytes hashed so far, for progress reports.
+    pub(crate) bytes_hashed: u64,
+}
+
+impl TreeBuilder {
+    pub(crate) fn new(root_path: &Path, use_cache: bool) -> Result<Self> {
+        let cache = if use_cache {
+            StatCache::load(root_path)?
+        } else {
+            StatCache::default()
+        };
+        let config = Config::load(root_path)?;
+        Ok(TreeBuilder {
+            max_file_size: config.max_file_size()?,
+            autocrlf: config.autocrlf()?,
+            root_path: root_path.to_path_buf(),
+            objects_path: gini_dir(root_path).join("objects"),
+            ignore: IgnoreRules::load(root_path)?,
+            cache,
+            seen: StatCache::default(),
+            files: BTreeMap::new(),
+            write_objects: true,
+            skip_large: false,
+            include: None,
+            skipped: Vec::new(),
+            progress: None,
+            files_done: 0,
+            bytes_hashed: 0,
+        })
+    }
+
+    pub(crate) fn write_tree(&mut self, dir_path: &Path) -> Result<String> {
+        let mut entries = BTreeMap::new();
+        let mut pending = Vec::new();
+
+        for entry in fs::read_dir(dir_path)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_name = path.file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| anyhow::anyhow!("Invalid filename"))?;
+
+            // Symlinks are recorded as links, never followed, so a link to a
+            // directory can neither duplicate content nor loop.
+            let file_type = entry.file_type()?;
+            if self.ignore.is_ignored(&path, file_type.is_dir()) {
+                continue;
+            }
+
+            if !file_type.is_dir() && !self.is_included(&path, false) {
+                continue;
+            }
+
+            if file_type.is_symlink() {
+                let link = self.write_link(&path)?;
+                entries.insert(
+                    file_name.to_string(),
+                    TreeEntry {
+                        kind: EntryKind::Link,
+                        hash: link.hash,
+                        mode: link.mode,
+                        name: file_name.to_string(),
+                    },
+                );
+            } else if file_type.is_dir() {
+                let sub_tree_hash = self.write_tree(&path)?;
+                // Directories are walked for matches inside them, but one
+                // with no matches is left out rather than kept as empty.
+                if sub_tree_hash == hash_bytes(&self.objects_path, b"")? && !self.is_included(&path, true) {
+                    continue;
+                }
+                entries.insert(file_name.to_string(), tree_entry(file_name, sub_tree_hash));
+            } else {
+                // Check file size before reading
+                let metadata = fs::metadata(&path)?;
+                if metadata.len() > self.max_file_size {
+                    if self.skip_large {
+                        self.skipped.push((self.relative(&path), metadata.len()));
+                        continue;
+                    }
+                    bail!(
+                        "File too large: {} (max {} bytes; raise core.maxFileSize, pass --skip-large, or set core.excludeLarge)",
+                        path.display(),
+                        self.max_file_size
+                    );
+                }
+
+                pending.push((file_name.to_string(), path, metadata));
+            }
+        }
+
+        // Regular files are hashed together so the work can be spread over
+        // threads; `entries` is ordered by name, so the tree hash does not
+        // depend on which finishes first.
+        for (name, blob) in self.write_blobs(pending)? {
+            entries.insert(
+                name.clone(),
+                TreeEntry {
+                    kind: EntryKind::Blob,
+                    hash: blob.hash,
+                    mode: blob.mode,
+                    name,
+                },
+            );
+        }
+
+        if entries.is_empty() && dir_path != self.root_path && self.is_included(dir_path, true) {
+            self.files.insert(
+                self.relative(dir_path),
+                FileEntry {
+                    hash: hash_bytes(&self.objects_path, b"")?,
+                    mode: MODE_DIR,
+                },
+            );
+        }
+        let relative = self.relative(dir_path);
+        self.report(&relative, true);
+
+        let tree_content = format_tree(&entries);
+        if self.write_objects {
+            hash_and_write_object(&self.objects_path, tree_content.as_bytes())
+        } else {
+            hash_bytes(&self.objects_path, tree_content.as_bytes())
+        }
+    }
+
+    /// Walks `dir_path` the way [`TreeBuilder::write_tree`] would, with the
+    /// same ignore, include and size rules, but only adds up the files it
+    /// would record and their sizes. Nothing is read or hashed.
+    pub(crate) fn count(&self, dir_path: &Path, totals: &mut TreeTotals) -> Result<()> {
+        for entry in fs::read_dir(dir_path)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if self.ignore.is_ignored(&path, file_type.is_dir()) {
+                continue;
+            }
+            if file_type.is_dir() {
+                self.count(&path, totals)?;
+                continue;
+            }
+            if !self.is_included(&path, false) {
+                continue;
+            }
+            let size = entry.metadata()?.len();
+            // Too large to record: skipped, or the real walk will fail on it.
+            if !file_type.is_symlink() && size > self.max_file_size {
+                continue;
+            }
+            totals.files += 1;
+            totals.bytes += size;
+        }
+        Ok(())
+    }
+
+    /// Whether `path` passes the `include` filter, if there is one.
+    fn is_included(&self, path: &Path, is_dir: bool) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.is_ignored_path(&self.relative(path), is_dir))
+    }
+
+    /// Stores a symlink's target as a blob. Links are tiny, so the stat
+    /// cache is not consulted.
+    pub(crate) fn write_link(&mut self, path: &Path) -> Result<FileEntry> {
+        let target = read_link_bytes(path)?;
+        let hash = if self.write_objects {
+            hash_and_write_object(&self.objects_path, &target)?
+        } else {
+            hash_bytes(&self.objects_path, &target)?
+        };
+        let file = FileEntry {
+            hash,
+            mode: MODE_SYMLINK,
+        };
+        let relative = self.relative(path);
+        self.report(&relative, false);
+        self.files.insert(relative, file.clone());
+        Ok(file)
+    }
+
+    /// Passes a progress report to the callback, if there is one. Files
+    /// are counted as they are reported.
+    fn report(&mut self, path: &str, is_dir: bool) {
+        if !is_dir {
+            self.files_done += 1;
+        }
+        if let Some(progress) = self.progress {
+            progress(&Progress {
+                path,
+                is_dir,
+                files: self.files_done,
+                bytes_hashed: self.bytes_hashed,
+            });
+        }
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root_path)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    /// Hashes (and, when writing, stores) a directory's regular files,
+    /// returning each name with its entry in input order. Files the stat
+    /// cache vouches for are not read; the rest are split across up to
+    /// [`std::thread::available_parallelism`] threads.
+    fn write_blobs(
+        &mut self,
+        files: Vec<(String, PathBuf, fs::Metadata)>,
+    ) -> Result<Vec<(String, FileEntry)>> {
+        let relatives: Vec<String> = files.iter().map(|(_, path, _)| self.relative(path)).collect();
+        let mut hashes: Vec<Option<String>> = files
+            .iter()
+            .zip(&relatives)
+            .map(|((_, _, metadata), relative)| self.cached_hash(relative, metadata))
+            .collect();
+
+        let uncached: Vec<usize> = (0..files.len()).filter(|&i| hashes[i].is_none()).collect();
+        self.bytes_hashed += uncached.iter().map(|&i| files[i].2.len()).sum::<u64>();
+        let hash_one = |path: &Path| {
+            if self.autocrlf {
+                hash_normalized_file(&self.objects_path, path, self.max_file_size, self.write_objects)
+            } else if self.write_objects {
+                hash_and_write_file(&self.objects_path, path, self.max_file_size)
+            } else {
+                hash_file(&self.objects_path, path)
+            }
+        };
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let mut failure = None;
+        if uncached.len() < 2 || threads < 2 {
+            for &i in &uncached {
+                match hash_one(&files[i].1) {
+                    Ok(hash) => hashes[i] = Some(hash),
+                    Err(e) => {
+                        failure = Some(e);
+                        break;
+                    }
+                }
+            }
+        } else {
+            let chunk_size = uncached.len().div_ceil(threads);
+            // Each worker stops at its first failure but keeps what it hashed.
+            let results: Vec<_> = std::thread::scope(|scope| {
+                let workers: Vec<_> = uncached
+                    .chunks(chunk_size)
+                    .map(|chunk| {
+                        let files = &files;
+                        let hash_one = &hash_one;
+                        scope.spawn(move || {
+                            let mut done = Vec::new();
+                            for &i in chunk {
+                                match hash_one(&files[i].1) {
+                                    Ok(hash) => done.push((i, hash)),
+                                    Err(e) => return (done, Some(e)),
+                                }
+                            }
+                            (done, None)
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().expect("hashing thread panicked"))
+                    .collect()
+            });
+            for (done, error) in results {
+                for (i, hash) in done {
+                    hashes[i] = Some(hash);
+                }
+                failure = failure.or(error);
+            }
+        }
+        if let Some(e) = failure {
+            // Remember the files already stored, so a checkpoint run again
+            // after the failure doesn't hash them a second time.
+            for (((_, _, metadata), relative), hash) in files.iter().zip(&relatives).zip(hashes) {
+                if let Some(hash) = hash {
+                    self.seen.entries.insert(
+                        relative.clone(),
+                        StatEntry { size: metadata.len(), mtime: mtime_nanos(metadata), hash },
+                    );
+                }
+            }
+            return Err(e);
+        }
+
+        let mut blobs = Vec::with_capacity(files.len());
+        for (((name, _, metadata), relative), hash) in files.into_iter().zip(relatives).zip(hashes) {
+            let hash = hash.expect("every file was hashed");
+            self.seen.entries.insert(
+                relative.clone(),
+                StatEntry {
+                    size: metadata.len(),
+                    mtime: mtime_nanos(&metadata),
+                    hash: hash.clone(),
+                },
+            );
+            let file = FileEntry {
+                hash,
+                mode: file_mode(&metadata),
+            };
+            self.report(&relative, false);
+            self.files.insert(relative, file.clone());
+            blobs.push((name, file));
+        }
+        Ok(blobs)
+    }
+
+    /// After a failed [`TreeBuilder::write_tree`], saves the stat cache with
+    /// every file stored so far added, so a checkpoint run again picks up
+    /// where this one stopped.
+    pub(crate) fn save_progress(&mut self, root_path: &Path) -> Result<()> {
+        let seen = std::mem::take(&mut self.seen.entries);
+        self.cache.entries.extend(seen);
+        self.cache.save(root_path)
+    }
+
+    /// The hash recorded for `relative` by the last checkpoint, if its size
+    /// and mtime still match and the blob is still stored.
+    fn cached_hash(&self, relative: &str, metadata: &fs::Metadata) -> Option<String> {
+        self.cache
+            .entries
+            .get(relative)
+            .filter(|cached| {
+                cached.size == metadata.len()
+                    && cached.mtime == mtime_nanos(metadata)
+                    && object_exists(&self.objects_path, &cached.hash)
+            })
+            .map(|cached| cached.hash.clone())
+    }
+}
+
+/// Stores the object for a working-tree entry found by
+/// [`scan_working_tree`], which only hashes. `autocrlf` must match the scan.
+pub(crate) fn write_working_object(
+    objects_path: &Path,
+    path: &Path,
+    file: &FileEntry,
+    max_size: u64,
+    autocrlf: bool,
+) -> Result<()> {
+    match file.mode {
+        MODE_SYMLINK => hash_and_write_object(objects_path, &read_link_bytes(path)?)?,
+        MODE_DIR => hash_and_write_object(objects_path, b"")?,
+        _ if autocrlf => hash_normalized_file(objects_path, path, max_size, true)?,
+        _ => hash_and_write_file(objects_path, path, max_size)?,
+    };
+    Ok(())
+}
+
+/// Hashes the working tree (honoring ignore rules) without storing any
+/// objects, returning each file's path, blob hash, and mode.
+pub(crate) fn scan_working_tree(root_path: &Path, use_cache: bool) -> Result<BTreeMap<String, FileEntry>> {
+    let mut builder = TreeBuilder::new(root_path, use_cache)?;
+    builder.write_objects = false;
+    builder.write_tree(root_path)?;
+    Ok(builder.files)
+}
+
+/// Like [`scan_working_tree`], but with every file a restore would replace:
+/// only `.git` is skipped, ignored and oversized files included.
+pub(crate) fn scan_all_files(root_path: &Path) -> Result<BTreeMap<String, FileEntry>> {
+    let mut builder = TreeBuilder::new(root_path, true)?;
+    builder.write_objects = false;
+    builder.ignore = IgnoreRules::parse(root_path, "/.git");
+    builder.max_file_size = u64::MAX;
+    builder.write_tree(root_path)?;
+    Ok(builder.files)
+}
+
+pub(crate) fn mtime_nanos(metadata: &fs::Metadata) -> u128 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct StatEntry {
+    pub(crate) size: u64,
+    pub(crate) mtime: u128,
+    pub(crate) hash: String,
+}
+
+/// `.gini/stat-cache`: one `<hash> <size> <mtime-ns> <path>` line per file
+/// recorded at the last checkpoint.
+#[derive(Debug, Default)]
+pub(crate) struct StatCache {
+    pub(crate) entries: BTreeMap<String, StatEntry>,
+}
+
+impl StatCache {
+    pub(crate) fn load(root_path: &Path) -> Result<Self> {
+        let path = gini_dir(root_path).join(STAT_CACHE_FILE_NAME);
+        let mut cache = StatCache::default();
+        if !path.is_file() {
+            return Ok(cache);
+        }
+        // A damaged cache is only a missed optimization, so bad lines are skipped.
+        for line in fs::read_to_string(&path)?.lines() {
+            let parts: Vec<&str> = line.splitn(4, ' ').collect();
+            let [hash, size, mtime, file] = parts[..] else {
+                continue;
+            };
+            if let (true, Ok(size), Ok(mtime)) =
+                (is_valid_hash(hash), size.parse(), mtime.parse())
+            {
+                cache.entries.insert(
+                    file.to_string(),
+                    StatEntry {
+                        size,
+                        mtime,
+                        hash: hash.to_string(),
+                    },
+                );
+            }
+        }
+        Ok(cache)
+    }
+
+    pub(crate) fn save(&self, root_path: &Path) -> Result<()> {
+        // Files modified within the last couple of seconds could change again
+        // without their mtime moving, so they are left out and re-hashed next time.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let racy_window = 2_000_000_000;
+        let content: String = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.mtime + racy_window < now)
+            .map(|(path, entry)| {
+                format!("{} {} {} {}\n", entry.hash, entry.size, entry.mtime, path)
+            })
+            .collect();
+        let path = gini_dir(root_path).join(STAT_CACHE_FILE_NAME);
+        write_file_atomic(&path, content.as_bytes())
+    }
+}
+
+/// The tree hash the working tree would get as a checkpoint, computed
+/// without storing any objects.
+pub(crate) fn hash_working_tree(root_path: &Path) -> Result<String> {
+    let mut builder = TreeBuilder::new(root_path, true)?;
+    builder.write_objects = false;
+    builder.write_tree(root_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A scratch store plus a working tree nested one level down, so
+    /// anything escaping the working tree lands in `outer`.
+    fn setup() -> (TempDir, PathBuf, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let objects_path = dir.path().join("store").join("objects");
+        let root_path = dir.path().join("outer").join("work");
+        fs::create_dir_all(&objects_path).unwrap();
+        fs::create_dir_all(&root_path).unwrap();
+        (dir, objects_path, root_path)
+    }
+
+    fn store(objects_path: &Path, content: &str) -> String {
+        hash_and_write_object(objects_path, content.as_bytes()).unwrap()
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn restore_rejects_dot_dot_entry() {
+        let (_dir, objects_path, root_path) = setup();
+        let blob = store(&objects_path, "escaped\n");
+        let inner = store(&objects_path, &format!("blob {} 100644 x", blob));
+        let tree = store(&objects_path, &format!("tree {} ..", inner));
+
+        assert!(restore_tree(&root_path, &objects_path, &tree, false).is_err());
+        assert_eq!(entries(root_path.parent().unwrap()), ["work"]);
+        assert!(entries(&root_path).is_empty());
+    }
+
+    #[test]
+    fn restore_rejects_nested_parent_path() {
+        let (_dir, objects_path, root_path) = setup();
+        let blob = store(&objects_path, "escaped\n");
+        let sub = store(&objects_path, &format!("blob {} 100644 ../../x", blob));
+        let tree = store(&objects_path, &format!("tree {} sub", sub));
+
+        assert!(restore_tree(&root_path, &objects_path, &tree, false).is_err());
+        assert_eq!(entries(root_path.parent().unwrap()), ["work"]);
+        assert!(!root_path.join("x").exists());
+    }
+}
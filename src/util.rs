@@ -0,0 +1,492 @@
+//! Filesystem and formatting helpers shared across commands.
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Metadata directories set with [`set_gini_dir`], by working tree.
+static GINI_DIR_OVERRIDES: OnceLock<Mutex<HashMap<PathBuf, PathBuf>>> = OnceLock::new();
+
+/// Where the repository rooted at `root_path` keeps its metadata: a
+/// directory given with [`set_gini_dir`], else the one a `.gini` file
+/// points at with a `gitdir: <path>` line, else the `.gini` directory.
+pub(crate) fn gini_dir(root_path: &Path) -> PathBuf {
+    let overrides = GINI_DIR_OVERRIDES.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(dir) = overrides.get(root_path) {
+        return dir.clone();
+    }
+    let path = root_path.join(".gini");
+    if path.is_file() {
+        if let Some(target) = read_gini_pointer(&path) {
+            return root_path.join(target);
+        }
+    }
+    path
+}
+
+/// The target of a `.gini` pointer file, or `None` if it has no `gitdir:` line.
+pub(crate) fn read_gini_pointer(path: &Path) -> Option<PathBuf> {
+    let content = fs::read_to_string(path).ok()?;
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("gitdir:"))
+        .map(|target| PathBuf::from(target.trim()))
+}
+
+/// Whether `path` looks like a repository's metadata directory.
+pub fn is_gini_dir(path: &Path) -> bool {
+    path.join("objects").is_dir() && path.join("HEAD").is_file()
+}
+
+/// Makes the repository rooted at `root_path` keep its metadata in `dir`
+/// for the rest of the process.
+pub(crate) fn set_gini_dir(root_path: &Path, dir: &Path) {
+    let mut overrides = GINI_DIR_OVERRIDES.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    overrides.insert(root_path.to_path_buf(), dir.to_path_buf());
+}
+
+pub(crate) fn write_file_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, content)?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+/// Copies `src` into `dst`, leaving out entries named in `exclude`.
+/// Symlinks are copied as links, not followed. When `dst` lies inside
+/// `src`, it is skipped rather than copied into itself over and over.
+pub(crate) fn copy_directory_excluding(src: &Path, dst: &Path, exclude: &[&str]) -> Result<()> {
+    if src.is_file() {
+        fs::copy(src, dst)?;
+        return Ok(());
+    }
+    
+    fs::create_dir_all(dst)?;
+    let dst_canonical = fs::canonicalize(dst)?;
+    let nested = fs::canonicalize(src).is_ok_and(|src| dst_canonical.starts_with(src));
+    copy_entries(src, dst, exclude, nested.then_some(dst_canonical.as_path()))
+}
+
+/// The body of [`copy_directory_excluding`]; `skip` is the destination when
+/// it sits inside the source.
+fn copy_entries(src: &Path, dst: &Path, exclude: &[&str], skip: Option<&Path>) -> Result<()> {
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        
+        if exclude.contains(&name) {
+            continue;
+        }
+        
+        let dst_path = dst.join(name);
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            create_symlink(&read_link_bytes(&path)?, &dst_path)?;
+        } else if file_type.is_dir() {
+            if skip.is_some_and(|skip| fs::canonicalize(&path).is_ok_and(|path| path == skip)) {
+                continue;
+            }
+            fs::create_dir_all(&dst_path)?;
+            copy_entries(&path, &dst_path, exclude, skip)?;
+        } else {
+            fs::copy(&path, &dst_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// The paths (relative to `src`) that `copy_directory_excluding` would
+/// overwrite in `dst`: anything already there except a directory being
+/// copied into a directory.
+pub(crate) fn copy_conflicts(src: &Path, dst: &Path, exclude: &[&str]) -> Result<Vec<String>> {
+    fn walk(src: &Path, dst: &Path, prefix: &str, exclude: &[&str], conflicts: &mut Vec<String>) -> Result<()> {
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if exclude.contains(&name.as_str()) {
+                continue;
+            }
+            let dst_path = dst.join(&name);
+            let Ok(existing) = fs::symlink_metadata(&dst_path) else {
+                continue;
+            };
+            if entry.file_type()?.is_dir() && existing.is_dir() {
+                walk(&entry.path(), &dst_path, &format!("{}{}/", prefix, name), exclude, conflicts)?;
+            } else {
+                conflicts.push(format!("{}{}", prefix, name));
+            }
+        }
+        Ok(())
+    }
+
+    let mut conflicts = Vec::new();
+    walk(src, dst, "", exclude, &mut conflicts)?;
+    conflicts.sort();
+    Ok(conflicts)
+}
+
+// Scratch directories under `.gini` used by `replace_working_tree`. Which of
+// them exist after a crash tells `finish_interrupted_replace` how far the
+// swap got.
+/// The new tree while it is being written.
+const REPLACE_STAGING: &str = "restore.tmp";
+/// The complete new tree, waiting to be moved in.
+const REPLACE_NEW: &str = "restore.new";
+/// The old tree while it is being moved out.
+const REPLACE_OLD_PARTIAL: &str = "restore.old.tmp";
+/// The old tree once it is entirely out of the way.
+const REPLACE_OLD: &str = "restore.old";
+
+/// Replaces everything in the working tree except `.gini` and `.git` with
+/// what `fill` writes into an empty directory. The new tree is built off
+/// to the side first, so a failure there leaves the working tree alone; it
+/// is then swapped in with renames, and an interruption during the swap is
+/// completed by [`finish_interrupted_replace`] rather than leaving a
+/// half-empty tree.
+pub(crate) fn replace_working_tree(
+    root_path: &Path,
+    fill: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+    finish_interrupted_replace(root_path)?;
+    let gini_path = gini_dir(root_path);
+    let staging = gini_path.join(REPLACE_STAGING);
+    fs::create_dir_all(&staging)?;
+    if let Err(e) = fill(&staging) {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    fs::rename(&staging, gini_path.join(REPLACE_NEW))?;
+    finish_interrupted_replace(root_path)
+}
+
+/// Completes a [`replace_working_tree`] that was cut short. A new tree that
+/// was never finished is discarded and the working tree kept; once the new
+/// tree is complete, the swap is carried through. Does nothing when no
+/// replace was in progress.
+pub(crate) fn finish_interrupted_replace(root_path: &Path) -> Result<()> {
+    let gini_path = gini_dir(root_path);
+    let staging = gini_path.join(REPLACE_STAGING);
+    let new = gini_path.join(REPLACE_NEW);
+    let old_partial = gini_path.join(REPLACE_OLD_PARTIAL);
+    let old = gini_path.join(REPLACE_OLD);
+
+    if staging.exists() {
+        remove_all(&staging)?;
+    }
+    if new.is_dir() {
+        // Until the old tree is entirely out, the working tree holds only
+        // old files; keep moving them.
+        if !old.is_dir() {
+            fs::create_dir_all(&old_partial)?;
+            move_entries(root_path, &old_partial, true)?;
+            fs::rename(&old_partial, &old)?;
+        }
+        // From here on, the working tree holds only new files.
+        move_entries(&new, root_path, false)?;
+        remove_all(&new)?;
+    }
+    if old.exists() {
+        remove_all(&old).map_err(|e| {
+            anyhow::anyhow!("{}\nThe rest of the old files are in .gini/{}", e, REPLACE_OLD)
+        })?;
+    }
+    Ok(())
+}
+
+/// Renames every entry of `from` into `to`, skipping `.gini` and `.git` in
+/// the working tree. An entry that can't be renamed (a mount point, say) is
+/// deleted when `delete_unmovable` is set.
+fn move_entries(from: &Path, to: &Path, delete_unmovable: bool) -> Result<()> {
+    let mut failed = Vec::new();
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == ".gini" || name == ".git" {
+            continue;
+        }
+        let path = entry.path();
+        if let Err(e) = fs::rename(&path, to.join(&name)) {
+            if delete_unmovable {
+                remove_collecting(&path, &mut failed);
+            } else if e.kind() == std::io::ErrorKind::CrossesDevices {
+                // The metadata directory is on another disk; copy instead.
+                move_by_copy(&path, &to.join(&name))?;
+            } else {
+                return Err(e.into());
+            }
+        }
+    }
+    report_unremovable(&failed)
+}
+
+/// Copies `from` to `to` and then deletes `from`, for a rename that can't
+/// cross filesystems.
+fn move_by_copy(from: &Path, to: &Path) -> Result<()> {
+    if fs::symlink_metadata(from)?.file_type().is_symlink() {
+        create_symlink(&read_link_bytes(from)?, to)?;
+    } else {
+        copy_directory_excluding(from, to, &[])?;
+    }
+    remove_all(from)
+}
+
+/// Deletes a file, symlink or whole directory tree. Read-only files and
+/// directories are made writable first where that is what stops them being
+/// removed. Keeps going past paths it can't remove and lists all of them in
+/// the error.
+pub(crate) fn remove_all(path: &Path) -> Result<()> {
+    let mut failed = Vec::new();
+    remove_collecting(path, &mut failed);
+    report_unremovable(&failed)
+}
+
+fn report_unremovable(failed: &[(PathBuf, std::io::Error)]) -> Result<()> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+    let list: Vec<String> = failed
+        .iter()
+        .map(|(path, e)| format!("  {} ({})", path.display(), e))
+        .collect();
+    bail!("Could not remove:\n{}", list.join("\n"))
+}
+
+fn remove_collecting(path: &Path, failed: &mut Vec<(PathBuf, std::io::Error)>) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return;
+    };
+    let remove = |path: &Path| {
+        if metadata.is_dir() {
+            fs::remove_dir(path)
+        } else {
+            fs::remove_file(path)
+        }
+    };
+    if metadata.is_dir() {
+        // A read-only directory can't have entries removed from it.
+        let entries = fs::read_dir(path).or_else(|_| {
+            make_writable(path);
+            fs::read_dir(path)
+        });
+        match entries {
+            Ok(entries) => {
+                if metadata.permissions().readonly() {
+                    make_writable(path);
+                }
+                for entry in entries.flatten() {
+                    remove_collecting(&entry.path(), failed);
+                }
+            }
+            Err(e) => {
+                failed.push((path.to_path_buf(), e));
+                return;
+            }
+        }
+    }
+    if remove(path).is_err() {
+        // Windows refuses to delete read-only files.
+        make_writable(path);
+        if let Err(e) = remove(path) {
+            failed.push((path.to_path_buf(), e));
+        }
+    }
+}
+
+/// Clears the read-only permission on `path`, ignoring failures: the
+/// removal that follows reports the real error.
+fn make_writable(path: &Path) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return;
+    };
+    if metadata.file_type().is_symlink() {
+        return;
+    }
+    let mut permissions = metadata.permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o700);
+    }
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    let _ = fs::set_permissions(path, permissions);
+}
+
+/// Reads a symlink's target as raw bytes, the form stored in link blobs.
+pub(crate) fn read_link_bytes(path: &Path) -> Result<Vec<u8>> {
+    let target = fs::read_link(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Ok(target.as_os_str().as_bytes().to_vec())
+    }
+    #[cfg(not(unix))]
+    Ok(target.to_string_lossy().replace('\\', "/").into_bytes())
+}
+
+/// Creates a symlink at `path` pointing to `target`, replacing whatever is
+/// there. Platforms without unprivileged symlinks get a warning instead.
+pub(crate) fn create_symlink(target: &[u8], path: &Path) -> Result<()> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        std::os::unix::fs::symlink(OsStr::from_bytes(target), path)?;
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = target;
+        eprintln!(
+            "gini: warning: skipping symlink {} (not supported on this platform)",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Converts a user-supplied path (relative to the current directory) into a
+/// normalized, `/`-separated path relative to the repository root.
+pub(crate) fn repo_relative_path(root_path: &Path, input: &str) -> Result<String> {
+    let relative = normalize_repo_path(root_path, input)?;
+    if relative.is_empty() {
+        bail!("Path refers to the repository root: {}", input);
+    }
+    Ok(relative)
+}
+
+pub(crate) fn normalize_repo_path(root_path: &Path, input: &str) -> Result<String> {
+    use std::path::Component;
+
+    // With `--repo` the caller may be running from outside the tree, in
+    // which case relative paths are taken from the repository root.
+    let cwd = std::env::current_dir()?;
+    let base = if cwd.starts_with(root_path) { cwd } else { root_path.to_path_buf() };
+    let absolute = base.join(input);
+    let mut parts: Vec<String> = Vec::new();
+    for component in absolute.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => parts.clear(),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop();
+            }
+            Component::Normal(name) => parts.push(name.to_string_lossy().into_owned()),
+        }
+    }
+
+    let root_parts: Vec<String> = root_path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    if parts.len() < root_parts.len() || parts[..root_parts.len()] != root_parts[..] {
+        bail!("Path is outside the repository: {}", input);
+    }
+    Ok(parts[root_parts.len()..].join("/"))
+}
+
+/// Like `repo_relative_path`, but the repository root itself is allowed and
+/// maps to the empty string.
+pub(crate) fn repo_relative_path_or_root(root_path: &Path, input: &str) -> Result<String> {
+    normalize_repo_path(root_path, input)
+}
+
+/// Deletes every directory beneath `dir` that is empty, or holds only
+/// directories that are, except those in `keep`. `dir` itself stays.
+/// Returns how many were removed.
+pub(crate) fn remove_empty_dirs(dir: &Path, keep: &[PathBuf]) -> Result<usize> {
+    let mut removed = 0;
+    if !dir.is_dir() {
+        return Ok(0);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !fs::symlink_metadata(&path)?.is_dir() {
+            continue;
+        }
+        removed += remove_empty_dirs(&path, keep)?;
+        if !keep.contains(&path) && fs::read_dir(&path)?.next().is_none() {
+            fs::remove_dir(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// True if the repository path `path` is `dir` itself or lies beneath it.
+pub(crate) fn path_is_within(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Encodes `value` as a JSON string literal.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Total size of the files under `path` (or of `path` itself if it is a
+/// file), without following symlinks. A missing path counts as zero.
+pub(crate) fn disk_usage(path: &Path) -> Result<u64> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += disk_usage(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn copy_skips_a_destination_nested_in_the_source() {
+        let dir = TempDir::new().unwrap();
+        let src = dir.path().join("work");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("a.txt"), "a").unwrap();
+        fs::write(src.join("sub").join("b.txt"), "b").unwrap();
+        let dst = src.join("backups").join("snapshot");
+
+        copy_directory_excluding(&src, &dst, &[".gini"]).unwrap();
+
+        assert_eq!(fs::read_to_string(dst.join("a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dst.join("sub").join("b.txt")).unwrap(), "b");
+        // The destination itself is left out rather than copied into itself.
+        assert!(!dst.join("backups").join("snapshot").exists());
+    }
+}
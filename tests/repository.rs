@@ -0,0 +1,297 @@
+use std::fs;
+use std::path::Path;
+
+use gini::{CheckpointOptions, Repository};
+use tempfile::TempDir;
+
+fn new_repo() -> (TempDir, Repository) {
+    let dir = TempDir::new().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    (dir, repo)
+}
+
+fn write(root: &Path, path: &str, content: &[u8]) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+fn checkpoint(repo: &Repository, message: &str) -> String {
+    repo.checkpoint(message, &CheckpointOptions::default()).unwrap().commit.unwrap()
+}
+
+fn hash_algo_round_trip(algo: &str, first_blob: &str) {
+    let (dir, repo) = new_repo();
+    repo.set_config("core.hashAlgo", algo).unwrap();
+    write(dir.path(), "a.txt", b"first\n");
+    write(dir.path(), "sub/b.txt", b"nested\n");
+    let first = checkpoint(&repo, "first");
+    assert_eq!(first.len(), first_blob.len());
+    assert_eq!(repo.cat_file(first_blob).unwrap(), b"first\n");
+
+    write(dir.path(), "a.txt", b"second\n");
+    checkpoint(&repo, "second");
+    repo.restore(&first, false, false, false, &[]).unwrap();
+
+    assert_eq!(fs::read(dir.path().join("a.txt")).unwrap(), b"first\n");
+    assert_eq!(fs::read(dir.path().join("sub/b.txt")).unwrap(), b"nested\n");
+    assert_eq!(repo.fsck().unwrap(), Vec::<String>::new());
+}
+
+#[test]
+fn sha1_repository_round_trip() {
+    hash_algo_round_trip("sha1", "271ac93c44ac198d92e706c6d6f1d84aefcfa337");
+}
+
+#[test]
+fn sha256_repository_round_trip() {
+    hash_algo_round_trip("sha256", "b640e840b19d378660b32fb51ae18d67dccb4a8596a29e7bd72c1b2ae5928f41");
+}
+
+#[test]
+fn restore_brings_back_a_deleted_file() {
+    let (dir, repo) = new_repo();
+    write(dir.path(), "kept.txt", b"kept\n");
+    write(dir.path(), "gone.txt", b"gone\n");
+    let before = checkpoint(&repo, "both files");
+    fs::remove_file(dir.path().join("gone.txt")).unwrap();
+    let after = checkpoint(&repo, "delete gone.txt");
+
+    repo.restore(&before, false, true, false, &[]).unwrap();
+    assert_eq!(fs::read(dir.path().join("gone.txt")).unwrap(), b"gone\n");
+
+    repo.restore(&after, false, true, false, &[]).unwrap();
+    assert!(!dir.path().join("gone.txt").exists());
+    assert_eq!(fs::read(dir.path().join("kept.txt")).unwrap(), b"kept\n");
+
+    // Restoring just the files brings it back too, leaving HEAD alone.
+    repo.restore_files(&before, false, false, &[]).unwrap();
+    assert_eq!(fs::read(dir.path().join("gone.txt")).unwrap(), b"gone\n");
+    assert_eq!(repo.resolve_revision("HEAD").unwrap(), after);
+}
+
+#[test]
+fn restore_path_handles_a_file_deleted_in_between() {
+    let (dir, repo) = new_repo();
+    write(dir.path(), "kept.txt", b"kept\n");
+    write(dir.path(), "gone.txt", b"gone\n");
+    let before = checkpoint(&repo, "both files");
+    fs::remove_file(dir.path().join("gone.txt")).unwrap();
+    let after = checkpoint(&repo, "delete gone.txt");
+
+    // Existed then: restoring the path from the earlier checkpoint brings it back.
+    repo.restore_path(&before, "gone.txt").unwrap();
+    assert_eq!(fs::read(dir.path().join("gone.txt")).unwrap(), b"gone\n");
+
+    // Gone now: with HEAD back on the checkpoint that tracks it, restoring
+    // the path from the later one deletes it again.
+    repo.restore(&before, false, true, false, &[]).unwrap();
+    repo.restore_path(&after, "gone.txt").unwrap();
+    assert!(!dir.path().join("gone.txt").exists());
+    assert_eq!(fs::read(dir.path().join("kept.txt")).unwrap(), b"kept\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn restore_keeps_the_executable_bit() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (dir, repo) = new_repo();
+    let script = dir.path().join("run.sh");
+    write(dir.path(), "run.sh", b"#!/bin/sh\necho hi\n");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let executable = checkpoint(&repo, "add script");
+    fs::remove_file(&script).unwrap();
+    checkpoint(&repo, "remove script");
+
+    repo.restore(&executable, false, false, false, &[]).unwrap();
+    let mode = fs::metadata(&script).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o755);
+}
+
+#[test]
+fn empty_directory_survives_restore() {
+    let (dir, repo) = new_repo();
+    write(dir.path(), "app.txt", b"app\n");
+    fs::create_dir(dir.path().join("logs")).unwrap();
+    let first = checkpoint(&repo, "empty logs dir");
+    fs::remove_dir(dir.path().join("logs")).unwrap();
+    checkpoint(&repo, "remove logs dir");
+
+    repo.restore(&first, false, false, false, &[]).unwrap();
+    let logs = dir.path().join("logs");
+    assert!(logs.is_dir());
+    assert_eq!(fs::read_dir(&logs).unwrap().count(), 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn export_tar_round_trips_through_a_tar_reader() {
+    use std::collections::BTreeMap;
+    use std::io::Read;
+    use std::os::unix::fs::PermissionsExt;
+
+    let (dir, repo) = new_repo();
+    let long_path = format!("{}/{}/{}.txt", "d".repeat(60), "e".repeat(60), "f".repeat(70));
+    let long_target = "t".repeat(120);
+    write(dir.path(), "plain.txt", b"plain\n");
+    write(dir.path(), "run.sh", b"#!/bin/sh\n");
+    fs::set_permissions(dir.path().join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    write(dir.path(), &long_path, b"long\n");
+    std::os::unix::fs::symlink("run.sh", dir.path().join("link")).unwrap();
+    std::os::unix::fs::symlink(&long_target, dir.path().join("long-link")).unwrap();
+    let commit = checkpoint(&repo, "archive me");
+
+    let mut archive = Vec::new();
+    repo.export_tar(&commit, &mut archive).unwrap();
+
+    let mut entries = BTreeMap::new();
+    for entry in tar::Archive::new(archive.as_slice()).entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = String::from_utf8(entry.path_bytes().into_owned()).unwrap();
+        let kind = entry.header().entry_type();
+        let mode = entry.header().mode().unwrap();
+        let link = entry.link_name_bytes().map(|link| link.into_owned());
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).unwrap();
+        entries.insert(path, (kind, mode, link, content));
+    }
+    assert_eq!(entries["plain.txt"], (tar::EntryType::Regular, 0o644, None, b"plain\n".to_vec()));
+    assert_eq!(entries["run.sh"], (tar::EntryType::Regular, 0o755, None, b"#!/bin/sh\n".to_vec()));
+    assert_eq!(entries[&long_path], (tar::EntryType::Regular, 0o644, None, b"long\n".to_vec()));
+    assert_eq!(entries["link"].0, tar::EntryType::Symlink);
+    assert_eq!(entries["link"].2.as_deref(), Some(&b"run.sh"[..]));
+    assert_eq!(entries["long-link"].2.as_deref(), Some(long_target.as_bytes()));
+
+    // Importing the archive elsewhere gives back the very same files.
+    let (other_dir, other) = new_repo();
+    let imported = other.import_tar(&mut archive.as_slice(), "imported").unwrap();
+    other.restore(&imported, false, false, false, &[]).unwrap();
+    let other_root = other_dir.path();
+    assert_eq!(fs::read(other_root.join("plain.txt")).unwrap(), b"plain\n");
+    assert_eq!(fs::read(other_root.join(&long_path)).unwrap(), b"long\n");
+    let mode = fs::metadata(other_root.join("run.sh")).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o755);
+    assert_eq!(fs::read_link(other_root.join("link")).unwrap(), Path::new("run.sh"));
+    assert_eq!(fs::read_link(other_root.join("long-link")).unwrap(), Path::new(&long_target));
+}
+
+#[test]
+fn import_tar_rejects_paths_that_leave_the_archive() {
+    for name in ["../evil.txt", "a/../../evil.txt", "/etc/evil.txt"] {
+        let mut header = tar::Header::new_ustar();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_size(5);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append(&header, &b"evil\n"[..]).unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        let (_dir, repo) = new_repo();
+        assert!(repo.import_tar(&mut archive.as_slice(), "evil").is_err(), "{} was accepted", name);
+        assert!(repo.resolve_revision("HEAD").is_err());
+    }
+}
+
+#[test]
+fn non_utf8_blob_round_trips() {
+    let (dir, repo) = new_repo();
+    let content: &[u8] = &[0xff, 0xfe, 0x00, b'a', 0x80, b'\n', 0xc3, 0x28, 0xf8];
+    write(dir.path(), "data.bin", content);
+    let first = checkpoint(&repo, "add binary");
+    fs::remove_file(dir.path().join("data.bin")).unwrap();
+    checkpoint(&repo, "remove binary");
+
+    repo.restore(&first, false, true, false, &[]).unwrap();
+    assert_eq!(fs::read(dir.path().join("data.bin")).unwrap(), content);
+}
+
+#[cfg(unix)]
+#[test]
+fn export_zip_round_trips_through_a_zip_reader() {
+    use chrono::{Datelike, TimeZone, Timelike};
+    use std::collections::BTreeMap;
+    use std::io::Read;
+    use std::os::unix::fs::PermissionsExt;
+
+    let (dir, repo) = new_repo();
+    write(dir.path(), "plain.txt", &b"plain text\n".repeat(20));
+    write(dir.path(), "bin/run.sh", b"#!/bin/sh\n");
+    fs::set_permissions(dir.path().join("bin/run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    std::os::unix::fs::symlink("bin/run.sh", dir.path().join("link")).unwrap();
+    let commit = checkpoint(&repo, "zip me");
+
+    let mut archive = Vec::new();
+    repo.export_zip(&commit, &mut archive).unwrap();
+
+    // Entries are dated the checkpoint's time as its author saw it, which
+    // here is the local time zone.
+    let timestamp = repo.get_commit_history().unwrap()[0].timestamp;
+    let date = chrono::Local.timestamp_opt(timestamp, 0).unwrap();
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(archive)).unwrap();
+    let mut entries = BTreeMap::new();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).unwrap();
+        let modified = file.last_modified().unwrap();
+        assert_eq!(
+            (modified.year(), modified.month(), modified.day()),
+            (date.year() as u16, date.month() as u8, date.day() as u8)
+        );
+        assert_eq!(
+            (modified.hour(), modified.minute(), modified.second()),
+            (date.hour() as u8, date.minute() as u8, (date.second() / 2 * 2) as u8)
+        );
+        let name = file.name().unwrap().into_owned();
+        let attributes = file.external_attributes();
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).unwrap();
+        let mut crc = flate2::Crc::new();
+        crc.update(&content);
+        assert_eq!(file.crc32(), crc.sum(), "{}", name);
+        entries.insert(name, (attributes, content));
+    }
+
+    // The upper 16 bits hold the Unix mode; 0x10 marks a directory for DOS.
+    assert_eq!(entries["bin/"].0, (0o040755 << 16) | 0x10);
+    assert_eq!(entries["bin/run.sh"], (0o100755 << 16, b"#!/bin/sh\n".to_vec()));
+    assert_eq!(entries["plain.txt"], (0o100644 << 16, b"plain text\n".repeat(20)));
+    assert_eq!(entries["link"], (0o120777 << 16, b"bin/run.sh".to_vec()));
+}
+
+#[test]
+fn identical_files_under_autocrlf_are_stored_once() {
+    let (dir, repo) = new_repo();
+    repo.set_config("core.autocrlf", "true").unwrap();
+    for i in 0..200 {
+        write(dir.path(), &format!("copies/{}.txt", i), b"same\r\ncontent\r\n");
+    }
+    let commit = checkpoint(&repo, "many copies");
+
+    assert_eq!(repo.file_at(&commit, "copies/123.txt").unwrap(), b"same\ncontent\n");
+    assert_eq!(repo.fsck().unwrap(), Vec::<String>::new());
+    let leftovers: Vec<_> = fs::read_dir(repo.gini_dir().join("objects"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty(), "temporary files left behind: {:?}", leftovers);
+}
+
+#[test]
+fn restore_refuses_to_delete_an_ignored_file() {
+    let (dir, repo) = new_repo();
+    write(dir.path(), ".giniignore", b"*.log\n");
+    write(dir.path(), "a.txt", b"a\n");
+    let commit = checkpoint(&repo, "first");
+    write(dir.path(), "build.log", b"only copy\n");
+
+    assert_eq!(repo.files_lost_by_restore(&commit).unwrap(), ["build.log"]);
+    let error = repo.restore(&commit, false, false, false, &[]).unwrap_err();
+    assert!(error.to_string().contains("build.log"), "unexpected error: {}", error);
+    assert_eq!(fs::read(dir.path().join("build.log")).unwrap(), b"only copy\n");
+
+    repo.restore(&commit, true, false, false, &[]).unwrap();
+    assert!(!dir.path().join("build.log").exists());
+}